@@ -0,0 +1,32 @@
+// Time of building SHA-256 trees, compare `cargo bench --bench sha256_tree` with and
+// without `--features simd`. SHA3-256 is timed too as the baseline neither changes. The
+// best of a few runs is kept, the others mostly measure the machine
+use merkle_tree::merkle_tree::MerkleTree;
+use merkle_tree::scheme::HashFunction;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const LEAVES: usize = 1 << 20;
+const RUNS: usize = 5;
+
+fn best_of(runs: usize, mut f: impl FnMut()) -> Duration {
+  (0..runs).map(|_| {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+  }).min().expect("at least one run")
+}
+
+fn main() {
+  let simd = if cfg!(feature = "simd") { "on" } else { "off" };
+  for len in [32, 100] {
+    let elements: Vec<String> = (0..LEAVES).map(|i| format!("{i:0len$}")).collect();
+    for function in [HashFunction::Sha256, HashFunction::Sha3_256] {
+      let time = best_of(RUNS, || {
+        black_box(MerkleTree::with_hash_function(black_box(&elements), function).root());
+      });
+      println!("{:>8} {LEAVES} leaves of {len:>3} bytes, simd {simd}: {:>7.1} ms, {:>5.0} ns/leaf",
+        function.name(), time.as_secs_f64() * 1e3, time.as_nanos() as f64 / LEAVES as f64);
+    }
+  }
+}
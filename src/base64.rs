@@ -0,0 +1,107 @@
+// Standard alphabet base64 with padding (RFC 4648), the encoding CT logs use for hashes
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// The URL and filename safe alphabet of RFC 4648, nothing in it needs escaping in a URL
+const URL_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// The crate only reads this alphabet, the tests write it
+#[cfg(test)]
+#[must_use]
+pub fn encode(bytes: &[u8]) -> String {
+  encode_with(bytes, ALPHABET, true)
+}
+
+pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+  if !text.len().is_multiple_of(4) {
+    return Err(format!("base64 length {} is not a multiple of 4", text.len()));
+  }
+  decode_with(text, ALPHABET)
+}
+
+// URL safe alphabet without padding, for proofs in URLs and tokens
+#[must_use]
+pub fn encode_url(bytes: &[u8]) -> String {
+  encode_with(bytes, URL_ALPHABET, false)
+}
+
+// Reads `encode_url` with or without padding
+pub fn decode_url(text: &str) -> Result<Vec<u8>, String> {
+  if text.ends_with('=') && !text.len().is_multiple_of(4) {
+    return Err(format!("misplaced padding in {text:?}"));
+  }
+  decode_with(text, URL_ALPHABET)
+}
+
+fn encode_with(bytes: &[u8], alphabet: &[u8; 64], pad: bool) -> String {
+  let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+  for chunk in bytes.chunks(3) {
+    let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+    for i in 0..4 {
+      if i <= chunk.len() {
+        out.push(char::from(alphabet[(n >> (18 - 6 * i) & 63) as usize]));
+      } else if pad {
+        out.push('=');
+      }
+    }
+  }
+  out
+}
+
+// Padding may only close the last group. The bits a short last group leaves over have to
+// be zero, so every byte string has one encoding
+fn decode_with(text: &str, alphabet: &[u8; 64]) -> Result<Vec<u8>, String> {
+  let data = text.trim_end_matches('=');
+  if text.len() - data.len() > 2 {
+    return Err(format!("misplaced padding in {text:?}"));
+  }
+  if data.len() % 4 == 1 {
+    return Err(format!("base64 length {} leaves a partial byte", data.len()));
+  }
+  let mut out = Vec::with_capacity(data.len() / 4 * 3 + 2);
+  for chunk in data.as_bytes().chunks(4) {
+    let mut n = 0u32;
+    for &c in chunk {
+      let value = alphabet.iter().position(|&a| a == c).ok_or_else(|| format!("invalid base64 character {:?}", char::from(c)))?;
+      n = n << 6 | value as u32;
+    }
+    let missing = 4 - chunk.len();
+    n <<= 6 * missing;
+    let bytes = n.to_be_bytes();
+    if bytes[4 - missing..].iter().any(|&b| b != 0) {
+      return Err(format!("trailing bits in {text:?}"));
+    }
+    out.extend_from_slice(&bytes[1..4 - missing]);
+  }
+  Ok(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::base64::{decode, decode_url, encode, encode_url};
+
+    #[test]
+    fn base64_round_trips() {
+        for (bytes, text) in [(&b""[..], ""), (b"f", "Zg=="), (b"fo", "Zm8="), (b"foo", "Zm9v"), (b"foobar", "Zm9vYmFy")] {
+            assert_eq!(encode(bytes), text);
+            assert_eq!(decode(text).unwrap(), bytes);
+        }
+        assert!(decode("Zm9").is_err());
+        assert!(decode("Zg==Zm9v").is_err());
+        assert!(decode("Zm9*").is_err());
+    }
+
+    #[test]
+    fn url_safe_base64_round_trips_with_or_without_padding() {
+        for (bytes, text) in [(&b""[..], ""), (b"f", "Zg"), (b"fo", "Zm8"), (b"foo", "Zm9v"), (&[0xfb, 0xff, 0xbf][..], "-_-_")] {
+            assert_eq!(encode_url(bytes), text);
+            assert_eq!(decode_url(text).unwrap(), bytes);
+        }
+        assert_eq!(decode_url("Zg==").unwrap(), b"f");
+        assert_eq!(decode_url("Zm8=").unwrap(), b"fo");
+        assert_eq!(encode(&[0xfb, 0xff, 0xbf]), "+/+/");
+        for bad in ["+/+/", "Zm9v!", "Z", "Zm9vZ", "Zg=", "Zg===", "Zg==Zg", "Zh", "Zm9", "Zm8=="] {
+            assert!(decode_url(bad).is_err(), "{bad:?}");
+        }
+    }
+}
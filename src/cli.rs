@@ -0,0 +1,240 @@
+use merkle_tree::error::{MerkleTreeError, VerifyError};
+use merkle_tree::hash::Root;
+use merkle_tree::merkle_tree::MerkleTree;
+use merkle_tree::proof::InclusionProof;
+use std::fs;
+
+const USAGE: &str = "usage:
+  merkle-tree root <elements file>
+  merkle-tree prove --index <i> --out <proof file> [--encoding <e>] <elements file>
+  merkle-tree verify [--verbose] --root <hex> --proof <proof file> [--encoding <e>]
+
+The elements file holds one element per line. Proof files are json unless --encoding
+is hex or base64 (URL safe, for proofs embedded in URLs and tokens).";
+
+// Runs one command, the output goes to stdout and errors to stderr with a failure exit code
+pub fn run(args: &[String]) -> Result<String, String> {
+  let (command, rest) = args.split_first().ok_or(USAGE)?;
+  let options = Options::parse(rest)?;
+  match command.as_str() {
+    "root" => Ok(read_tree(options.input()?)?.root().to_string()),
+    "prove" => {
+      let tree = read_tree(options.input()?)?;
+      let index = options.value("--index")?.parse().map_err(|_| "--index must be a number".to_string())?;
+      let proof = tree.inclusion_proof(index).map_err(|e| e.to_string())?;
+      let out = options.value("--out")?;
+      fs::write(out, Encoding::of(&options)?.write(&proof)?).map_err(|e| format!("cannot write {out}: {e}"))?;
+      Ok(format!("proof of leaf {index} written to {out}"))
+    },
+    "verify" => verify(options.value("--root")?, options.value("--proof")?, Encoding::of(&options)?, options.switch("--verbose")),
+    _ => Err(USAGE.to_string()),
+  }
+}
+
+// With `verbose` the proof path is printed before the outcome, see `MerkleProof::explain`.
+// Failures are reported as `MerkleProof::verify_detailed` names them
+fn verify(root: &str, proof_path: &str, encoding: Encoding, verbose: bool) -> Result<String, String> {
+  let root = match root.parse::<Root>() {
+    Err(MerkleTreeError::InvalidEncoding(e)) => return Err(format!("bad hex in --root: {e}")),
+    root => root,
+  };
+  let text = fs::read_to_string(proof_path).map_err(|e| format!("cannot read {proof_path}: {e}"))?;
+  let proof = encoding.read(&text)?;
+  // the hash function named by the proof decides the size of every hash
+  let hash_len = proof.proof.function.output_len();
+  let root = match root {
+    Ok(root) if root.len() == hash_len => root,
+    Ok(root) => return Err(format!("--root is {} bytes, expected {hash_len}", root.len())),
+    Err(MerkleTreeError::InvalidHashLength { got, .. }) => return Err(format!("--root is {got} bytes, expected {hash_len}")),
+    Err(e) => return Err(e.to_string()),
+  };
+  if let Err(e) = proof.proof.verify_detailed(&root, &proof.leaf) {
+    // the path can only be explained for proofs of the right shape
+    return Err(match e {
+      VerifyError::RootMismatch { .. } if verbose => format!("{}\n{e}", proof.proof.explain(&proof.leaf, &root)),
+      e => e.to_string(),
+    });
+  }
+  let explanation = if verbose { proof.proof.explain(&proof.leaf, &root) + "\n" } else { String::new() };
+  Ok(format!("{explanation}leaf {} is included under root {root}", proof.proof.index))
+}
+
+// How proof files are written and read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+  Json,
+  // `InclusionProof::to_bytes` as 0x prefixed hex
+  Hex,
+  Base64,
+}
+
+impl Encoding {
+  fn of(options: &Options) -> Result<Self, String> {
+    match options.optional("--encoding") {
+      None | Some("json") => Ok(Self::Json),
+      Some("hex") => Ok(Self::Hex),
+      Some("base64") => Ok(Self::Base64),
+      Some(other) => Err(format!("unknown --encoding {other}, expected base64, hex or json")),
+    }
+  }
+
+  fn write(self, proof: &InclusionProof) -> Result<String, String> {
+    match self {
+      Self::Json => Ok(proof.to_json()),
+      Self::Hex => proof.to_hex(),
+      Self::Base64 => proof.to_base64(),
+    }.map_err(|e| e.to_string())
+  }
+
+  // Surrounding whitespace is ignored, files often end with a newline
+  fn read(self, text: &str) -> Result<InclusionProof, String> {
+    let text = text.trim();
+    match self {
+      Self::Json => InclusionProof::from_json(text),
+      Self::Hex => InclusionProof::from_hex(text),
+      Self::Base64 => InclusionProof::from_base64(text),
+    }.map_err(|e| e.to_string())
+  }
+}
+
+fn read_tree(path: &str) -> Result<MerkleTree, String> {
+  let text = fs::read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+  Ok(MerkleTree::new(&text.lines().map(str::to_string).collect::<Vec<_>>()))
+}
+
+// Flags that take no value
+const SWITCHES: [&str; 1] = ["--verbose"];
+
+// `--flag value` pairs, switches and the positional arguments
+struct Options<'a> {
+  flags: Vec<(&'a str, &'a str)>,
+  switches: Vec<&'a str>,
+  positional: Vec<&'a str>,
+}
+
+impl<'a> Options<'a> {
+  fn parse(args: &'a [String]) -> Result<Self, String> {
+    let mut options = Self { flags: vec![], switches: vec![], positional: vec![] };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+      if SWITCHES.contains(&arg.as_str()) {
+        options.switches.push(arg);
+      } else if arg.starts_with("--") {
+        let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+        options.flags.push((arg, value));
+      } else {
+        options.positional.push(arg);
+      }
+    }
+    Ok(options)
+  }
+
+  fn value(&self, flag: &str) -> Result<&'a str, String> {
+    self.optional(flag).ok_or_else(|| format!("missing {flag}\n\n{USAGE}"))
+  }
+
+  fn optional(&self, flag: &str) -> Option<&'a str> {
+    self.flags.iter().find(|(f, _)| *f == flag).map(|(_, v)| *v)
+  }
+
+  fn switch(&self, switch: &str) -> bool {
+    self.switches.contains(&switch)
+  }
+
+  fn input(&self) -> Result<&'a str, String> {
+    self.positional.first().copied().ok_or_else(|| format!("missing the elements file\n\n{USAGE}"))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::run;
+    use merkle_tree::hash::Root;
+    use merkle_tree::merkle_tree::MerkleTree;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("merkle-tree-cli-{}-{name}", std::process::id()))
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn verify(root: &str, proof: &Path) -> Result<String, String> {
+        run(&args(&["verify", "--root", root, "--proof", proof.to_str().unwrap()]))
+    }
+
+    #[test]
+    fn verifies_a_proof_file_and_names_the_problem_when_corrupted() {
+        let elements: Vec<String> = (0..5).map(|i| format!("element {i}")).collect();
+        let tree = MerkleTree::new(&elements);
+        let root = tree.root().to_string();
+        let path = temp_file("proof.json");
+        let json = tree.inclusion_proof(3).unwrap().to_json();
+        fs::write(&path, &json).unwrap();
+        assert!(verify(&root, &path).is_ok());
+
+        // flipping one byte of a sibling changes the computed root
+        let sibling = Root::try_from(tree.proof(3)[0].as_slice()).unwrap().to_string();
+        let flipped = format!("0x{}{}", if &sibling[2..3] == "0" { "1" } else { "0" }, &sibling[3..]);
+        fs::write(&path, json.replace(&sibling, &flipped)).unwrap();
+        assert!(verify(&root, &path).unwrap_err().starts_with("hash mismatch"));
+
+        fs::write(&path, json.replace(&sibling, &sibling[..sibling.len() - 2])).unwrap();
+        assert_eq!(verify(&root, &path).unwrap_err(), "sibling on level 0 is 31 bytes, expected 32");
+
+        fs::write(&path, json.replace(&sibling, &format!("0xgg{}", &sibling[4..]))).unwrap();
+        assert!(verify(&root, &path).unwrap_err().contains("bad hex in siblings[0]"));
+
+        fs::write(&path, &json).unwrap();
+        assert!(verify("0xnothex", &path).unwrap_err().starts_with("bad hex in --root"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn prove_writes_a_proof_that_verify_accepts() {
+        let input = temp_file("elements.txt");
+        let out = temp_file("prove.json");
+        fs::write(&input, "hola\nmoikka\nheippa\nahoj\n").unwrap();
+        let root = run(&args(&["root", input.to_str().unwrap()])).unwrap();
+        assert_eq!(root, "0x8321751cd2de3135bcc3ee9ad978061b284d1ec23f83279192ebcc3666c9e5cc");
+        run(&args(&["prove", "--index", "2", "--out", out.to_str().unwrap(), input.to_str().unwrap()])).unwrap();
+        assert!(verify(&root, &out).is_ok());
+
+        let verbose = run(&args(&["verify", "--verbose", "--root", &root, "--proof", out.to_str().unwrap()])).unwrap();
+        assert!(verbose.starts_with("leaf 0x"));
+        assert!(verbose.contains("level 1: right child, sibling 0x"));
+        assert!(verbose.ends_with("verified\n\nleaf 2 is included under root 0x8321751cd2de3135bcc3ee9ad978061b284d1ec23f83279192ebcc3666c9e5cc"));
+        let wrong_root = format!("0x{}", "00".repeat(32));
+        let failure = run(&args(&["verify", "--root", &wrong_root, "--proof", out.to_str().unwrap(), "--verbose"])).unwrap_err();
+        assert!(failure.contains("MISMATCH at the root\n\nhash mismatch"));
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&out).unwrap();
+    }
+
+    #[test]
+    fn proofs_can_be_written_as_hex_or_base64() {
+        let input = temp_file("encoded-elements.txt");
+        fs::write(&input, "hola\nmoikka\nheippa\n").unwrap();
+        let root = run(&args(&["root", input.to_str().unwrap()])).unwrap();
+        let tree = MerkleTree::new(&["hola".to_string(), "moikka".to_string(), "heippa".to_string()]);
+        let proof = tree.inclusion_proof(1).unwrap();
+        for (encoding, expected) in [("base64", proof.to_base64().unwrap()), ("hex", proof.to_hex().unwrap()), ("json", proof.to_json())] {
+            let out = temp_file(&format!("proof.{encoding}"));
+            let out = out.to_str().unwrap();
+            run(&args(&["prove", "--index", "1", "--encoding", encoding, "--out", out, input.to_str().unwrap()])).unwrap();
+            assert_eq!(fs::read_to_string(out).unwrap(), expected);
+            assert!(run(&args(&["verify", "--root", &root, "--proof", out, "--encoding", encoding])).is_ok());
+            // another encoding does not read the file
+            let other = if encoding == "json" { "base64" } else { "json" };
+            assert!(run(&args(&["verify", "--root", &root, "--proof", out, "--encoding", other])).is_err());
+            fs::remove_file(out).unwrap();
+        }
+        let unknown = run(&args(&["prove", "--index", "1", "--encoding", "utf-7", "--out", "unused", input.to_str().unwrap()]));
+        assert_eq!(unknown.unwrap_err(), "unknown --encoding utf-7, expected base64, hex or json");
+        fs::remove_file(&input).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+// Shared pieces of the text (JSON) and binary encodings of trees and proofs.
+// Text formats write hashes as "0x…" hex strings and read either hex strings or
+// arrays of bytes. Binary formats write raw bytes and u64 integers in the byte order of
+// the tree, little endian unless configured otherwise
+use crate::error::MerkleTreeError;
+use crate::hex;
+use crate::json::Json;
+use crate::scheme::{Padding, PairOrder};
+
+// Order of the integers a tree hashes (the lengths framing V1 leaves) and writes (the
+// binary formats). Little endian is the default since every root and encoding predating
+// the choice uses it. New consumers without a preference should pick big endian, network
+// order and what the Solidity ABI uses; Bitcoin style consumers keep little endian
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ByteOrder {
+  #[default]
+  LittleEndian,
+  BigEndian,
+}
+
+impl ByteOrder {
+  // Name used by the text formats
+  #[must_use]
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::LittleEndian => "little-endian",
+      Self::BigEndian => "big-endian",
+    }
+  }
+
+  #[must_use]
+  pub fn from_name(name: &str) -> Option<Self> {
+    [Self::LittleEndian, Self::BigEndian].into_iter().find(|o| o.name() == name)
+  }
+
+  #[must_use]
+  pub fn u64_bytes(self, n: u64) -> [u8; 8] {
+    match self {
+      Self::LittleEndian => n.to_le_bytes(),
+      Self::BigEndian => n.to_be_bytes(),
+    }
+  }
+
+  #[must_use]
+  pub fn u64_from_bytes(self, bytes: [u8; 8]) -> u64 {
+    match self {
+      Self::LittleEndian => u64::from_le_bytes(bytes),
+      Self::BigEndian => u64::from_be_bytes(bytes),
+    }
+  }
+}
+
+pub(crate) fn invalid(reason: impl Into<String>) -> MerkleTreeError {
+  MerkleTreeError::InvalidEncoding(reason.into())
+}
+
+pub(crate) fn bytes_to_json(bytes: &[u8]) -> Json {
+  hex::encode(bytes).into()
+}
+
+pub(crate) fn bytes_from_json(field: &str, value: Option<&Json>) -> Result<Vec<u8>, MerkleTreeError> {
+  match value {
+    Some(Json::String(text)) => hex::decode(text).map_err(|e| invalid(format!("bad hex in {field}: {e}"))),
+    Some(Json::Array(items)) => items.iter().map(|item| {
+      item.as_u64().and_then(|b| u8::try_from(b).ok()).ok_or_else(|| invalid(format!("{field} must only hold bytes")))
+    }).collect(),
+    _ => Err(invalid(format!("{field} must be a hex string or an array of bytes"))),
+  }
+}
+
+pub(crate) fn list_from_json(field: &str, value: Option<&Json>) -> Result<Vec<Vec<u8>>, MerkleTreeError> {
+  let items = value.and_then(Json::as_array).ok_or_else(|| invalid(format!("{field} must be an array")))?;
+  items.iter().enumerate().map(|(i, item)| bytes_from_json(&format!("{field}[{i}]"), Some(item))).collect()
+}
+
+pub(crate) fn usize_from_json(field: &str, value: Option<&Json>) -> Result<usize, MerkleTreeError> {
+  value.and_then(Json::as_u64).and_then(|n| usize::try_from(n).ok())
+    .ok_or_else(|| invalid(format!("{field} must be a non negative integer")))
+}
+
+// Little endian when missing, as documents written before there was a choice
+pub(crate) fn byte_order_from_json(value: Option<&Json>) -> Result<ByteOrder, MerkleTreeError> {
+  match value {
+    Some(name) => name.as_str().and_then(ByteOrder::from_name)
+      .ok_or_else(|| invalid("byte_order must be \"little-endian\" or \"big-endian\"")),
+    None => Ok(ByteOrder::default())
+  }
+}
+
+// Promoted odd nodes when missing, the only completion trees have. A document naming
+// another one is refused rather than read as a promoting tree
+pub(crate) fn check_padding(value: Option<&Json>) -> Result<(), MerkleTreeError> {
+  match value {
+    Some(name) => name.as_str().and_then(Padding::from_name).map(|_| ())
+      .ok_or_else(|| invalid("padding must be \"promote-odd\"")),
+    None => Ok(())
+  }
+}
+
+// In order when missing, as documents written before pairs could be sorted
+pub(crate) fn pair_order_from_json(value: Option<&Json>) -> Result<PairOrder, MerkleTreeError> {
+  match value {
+    Some(name) => name.as_str().and_then(PairOrder::from_name)
+      .ok_or_else(|| invalid("pair_order must be \"in-order\" or \"sorted\"")),
+    None => Ok(PairOrder::default())
+  }
+}
+
+pub(crate) fn put_u64(out: &mut Vec<u8>, n: usize, order: ByteOrder) {
+  out.extend_from_slice(&order.u64_bytes(n as u64));
+}
+
+// Hashes of a single length: the length as one byte, their count, then the raw bytes
+pub(crate) fn put_hashes(out: &mut Vec<u8>, hashes: &[Vec<u8>], order: ByteOrder) -> Result<(), MerkleTreeError> {
+  let len = hashes.first().map_or(0, Vec::len);
+  if let Some(h) = hashes.iter().find(|h| h.len() != len) {
+    return Err(MerkleTreeError::InvalidHashLength { expected: len, got: h.len() });
+  }
+  out.push(u8::try_from(len).map_err(|_| invalid("hashes longer than 255 bytes"))?);
+  put_u64(out, hashes.len(), order);
+  for hash in hashes {
+    out.extend_from_slice(hash);
+  }
+  Ok(())
+}
+
+// Reads integers little endian until told the order of the input
+pub(crate) struct Reader<'a> {
+  bytes: &'a [u8],
+  pub(crate) order: ByteOrder
+}
+
+impl<'a> Reader<'a> {
+  pub(crate) fn new(bytes: &'a [u8]) -> Self {
+    Self { bytes, order: ByteOrder::default() }
+  }
+
+  // The byte `offset` bytes ahead without consuming anything, formats keep the one
+  // naming their byte order after integers written in it
+  pub(crate) fn peek(&self, offset: usize) -> Result<u8, MerkleTreeError> {
+    self.bytes.get(offset).copied().ok_or_else(|| invalid("unexpected end of input"))
+  }
+
+  pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], MerkleTreeError> {
+    if len > self.bytes.len() {
+      return Err(invalid("unexpected end of input"));
+    }
+    let (taken, rest) = self.bytes.split_at(len);
+    self.bytes = rest;
+    Ok(taken)
+  }
+
+  pub(crate) fn u8(&mut self) -> Result<u8, MerkleTreeError> {
+    Ok(self.take(1)?[0])
+  }
+
+  pub(crate) fn u64(&mut self) -> Result<u64, MerkleTreeError> {
+    let bytes = self.take(8)?.try_into().expect("took 8 bytes");
+    Ok(self.order.u64_from_bytes(bytes))
+  }
+
+  pub(crate) fn usize(&mut self) -> Result<usize, MerkleTreeError> {
+    usize::try_from(self.u64()?).map_err(|_| invalid("integer does not fit in usize"))
+  }
+
+  // Hashes written by `put_hashes`, once `check` accepted their length and count and
+  // before anything is allocated
+  pub(crate) fn hashes_checked(&mut self, check: impl FnOnce(usize, usize) -> Result<(), MerkleTreeError>) -> Result<Vec<Vec<u8>>, MerkleTreeError> {
+    let len = usize::from(self.u8()?);
+    let count = self.usize()?;
+    check(len, count)?;
+    // hashes of no bytes take no input, the size check below would let any count through
+    if len == 0 && count > 0 {
+      return Err(invalid(format!("{count} hashes of zero bytes")));
+    }
+    // the count comes from the input, check it against what is left before allocating
+    if count.checked_mul(len).is_none_or(|total| total > self.bytes.len()) {
+      return Err(invalid("unexpected end of input"));
+    }
+    (0..count).map(|_| self.take(len).map(<[u8]>::to_vec)).collect()
+  }
+
+  pub(crate) fn is_empty(&self) -> bool {
+    self.bytes.is_empty()
+  }
+
+  pub(crate) fn finish(self) -> Result<(), MerkleTreeError> {
+    if self.bytes.is_empty() {
+      Ok(())
+    } else {
+      Err(invalid(format!("{} trailing bytes", self.bytes.len())))
+    }
+  }
+}
@@ -0,0 +1,136 @@
+use crate::hash::Root;
+use crate::scheme::TreeConfig;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MerkleTreeError {
+  // The operation needs at least one leaf
+  Empty,
+  // The requested leaf range does not fit in the tree
+  RangeOutOfBounds { start: usize, end: usize, len: usize },
+  // A hash does not have the digest size of the tree
+  InvalidHashLength { expected: usize, got: usize },
+  // The leaf index does not exist in the tree
+  IndexOutOfBounds { index: usize, len: usize },
+  // The proof does not have the number of siblings the leaf position needs
+  InvalidProofLength { expected: usize, got: usize },
+  // The checkpoint was already rolled back or committed
+  UnknownCheckpoint,
+  // Text or binary input that could not be decoded
+  InvalidEncoding(String),
+  // Interior nodes need at least two children
+  InvalidArity(usize),
+  // Chunks need at least one byte
+  InvalidChunkSize,
+  // A piece of chunked data has to start where a chunk starts
+  MisalignedOffset { offset: u64, chunk_size: usize },
+  // A piece of chunked data has to be the whole chunk at its offset, no more and no less
+  InvalidPieceLength { offset: u64, expected: usize, got: usize },
+  // The tree no longer has the root the caller expected, `actual` is the current one
+  RootMismatch { actual: Root },
+  // The proof was made under other conventions than the verifier expects
+  ConfigMismatch { expected: TreeConfig, got: TreeConfig },
+  // A padded tree of that depth has fewer slots than the leaves
+  DepthTooSmall { depth: usize, len: usize },
+  // A forest already has a subtree of that name
+  DuplicateSubtree(String),
+  // Trees, proofs or rows of kinds that do not go together, such as a merge of trees of
+  // other configs or a proof another library's format cannot express without changing
+  // its root
+  Incompatible(String),
+  // The same leaf index was given more than once
+  DuplicateIndex(usize),
+  // A format with a node cap was asked to hold a tree of more nodes
+  TooManyNodes { limit: usize },
+  // A shard of a distributed build cannot be placed in the tree
+  InvalidShard { shard: usize, reason: String },
+  // The shards to combine skip this one
+  MissingShard(usize),
+  // A depth past the `DepthLimit` in force
+  DepthTooLarge { requested: usize, max: usize },
+  // The slot of a padded tree holds a real leaf
+  NotPadding { index: usize, leaf_count: usize },
+  // Untrusted input larger than the `ProofLimits` allow, `limit` names the field
+  LimitExceeded { limit: &'static str, max: usize, got: usize },
+}
+
+impl fmt::Display for MerkleTreeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Empty => write!(f, "the tree would have no leaves"),
+      Self::RangeOutOfBounds { start, end, len } => write!(f, "range {start}..{end} is out of bounds for {len} leaves"),
+      Self::InvalidHashLength { expected, got } => write!(f, "expected a {expected} byte hash, got {got} bytes"),
+      Self::IndexOutOfBounds { index, len } => write!(f, "leaf index {index} is out of bounds for {len} leaves"),
+      Self::InvalidProofLength { expected, got } => write!(f, "expected a proof of {expected} siblings, got {got}"),
+      Self::UnknownCheckpoint => write!(f, "the checkpoint is no longer open"),
+      Self::InvalidEncoding(reason) => write!(f, "invalid encoding: {reason}"),
+      Self::InvalidArity(arity) => write!(f, "arity {arity} is too small, nodes need at least two children"),
+      Self::InvalidChunkSize => write!(f, "the chunk size must be at least one byte"),
+      Self::MisalignedOffset { offset, chunk_size } => write!(f, "offset {offset} is not a multiple of the {chunk_size} byte chunk size"),
+      Self::InvalidPieceLength { offset, expected, got } => write!(f, "the chunk at offset {offset} holds {expected} bytes, got a piece of {got}"),
+      Self::RootMismatch { actual } => write!(f, "the tree root is {actual}, not the expected one"),
+      Self::ConfigMismatch { expected, got } => write!(f, "expected a proof of a {expected}, got one of a {got}"),
+      Self::DepthTooSmall { depth, len } => write!(f, "a tree of depth {depth} cannot hold {len} leaves"),
+      Self::DuplicateSubtree(name) => write!(f, "the forest already has a subtree named {name:?}"),
+      Self::Incompatible(reason) => write!(f, "incompatible: {reason}"),
+      Self::DuplicateIndex(index) => write!(f, "leaf index {index} is given more than once"),
+      Self::TooManyNodes { limit } => write!(f, "the tree has more than {limit} nodes"),
+      Self::InvalidShard { shard, reason } => write!(f, "shard {shard} does not fit the tree: {reason}"),
+      Self::MissingShard(shard) => write!(f, "shard {shard} is missing"),
+      Self::DepthTooLarge { requested, max } => write!(f, "depth {requested} is over the maximum of {max}"),
+      Self::NotPadding { index, leaf_count } => write!(f, "slot {index} holds one of the {leaf_count} real leaves, not padding"),
+      Self::LimitExceeded { limit, max, got } => write!(f, "{got} is over the {limit} limit of {max}"),
+    }
+  }
+}
+
+impl std::error::Error for MerkleTreeError {}
+
+// Why a proof did not verify, see `MerkleProof::verify_detailed`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+  // Interior nodes of the claimed tree would have fewer than two children
+  BadArity { arity: usize },
+  IndexOutOfRange { index: usize, leaf_count: usize },
+  WrongProofLength { expected: usize, got: usize },
+  BadLeafLength { expected: usize, got: usize },
+  // `level` counts from the leaves, the first sibling of a proof is on level 0
+  BadSiblingLength { level: usize, expected: usize, got: usize },
+  // Everything was well formed but the proof leads to `computed`
+  RootMismatch { computed: Root, expected: Root },
+}
+
+impl fmt::Display for VerifyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::BadArity { arity } => write!(f, "arity {arity} is too small, nodes need at least two children"),
+      Self::IndexOutOfRange { index, leaf_count } => write!(f, "leaf index {index} is out of range for {leaf_count} leaves"),
+      Self::WrongProofLength { expected, got } => write!(f, "the proof has {got} siblings, expected {expected}"),
+      Self::BadLeafLength { expected, got } => write!(f, "the leaf is {got} bytes, expected {expected}"),
+      Self::BadSiblingLength { level, expected, got } => write!(f, "sibling on level {level} is {got} bytes, expected {expected}"),
+      Self::RootMismatch { computed, expected } => write!(f, "hash mismatch: the proof computes root {computed} but expected {expected}"),
+    }
+  }
+}
+
+impl std::error::Error for VerifyError {}
+
+// The item at `index` of the elements a tree was being built from was an error, see
+// `MerkleTree::try_from_iter`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeBuildError<E> {
+  pub index: usize,
+  pub error: E
+}
+
+impl<E: fmt::Display> fmt::Display for TreeBuildError<E> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "element {} could not be read: {}", self.index, self.error)
+  }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for TreeBuildError<E> {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    Some(&self.error)
+  }
+}
@@ -0,0 +1,172 @@
+use crate::error::MerkleTreeError;
+use crate::hash::{LeafHash, Root};
+use crate::merkle_tree::MerkleTree;
+use crate::proof::MerkleProof;
+
+// Named subtrees committed to by one super-root, the root of a binary tree whose
+// leaves are the subtree roots in insertion order
+pub struct MerkleForest {
+  names: Vec<String>,
+  subtrees: Vec<MerkleTree>,
+  top: MerkleTree
+}
+
+// Inclusion of a leaf in its subtree followed by the inclusion of that subtree's root
+// in the forest, see `verify_compound`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompoundProof {
+  pub leaf: MerkleProof,
+  pub subtree: MerkleProof
+}
+
+impl Default for MerkleForest {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl MerkleForest {
+  #[must_use]
+  pub fn new() -> Self {
+    Self { names: vec![], subtrees: vec![], top: MerkleTree::new(&[]) }
+  }
+
+  // Adds `tree` as the last subtree and returns its index. Names are unique and
+  // the subtree roots must have the size of the top tree hashes
+  pub fn insert(&mut self, name: impl Into<String>, tree: MerkleTree) -> Result<usize, MerkleTreeError> {
+    let name = name.into();
+    if self.names.contains(&name) {
+      return Err(MerkleTreeError::DuplicateSubtree(name));
+    }
+    self.top.push_leaf(subtree_leaf(&tree)?)?;
+    self.names.push(name);
+    self.subtrees.push(tree);
+    Ok(self.subtrees.len() - 1)
+  }
+
+  // Changes the subtree at `index` with `f`, only the path of its root in the top tree
+  // is hashed again
+  pub fn update(&mut self, index: usize, f: impl FnOnce(&mut MerkleTree)) -> Result<(), MerkleTreeError> {
+    let tree = self.subtrees.get_mut(index).ok_or(MerkleTreeError::IndexOutOfBounds { index, len: self.names.len() })?;
+    f(tree);
+    self.top.update_leaf(index, subtree_leaf(tree)?)
+  }
+
+  #[must_use]
+  pub fn super_root(&self) -> Root {
+    self.top.root()
+  }
+
+  #[must_use]
+  pub fn len(&self) -> usize {
+    self.subtrees.len()
+  }
+
+  #[must_use]
+  pub fn is_empty(&self) -> bool {
+    self.subtrees.is_empty()
+  }
+
+  #[must_use]
+  pub fn index_of(&self, name: &str) -> Option<usize> {
+    self.names.iter().position(|n| n == name)
+  }
+
+  #[must_use]
+  pub fn subtree(&self, index: usize) -> Option<&MerkleTree> {
+    self.subtrees.get(index)
+  }
+
+  #[must_use]
+  pub fn name(&self, index: usize) -> Option<&str> {
+    self.names.get(index).map(String::as_str)
+  }
+
+  // Proof of the leaf at `leaf_index` of the subtree at `index` against the super-root
+  pub fn compound_proof(&self, index: usize, leaf_index: usize) -> Result<CompoundProof, MerkleTreeError> {
+    let tree = self.subtrees.get(index).ok_or(MerkleTreeError::IndexOutOfBounds { index, len: self.subtrees.len() })?;
+    Ok(CompoundProof {
+      leaf: tree.inclusion_proof(leaf_index)?.proof,
+      subtree: self.top.inclusion_proof(index)?.proof
+    })
+  }
+}
+
+// Whether `leaf` is the leaf at `index` of the subtree at `subtree` in the forest of `super_root`
+#[must_use]
+pub fn verify_compound(super_root: &Root, subtree: usize, leaf: &LeafHash, index: usize, proof: &CompoundProof) -> bool {
+  if proof.leaf.index != index || proof.subtree.index != subtree {
+    return false;
+  }
+  proof.leaf.compute_root(leaf)
+    .and_then(|root| LeafHash::try_from(root.as_bytes()))
+    .is_ok_and(|root| proof.subtree.verify(super_root, &root))
+}
+
+fn subtree_leaf(tree: &MerkleTree) -> Result<LeafHash, MerkleTreeError> {
+  LeafHash::try_from(tree.root().as_bytes())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::forest::{verify_compound, MerkleForest};
+    use crate::hash::LeafHash;
+    use crate::merkle_tree::MerkleTree;
+    use crate::scheme::HashFunction;
+
+    fn elements(tenant: &str, count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("{tenant} {i}")).collect()
+    }
+
+    fn assert_all_verify(forest: &MerkleForest) {
+        for index in 0..forest.len() {
+            let tree = forest.subtree(index).unwrap();
+            for leaf_index in 0..tree.len() {
+                let proof = forest.compound_proof(index, leaf_index).unwrap();
+                let leaf = tree.leaf_hash(leaf_index).unwrap();
+                assert!(verify_compound(&forest.super_root(), index, &leaf, leaf_index, &proof));
+                assert!(!verify_compound(&forest.super_root(), (index + 1) % 3, &leaf, leaf_index, &proof));
+            }
+        }
+    }
+
+    #[test]
+    fn compound_proofs_verify_against_the_super_root() {
+        let mut forest = MerkleForest::new();
+        for (tenant, count) in [("acme", 5), ("globex", 1), ("initech", 8)] {
+            forest.insert(tenant, MerkleTree::new(&elements(tenant, count))).unwrap();
+        }
+        let roots: Vec<LeafHash> = (0..3).map(|i| LeafHash::try_from(forest.subtree(i).unwrap().root().as_bytes()).unwrap()).collect();
+        assert_eq!(forest.super_root(), MerkleTree::from_leaves(&roots).unwrap().root());
+        assert_all_verify(&forest);
+
+        let before = forest.super_root();
+        let old_leaf = forest.subtree(0).unwrap().leaf_hash(2).unwrap();
+        let index = forest.index_of("acme").unwrap();
+        forest.update(index, |tree| tree.update(2, "acme changed").unwrap()).unwrap();
+        assert_ne!(forest.super_root(), before);
+        assert!(!verify_compound(&forest.super_root(), 0, &old_leaf, 2, &forest.compound_proof(0, 2).unwrap()));
+        assert_all_verify(&forest);
+
+        // the super-root is the same as rebuilding the forest from scratch
+        let mut rebuilt = MerkleForest::new();
+        for index in 0..3 {
+            rebuilt.insert(forest.name(index).unwrap(), forest.subtree(index).unwrap().clone()).unwrap();
+        }
+        assert_eq!(rebuilt.super_root(), forest.super_root());
+    }
+
+    #[test]
+    fn names_are_unique_and_hash_sizes_must_match() {
+        let mut forest = MerkleForest::new();
+        forest.insert("acme", MerkleTree::new(&elements("acme", 2))).unwrap();
+        assert_eq!(forest.insert("acme", MerkleTree::new(&[])).err(), Some(MerkleTreeError::DuplicateSubtree("acme".to_string())));
+        let wide = MerkleTree::with_hash_function(&elements("wide", 2), HashFunction::Sha3_512);
+        assert_eq!(forest.insert("wide", wide).err(), Some(MerkleTreeError::InvalidHashLength { expected: 32, got: 64 }));
+        assert_eq!(forest.len(), 1);
+        assert!(forest.compound_proof(1, 0).is_err());
+        assert!(forest.update(1, |_| ()).is_err());
+    }
+}
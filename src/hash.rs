@@ -0,0 +1,159 @@
+//! Typed hashes, so a leaf hash cannot be passed where a root is expected. They hold
+//! the output of any supported `HashFunction`, up to `MAX_HASH_LEN` bytes.
+//!
+//! ```compile_fail
+//! use merkle_tree::hash::{LeafHash, Root};
+//! let leaf = LeafHash::new([0; 32]);
+//! let root: Root = leaf;
+//! ```
+//!
+//! ```compile_fail
+//! use merkle_tree::hash::LeafHash;
+//! use merkle_tree::merkle_tree::{verify_proof, MerkleTree};
+//! let tree = MerkleTree::new(&["a".to_string(), "b".to_string()]);
+//! let leaf = LeafHash::try_from(tree.leaves()[0].as_slice()).unwrap();
+//! verify_proof(&leaf, &leaf, 0, 2, &tree.proof(0));
+//! ```
+use crate::error::MerkleTreeError;
+use crate::hex;
+use crate::merkle_tree::HASH_LEN;
+use crate::scheme::HashFunction;
+use std::fmt;
+use std::str::FromStr;
+
+// Longest output of the supported hash functions
+pub const MAX_HASH_LEN: usize = 64;
+
+macro_rules! hash_type {
+  ($name:ident) => {
+    // The bytes past `len` are always zero, so the derived comparisons only see the hash
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct $name {
+      bytes: [u8; MAX_HASH_LEN],
+      len: u8
+    }
+
+    impl $name {
+      // A hash of the default 32 byte length
+      #[must_use]
+      pub const fn new(hash: [u8; HASH_LEN]) -> Self {
+        let mut bytes = [0; MAX_HASH_LEN];
+        let mut i = 0;
+        while i < HASH_LEN {
+          bytes[i] = hash[i];
+          i += 1;
+        }
+        Self { bytes, len: HASH_LEN as u8 }
+      }
+
+      #[must_use]
+      pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..usize::from(self.len)]
+      }
+
+      #[must_use]
+      pub fn to_vec(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+      }
+
+      #[must_use]
+      pub fn len(&self) -> usize {
+        usize::from(self.len)
+      }
+
+      #[must_use]
+      pub fn is_empty(&self) -> bool {
+        self.len == 0
+      }
+
+      // Equality whose time does not depend on where the hashes differ
+      #[must_use]
+      pub fn ct_eq(&self, other: &Self) -> bool {
+        let diff = self.bytes.iter().zip(&other.bytes).fold(self.len ^ other.len, |diff, (a, b)| diff | (a ^ b));
+        std::hint::black_box(diff) == 0
+      }
+    }
+
+    impl AsRef<[u8]> for $name {
+      fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+      }
+    }
+
+    impl From<[u8; HASH_LEN]> for $name {
+      fn from(hash: [u8; HASH_LEN]) -> Self {
+        Self::new(hash)
+      }
+    }
+
+    impl TryFrom<$name> for [u8; HASH_LEN] {
+      type Error = MerkleTreeError;
+
+      fn try_from(hash: $name) -> Result<Self, MerkleTreeError> {
+        hash.as_bytes().try_into().map_err(|_| MerkleTreeError::InvalidHashLength { expected: HASH_LEN, got: hash.len() })
+      }
+    }
+
+    // Any length some `HashFunction` outputs
+    impl TryFrom<&[u8]> for $name {
+      type Error = MerkleTreeError;
+
+      fn try_from(hash: &[u8]) -> Result<Self, MerkleTreeError> {
+        if !HashFunction::ALL.iter().any(|f| f.output_len() == hash.len()) {
+          return Err(MerkleTreeError::InvalidHashLength { expected: HASH_LEN, got: hash.len() });
+        }
+        let mut bytes = [0; MAX_HASH_LEN];
+        bytes[..hash.len()].copy_from_slice(hash);
+        Ok(Self { bytes, len: hash.len() as u8 })
+      }
+    }
+
+    // "0x" prefixed hex, the form the text formats use
+    impl fmt::Display for $name {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.as_bytes()))
+      }
+    }
+
+    impl fmt::Debug for $name {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({self})", stringify!($name))
+      }
+    }
+
+    impl FromStr for $name {
+      type Err = MerkleTreeError;
+
+      fn from_str(text: &str) -> Result<Self, MerkleTreeError> {
+        let bytes = hex::decode(text).map_err(MerkleTreeError::InvalidEncoding)?;
+        Self::try_from(bytes.as_slice())
+      }
+    }
+  };
+}
+
+// Root of a tree, or the root a proof computes
+hash_type!(Root);
+// Hash of a leaf as it sits in the tree
+hash_type!(LeafHash);
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::hash::{LeafHash, Root};
+
+    #[test]
+    fn hashes_convert_and_print_as_hex() {
+        let root = Root::new([0xab; 32]);
+        assert_eq!(root.to_string(), format!("0x{}", "ab".repeat(32)));
+        assert_eq!(root.to_string().parse::<Root>().unwrap(), root);
+        assert_eq!(<[u8; 32]>::try_from(root), Ok([0xab; 32]));
+        let long = Root::try_from(&[7u8; 64][..]).unwrap();
+        assert_eq!((long.len(), long.as_bytes()), (64, &[7u8; 64][..]));
+        assert!(<[u8; 32]>::try_from(long).is_err());
+        assert_ne!(Root::try_from(&[0u8; 64][..]).unwrap(), Root::new([0; 32]));
+        assert_eq!(LeafHash::try_from(&[1u8; 31][..]), Err(MerkleTreeError::InvalidHashLength { expected: 32, got: 31 }));
+        assert!("0x12".parse::<LeafHash>().is_err());
+    }
+}
@@ -0,0 +1,38 @@
+// "0x" prefixed lowercase hex, used by the text formats
+#[must_use]
+pub fn encode(bytes: &[u8]) -> String {
+  let mut out = String::with_capacity(2 + bytes.len() * 2);
+  out.push_str("0x");
+  for byte in bytes {
+    out.push_str(&format!("{byte:02x}"));
+  }
+  out
+}
+
+// Accepts hex with or without the "0x" prefix
+pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+  let digits = text.strip_prefix("0x").unwrap_or(text);
+  if !digits.len().is_multiple_of(2) {
+    return Err(format!("odd number of hex digits in {text:?}"));
+  }
+  (0..digits.len()).step_by(2).map(|i| {
+    digits.get(i..i + 2)
+      .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+      .ok_or_else(|| format!("invalid hex digit in {text:?}"))
+  }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::hex::{decode, encode};
+
+    #[test]
+    fn hex_round_trips() {
+        assert_eq!(encode(&[0, 171, 255]), "0x00abff");
+        assert_eq!(decode("0x00abff").unwrap(), vec![0, 171, 255]);
+        assert_eq!(decode("00ABff").unwrap(), vec![0, 171, 255]);
+        assert!(decode("0xabc").is_err());
+        assert!(decode("0xzz").is_err());
+    }
+}
@@ -0,0 +1,347 @@
+// Minimal JSON support for the proof and tree artifacts, the crate has no serde dependency
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+  Null,
+  Bool(bool),
+  // The literal as written, so integers past 2^53 keep every digit
+  Number(String),
+  String(String),
+  Array(Vec<Json>),
+  // keys keep their insertion order so the output is stable
+  Object(Vec<(String, Json)>),
+}
+
+impl Json {
+  #[must_use]
+  pub fn object(fields: impl IntoIterator<Item = (&'static str, Json)>) -> Self {
+    Self::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+  }
+
+  #[must_use]
+  pub fn get(&self, key: &str) -> Option<&Json> {
+    match self {
+      Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+      _ => None,
+    }
+  }
+
+  #[must_use]
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Self::String(s) => Some(s),
+      _ => None,
+    }
+  }
+
+  #[must_use]
+  pub fn as_array(&self) -> Option<&[Json]> {
+    match self {
+      Self::Array(items) => Some(items),
+      _ => None,
+    }
+  }
+
+  // Non negative integers written as plain digits only, which is all the artifacts use
+  #[must_use]
+  pub fn as_u64(&self) -> Option<u64> {
+    match self {
+      Self::Number(n) if n.bytes().all(|b| b.is_ascii_digit()) => n.parse().ok(),
+      _ => None,
+    }
+  }
+
+  #[must_use]
+  pub fn as_bool(&self) -> Option<bool> {
+    match self {
+      Self::Bool(b) => Some(*b),
+      _ => None,
+    }
+  }
+
+  pub fn parse(input: &str) -> Result<Self, String> {
+    let mut parser = Parser { bytes: input.as_bytes(), pos: 0, depth: 0 };
+    let value = parser.value()?;
+    parser.whitespace();
+    if parser.pos != parser.bytes.len() {
+      return Err(format!("trailing characters at {}", parser.pos));
+    }
+    Ok(value)
+  }
+}
+
+impl std::fmt::Display for Json {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Null => f.write_str("null"),
+      Self::Bool(b) => write!(f, "{b}"),
+      Self::Number(n) => f.write_str(n),
+      Self::String(s) => write_string(f, s),
+      Self::Array(items) => {
+        f.write_char('[')?;
+        for (i, item) in items.iter().enumerate() {
+          if i > 0 {
+            f.write_char(',')?;
+          }
+          write!(f, "{item}")?;
+        }
+        f.write_char(']')
+      },
+      Self::Object(fields) => {
+        f.write_char('{')?;
+        for (i, (key, value)) in fields.iter().enumerate() {
+          if i > 0 {
+            f.write_char(',')?;
+          }
+          write_string(f, key)?;
+          write!(f, ":{value}")?;
+        }
+        f.write_char('}')
+      }
+    }
+  }
+}
+
+impl From<u64> for Json {
+  fn from(n: u64) -> Self {
+    Self::Number(n.to_string())
+  }
+}
+
+impl From<usize> for Json {
+  fn from(n: usize) -> Self {
+    Self::Number(n.to_string())
+  }
+}
+
+impl From<&str> for Json {
+  fn from(s: &str) -> Self {
+    Self::String(s.to_string())
+  }
+}
+
+impl From<String> for Json {
+  fn from(s: String) -> Self {
+    Self::String(s)
+  }
+}
+
+impl From<bool> for Json {
+  fn from(b: bool) -> Self {
+    Self::Bool(b)
+  }
+}
+
+fn write_string(f: &mut impl Write, s: &str) -> std::fmt::Result {
+  f.write_char('"')?;
+  for c in s.chars() {
+    match c {
+      '"' => f.write_str("\\\"")?,
+      '\\' => f.write_str("\\\\")?,
+      '\n' => f.write_str("\\n")?,
+      '\r' => f.write_str("\\r")?,
+      '\t' => f.write_str("\\t")?,
+      c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+      c => f.write_char(c)?,
+    }
+  }
+  f.write_char('"')
+}
+
+// Arrays and objects nested deeper than this are rejected
+const MAX_DEPTH: usize = 512;
+
+struct Parser<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+  depth: usize,
+}
+
+impl Parser<'_> {
+  fn whitespace(&mut self) {
+    while matches!(self.bytes.get(self.pos), Some(b' ' | b'\n' | b'\r' | b'\t')) {
+      self.pos += 1;
+    }
+  }
+
+  fn expect(&mut self, byte: u8) -> Result<(), String> {
+    self.whitespace();
+    if self.bytes.get(self.pos) == Some(&byte) {
+      self.pos += 1;
+      Ok(())
+    } else {
+      Err(format!("expected '{}' at {}", byte as char, self.pos))
+    }
+  }
+
+  fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+    if self.bytes[self.pos..].starts_with(word.as_bytes()) {
+      self.pos += word.len();
+      Ok(value)
+    } else {
+      Err(format!("unexpected token at {}", self.pos))
+    }
+  }
+
+  fn value(&mut self) -> Result<Json, String> {
+    self.whitespace();
+    match self.bytes.get(self.pos) {
+      Some(b'n') => self.literal("null", Json::Null),
+      Some(b't') => self.literal("true", Json::Bool(true)),
+      Some(b'f') => self.literal("false", Json::Bool(false)),
+      Some(b'"') => self.string().map(Json::String),
+      Some(b'[' | b'{') => {
+        // nesting is recursion, hostile input could otherwise run the stack out
+        if self.depth == MAX_DEPTH {
+          return Err(format!("nesting deeper than {MAX_DEPTH} at {}", self.pos));
+        }
+        self.depth += 1;
+        let value = if self.bytes[self.pos] == b'[' { self.array() } else { self.object() };
+        self.depth -= 1;
+        value
+      },
+      Some(b'-' | b'0'..=b'9') => self.number(),
+      _ => Err(format!("unexpected token at {}", self.pos)),
+    }
+  }
+
+  fn array(&mut self) -> Result<Json, String> {
+    self.pos += 1;
+    let mut items = vec![];
+    self.whitespace();
+    if self.bytes.get(self.pos) == Some(&b']') {
+      self.pos += 1;
+      return Ok(Json::Array(items));
+    }
+    loop {
+      items.push(self.value()?);
+      self.whitespace();
+      match self.bytes.get(self.pos) {
+        Some(b',') => self.pos += 1,
+        Some(b']') => {
+          self.pos += 1;
+          return Ok(Json::Array(items));
+        },
+        _ => return Err(format!("expected ',' or ']' at {}", self.pos)),
+      }
+    }
+  }
+
+  fn object(&mut self) -> Result<Json, String> {
+    self.pos += 1;
+    let mut fields = vec![];
+    self.whitespace();
+    if self.bytes.get(self.pos) == Some(&b'}') {
+      self.pos += 1;
+      return Ok(Json::Object(fields));
+    }
+    loop {
+      self.whitespace();
+      let key = self.string()?;
+      self.expect(b':')?;
+      fields.push((key, self.value()?));
+      self.whitespace();
+      match self.bytes.get(self.pos) {
+        Some(b',') => self.pos += 1,
+        Some(b'}') => {
+          self.pos += 1;
+          return Ok(Json::Object(fields));
+        },
+        _ => return Err(format!("expected ',' or '}}' at {}", self.pos)),
+      }
+    }
+  }
+
+  fn number(&mut self) -> Result<Json, String> {
+    let start = self.pos;
+    while matches!(self.bytes.get(self.pos), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+      self.pos += 1;
+    }
+    let text = std::str::from_utf8(&self.bytes[start..self.pos]).map_err(|e| e.to_string())?;
+    // parsed only to check the syntax, the value is kept as text
+    text.parse::<f64>().map(|_| Json::Number(text.to_string())).map_err(|_| format!("invalid number at {start}"))
+  }
+
+  fn string(&mut self) -> Result<String, String> {
+    if self.bytes.get(self.pos) != Some(&b'"') {
+      return Err(format!("expected a string at {}", self.pos));
+    }
+    self.pos += 1;
+    let mut out = Vec::new();
+    loop {
+      match self.bytes.get(self.pos) {
+        None => return Err("unterminated string".to_string()),
+        Some(b'"') => break,
+        Some(b'\\') => {
+          let escaped = match self.bytes.get(self.pos + 1) {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'u') => {
+              let code = self.bytes.get(self.pos + 2..self.pos + 6)
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| u32::from_str_radix(h, 16).ok())
+                .ok_or(format!("invalid escape at {}", self.pos))?;
+              self.pos += 4;
+              char::from_u32(code).ok_or(format!("invalid escape at {}", self.pos))?
+            },
+            _ => return Err(format!("invalid escape at {}", self.pos)),
+          };
+          let mut buf = [0; 4];
+          out.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+          self.pos += 2;
+        },
+        Some(&b) => {
+          out.push(b);
+          self.pos += 1;
+        }
+      }
+    }
+    self.pos += 1;
+    String::from_utf8(out).map_err(|e| e.to_string())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::json::Json;
+
+    #[test]
+    fn values_round_trip_through_text() {
+        let text = r#"{"leaf":"0xab","index":3,"siblings":["a\"b",[],{}],"ok":true,"none":null}"#;
+        let value = Json::parse(text).unwrap();
+        assert_eq!(value.get("index").and_then(Json::as_u64), Some(3));
+        assert_eq!(value.get("siblings").and_then(Json::as_array).map(<[Json]>::len), Some(3));
+        assert_eq!(value.to_string(), text);
+    }
+
+    #[test]
+    fn integers_keep_every_digit() {
+        for n in [(1u64 << 53) + 1, usize::MAX as u64, u64::MAX] {
+            let text = Json::from(n).to_string();
+            assert_eq!(text, n.to_string());
+            assert_eq!(Json::parse(&text).unwrap().as_u64(), Some(n));
+        }
+        assert_eq!(Json::parse("18446744073709551616").unwrap().as_u64(), None);
+        for text in ["-1", "1.5", "1e3", "+1"] {
+            assert_eq!(Json::parse(text).ok().and_then(|n| n.as_u64()), None, "{text}");
+        }
+        assert_eq!(Json::parse("[1.5e3]").unwrap().to_string(), "[1.5e3]");
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        for text in ["{", "[1,]", r#"{"a" 1}"#, "tru", r#""open"#, "1 2"] {
+            assert!(Json::parse(text).is_err(), "{text}");
+        }
+        assert!(Json::parse(&format!("{}{}", "[".repeat(512), "]".repeat(512))).is_ok());
+        assert!(Json::parse(&format!("{}{}", "[".repeat(513), "]".repeat(513))).is_err());
+    }
+}
@@ -0,0 +1,19 @@
+mod base64;
+mod codec;
+pub mod ct;
+pub mod error;
+pub mod forest;
+pub mod hash;
+mod hex;
+mod json;
+pub mod merkle_tree;
+pub mod prelude;
+pub mod proof;
+pub mod root_hasher;
+#[cfg(feature = "rs-merkle")]
+pub mod rs_merkle;
+pub mod scheme;
+mod sha256;
+pub mod signed_root;
+pub mod sync;
+mod tree_math;
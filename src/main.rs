@@ -1,5 +1,12 @@
-pub mod merkle_tree;
+mod cli;
 
 fn main() {
-  
-}
\ No newline at end of file
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  match cli::run(&args) {
+    Ok(output) => println!("{output}"),
+    Err(message) => {
+      eprintln!("{message}");
+      std::process::exit(1);
+    }
+  }
+}
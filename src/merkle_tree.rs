@@ -1,86 +1,1102 @@
+use hex_literal::hex;
 use sha3::{Sha3_256, Digest};
-use std::ops::Rem;
+use std::ops::{Index, Range};
+use crate::error::MerkleTreeError;
+use crate::hash::{LeafHash, Root};
+use crate::proof::{InclusionProof, MerkleProof, UpdateProof};
+use crate::scheme::{ByteOrder, HashFunction, HashScheme, Padding, PairOrder, TreeConfig, TreeHasher};
+use crate::tree_math::{self, group_bounds, level_sizes, proof_len};
+
+mod builder;
+mod checkpoint;
+mod compare;
+mod encoding;
+#[cfg(feature = "fs")]
+pub mod fs;
+mod index;
+mod meta;
+mod observer;
+mod padded;
+#[cfg(feature = "paranoid")]
+mod paranoid;
+mod replication;
+mod scratch;
+mod shard;
+mod sorted;
+mod stats;
+mod stream;
+pub mod testgen;
+mod tuple;
+
+pub use builder::MerkleTreeBuilder;
+pub use checkpoint::CheckpointId;
+pub use compare::{ComparisonReport, LeafDifference};
+pub use encoding::MAX_JSON_TREE_NODES;
+pub use observer::TreeEvent;
+pub use padded::{verify_padded_proof, ZeroHashes};
+pub use scratch::MerkleTreeScratch;
+pub use shard::{combine_shards, ShardBuilder, ShardResult};
+pub use stats::TreeStats;
+pub use stream::{ChunkedCommitment, MerkleWriter};
+pub use tuple::{TupleEncoding, TupleLeaf};
+pub use crate::tree_math::{DepthLimit, Layout, HARD_MAX_DEPTH, MAX_SUPPORTED_DEPTH};
+use checkpoint::Undo;
+use index::LeafIndex;
+use observer::Observer;
+
+// Size in bytes of every node hash of the default SHA3-256 trees
+pub const HASH_LEN: usize = 32;
+
+// Root of a tree without leaves, the hash of no data. A legacy leaf is the plain hash of
+// its element, so the tree of one empty element has this root too: legacy roots do not
+// tell the two apart, V1 roots, `len` and the padded commitments do
+pub const EMPTY_ROOT: Root = Root::new(hex!("a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a"));
+
+// Binary trees unless built `with_arity`
+pub const DEFAULT_ARITY: usize = 2;
 
 pub struct MerkleTree {
   hashes: Vec<Vec<u8>>,
-  count: usize
+  count: usize,
+  // Number of children hashed into each interior node
+  arity: usize,
+  // Raw element bytes, only kept by trees built with `new_retaining`
+  elements: Option<Vec<Vec<u8>>>,
+  // Opaque value per leaf, not hashed into it. Only kept once a leaf came with one, see
+  // `push_with_meta`
+  meta: Option<Vec<Option<u64>>>,
+  // Undo log of the mutations since the oldest open checkpoint
+  journal: Vec<Undo>,
+  // Id of each open checkpoint and the position in `journal` where it starts
+  checkpoints: Vec<(CheckpointId, usize)>,
+  // Id the next checkpoint gets, so a closed one is never mistaken for a newer one
+  next_checkpoint: usize,
+  // Leaves kept in ascending order so the root commits to the set, see `new_sorted`
+  sorted: bool,
+  scheme: HashScheme,
+  // Digest of every node, its output length is the size of the nodes
+  function: HashFunction,
+  // Of the lengths framing V1 leaves and of the integers of `to_bytes`
+  byte_order: ByteOrder,
+  pair_order: PairOrder,
+  // First position of each leaf hash, built on demand, see `index_of`
+  index: LeafIndex,
+  // Receives the changes, see `on_change`
+  observer: Option<Observer>
+}
+
+// The observer belongs to the original tree, clones start without one
+impl Clone for MerkleTree {
+  fn clone(&self) -> Self {
+    Self {
+      hashes: self.hashes.clone(),
+      count: self.count,
+      arity: self.arity,
+      elements: self.elements.clone(),
+      meta: self.meta.clone(),
+      journal: self.journal.clone(),
+      checkpoints: self.checkpoints.clone(),
+      next_checkpoint: self.next_checkpoint,
+      sorted: self.sorted,
+      scheme: self.scheme,
+      function: self.function,
+      byte_order: self.byte_order,
+      pair_order: self.pair_order,
+      index: self.index.clone(),
+      observer: None
+    }
+  }
 }
 
 
 impl MerkleTree {
   #[must_use]
   pub fn new(elements: &[String]) -> Self {
-    let leaves = Self::hash_elements(elements);
-    let hashes = Self::build_hashes(leaves);
-    Self{hashes, count: elements.len()}
+    Self::with_scheme(elements, HashScheme::Legacy)
+  }
+
+  // Tree hashing its leaves and nodes with the conventions of `scheme`
+  #[must_use]
+  pub fn with_scheme(elements: &[String], scheme: HashScheme) -> Self {
+    Self::with_hasher(elements, TreeHasher::new(scheme, HashFunction::default()))
+  }
+
+  // Tree hashing with `function` in place of SHA3-256, every node is `function.output_len()` bytes
+  #[must_use]
+  pub fn with_hash_function(elements: &[String], function: HashFunction) -> Self {
+    Self::with_hasher(elements, TreeHasher::new(HashScheme::Legacy, function))
+  }
+
+  fn with_hasher(elements: &[String], hasher: TreeHasher) -> Self {
+    let leaves = Self::hash_elements(elements, hasher);
+    let hashes = Self::build_hashes(leaves, DEFAULT_ARITY, hasher);
+    let mut tree = Self::with_layout(hashes, elements.len(), DEFAULT_ARITY);
+    tree.set_hasher(hasher);
+    tree
+  }
+
+  // k-ary tree, every interior node hashes up to `arity` children
+  pub fn with_arity(elements: &[String], arity: usize) -> Result<Self, MerkleTreeError> {
+    if arity < 2 {
+      return Err(MerkleTreeError::InvalidArity(arity));
+    }
+    let leaves = Self::hash_elements(elements, TreeHasher::default());
+    let hashes = Self::build_hashes(leaves, arity, TreeHasher::default());
+    Ok(Self::with_layout(hashes, elements.len(), arity))
+  }
+
+  // Builds the tree over already hashed SHA3-256 leaves
+  pub fn from_leaves(leaves: &[LeafHash]) -> Result<Self, MerkleTreeError> {
+    Self::from_leaves_with(leaves, HashFunction::default())
+  }
+
+  // Builds the tree over leaves hashed with `function`, all of them must have its output length
+  pub fn from_leaves_with(leaves: &[LeafHash], function: HashFunction) -> Result<Self, MerkleTreeError> {
+    Self::from_leaves_hashed(leaves, TreeHasher::new(HashScheme::Legacy, function))
+  }
+
+  // Keccak-256 tree over `leaves` hashing each pair sorted, the tree OpenZeppelin's
+  // `MerkleProof.verify` checks proofs against. See `MerkleProof::to_solidity_calldata`
+  pub fn from_sorted_pair_leaves(leaves: &[LeafHash]) -> Result<Self, MerkleTreeError> {
+    Self::from_leaves_hashed(leaves, TreeHasher::new(HashScheme::Legacy, HashFunction::Keccak256).with_pair_order(PairOrder::Sorted))
+  }
+
+  fn from_leaves_hashed(leaves: &[LeafHash], hasher: TreeHasher) -> Result<Self, MerkleTreeError> {
+    if let Some(leaf) = leaves.iter().find(|l| l.len() != hasher.output_len()) {
+      return Err(MerkleTreeError::InvalidHashLength { expected: hasher.output_len(), got: leaf.len() });
+    }
+    let hashes = Self::build_hashes(leaves.iter().map(LeafHash::to_vec).collect(), DEFAULT_ARITY, hasher);
+    let mut tree = Self::with_layout(hashes, leaves.len(), DEFAULT_ARITY);
+    tree.set_hasher(hasher);
+    Ok(tree)
+  }
+
+  fn with_layout(hashes: Vec<Vec<u8>>, count: usize, arity: usize) -> Self {
+    Self{hashes, count, arity, elements: None, meta: None, journal: vec![], checkpoints: vec![], next_checkpoint: 0, sorted: false, scheme: HashScheme::Legacy, function: HashFunction::default(), byte_order: ByteOrder::default(), pair_order: PairOrder::default(), index: LeafIndex::default(), observer: None}
   }
 
+  // Same tree as `new` but it also stores the original elements
   #[must_use]
-  pub fn root(&self) -> &[u8] {
-    &self.hashes[0]
+  pub fn new_retaining(elements: &[String]) -> Self {
+    let mut tree = Self::new(elements);
+    tree.elements = Some(elements.iter().map(|e| e.as_bytes().to_vec()).collect());
+    tree
+  }
+
+  #[must_use]
+  pub fn root(&self) -> Root {
+    match self.hashes.first() {
+      Some(root) => Root::try_from(root.as_slice()).expect("nodes have the output length of the hash function"),
+      None if self.function == HashFunction::default() => EMPTY_ROOT,
+      None => Root::try_from(self.function.digest(&[]).as_slice()).expect("digests have a supported length")
+    }
   }
 
   pub fn add(&mut self, elements: &[String]) {
-    let new_leaves = Self::hash_elements(elements);
-    // range (self.count - 1, ..) only tree leaves
-    let old_leaves = self.hashes[{self.count - 1}..].to_vec();
-    let leaves = [old_leaves, new_leaves].concat();
+    self.add_with_slots(elements, vec![]);
+  }
+
+  // `add` giving the new leaves the metadata in `meta`, the ones past its end get none
+  fn add_with_slots(&mut self, elements: &[String], meta: Vec<Option<u64>>) {
+    let (old_root, start) = (self.root(), self.count);
+    let new_leaves = Self::hash_elements(elements, self.hasher());
+    if self.sorted {
+      let retained = elements.iter().map(|e| e.as_bytes().to_vec()).collect();
+      return self.insert_sorted(new_leaves, Some(retained), meta);
+    }
+    if let Some(retained) = self.elements.as_mut() {
+      retained.extend(elements.iter().map(|e| e.as_bytes().to_vec()));
+    }
+    if meta.iter().any(Option::is_some) {
+      self.meta.get_or_insert_with(|| vec![None; start]);
+    }
+    self.record(Undo::Append(elements.len()));
+    self.append_leaves(new_leaves);
+    if let Some(slots) = self.meta.as_mut() {
+      for (slot, value) in slots[start..].iter_mut().zip(meta) {
+        *slot = value;
+      }
+    }
+    self.notify(old_root, |tree| tree.appended_since(start));
+  }
+
+  pub fn push(&mut self, element: &str) {
+    let (old_root, start) = (self.root(), self.count);
+    if self.sorted {
+      return self.insert_sorted(vec![self.hasher().leaf(element.as_bytes())], Some(vec![element.as_bytes().to_vec()]), vec![]);
+    }
+    if let Some(retained) = self.elements.as_mut() {
+      retained.push(element.as_bytes().to_vec());
+    }
+    self.record(Undo::Append(1));
+    self.append_leaves([self.hasher().leaf(element.as_bytes())]);
+    self.notify(old_root, |tree| tree.appended_since(start));
+  }
+
+  // Appends already hashed leaves, they must have the output length of the hash function.
+  // The tree stops retaining elements since these leaves come without one
+  pub fn add_leaves(&mut self, leaves: &[LeafHash]) -> Result<(), MerkleTreeError> {
+    let expected = self.function.output_len();
+    if let Some(leaf) = leaves.iter().find(|l| l.len() != expected) {
+      return Err(MerkleTreeError::InvalidHashLength { expected, got: leaf.len() });
+    }
+    let (old_root, start) = (self.root(), self.count);
+    let new_leaves: Vec<Vec<u8>> = leaves.iter().map(LeafHash::to_vec).collect();
+    self.drop_elements();
+    if self.sorted {
+      self.insert_sorted(new_leaves, None, vec![]);
+      return Ok(());
+    }
+    self.record(Undo::Append(leaves.len()));
+    self.append_leaves(new_leaves);
+    self.notify(old_root, |tree| tree.appended_since(start));
+    Ok(())
+  }
+
+  pub fn push_leaf(&mut self, leaf: LeafHash) -> Result<(), MerkleTreeError> {
+    self.add_leaves(&[leaf])
+  }
+
+  // Swaps the contents for the leaves of `elements` keeping the configuration of the tree.
+  // The result is the tree `new` would build, but the node storage is reused and only
+  // grows when the new tree needs more nodes than it has room for
+  pub fn replace_leaves(&mut self, elements: &[impl AsRef<[u8]>]) {
+    let data: Vec<&[u8]> = elements.iter().map(AsRef::as_ref).collect();
+    let leaves = self.hasher().leaves(&data);
+    self.replace_with(leaves, Some(&data));
+  }
+
+  // Same as `replace_leaves` over already hashed leaves, retained elements are dropped
+  pub fn replace_leaf_hashes(&mut self, leaves: &[LeafHash]) -> Result<(), MerkleTreeError> {
+    let expected = self.function.output_len();
+    if let Some(leaf) = leaves.iter().find(|l| l.len() != expected) {
+      return Err(MerkleTreeError::InvalidHashLength { expected, got: leaf.len() });
+    }
+    self.replace_with(leaves.iter().map(LeafHash::to_vec).collect(), None);
+    Ok(())
+  }
+
+  fn replace_with(&mut self, mut leaves: Vec<Vec<u8>>, elements: Option<&[&[u8]]>) {
+    let (old_root, old_count) = (self.root(), self.count);
+    // undone as removing the new leaves and inserting the old ones back in order
+    if !self.checkpoints.is_empty() {
+      for index in (0..self.count).rev() {
+        let element = self.get_element(index).map(<[u8]>::to_vec);
+        self.journal.push(Undo::Remove { index, leaf: self[index].to_vec(), element, meta: self.leaf_meta(index) });
+      }
+      if self.elements.is_some() && elements.is_none() {
+        self.journal.push(Undo::Elements(vec![]));
+      }
+      self.journal.push(Undo::Append(leaves.len()));
+    }
+    let mut order: Vec<usize> = (0..leaves.len()).collect();
+    if self.sorted {
+      order.sort_by(|&a, &b| leaves[a].cmp(&leaves[b]));
+    }
+    self.elements = match (self.elements.take(), elements) {
+      (Some(mut retained), Some(elements)) => {
+        retained.clear();
+        retained.extend(order.iter().map(|&i| elements[i].to_vec()));
+        Some(retained)
+      },
+      _ => None
+    };
+    self.meta = None;
+    self.invalidate_index();
+    self.hashes.clear();
+    self.hashes.extend(order.iter().map(|&i| std::mem::take(&mut leaves[i])));
+    self.count = self.hashes.len();
+    let hasher = self.hasher();
+    build_in_place(&mut self.hashes, self.arity, hasher);
+    self.notify(old_root, |tree| {
+      let removed = (0..old_count).rev().map(|index| TreeEvent::LeafRemoved { index });
+      removed.chain(tree.appended_since(0)).collect()
+    });
+  }
+
+  // Replaces the leaf at `index`, only the nodes on its path to the root are hashed again.
+  // A sorted tree moves the new leaf to its sorted position instead. The metadata of the
+  // leaf stays with it
+  pub fn update(&mut self, index: usize, element: &str) -> Result<(), MerkleTreeError> {
+    self.update_bytes(index, element.as_bytes())
+  }
+
+  // `update` only while the tree still has `expected_root`, compared in constant time.
+  // Returns the new root, or on a mismatch an error with the current one so the caller
+  // can read again and retry
+  pub fn update_if_root_matches(&mut self, expected_root: &Root, index: usize, element: impl AsRef<[u8]>) -> Result<Root, MerkleTreeError> {
+    self.update_all_if_root_matches(expected_root, &[(index, element)])
+  }
+
+  // Applies every update in order or none of them, the indices are all checked before
+  // the first one is applied. They name the leaves as they are before the batch, which
+  // on a sorted tree move once updated
+  pub fn update_all_if_root_matches<E: AsRef<[u8]>>(&mut self, expected_root: &Root, updates: &[(usize, E)]) -> Result<Root, MerkleTreeError> {
+    let actual = self.root();
+    if !actual.ct_eq(expected_root) {
+      return Err(MerkleTreeError::RootMismatch { actual });
+    }
+    if let Some(&(index, _)) = updates.iter().find(|(index, _)| *index >= self.count) {
+      return Err(MerkleTreeError::IndexOutOfBounds { index, len: self.count });
+    }
+    if self.sorted {
+      // removed from the last index down the others keep theirs, the last update of an
+      // index wins as it does in order
+      let latest: std::collections::BTreeMap<usize, &[u8]> = updates.iter().map(|(index, element)| (*index, element.as_ref())).collect();
+      let meta = latest.keys().map(|&index| self.leaf_meta(index)).collect();
+      let leaves = latest.values().map(|element| self.hasher().leaf(element)).collect();
+      let elements = latest.values().map(|element| element.to_vec()).collect();
+      for &index in latest.keys().rev() {
+        self.remove(index)?;
+      }
+      self.insert_sorted(leaves, Some(elements), meta);
+      return Ok(self.root());
+    }
+    for (index, element) in updates {
+      self.update_bytes(*index, element.as_ref())?;
+    }
+    Ok(self.root())
+  }
+
+  fn update_bytes(&mut self, index: usize, element: &[u8]) -> Result<(), MerkleTreeError> {
+    if index >= self.count {
+      return Err(MerkleTreeError::IndexOutOfBounds { index, len: self.count });
+    }
+    if self.sorted {
+      let meta = self.leaf_meta(index);
+      self.remove(index)?;
+      self.insert_sorted(vec![self.hasher().leaf(element)], Some(vec![element.to_vec()]), vec![meta]);
+      return Ok(());
+    }
+    let (old_root, offset) = (self.root(), self.leaf_offset());
+    let new_leaf = self.hasher().leaf(element);
+    let leaf = std::mem::replace(&mut self.hashes[offset + index], new_leaf);
+    self.invalidate_index();
+    let element = self.elements.as_mut().map(|e| std::mem::replace(&mut e[index], element.to_vec()));
+    let old = LeafHash::try_from(leaf.as_slice()).expect("leaves have the output length of the hash function");
+    self.record(Undo::Update { index, leaf, element });
+    self.update_path(index);
+    self.notify(old_root, |tree| vec![TreeEvent::LeafUpdated { index, old, new: tree.leaf_hash(index).expect("updated leaf") }]);
+    Ok(())
+  }
+
+  // `update` with an already hashed leaf, it must have the output length of the hash
+  // function. Like `add_leaves` the tree stops retaining elements
+  pub fn update_leaf(&mut self, index: usize, leaf: LeafHash) -> Result<(), MerkleTreeError> {
+    if index >= self.count {
+      return Err(MerkleTreeError::IndexOutOfBounds { index, len: self.count });
+    }
+    let expected = self.function.output_len();
+    if leaf.len() != expected {
+      return Err(MerkleTreeError::InvalidHashLength { expected, got: leaf.len() });
+    }
+    if self.sorted {
+      let meta = self.leaf_meta(index);
+      self.remove(index)?;
+      self.insert_sorted(vec![leaf.to_vec()], None, vec![meta]);
+      return Ok(());
+    }
+    let (old_root, offset) = (self.root(), self.leaf_offset());
+    let old_leaf = std::mem::replace(&mut self.hashes[offset + index], leaf.to_vec());
+    self.invalidate_index();
+    self.drop_elements();
+    let old = LeafHash::try_from(old_leaf.as_slice()).expect("leaves have the output length of the hash function");
+    self.record(Undo::Update { index, leaf: old_leaf, element: None });
+    self.update_path(index);
+    self.notify(old_root, |_| vec![TreeEvent::LeafUpdated { index, old, new: leaf }]);
+    Ok(())
+  }
+
+  // Removes the leaf at `index`, the following leaves shift one position to the left
+  pub fn remove(&mut self, index: usize) -> Result<(), MerkleTreeError> {
+    if index >= self.count {
+      return Err(MerkleTreeError::IndexOutOfBounds { index, len: self.count });
+    }
+    let old_root = self.root();
+    let mut leaves = self.take_leaves();
+    let leaf = leaves.remove(index);
+    let element = self.elements.as_mut().map(|e| e.remove(index));
+    let meta = self.meta.as_mut().and_then(|m| m.remove(index));
+    self.record(Undo::Remove { index, leaf, element, meta });
+    self.set_leaves(leaves);
+    self.notify(old_root, |_| vec![TreeEvent::LeafRemoved { index }]);
+    Ok(())
+  }
+
+  // Keeps only the leaves accepted by `pred`, which gets the leaf index and the original
+  // element when the tree retains them, the leaf hash otherwise. The interior is laid out
+  // once for all the removals
+  pub fn retain(&mut self, mut pred: impl FnMut(usize, &[u8]) -> bool) {
+    let keep: Vec<bool> = (0..self.count).map(|i| pred(i, self.get_element(i).unwrap_or(&self[i]))).collect();
+    self.retain_flagged(&keep);
+  }
+
+  // Drops the leaves from `len` on, nothing happens when the tree is not longer than that
+  pub fn truncate(&mut self, len: usize) {
+    let keep: Vec<bool> = (0..self.count).map(|i| i < len).collect();
+    self.retain_flagged(&keep);
+  }
+
+  fn retain_flagged(&mut self, keep: &[bool]) {
+    // from the last one, so each index is still the original position when it is removed
+    let removed: Vec<usize> = (0..self.count).rev().filter(|&i| !keep[i]).collect();
+    if removed.is_empty() {
+      return;
+    }
+    let old_root = self.root();
+    let mut leaves = self.take_leaves();
+    if !self.checkpoints.is_empty() {
+      for &index in &removed {
+        let element = self.get_element(index).map(<[u8]>::to_vec);
+        self.journal.push(Undo::Remove { index, leaf: leaves[index].clone(), element, meta: self.leaf_meta(index) });
+      }
+    }
+    let mut flags = keep.iter();
+    leaves.retain(|_| *flags.next().expect("a flag per leaf"));
+    if let Some(elements) = self.elements.as_mut() {
+      let mut flags = keep.iter();
+      elements.retain(|_| *flags.next().expect("a flag per element"));
+    }
+    if let Some(meta) = self.meta.as_mut() {
+      let mut flags = keep.iter();
+      meta.retain(|_| *flags.next().expect("a flag per leaf"));
+    }
+    self.set_leaves(leaves);
+    self.notify(old_root, |_| removed.iter().map(|&index| TreeEvent::LeafRemoved { index }).collect());
+  }
+
+  fn update_path(&mut self, index: usize) {
+    let layout = self.layout();
+    let mut node = layout.node_index(0, index).expect("the updated leaf is in the tree");
+    while let Some(parent) = layout.parent(node) {
+      let group = layout.group(node).expect("only the root has no group");
+      self.hashes[parent] = match &self.hashes[group] {
+        [single] => single.clone(),
+        children => self.hasher().node(&children.iter().map(Vec::as_slice).collect::<Vec<_>>())
+      };
+      node = parent;
+    }
+    #[cfg(feature = "paranoid")]
+    paranoid::check_nodes(&self.hashes, self.count, self.arity);
+  }
+
+  // Leaves the node storage holding only the leaves and hands it over
+  fn take_leaves(&mut self) -> Vec<Vec<u8>> {
+    let offset = self.leaf_offset();
+    let mut hashes = std::mem::take(&mut self.hashes);
+    hashes.drain(..offset);
+    self.count = 0;
+    self.invalidate_index();
+    hashes
+  }
+
+  // Appended leaves get no metadata, dropped ones lose theirs
+  fn set_leaves(&mut self, mut leaves: Vec<Vec<u8>>) {
     self.count = leaves.len();
-    self.hashes = Self::build_hashes(leaves);
+    if let Some(meta) = self.meta.as_mut() {
+      meta.resize(self.count, None);
+    }
+    build_in_place(&mut leaves, self.arity, self.hasher());
+    self.hashes = leaves;
+  }
+
+  // Keeps only the leaves in the node storage, appends the new ones and lays the tree out again
+  fn append_leaves(&mut self, leaves: impl IntoIterator<Item = Vec<u8>>) {
+    let mut hashes = self.take_leaves();
+    hashes.extend(leaves);
+    self.set_leaves(hashes);
+  }
+
+  // Empty tree with node storage for `capacity` leaves
+  #[must_use]
+  pub fn with_capacity(capacity: usize) -> Self {
+    let hashes = Vec::with_capacity(tree_math::node_count(capacity));
+    Self::with_layout(hashes, 0, DEFAULT_ARITY)
+  }
+
+  // Makes room for `additional` more leaves so growing up to them does not reallocate
+  pub fn reserve(&mut self, additional: usize) {
+    let nodes = Layout::of(self.count + additional, self.arity).node_count();
+    self.hashes.reserve(nodes - self.hashes.len());
+    if let Some(elements) = self.elements.as_mut() {
+      elements.reserve(additional);
+    }
+    if let Some(meta) = self.meta.as_mut() {
+      meta.reserve(additional);
+    }
+  }
+
+  // Number of leaves the tree can hold without reallocating its node storage
+  #[must_use]
+  pub fn capacity(&self) -> usize {
+    let nodes = self.hashes.capacity();
+    // node_count grows with the leaves, search the largest count that fits
+    let (mut low, mut high) = (0, nodes);
+    while low < high {
+      let mid = low + (high - low).div_ceil(2);
+      if Layout::of(mid, self.arity).node_count() <= nodes {
+        low = mid;
+      } else {
+        high = mid - 1;
+      }
+    }
+    low
+  }
+
+  #[must_use]
+  pub fn arity(&self) -> usize {
+    self.arity
+  }
+
+  #[must_use]
+  pub fn scheme(&self) -> HashScheme {
+    self.scheme
+  }
+
+  #[must_use]
+  pub fn hash_function(&self) -> HashFunction {
+    self.function
+  }
+
+  #[must_use]
+  pub fn byte_order(&self) -> ByteOrder {
+    self.byte_order
+  }
+
+  // Whether nodes hash their children in order or sorted
+  #[must_use]
+  pub fn pair_order(&self) -> PairOrder {
+    self.pair_order
+  }
+
+  pub(crate) fn hasher(&self) -> TreeHasher {
+    TreeHasher::new(self.scheme, self.function).with_byte_order(self.byte_order).with_pair_order(self.pair_order)
+  }
+
+  pub(crate) fn set_hasher(&mut self, hasher: TreeHasher) {
+    (self.scheme, self.function, self.byte_order, self.pair_order) = (hasher.scheme, hasher.function, hasher.byte_order, hasher.pair_order);
+  }
+
+  // The conventions the proofs of this tree are verified with
+  #[must_use]
+  pub fn config(&self) -> TreeConfig {
+    TreeConfig { arity: self.arity, scheme: self.scheme, function: self.function, byte_order: self.byte_order, pair_order: self.pair_order, padding: Padding::PromoteOdd }
+  }
+
+  // Where each node sits in the node storage, see `tree_math`
+  #[must_use]
+  pub fn layout(&self) -> Layout {
+    Layout::of(self.count, self.arity)
+  }
+
+  // Node at `index` of the node storage, the root first and the leaves last
+  #[must_use]
+  pub fn node(&self, index: usize) -> Option<&[u8]> {
+    self.hashes.get(index).map(Vec::as_slice)
+  }
+
+  // Node at `index` on `level`, level 0 being the leaves
+  #[must_use]
+  pub fn node_at(&self, level: usize, index: usize) -> Option<&[u8]> {
+    self.node(self.layout().node_index(level, index)?)
+  }
+
+  // (level, index) of every sibling in the proof of the leaf at `leaf_index`, in proof order
+  pub fn auth_path_indices(&self, leaf_index: usize) -> Result<Vec<(usize, usize)>, MerkleTreeError> {
+    let layout = self.layout();
+    let mut node = layout.node_index(0, leaf_index).ok_or(MerkleTreeError::IndexOutOfBounds { index: leaf_index, len: self.count })?;
+    let mut path = vec![];
+    while let (Some(group), Some(parent)) = (layout.group(node), layout.parent(node)) {
+      path.extend(group.filter(|&n| n != node).filter_map(|n| layout.position(n)));
+      node = parent;
+    }
+    Ok(path)
+  }
+
+  // (level, index) of the ancestors of the leaf at `leaf_index`, from its parent to the root
+  pub fn path_to_root_indices(&self, leaf_index: usize) -> Result<Vec<(usize, usize)>, MerkleTreeError> {
+    let layout = self.layout();
+    let leaf = layout.node_index(0, leaf_index).ok_or(MerkleTreeError::IndexOutOfBounds { index: leaf_index, len: self.count })?;
+    Ok(std::iter::successors(layout.parent(leaf), |&n| layout.parent(n)).filter_map(|n| layout.position(n)).collect())
+  }
+
+  // Independent tree over the leaves in `range`. When the range is a complete
+  // subtree (length a power of the arity and aligned start) its nodes are copied instead of hashed again
+  pub fn subtree(&self, range: Range<usize>) -> Result<Self, MerkleTreeError> {
+    let Range { start, end } = range;
+    if start >= end {
+      return Err(MerkleTreeError::Empty);
+    }
+    if end > self.count {
+      return Err(MerkleTreeError::RangeOutOfBounds { start, end, len: self.count });
+    }
+    Ok(self.slice(start, end))
+  }
+
+  // Divides the tree into the leaves `0..index` and `index..len`, reusing the
+  // interior nodes of each half when it is a complete subtree. One side is empty
+  // when splitting at 0 or `len`
+  pub fn split_at(&self, index: usize) -> Result<(Self, Self), MerkleTreeError> {
+    if index > self.count {
+      return Err(MerkleTreeError::RangeOutOfBounds { start: index, end: self.count, len: self.count });
+    }
+    Ok((self.slice(0, index), self.slice(index, self.count)))
+  }
+
+  // Root the tree had when it held its first `tree_size` leaves. The old levels are rebuilt
+  // from the leaves, so this is that root as long as the tree was only appended to since
+  pub fn root_at_size(&self, tree_size: usize) -> Result<Root, MerkleTreeError> {
+    Ok(self.at_size(tree_size)?.root())
+  }
+
+  // Proof of the leaf at `index` against `root_at_size(tree_size)`, for auditors of an
+  // append-only log who hold an old root
+  pub fn prove_at_size(&self, index: usize, tree_size: usize) -> Result<MerkleProof, MerkleTreeError> {
+    let old = self.at_size(tree_size)?;
+    if index >= tree_size {
+      return Err(MerkleTreeError::IndexOutOfBounds { index, len: tree_size });
+    }
+    Ok(old.merkle_proof_with(&old.level_bounds(), index))
+  }
+
+  fn at_size(&self, tree_size: usize) -> Result<Self, MerkleTreeError> {
+    if self.sorted {
+      return Err(MerkleTreeError::Incompatible("sorted trees move their leaves as they grow".to_string()));
+    }
+    if tree_size > self.count {
+      return Err(MerkleTreeError::RangeOutOfBounds { start: 0, end: tree_size, len: self.count });
+    }
+    Ok(self.slice(0, tree_size))
+  }
+
+  fn slice(&self, start: usize, end: usize) -> Self {
+    let len = end - start;
+    let mut tree = match complete_height(len, self.arity) {
+      Some(height) if start.is_multiple_of(len) => {
+        let mut hashes = vec![];
+        for level in (0..=height).rev() {
+          let offset = self.level_offset(level);
+          let width = self.arity.pow(level as u32);
+          hashes.extend_from_slice(&self.hashes[offset + start / width..offset + end / width]);
+        }
+        Self::with_layout(hashes, len, self.arity)
+      },
+      _ => {
+        let hashes = Self::build_hashes(self.leaves()[start..end].to_vec(), self.arity, self.hasher());
+        Self::with_layout(hashes, len, self.arity)
+      }
+    };
+    tree.elements = self.elements.as_ref().map(|e| e[start..end].to_vec());
+    tree.meta = self.meta.as_ref().map(|m| m[start..end].to_vec());
+    tree.set_hasher(self.hasher());
+    tree
+  }
+
+  // Appends the leaves of `other`, elements stay retained only if both trees kept them.
+  // Metadata of either tree is kept. Both trees need the same arity and hashing, leaves of
+  // another function or scheme would never hash like the leaves of `self`
+  pub fn merge(&mut self, other: Self) -> Result<(), MerkleTreeError> {
+    if other.hasher() != self.hasher() || other.arity != self.arity {
+      return Err(MerkleTreeError::Incompatible(format!("cannot merge a {} into a {}", other.config(), self.config())));
+    }
+    let (old_root, start) = (self.root(), self.count);
+    let offset = other.leaf_offset();
+    if self.meta.is_some() || other.meta.is_some() {
+      let mut meta = self.meta.take().unwrap_or_else(|| vec![None; self.count]);
+      meta.extend(other.meta.unwrap_or_else(|| vec![None; other.count]));
+      self.meta = Some(meta);
+    }
+    match (self.elements.as_mut(), other.elements) {
+      (Some(elements), Some(other_elements)) => elements.extend(other_elements),
+      _ => self.drop_elements()
+    }
+    let mut hashes = other.hashes;
+    if self.sorted {
+      let leaves = hashes.drain(offset..).collect();
+      let elements = self.elements.as_mut().map(|e| e.split_off(self.count));
+      let meta = self.meta.as_mut().map(|m| m.split_off(self.count)).unwrap_or_default();
+      self.insert_sorted(leaves, elements, meta);
+      return Ok(());
+    }
+    self.record(Undo::Append(other.count));
+    self.append_leaves(hashes.drain(offset..));
+    self.notify(old_root, |tree| tree.appended_since(start));
+    Ok(())
   }
 
   pub fn get_hashes(&mut self) -> Vec<Vec<u8>> {
     self.hashes.clone()
   }
 
-  fn hash_elements(elements: &[String]) -> Vec<Vec<u8>> {
-    elements.iter().map(|e| hash(e.to_string())).collect()
+  #[must_use]
+  pub fn len(&self) -> usize {
+    self.count
+  }
 
+  #[must_use]
+  pub fn is_empty(&self) -> bool {
+    self.count == 0
+  }
+
+  // Leaf hashes in index order, they are the last `count` nodes
+  #[must_use]
+  pub fn leaves(&self) -> &[Vec<u8>] {
+    &self.hashes[self.leaf_offset()..]
+  }
+
+  #[must_use]
+  pub fn get_leaf(&self, index: usize) -> Option<&[u8]> {
+    self.leaves().get(index).map(Vec::as_slice)
+  }
+
+  // Typed copy of the leaf at `index`, what `verify_proof` takes
+  #[must_use]
+  pub fn leaf_hash(&self, index: usize) -> Option<LeafHash> {
+    self.get_leaf(index).map(|leaf| LeafHash::try_from(leaf).expect("leaves have the output length of the hash function"))
+  }
+
+  #[must_use]
+  pub fn get_element(&self, index: usize) -> Option<&[u8]> {
+    self.elements.as_ref()?.get(index).map(Vec::as_slice)
+  }
+
+  #[must_use]
+  pub fn elements(&self) -> Option<&[Vec<u8>]> {
+    self.elements.as_deref()
+  }
+
+  pub fn iter_leaves(&self) -> impl ExactSizeIterator<Item = &[u8]> {
+    self.leaves().iter().map(Vec::as_slice)
+  }
+
+  fn leaf_offset(&self) -> usize {
+    self.hashes.len() - self.count
+  }
+
+  // Position in `hashes` of the first node of `level` (0 being the leaves), upper levels are stored first
+  fn level_offset(&self, level: usize) -> usize {
+    self.layout().level_offset(level).expect("levels of the tree")
+  }
+
+  fn hash_elements(elements: &[String], hasher: TreeHasher) -> Vec<Vec<u8>> {
+    hasher.leaves(&elements.iter().map(|e| e.as_bytes()).collect::<Vec<_>>())
   }
 
   // This fun creates the hierarchy of hashes and stops on the root hash 
-  fn build_hashes(hashes: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+  fn build_hashes(mut hashes: Vec<Vec<u8>>, arity: usize, hasher: TreeHasher) -> Vec<Vec<u8>> {
+    build_in_place(&mut hashes, arity, hasher);
+    // Each element of the result array is a node in merkle tree
+    hashes
+  }
+
+  // Siblings of every level from the leaf up, `arity - 1` per level (fewer in a ragged last
+  // group). Empty when `index` is out of range, see `try_proof`
+  #[must_use]
+  pub fn proof(&self, index: usize) -> Vec<Vec<u8>> {
+    self.try_proof(index).unwrap_or_default()
+  }
 
-    if hashes.len() == 1 {
-      return hashes;
+  // Number of siblings `proof` gives for the leaf at `index`, 0 when it is out of range.
+  // Leaves next to a promoted node have shorter proofs
+  #[must_use]
+  pub fn proof_len(&self, index: usize) -> usize {
+    if index >= self.count {
+      return 0;
     }
+    proof_len(index, self.count, self.arity)
+  }
 
-    let h: Vec<Vec<u8>> = hashes.chunks(2).clone().map(|e| hash_pair(e[0].clone(), e[1].clone())).collect();
-    // Each element of the result array is a node in merkle tree
-    [Self::build_hashes(h), hashes].concat()
+  // Longest proof of the tree, the first leaf is always in the fullest group of every level
+  #[must_use]
+  pub fn expected_proof_len(&self) -> usize {
+    self.proof_len(0)
+  }
+
+  // Serialized size of the longest `MerkleProof` of the tree
+  #[must_use]
+  pub fn max_proof_bytes(&self) -> usize {
+    MerkleProof::serialized_size_of(self.expected_proof_len() * self.function.output_len())
+  }
+
+  pub fn try_proof(&self, index: usize) -> Result<Vec<Vec<u8>>, MerkleTreeError> {
+    if index >= self.count {
+      return Err(MerkleTreeError::IndexOutOfBounds { index, len: self.count });
+    }
+    Ok(self.siblings_with(&self.level_bounds(), index))
+  }
+
+  // Siblings of `proof`, copied straight into a vector of the final size
+  fn siblings_with(&self, bounds: &[(usize, usize)], mut index: usize) -> Vec<Vec<u8>> {
+    let mut siblings = Vec::with_capacity(proof_len(index, self.count, self.arity));
+    for &(offset, size) in bounds {
+      let (start, end) = group_bounds(index, size, self.arity);
+      siblings.extend((start..end).filter(|&s| s != index).map(|s| self.hashes[offset + s].clone()));
+      index /= self.arity;
+    }
+    siblings
+  }
+
+  // Same siblings as `proof` grouped by level, along with the slot the path takes in each group
+  #[must_use]
+  pub fn proof_levels(&self, index: usize) -> Vec<ProofLevel> {
+    if index >= self.count {
+      return vec![];
+    }
+    self.proof_levels_with(&self.level_bounds(), index)
+  }
+
+  fn proof_levels_with(&self, bounds: &[(usize, usize)], mut index: usize) -> Vec<ProofLevel> {
+    let mut levels = vec![];
+    for &(i, size) in bounds {
+        let (start, end) = group_bounds(index, size, self.arity);
+        // a promoted node has no siblings on this level
+        if end - start > 1 {
+            let siblings = (start..end).filter(|&s| s != index).map(|s| self.hashes[i + s].clone()).collect();
+            levels.push(ProofLevel { position: index - start, siblings });
+        }
+        index /= self.arity;
+    }
+    levels
+  }
+
+  // Node above the leaf at `index` on every level, the root last
+  pub(crate) fn path_nodes(&self, mut index: usize) -> Vec<Vec<u8>> {
+    let mut nodes = vec![];
+    let bounds = self.level_bounds();
+    for (level, _) in bounds.iter().enumerate() {
+      index /= self.arity;
+      let offset = bounds.get(level + 1).map_or(0, |&(offset, _)| offset);
+      nodes.push(self.hashes[offset + index].clone());
+    }
+    nodes
+  }
+
+  // (offset, size) of every level below the root, from the leaves up
+  fn level_bounds(&self) -> Vec<(usize, usize)> {
+    let layout = self.layout();
+    (0..layout.height()).map(|level| (layout.level_offset(level).unwrap_or(0), layout.level_size(level).unwrap_or(0))).collect()
+  }
+
+  fn merkle_proof_with(&self, bounds: &[(usize, usize)], index: usize) -> MerkleProof {
+    let siblings = self.siblings_with(bounds, index);
+    MerkleProof { index, leaf_count: self.count, siblings, arity: self.arity, scheme: self.scheme, function: self.function, byte_order: self.byte_order, pair_order: self.pair_order }
+  }
+
+  // Proof of the leaf at `index` that verifies without the tree
+  pub fn inclusion_proof(&self, index: usize) -> Result<InclusionProof, MerkleTreeError> {
+    let leaf = self.leaf_hash(index).ok_or(MerkleTreeError::IndexOutOfBounds { index, len: self.count })?;
+    let proof = self.merkle_proof_with(&self.level_bounds(), index);
+    Ok(InclusionProof { leaf, proof })
+  }
+
+  // Proof that the leaf at `index` was `old_leaf` before the last change and is what the
+  // tree holds now. Sorted trees move updated leaves, their updates cannot be proven this way
+  pub fn prove_update(&self, index: usize, old_leaf: &LeafHash) -> Result<UpdateProof, MerkleTreeError> {
+    if self.sorted {
+      return Err(MerkleTreeError::Incompatible("updates move the leaves of sorted trees".to_string()));
+    }
+    let InclusionProof { leaf, proof } = self.inclusion_proof(index)?;
+    Ok(UpdateProof { old_leaf: *old_leaf, new_leaf: leaf, proof })
+  }
+
+  // `update` along with the proof of the transition from the old root to the new one
+  pub fn update_with_proof(&mut self, index: usize, element: &str) -> Result<UpdateProof, MerkleTreeError> {
+    if self.sorted {
+      return Err(MerkleTreeError::Incompatible("updates move the leaves of sorted trees".to_string()));
+    }
+    let old_leaf = self.leaf_hash(index).ok_or(MerkleTreeError::IndexOutOfBounds { index, len: self.count })?;
+    self.update(index, element)?;
+    self.prove_update(index, &old_leaf)
+  }
+
+  // Proofs of every leaf in index order, the level layout is worked out once for all of them
+  #[must_use]
+  pub fn proofs_for_all(&self) -> Vec<MerkleProof> {
+    self.proofs_where(|_, _| true).into_iter().map(|(_, proof)| proof).collect()
+  }
+
+  // Proofs of the leaves accepted by `pred`, in index order. The predicate gets the leaf
+  // index and the original element when the tree retains them, the leaf hash otherwise
+  pub fn proofs_where(&self, pred: impl Fn(usize, &[u8]) -> bool) -> Vec<(usize, MerkleProof)> {
+    let bounds = self.level_bounds();
+    (0..self.count)
+      .filter(|&i| pred(i, self.get_element(i).unwrap_or(&self[i])))
+      .map(|i| (i, self.merkle_proof_with(&bounds, i)))
+      .collect()
+  }
+
+  // Index and proof of the first leaf holding `element`. Sorted trees find it by binary search,
+  // otherwise the leaf index is built on the first lookup
+  #[must_use]
+  pub fn proof_for(&self, element: &str) -> Option<(usize, Vec<Vec<u8>>)> {
+    let index = self.position_of_leaf(&self.hasher().leaf(element.as_bytes()))?;
+    Some((index, self.proof(index)))
+  }
+
+  // Index of the first leaf with the hash `leaf`, for callers that only kept the leaf hashes
+  #[must_use]
+  pub fn index_of_leaf_hash(&self, leaf: &LeafHash) -> Option<usize> {
+    self.position_of_leaf(leaf.as_bytes())
+  }
+
+  #[must_use]
+  pub fn proof_by_leaf_hash(&self, leaf: &LeafHash) -> Option<MerkleProof> {
+    let index = self.index_of_leaf_hash(leaf)?;
+    Some(self.merkle_proof_with(&self.level_bounds(), index))
   }
 
-  fn proof(&self, mut index: usize) -> Vec<Vec<u8>> {
-    let mut proof = vec![];
-    let mut i = self.count-1;
+  // Proofs of every leaf with the hash `leaf` when it appears more than once, in index order
+  #[must_use]
+  pub fn proofs_by_leaf_hash(&self, leaf: &LeafHash) -> Vec<(usize, MerkleProof)> {
+    let leaves = self.leaves();
+    self.proofs_where(|i, _| leaves[i] == leaf.as_bytes())
+  }
 
-    while i != 0 {
-        let h: Vec<u8> = match index.rem(2) {
-            0 => self.hashes[index+1+i].clone(),
-            _ => self.hashes[index+i-1].clone()
-        };
-        proof.append(&mut vec![h]);
-        index /= 2;
-        i = (i+1)/2 - 1;
+  // Sorted trees find the leaf by binary search, others look it up in the leaf index
+  fn position_of_leaf(&self, leaf: &[u8]) -> Option<usize> {
+    if self.sorted {
+      let index = self.leaves().partition_point(|l| l.as_slice() < leaf);
+      (self.get_leaf(index)? == leaf).then_some(index)
+    } else {
+      self.indexed_position(leaf)
     }
-    proof
   }
 
-  fn verify(&self, proof: &[Vec<u8>], mut index: usize) -> bool {
-    // hash of element to verufy 
-    let mut hash = self.hashes[self.count - 1 + index].clone();
-    // iterating each of elements that create the proof (brother and aunts)
-    for p in proof {
-      // depending if left or right leaf 
+  #[must_use]
+  pub fn verify(&self, proof: &[Vec<u8>], index: usize) -> bool {
+    // hash of element to verufy, the index may come from anyone so it is not trusted
+    let Some(leaf) = self.get_leaf(index) else {
+      return false;
+    };
+    compute_root(leaf, index, self.count, self.arity, self.hasher(), proof).is_ok_and(|root| root == self.root())
+  }
+}
+
+
+// Root implied by a binary SHA3-256 tree proof of the leaf at `index` in a tree of `leaf_count` leaves
+pub fn compute_root_from_proof(leaf_hash: &LeafHash, index: usize, leaf_count: usize, proof: &[Vec<u8>]) -> Result<Root, MerkleTreeError> {
+  DepthLimit::default().check(proof.len())?;
+  compute_root(leaf_hash.as_bytes(), index, leaf_count, DEFAULT_ARITY, TreeHasher::default(), proof)
+}
+
+#[must_use]
+pub fn verify_proof(root: &Root, leaf_hash: &LeafHash, index: usize, leaf_count: usize, proof: &[Vec<u8>]) -> bool {
+  compute_root_from_proof(leaf_hash, index, leaf_count, proof).is_ok_and(|computed| computed == *root)
+}
+
+// `verify_proof` of a proof from anyone for a tree known to hold at most `max_leaf_count`
+// leaves. A larger claimed tree or more siblings than such a tree is deep fail before
+// anything is hashed
+#[must_use]
+pub fn verify_proof_within(root: &Root, leaf_hash: &LeafHash, index: usize, leaf_count: usize, proof: &[Vec<u8>], max_leaf_count: usize) -> bool {
+  leaf_count <= max_leaf_count
+    && proof.len() <= Layout::binary(max_leaf_count).height()
+    && verify_proof(root, leaf_hash, index, leaf_count, proof)
+}
+
+pub(crate) fn compute_root(leaf_hash: &[u8], mut index: usize, leaf_count: usize, arity: usize, hasher: TreeHasher, proof: &[Vec<u8>]) -> Result<Root, MerkleTreeError> {
+  // decoded proofs can claim any arity, fewer than two children would never reach the root
+  if arity < 2 {
+    return Err(MerkleTreeError::InvalidArity(arity));
+  }
+  if index >= leaf_count {
+    return Err(MerkleTreeError::IndexOutOfBounds { index, len: leaf_count });
+  }
+  let expected = proof_len(index, leaf_count, arity);
+  if proof.len() != expected {
+    return Err(MerkleTreeError::InvalidProofLength { expected, got: proof.len() });
+  }
+  // hashes of another size come from another function, they would never hash to the root
+  let expected = hasher.output_len();
+  if let Some(h) = std::iter::once(leaf_hash).chain(proof.iter().map(Vec::as_slice)).find(|h| h.len() != expected) {
+    return Err(MerkleTreeError::InvalidHashLength { expected, got: h.len() });
+  }
+  let mut hash = leaf_hash.to_vec();
+  let mut size = leaf_count;
+  // iterating each of elements that create the proof (brother and aunts)
+  let mut proof = proof.iter();
+  while size > 1 {
+    let (start, end) = group_bounds(index, size, arity);
+    // a promoted node goes up without consuming siblings
+    if end - start > 1 {
+      let mut children: Vec<&[u8]> = proof.by_ref().take(end - start - 1).map(Vec::as_slice).collect();
+      // our running hash takes its slot among the siblings
       // creating parent hash 
-      hash = match index.rem(2) {
-        0 => hash_pair(hash, p.clone()),
-        _ => hash_pair(p.clone(), hash)
-      };
-      // we go to the left  
-      index /= 2;
+      children.insert(index - start, &hash);
+      hash = hasher.node(&children);
+    }
+    index /= arity;
+    size = size.div_ceil(arity);
+  }
+  Root::try_from(hash.as_slice())
+}
+
+
+
+// Siblings of one level of a proof and the position of the proven path among them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofLevel {
+  pub position: usize,
+  pub siblings: Vec<Vec<u8>>
+}
+
+
+// Height of a complete subtree with `len` leaves, if there is one
+fn complete_height(len: usize, arity: usize) -> Option<usize> {
+  let mut height = 0;
+  let mut width = 1;
+  while width < len {
+    width *= arity;
+    height += 1;
+  }
+  (width == len).then_some(height)
+}
+
+// Lays the tree out over the leaves held in `hashes`, upper levels first, reusing its allocation
+fn build_in_place(hashes: &mut Vec<Vec<u8>>, arity: usize, hasher: TreeHasher) {
+  let sizes = level_sizes(hashes.len(), arity);
+  let interior: usize = sizes[1..].iter().sum();
+  hashes.resize(hashes.len() + interior, vec![]);
+  hashes.rotate_right(interior);
+
+  let mut offset = interior;
+  for level in sizes.windows(2) {
+    let (upper, lower) = hashes.split_at_mut(offset);
+    let parents = &mut upper[offset - level[1]..];
+    let groups: Vec<&[Vec<u8>]> = lower[..level[0]].chunks(arity).collect();
+    // A last group with a single node has nothing to hash with and is promoted as it is
+    let hashed = groups.len() - usize::from(groups.last().is_some_and(|g| g.len() == 1));
+    hasher.nodes_into(&groups[..hashed], &mut parents[..hashed]);
+    if let Some(promoted) = groups.get(hashed) {
+      parents[hashed].clone_from(&promoted[0]);
     }
-    hash == self.root()
+    offset -= level[1];
+  }
+  #[cfg(feature = "paranoid")]
+  paranoid::check_nodes(hashes, sizes[0], arity);
+}
+
+
+
+// Leaf access by index, panics when out of range like slices do
+impl Index<usize> for MerkleTree {
+  type Output = [u8];
+
+  fn index(&self, index: usize) -> &[u8] {
+    &self.leaves()[index]
+  }
+}
+
+// Consumes the tree yielding the owned leaf hashes, interior nodes are dropped
+impl IntoIterator for MerkleTree {
+  type Item = Vec<u8>;
+  type IntoIter = std::vec::IntoIter<Vec<u8>>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    let offset = self.leaf_offset();
+    let mut hashes = self.hashes;
+    hashes.drain(..offset);
+    hashes.into_iter()
   }
 }
 
@@ -92,10 +1108,28 @@ pub fn hash(element: String) -> Vec<u8> {
   hasher.finalize().to_vec()
 }
 
+// `hash` of everything `reader` holds, streamed through the hasher so large elements
+// never have to be in memory at once
+pub fn hash_reader(reader: impl std::io::Read) -> std::io::Result<[u8; HASH_LEN]> {
+  let (digest, _) = HashFunction::Sha3_256.digest_reader(&[], reader)?;
+  Ok(digest.try_into().expect("SHA3-256 digests are 32 bytes"))
+}
+
+// Parent hash over any number of children, for two children it is `hash_pair`
+#[must_use]
+pub fn hash_node(children: &[&[u8]]) -> Vec<u8> {
+  let mut hasher = Sha3_256::default();
+  for child in children {
+    hasher.update(child);
+  }
+  hasher.finalize().to_vec()
+}
+
 #[must_use]
 pub fn hash_pair(e1: Vec<u8>, e2: Vec<u8>) -> Vec<u8> {
   let mut hasher = Sha3_256::default();
-  hasher.update([e1, e2].concat());
+  hasher.update(e1);
+  hasher.update(e2);
   hasher.finalize().to_vec()
 }
 
@@ -103,20 +1137,24 @@ pub fn hash_pair(e1: Vec<u8>, e2: Vec<u8>) -> Vec<u8> {
 #[cfg(test)]
 mod tests {
     use hex_literal::hex;
+    use crate::hash::LeafHash;
     use crate::merkle_tree::*;
+    use crate::proof::InclusionProof;
+    use crate::scheme::HashFunction;
+    use crate::error::MerkleTreeError;
 
     #[test]
     fn root_hash_of_hola_moikka_is_correct() {
         let tree = MerkleTree::new(&["hola".to_string(), "moikka".to_string()]);
 
-        assert_eq!(tree.root(), hex!("d703ed960de71d89e617a637f87813b9da95461f30d5d5030329b979ff931032"));
+        assert_eq!(tree.root().as_bytes(), hex!("d703ed960de71d89e617a637f87813b9da95461f30d5d5030329b979ff931032"));
     }
 
     #[test]
     fn when_adding_two_more_elements_to_the_tree_the_root_hash_is_correct() {
         let mut tree = MerkleTree::new(&["hola".to_string(), "moikka".to_string()]);
         tree.add( &["heippa".to_string(), "ahoj".to_string()]);
-        assert_eq!(tree.root(), hex!("8321751cd2de3135bcc3ee9ad978061b284d1ec23f83279192ebcc3666c9e5cc"));
+        assert_eq!(tree.root().as_bytes(), hex!("8321751cd2de3135bcc3ee9ad978061b284d1ec23f83279192ebcc3666c9e5cc"));
     }
 
     #[test]
@@ -157,4 +1195,821 @@ mod tests {
         let proof = tree.proof(3);
         assert!(tree.verify(&proof, 3));
     }
+
+    #[test]
+    fn leaves_are_reachable_by_index() {
+        let tree = MerkleTree::new(&["hola".to_string(), "moikka".to_string(), "heippa".to_string(), "ahoj".to_string()]);
+        assert_eq!(&tree[0], hash("hola".to_string()).as_slice());
+        assert_eq!(&tree[3], hash("ahoj".to_string()).as_slice());
+        assert_eq!(tree.get_leaf(1), Some(hash("moikka".to_string()).as_slice()));
+        assert_eq!(tree.get_leaf(4), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn indexing_out_of_range_panics() {
+        let tree = MerkleTree::new(&["hola".to_string(), "moikka".to_string()]);
+        let _ = &tree[2];
+    }
+
+    #[test]
+    fn leaf_indexing_follows_the_layout_after_add() {
+        let mut tree = MerkleTree::new(&["hola".to_string(), "moikka".to_string()]);
+        tree.add(&["heippa".to_string(), "ahoj".to_string()]);
+        assert_eq!(&tree[0], hash("hola".to_string()).as_slice());
+        assert_eq!(&tree[2], hash("heippa".to_string()).as_slice());
+        assert_eq!(tree.get_leaf(3), Some(hash("ahoj".to_string()).as_slice()));
+    }
+
+    #[test]
+    fn chained_adds_of_uneven_batches_match_a_tree_built_at_once() {
+        let elements = numbered_elements(9);
+        for (initial, arity) in [(0, 2), (1, 2), (3, 2), (3, 3)] {
+            let mut tree = MerkleTree::with_arity(&elements[..initial], arity).unwrap();
+            let mut end = initial;
+            for batch in [2, 3, 1] {
+                tree.add(&elements[end..end + batch]);
+                end += batch;
+                let expected = MerkleTree::with_arity(&elements[..end], arity).unwrap();
+                assert_eq!(tree.root(), expected.root());
+                assert_eq!(tree.leaves(), expected.leaves());
+                assert!((0..end).all(|index| tree.verify(&expected.proof(index), index)));
+            }
+        }
+    }
+
+    #[test]
+    fn path_coordinates_address_the_proof_siblings() {
+        for arity in [2, 3] {
+            for count in [1, 2, 5, 8, 13] {
+                let tree = MerkleTree::with_arity(&numbered_elements(count), arity).unwrap();
+                for index in 0..count {
+                    let siblings: Vec<Vec<u8>> = tree.auth_path_indices(index).unwrap().into_iter()
+                        .map(|(level, i)| tree.node_at(level, i).unwrap().to_vec()).collect();
+                    assert_eq!(siblings, tree.proof(index));
+                    let ancestors = tree.path_to_root_indices(index).unwrap();
+                    assert_eq!(ancestors.len(), tree.layout().height());
+                    assert_eq!(ancestors.iter().map(|&(level, i)| tree.node_at(level, i).unwrap().to_vec()).collect::<Vec<_>>(), tree.path_nodes(index));
+                }
+                assert_eq!(tree.auth_path_indices(count), Err(MerkleTreeError::IndexOutOfBounds { index: count, len: count }));
+                assert!(tree.path_to_root_indices(count).is_err());
+            }
+        }
+        // the leaf at 4 of 5 is promoted twice, its only sibling is the other half of the tree
+        let tree = MerkleTree::new(&numbered_elements(5));
+        assert_eq!(tree.auth_path_indices(4).unwrap(), vec![(2, 0)]);
+        assert_eq!(tree.path_to_root_indices(4).unwrap(), vec![(1, 2), (2, 1), (3, 0)]);
+    }
+
+    #[test]
+    fn retaining_tree_returns_the_original_elements_after_add() {
+        let mut tree = MerkleTree::new_retaining(&["hola".to_string(), "moikka".to_string()]);
+        tree.add(&["heippa".to_string(), "ahoj".to_string()]);
+        assert_eq!(tree.get_element(0), Some("hola".as_bytes()));
+        assert_eq!(tree.get_element(3), Some("ahoj".as_bytes()));
+        assert_eq!(tree.get_element(4), None);
+        assert_eq!(tree.elements().map(<[Vec<u8>]>::len), Some(4));
+        assert_eq!(tree.root(), MerkleTree::new(&["hola".to_string(), "moikka".to_string(), "heippa".to_string(), "ahoj".to_string()]).root());
+    }
+
+    #[test]
+    fn non_retaining_tree_has_no_elements() {
+        let tree = MerkleTree::new(&["hola".to_string(), "moikka".to_string()]);
+        assert_eq!(tree.get_element(0), None);
+        assert!(tree.elements().is_none());
+    }
+
+    #[test]
+    fn odd_sized_levels_promote_their_last_node() {
+        let tree = MerkleTree::new(&["hola".to_string(), "moikka".to_string(), "heippa".to_string()]);
+        let hola_moikka = hash_pair(hash("hola".to_string()), hash("moikka".to_string()));
+        assert_eq!(tree.root().as_bytes(), hash_pair(hola_moikka.clone(), hash("heippa".to_string())));
+        assert_eq!(tree.proof(2), vec![hola_moikka]);
+    }
+
+    // Counts the allocations of the current thread, so tests running alongside do not interfere
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+        let before = ALLOCATIONS.with(|count| count.get());
+        let result = f();
+        (result, ALLOCATIONS.with(|count| count.get()) - before)
+    }
+
+    #[test]
+    fn proofs_of_deep_trees_allocate_once_per_sibling() {
+        let leaves: Vec<LeafHash> = (0..(1u32 << 19) + 1).map(|i| LeafHash::try_from(hash_node(&[&i.to_le_bytes()]).as_slice()).unwrap()).collect();
+        let tree = MerkleTree::from_leaves(&leaves).unwrap();
+        for index in [0, 1 << 18, 1 << 19] {
+            let (proof, count) = allocations(|| tree.proof(index));
+            assert_eq!(proof, tree.proof_levels(index).into_iter().flat_map(|level| level.siblings).collect::<Vec<_>>());
+            assert!(verify_proof(&tree.root(), &leaves[index], index, leaves.len(), &proof));
+            // the siblings, the proof itself and the level layout
+            assert!(count <= proof.len() + 4, "{count} allocations for {} siblings", proof.len());
+        }
+        assert_eq!(tree.proof(0).len(), 20);
+        assert_eq!(tree.proof(1 << 19).len(), 1);
+    }
+
+    // the paranoid checks allocate a map of the nodes on every build
+    #[test]
+    #[cfg(not(feature = "paranoid"))]
+    fn scratch_builds_stop_allocating_after_warm_up() {
+        let batches: Vec<Vec<String>> = [500, 50, 317, 1, 0, 128, 499].iter().map(|&n| numbered_elements(n)).collect();
+        let mut scratch = MerkleTreeScratch::new();
+        for batch in &batches {
+            let tree = MerkleTree::new_in(&mut scratch, batch);
+            let index = batch.len() / 2;
+            assert!(batch.is_empty() || tree.verify(&tree.proof(index), index));
+            tree.recycle(&mut scratch);
+        }
+        for batch in &batches {
+            let (root, count) = allocations(|| {
+                let tree = MerkleTree::new_in(&mut scratch, batch);
+                let root = tree.root();
+                tree.recycle(&mut scratch);
+                root
+            });
+            assert_eq!(root, MerkleTree::new(batch).root());
+            assert_eq!(count, 0, "{} leaves", batch.len());
+        }
+    }
+
+    #[test]
+    fn compact_proofs_of_deep_trees_serialize_like_proofs() {
+        use crate::proof::CompactProof;
+        let leaves: Vec<LeafHash> = (0..(1u32 << 19) + 1).map(|i| LeafHash::try_from(hash_node(&[&i.to_le_bytes()]).as_slice()).unwrap()).collect();
+        let tree = MerkleTree::from_leaves(&leaves).unwrap();
+        for index in [0, 12345, 1 << 19] {
+            let (compact, count) = allocations(|| tree.compact_proof(index).unwrap());
+            // the level layout only, the siblings are inline
+            assert!(count <= 3, "{count} allocations");
+            let (inclusion, count) = allocations(|| tree.compact_inclusion_proof(index).unwrap());
+            assert!(count <= 3 && inclusion.proof == compact && inclusion.leaf == leaves[index], "{count} allocations");
+            let (verified, count) = allocations(|| compact.verify(&tree.root(), &leaves[index]));
+            assert!(verified && count <= 2, "{count} allocations");
+
+            let proof = tree.inclusion_proof(index).unwrap().proof;
+            let (bytes, json) = (compact.to_bytes().unwrap(), compact.to_json());
+            assert_eq!((&bytes, &json), (&proof.to_bytes().unwrap(), &proof.to_json()));
+            assert_eq!(CompactProof::from_bytes(&bytes).unwrap(), compact);
+            assert_eq!(CompactProof::from_json(&json).unwrap(), compact);
+        }
+        assert_eq!(tree.compact_proof(0).unwrap().siblings().len(), 20);
+    }
+
+    #[test]
+    fn proof_sizes_are_known_before_proving() {
+        for arity in [2, 3, 4] {
+            for count in 1..=64 {
+                let tree = MerkleTree::with_arity(&numbered_elements(count), arity).unwrap();
+                let lens: Vec<usize> = (0..count).map(|i| tree.proof_len(i)).collect();
+                for (index, &len) in lens.iter().enumerate() {
+                    assert_eq!(tree.proof(index).len(), len);
+                    let proof = tree.inclusion_proof(index).unwrap().proof;
+                    assert_eq!(proof.serialized_size(), proof.to_bytes().unwrap().len());
+                    assert!(proof.serialized_size() <= tree.max_proof_bytes());
+                }
+                assert_eq!(lens.iter().max(), Some(&tree.expected_proof_len()));
+                assert_eq!(tree.inclusion_proof(0).unwrap().proof.serialized_size(), tree.max_proof_bytes());
+                assert_eq!(tree.proof_len(count), 0);
+            }
+        }
+        let tree = MerkleTree::with_hash_function(&numbered_elements(5), HashFunction::Sha3_512);
+        let proof = tree.inclusion_proof(0).unwrap();
+        assert_eq!(proof.serialized_size(), proof.to_bytes().unwrap().len());
+        assert_eq!(tree.max_proof_bytes(), proof.proof.to_bytes().unwrap().len());
+        assert_eq!(MerkleTree::new(&[]).expected_proof_len(), 0);
+    }
+
+    // the paranoid checks allocate a map of the nodes on every build
+    #[test]
+    #[cfg(not(feature = "paranoid"))]
+    fn building_allocates_only_the_nodes() {
+        for arity in [2, 3] {
+            let leaves: Vec<Vec<u8>> = (0..(1u32 << 16) + 3).map(|i| hash_node(&[&i.to_le_bytes()])).collect();
+            let interior = crate::tree_math::Layout::of(leaves.len(), arity).node_count() - leaves.len();
+            let (hashes, count) = allocations(|| MerkleTree::build_hashes(leaves, arity, TreeHasher::default()));
+            assert_eq!(hashes.len(), interior + (1 << 16) + 3);
+            // a buffer per interior node, then the storage, the layout and the groups of every level
+            assert!(count <= interior + 3 + 2 * 17, "{count} allocations for {interior} interior nodes");
+        }
+    }
+
+    #[test]
+    fn hostile_verification_input_is_rejected_without_panicking() {
+        let tree = MerkleTree::new(&numbered_elements(4));
+        let (root, leaf, proof) = (tree.root(), tree.leaf_hash(1).unwrap(), tree.proof(1));
+        assert!(!tree.verify(&proof, 9));
+        assert!(!tree.verify(&[], 1));
+        assert!(!tree.verify(&vec![vec![]; 2], 1));
+        assert!(!tree.verify(&[proof[0][..1].to_vec(), proof[1].clone()], 1));
+        assert!(!MerkleTree::new(&[]).verify(&[], 0));
+
+        assert!(!verify_proof(&root, &leaf, 9, 4, &proof));
+        assert!(!verify_proof(&root, &leaf, 0, 0, &[]));
+        assert!(!verify_proof(&root, &leaf, 1, 4, &[]));
+        assert!(!verify_proof(&root, &leaf, 1, usize::MAX, &proof));
+        assert!(!verify_proof(&root, &leaf, 1, 4, &[vec![], proof[1].clone()]));
+
+        let mut verifier = crate::proof::ProofVerifier::new(&leaf, 1, 4).unwrap();
+        assert!(verifier.feed_sibling(&[]).is_err());
+        assert!(crate::proof::ProofVerifier::new(&leaf, 9, 4).is_err());
+
+        // decoded proofs claim their own shape, including impossible arities
+        let mut decoded = tree.inclusion_proof(1).unwrap();
+        for arity in [0, 1] {
+            decoded.proof.arity = arity;
+            let decoded = InclusionProof::from_bytes(&decoded.to_bytes().unwrap()).unwrap();
+            assert!(!decoded.verify(&root));
+            assert_eq!(decoded.proof.compute_root(&leaf), Err(MerkleTreeError::InvalidArity(arity)));
+            assert!(decoded.proof.explain(&leaf, &root).contains("cannot be evaluated"));
+        }
+    }
+
+    #[test]
+    fn indices_near_usize_max_are_out_of_bounds() {
+        use crate::error::VerifyError;
+        use crate::proof::ProofVerifier;
+        let mut tree = MerkleTree::new(&numbered_elements(5));
+        let (root, leaf, proof) = (tree.root(), tree.leaf_hash(1).unwrap(), tree.proof(1));
+        for index in [5, 6, usize::MAX - 1, usize::MAX] {
+            let out_of_bounds = Some(MerkleTreeError::IndexOutOfBounds { index, len: 5 });
+            assert!(tree.proof(index).is_empty() && tree.proof_levels(index).is_empty());
+            assert_eq!(tree.try_proof(index).err(), out_of_bounds);
+            assert_eq!(tree.inclusion_proof(index).err(), out_of_bounds);
+            assert_eq!(tree.prove_update(index, &leaf).err(), out_of_bounds);
+            assert_eq!(tree.auth_path_indices(index).err(), out_of_bounds);
+            assert!(tree.path_to_root_indices(index).is_err() && tree.padded_proof(index, 3).is_err());
+            assert!(tree.get_leaf(index).is_none() && tree.leaf_hash(index).is_none() && tree.get_element(index).is_none());
+            assert!(tree.node(index.saturating_add(6)).is_none() && tree.node_at(0, index).is_none() && tree.node_at(index, 0).is_none());
+            assert!(tree.update(index, "x").is_err() && tree.update_leaf(index, leaf).is_err() && tree.remove(index).is_err());
+            assert!(tree.update_with_proof(index, "x").is_err() && tree.update_if_root_matches(&root, index, "x").is_err());
+            assert!(tree.split_at(index.max(6)).is_err());
+            assert_eq!(tree.root(), root);
+
+            assert!(!tree.verify(&proof, index));
+            assert_eq!(compute_root_from_proof(&leaf, index, 5, &proof).err(), out_of_bounds);
+            assert!(!verify_proof(&root, &leaf, index, 5, &proof));
+            assert!(ProofVerifier::new(&leaf, index, 5).is_err());
+            assert!(crate::tree_math::Layout::binary(5).node_index(0, index).is_none());
+
+            // a decoded proof can claim a tree big enough to hold the index
+            let mut decoded = tree.inclusion_proof(1).unwrap();
+            decoded.proof.index = index;
+            assert!(matches!(decoded.proof.verify_detailed(&root, &leaf), Err(VerifyError::IndexOutOfRange { .. })));
+            for arity in [2, 3, usize::MAX] {
+                decoded.proof.leaf_count = usize::MAX;
+                decoded.proof.arity = arity;
+                assert!(matches!(decoded.proof.verify_detailed(&root, &leaf), Err(VerifyError::WrongProofLength { .. }) | Err(VerifyError::IndexOutOfRange { .. })));
+                assert!(!decoded.verify(&root));
+            }
+            assert!(!verify_proof(&root, &leaf, index - 1, usize::MAX, &proof));
+        }
+    }
+
+    #[test]
+    fn proofs_verify_for_every_index_of_unbalanced_trees() {
+        for size in 1..=9 {
+            let elements: Vec<String> = (0..size).map(|i| i.to_string()).collect();
+            let tree = MerkleTree::new(&elements);
+            for index in 0..size {
+                assert!(tree.verify(&tree.proof(index), index));
+            }
+        }
+    }
+
+    #[test]
+    fn from_leaves_matches_new() {
+        let elements = ["hola".to_string(), "moikka".to_string(), "heippa".to_string()];
+        let leaves: Vec<LeafHash> = elements.iter().map(|e| LeafHash::try_from(hash(e.to_string()).as_slice()).unwrap()).collect();
+        let tree = MerkleTree::from_leaves(&leaves).unwrap();
+        assert_eq!(tree.root(), MerkleTree::new(&elements).root());
+        assert_eq!(MerkleTree::from_leaves(&[]).unwrap().root(), EMPTY_ROOT);
+        assert_eq!(LeafHash::try_from(&[0; 3][..]).err(), Some(MerkleTreeError::InvalidHashLength { expected: 32, got: 3 }));
+        let wide = LeafHash::try_from(&[0; 64][..]).unwrap();
+        assert_eq!(MerkleTree::from_leaves(&[leaves[0], wide]).err(), Some(MerkleTreeError::InvalidHashLength { expected: 32, got: 64 }));
+    }
+
+    #[test]
+    fn added_leaves_are_not_hashed_again() {
+        let elements = eight_elements();
+        let mut tree = MerkleTree::new_retaining(&elements[..3]);
+        let leaves: Vec<LeafHash> = elements[3..7].iter().map(|e| LeafHash::try_from(hash(e.to_string()).as_slice()).unwrap()).collect();
+        tree.add_leaves(&leaves).unwrap();
+        tree.push_leaf(LeafHash::try_from(hash(elements[7].clone()).as_slice()).unwrap()).unwrap();
+        assert_eq!(tree.root(), MerkleTree::new(&elements).root());
+        assert!(tree.elements().is_none());
+        for index in 0..8 {
+            assert!(tree.verify(&tree.proof(index), index));
+        }
+
+        let wide = LeafHash::try_from(&[0; 64][..]).unwrap();
+        assert_eq!(tree.add_leaves(&[leaves[0], wide]), Err(MerkleTreeError::InvalidHashLength { expected: 32, got: 64 }));
+        assert_eq!(tree.push_leaf(wide), Err(MerkleTreeError::InvalidHashLength { expected: 32, got: 64 }));
+        assert_eq!(tree.len(), 8);
+
+        let mut updated = MerkleTree::new(&elements);
+        updated.update(5, "hei").unwrap();
+        tree.update_leaf(5, LeafHash::try_from(hash("hei".to_string()).as_slice()).unwrap()).unwrap();
+        assert_eq!(tree.root(), updated.root());
+        assert_eq!(tree.update_leaf(8, leaves[0]), Err(MerkleTreeError::IndexOutOfBounds { index: 8, len: 8 }));
+    }
+
+    #[test]
+    fn replacing_the_leaves_reuses_the_node_storage() {
+        let mut tree = MerkleTree::new_retaining(&numbered_elements(100));
+        let storage = (tree.hashes.as_ptr(), tree.hashes.capacity());
+        for epoch in 0..3 {
+            let snapshot: Vec<String> = (0..100).map(|i| format!("{epoch}-{i}")).collect();
+            tree.replace_leaves(&snapshot);
+            assert_eq!((tree.hashes.as_ptr(), tree.hashes.capacity()), storage);
+            let mut fresh = MerkleTree::new_retaining(&snapshot);
+            assert_eq!(tree.get_hashes(), fresh.get_hashes());
+            assert_eq!(tree.elements(), fresh.elements());
+        }
+        // fewer leaves fit in the same storage, more grow it
+        tree.replace_leaves(&["hola", "moikka"]);
+        assert_eq!(tree.hashes.as_ptr(), storage.0);
+        assert_eq!(tree.root(), MerkleTree::new(&["hola".to_string(), "moikka".to_string()]).root());
+        tree.replace_leaves(&numbered_elements(300));
+        assert_eq!(tree.root(), MerkleTree::new(&numbered_elements(300)).root());
+        tree.replace_leaves(&Vec::<String>::new());
+        assert_eq!(tree.root(), EMPTY_ROOT);
+    }
+
+    #[test]
+    fn replacing_keeps_the_configuration_and_can_be_rolled_back() {
+        let mut sorted = MerkleTree::new_sorted(&numbered_elements(5));
+        sorted.replace_leaves(&["c", "a", "b"]);
+        assert_eq!(sorted.root(), MerkleTree::new_sorted(&["b".to_string(), "a".to_string(), "c".to_string()]).root());
+
+        let mut tree = MerkleTree::with_arity(&numbered_elements(9), 3).unwrap();
+        let before = tree.root();
+        let checkpoint = tree.checkpoint();
+        let leaves: Vec<LeafHash> = numbered_elements(4).iter().map(|e| LeafHash::try_from(hash(e.to_string()).as_slice()).unwrap()).collect();
+        tree.replace_leaf_hashes(&leaves).unwrap();
+        assert_eq!(tree.root(), MerkleTree::with_arity(&numbered_elements(4), 3).unwrap().root());
+        tree.rollback(checkpoint).unwrap();
+        assert_eq!(tree.root(), before);
+        let wide = LeafHash::try_from(&[0; 64][..]).unwrap();
+        assert_eq!(tree.replace_leaf_hashes(&[wide]), Err(MerkleTreeError::InvalidHashLength { expected: 32, got: 64 }));
+    }
+
+    #[test]
+    fn retain_keeps_the_accepted_leaves() {
+        let elements = numbered_elements(13);
+        let mut tree = MerkleTree::new_retaining(&elements);
+        tree.retain(|index, _| index % 2 == 0);
+        let survivors: Vec<String> = elements.iter().step_by(2).cloned().collect();
+        assert_eq!(tree.root(), MerkleTree::new(&survivors).root());
+        assert_eq!(tree.elements().unwrap(), survivors.iter().map(|e| e.as_bytes().to_vec()).collect::<Vec<_>>());
+        for index in 0..tree.len() {
+            assert!(tree.verify(&tree.proof(index), index));
+        }
+        // the predicate sees the retained elements
+        tree.retain(|_, element| element != b"4");
+        assert_eq!(tree.root(), MerkleTree::new(&["0", "2", "6", "8", "10", "12"].map(String::from)).root());
+
+        let checkpoint = tree.checkpoint();
+        tree.retain(|_, _| false);
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), EMPTY_ROOT);
+        tree.rollback(checkpoint).unwrap();
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.get_element(2), Some("6".as_bytes()));
+    }
+
+    #[test]
+    fn truncate_drops_the_tail() {
+        let elements = numbered_elements(16);
+        let mut tree = MerkleTree::with_arity(&elements, 3).unwrap();
+        tree.truncate(20);
+        assert_eq!(tree.len(), 16);
+        tree.truncate(11);
+        assert_eq!(tree.root(), MerkleTree::with_arity(&elements[..11], 3).unwrap().root());
+        for index in 0..11 {
+            assert!(tree.verify(&tree.proof(index), index));
+        }
+        tree.truncate(0);
+        assert_eq!(tree.root(), EMPTY_ROOT);
+    }
+
+    #[test]
+    fn updates_only_apply_to_the_expected_root() {
+        let elements = numbered_elements(7);
+        let mut tree = MerkleTree::new(&elements);
+        let read = tree.root();
+        let new_root = tree.update_if_root_matches(&read, 3, "three").unwrap();
+        let mut expected = elements.clone();
+        expected[3] = "three".to_string();
+        assert_eq!(new_root, MerkleTree::new(&expected).root());
+        assert_eq!(tree.root(), new_root);
+
+        // a writer still holding the old root is told the current one
+        assert_eq!(tree.update_if_root_matches(&read, 3, b"other"), Err(MerkleTreeError::RootMismatch { actual: new_root }));
+        assert_eq!(tree.root(), new_root);
+        assert!(tree.update_if_root_matches(&new_root, 7, "x").is_err());
+    }
+
+    #[test]
+    fn batched_updates_are_atomic() {
+        let elements = numbered_elements(9);
+        let mut tree = MerkleTree::new_retaining(&elements);
+        let root = tree.root();
+        let stale = MerkleTree::new(&elements[..8]).root();
+        assert!(matches!(tree.update_all_if_root_matches(&stale, &[(0, "a"), (1, "b")]), Err(MerkleTreeError::RootMismatch { .. })));
+        assert_eq!(tree.update_all_if_root_matches(&root, &[(0, "a"), (9, "b")]), Err(MerkleTreeError::IndexOutOfBounds { index: 9, len: 9 }));
+        assert_eq!(tree.root(), root);
+        assert_eq!(tree.get_element(0), Some("0".as_bytes()));
+
+        let new_root = tree.update_all_if_root_matches(&root, &[(0, "a"), (8, "b")]).unwrap();
+        let mut expected = elements.clone();
+        (expected[0], expected[8]) = ("a".to_string(), "b".to_string());
+        assert_eq!(new_root, MerkleTree::new(&expected).root());
+        assert_eq!(tree.get_element(8), Some("b".as_bytes()));
+        assert!(root.ct_eq(&root) && !root.ct_eq(&new_root));
+    }
+
+    #[test]
+    fn batched_updates_of_a_sorted_tree_replace_the_leaves_named_before_the_batch() {
+        let mut elements: Vec<String> = ["a", "b", "c", "d", "e", "f"].map(String::from).to_vec();
+        let mut tree = MerkleTree::new_sorted(&elements);
+        elements.sort_by_key(|element| HashScheme::Legacy.hash_leaf(element.as_bytes()));
+        let root = tree.root();
+        let new_root = tree.update_all_if_root_matches(&root, &[(0, "zzzz"), (1, "yyyy")]).unwrap();
+        let mut expected = elements[2..].to_vec();
+        expected.extend(["zzzz", "yyyy"].map(String::from));
+        assert_eq!(new_root, MerkleTree::new_sorted(&expected).root());
+
+        // the last update of an index wins
+        let mut tree = MerkleTree::new_sorted(&elements);
+        let new_root = tree.update_all_if_root_matches(&root, &[(3, "x"), (0, "y"), (3, "z")]).unwrap();
+        let mut expected = elements.clone();
+        (expected[0], expected[3]) = ("y".to_string(), "z".to_string());
+        assert_eq!(new_root, MerkleTree::new_sorted(&expected).root());
+        assert!(tree.is_sorted() && tree.len() == 6);
+    }
+
+    #[test]
+    fn proofs_by_leaf_hash_find_the_leaf_from_its_digest() {
+        let mut elements = numbered_elements(6);
+        elements.push("2".to_string());
+        let tree = MerkleTree::new(&elements);
+        let leaf = LeafHash::try_from(hash("2".to_string()).as_slice()).unwrap();
+        assert_eq!(tree.index_of_leaf_hash(&leaf), Some(2));
+        let proof = tree.proof_by_leaf_hash(&leaf).unwrap();
+        assert_eq!(proof.index, 2);
+        assert!(proof.verify(&tree.root(), &leaf));
+
+        let all = tree.proofs_by_leaf_hash(&leaf);
+        assert_eq!(all.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![2, 6]);
+        assert!(all.iter().all(|(_, proof)| proof.verify(&tree.root(), &leaf)));
+
+        let absent = LeafHash::try_from(hash("absent".to_string()).as_slice()).unwrap();
+        assert!(tree.proof_by_leaf_hash(&absent).is_none());
+        assert!(tree.proofs_by_leaf_hash(&absent).is_empty());
+        let sorted = MerkleTree::new_sorted(&elements);
+        assert!(sorted.proof_by_leaf_hash(&leaf).unwrap().verify(&sorted.root(), &leaf));
+    }
+
+    #[test]
+    fn sha3_512_trees_have_64_byte_nodes() {
+        let elements = eight_elements();
+        let tree = MerkleTree::with_hash_function(&elements, HashFunction::Sha3_512);
+        assert_eq!(tree.hash_function(), HashFunction::Sha3_512);
+        assert!(tree.hashes.iter().all(|h| h.len() == 64));
+        assert_eq!(tree.root().len(), 64);
+        let leaves: Vec<Vec<u8>> = elements.iter().map(|e| HashFunction::Sha3_512.digest(&[e.as_bytes()])).collect();
+        let pair = |l: &[u8], r: &[u8]| HashFunction::Sha3_512.digest(&[l, r]);
+        let quarters: Vec<Vec<u8>> = leaves.chunks(2).map(|p| pair(&p[0], &p[1])).collect();
+        assert_eq!(tree.root().as_bytes(), pair(&pair(&quarters[0], &quarters[1]), &pair(&quarters[2], &quarters[3])));
+        for index in 0..8 {
+            let proof = tree.inclusion_proof(index).unwrap();
+            assert!(proof.proof.siblings.iter().all(|s| s.len() == 64));
+            assert!(proof.verify(&tree.root()));
+            assert!(tree.verify(&proof.proof.siblings, index));
+            assert_eq!(InclusionProof::from_bytes(&proof.to_bytes().unwrap()).unwrap(), proof);
+            assert_eq!(InclusionProof::from_json(&proof.to_json()).unwrap(), proof);
+        }
+        let typed: Vec<LeafHash> = leaves.iter().map(|l| LeafHash::try_from(l.as_slice()).unwrap()).collect();
+        assert_eq!(MerkleTree::from_leaves_with(&typed, HashFunction::Sha3_512).unwrap().root(), tree.root());
+        assert_eq!(MerkleTree::with_hash_function(&[], HashFunction::Sha3_512).root().as_bytes(), sha3::Sha3_512::digest(b"").as_slice());
+    }
+
+    #[test]
+    fn hashes_of_another_size_are_rejected() {
+        let elements = eight_elements();
+        let wide = MerkleTree::with_hash_function(&elements, HashFunction::Sha3_512);
+        let narrow = MerkleTree::new(&elements);
+        // a 32 byte proof against the 64 byte tree and the other way round
+        assert!(!wide.verify(&narrow.proof(3), 3));
+        assert!(!narrow.verify(&wide.proof(3), 3));
+        let mut proof = wide.inclusion_proof(3).unwrap();
+        assert_eq!(proof.proof.compute_root(&narrow.leaf_hash(3).unwrap()), Err(MerkleTreeError::InvalidHashLength { expected: 64, got: 32 }));
+        proof.proof.siblings = narrow.proof(3);
+        assert_eq!(proof.proof.compute_root(&proof.leaf), Err(MerkleTreeError::InvalidHashLength { expected: 64, got: 32 }));
+        let leaf = wide.leaf_hash(3).unwrap();
+        assert_eq!(compute_root_from_proof(&leaf, 3, 8, &wide.proof(3)), Err(MerkleTreeError::InvalidHashLength { expected: 32, got: 64 }));
+        assert!(!verify_proof(&wide.root(), &leaf, 3, 8, &wide.proof(3)));
+    }
+
+    fn eight_elements() -> Vec<String> {
+        ["hola", "moikka", "heippa", "ahoj", "privet", "bonjour", "konichiwa", "rytsas"].iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn aligned_subtree_matches_a_fresh_tree() {
+        let elements = eight_elements();
+        let tree = MerkleTree::new(&elements);
+        let mut subtree = tree.subtree(4..8).unwrap();
+        let mut fresh = MerkleTree::new(&elements[4..8]);
+        assert_eq!(subtree.root(), fresh.root());
+        assert_eq!(subtree.get_hashes(), fresh.get_hashes());
+        assert!(subtree.verify(&subtree.proof(1), 1));
+    }
+
+    #[test]
+    fn unaligned_subtree_matches_a_fresh_tree() {
+        let elements = eight_elements();
+        let tree = MerkleTree::new_retaining(&elements);
+        let subtree = tree.subtree(1..6).unwrap();
+        assert_eq!(subtree.root(), MerkleTree::new(&elements[1..6]).root());
+        assert_eq!(subtree.len(), 5);
+        assert_eq!(subtree.get_element(0), Some("moikka".as_bytes()));
+    }
+
+    #[test]
+    fn empty_or_out_of_range_subtrees_are_errors() {
+        let tree = MerkleTree::new(&eight_elements());
+        assert_eq!(tree.subtree(3..3).err(), Some(MerkleTreeError::Empty));
+        assert_eq!(tree.subtree(6..9).err(), Some(MerkleTreeError::RangeOutOfBounds { start: 6, end: 9, len: 8 }));
+    }
+
+    #[test]
+    fn split_then_merge_round_trips_to_the_original_root() {
+        let elements = eight_elements();
+        let tree = MerkleTree::new(&elements);
+        for index in 1..8 {
+            let (mut left, right) = tree.split_at(index).unwrap();
+            assert_eq!(left.root(), MerkleTree::new(&elements[..index]).root());
+            assert_eq!(right.root(), MerkleTree::new(&elements[index..]).root());
+            left.merge(right).unwrap();
+            assert_eq!(left.root(), tree.root());
+        }
+    }
+
+    #[test]
+    fn trees_of_other_hashing_or_arity_do_not_merge() {
+        let elements = eight_elements();
+        let mut tree = MerkleTree::new(&elements[..4]);
+        let root = tree.root();
+        let wide = MerkleTree::with_hash_function(&elements[4..], HashFunction::Sha3_512);
+        assert!(matches!(tree.merge(wide), Err(MerkleTreeError::Incompatible(_))));
+        assert_eq!(tree.merge(MerkleTree::with_scheme(&elements[4..], HashScheme::V1)).err().unwrap().to_string(),
+            "incompatible: cannot merge a 2-ary sha3-256 tree with the v1 scheme into a 2-ary sha3-256 tree with the legacy scheme");
+        assert!(matches!(tree.merge(MerkleTree::with_arity(&elements[4..], 3).unwrap()), Err(MerkleTreeError::Incompatible(_))));
+        assert_eq!((tree.root(), tree.len()), (root, 4));
+        assert_eq!(tree.to_bytes(), MerkleTree::new(&elements[..4]).to_bytes());
+        tree.merge(MerkleTree::new(&elements[4..])).unwrap();
+        assert_eq!(tree.root(), MerkleTree::new(&elements).root());
+    }
+
+    #[test]
+    fn proofs_of_the_halves_verify_against_their_roots() {
+        let tree = MerkleTree::new(&eight_elements());
+        let (left, right) = tree.split_at(3).unwrap();
+        for index in 0..3 {
+            assert!(left.verify(&left.proof(index), index));
+        }
+        for index in 0..5 {
+            assert!(right.verify(&right.proof(index), index));
+        }
+    }
+
+    #[test]
+    fn proofs_at_old_sizes_verify_against_the_old_roots() {
+        for arity in [2, 3] {
+            let mut tree = MerkleTree::with_arity(&[], arity).unwrap();
+            let mut snapshots = vec![];
+            for (size, element) in numbered_elements(37).iter().enumerate() {
+                if [0, 1, 2, 7, 8, 9, 16, 27].contains(&size) {
+                    snapshots.push((size, tree.root()));
+                }
+                tree.push(element);
+            }
+            snapshots.push((37, tree.root()));
+            for &(size, root) in &snapshots {
+                assert_eq!(tree.root_at_size(size), Ok(root));
+                for index in 0..size {
+                    let proof = tree.prove_at_size(index, size).unwrap();
+                    assert_eq!(proof.leaf_count, size);
+                    assert!(proof.verify(&root, &tree.leaf_hash(index).unwrap()));
+                }
+                assert_eq!(tree.prove_at_size(size, size), Err(MerkleTreeError::IndexOutOfBounds { index: size, len: size }));
+            }
+            assert_eq!(tree.prove_at_size(0, 38), Err(MerkleTreeError::RangeOutOfBounds { start: 0, end: 38, len: 37 }));
+            assert!(tree.root_at_size(38).is_err());
+        }
+        let sorted = MerkleTree::new_sorted(&numbered_elements(4));
+        assert!(matches!(sorted.prove_at_size(0, 2), Err(MerkleTreeError::Incompatible(_))));
+    }
+
+    #[test]
+    fn splitting_at_the_ends_yields_an_empty_tree() {
+        let tree = MerkleTree::new(&eight_elements());
+        let (left, right) = tree.split_at(0).unwrap();
+        assert!(left.is_empty());
+        assert_eq!(left.root(), EMPTY_ROOT);
+        assert_eq!(right.root(), tree.root());
+        let (mut left, right) = tree.split_at(8).unwrap();
+        assert!(right.is_empty());
+        left.merge(right).unwrap();
+        assert_eq!(left.root(), tree.root());
+        assert!(tree.split_at(9).is_err());
+    }
+
+    #[test]
+    fn empty_tree_has_the_empty_root_and_can_grow() {
+        let mut tree = MerkleTree::new(&[]);
+        assert_eq!(tree.root().as_bytes(), sha3::Sha3_256::digest(b"").as_slice());
+        assert_eq!(tree.leaves().len(), 0);
+        tree.add(&["hola".to_string(), "moikka".to_string()]);
+        assert_eq!(tree.root().as_bytes(), hex!("d703ed960de71d89e617a637f87813b9da95461f30d5d5030329b979ff931032"));
+    }
+
+    fn numbered_elements(count: usize) -> Vec<String> {
+        (0..count).map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn four_ary_root_over_sixteen_leaves_hashes_groups_of_four() {
+        let elements = numbered_elements(16);
+        let tree = MerkleTree::with_arity(&elements, 4).unwrap();
+        let leaves: Vec<Vec<u8>> = elements.iter().map(|e| hash(e.to_string())).collect();
+        let groups: Vec<Vec<u8>> = leaves.chunks(4).map(|g| hash_node(&g.iter().map(Vec::as_slice).collect::<Vec<_>>())).collect();
+        assert_eq!(tree.root().as_bytes(), hash_node(&groups.iter().map(Vec::as_slice).collect::<Vec<_>>()));
+        assert_eq!(tree.proof(6).len(), 6);
+        assert_eq!(tree.proof_levels(6).iter().map(|l| l.position).collect::<Vec<_>>(), vec![2, 1]);
+        for index in [0, 5, 6, 15] {
+            assert!(tree.verify(&tree.proof(index), index));
+        }
+    }
+
+    #[test]
+    fn four_ary_proofs_verify_with_a_ragged_last_group() {
+        // 13 leaves: the last group holds a single promoted leaf, the level above has 4 nodes
+        let tree = MerkleTree::with_arity(&numbered_elements(13), 4).unwrap();
+        assert_eq!(tree.proof(12).len(), 3);
+        for index in 0..13 {
+            assert!(tree.verify(&tree.proof(index), index));
+        }
+        let ragged = MerkleTree::with_arity(&numbered_elements(14), 4).unwrap();
+        assert_eq!(ragged.proof_levels(13)[0], ProofLevel { position: 1, siblings: vec![hash("12".to_string())] });
+        assert!(ragged.verify(&ragged.proof(13), 13));
+    }
+
+    #[test]
+    fn binary_is_the_default_arity() {
+        let elements = numbered_elements(7);
+        let tree = MerkleTree::with_arity(&elements, 2).unwrap();
+        assert_eq!(tree.arity(), DEFAULT_ARITY);
+        assert_eq!(tree.root(), MerkleTree::new(&elements).root());
+        assert_eq!(MerkleTree::with_arity(&elements, 1).err(), Some(MerkleTreeError::InvalidArity(1)));
+    }
+
+    #[test]
+    fn computed_root_from_valid_proofs_is_the_tree_root() {
+        for size in 1..=12 {
+            let tree = MerkleTree::new(&numbered_elements(size));
+            for index in 0..size {
+                let leaf = tree.leaf_hash(index).unwrap();
+                let root = compute_root_from_proof(&leaf, index, size, &tree.proof(index)).unwrap();
+                assert_eq!(root, tree.root());
+                assert!(verify_proof(&tree.root(), &leaf, index, size, &tree.proof(index)));
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_proofs_compute_another_root() {
+        let tree = MerkleTree::new(&numbered_elements(6));
+        let mut proof = tree.proof(4);
+        proof[0][0] ^= 1;
+        let leaf = tree.leaf_hash(4).unwrap();
+        let root = compute_root_from_proof(&leaf, 4, 6, &proof).unwrap();
+        assert_ne!(root, tree.root());
+        assert!(!verify_proof(&tree.root(), &leaf, 4, 6, &proof));
+        assert!(!tree.verify(&proof, 4));
+    }
+
+    #[test]
+    fn malformed_proofs_are_errors() {
+        let tree = MerkleTree::new(&numbered_elements(6));
+        let proof = tree.proof(1);
+        let leaf = tree.leaf_hash(1).unwrap();
+        assert_eq!(compute_root_from_proof(&leaf, 1, 6, &proof[1..]), Err(MerkleTreeError::InvalidProofLength { expected: 3, got: 2 }));
+        let longer = [proof.clone(), vec![tree[0].to_vec()]].concat();
+        assert_eq!(compute_root_from_proof(&leaf, 1, 6, &longer), Err(MerkleTreeError::InvalidProofLength { expected: 3, got: 4 }));
+        let mut short_sibling = proof.clone();
+        short_sibling[2].pop();
+        assert_eq!(compute_root_from_proof(&leaf, 1, 6, &short_sibling), Err(MerkleTreeError::InvalidHashLength { expected: 32, got: 31 }));
+        assert_eq!(compute_root_from_proof(&leaf, 6, 6, &proof), Err(MerkleTreeError::IndexOutOfBounds { index: 6, len: 6 }));
+    }
+
+    #[test]
+    fn adding_up_to_the_capacity_does_not_reallocate() {
+        let elements = numbered_elements(1024);
+        let mut tree = MerkleTree::with_capacity(1024);
+        assert!(tree.capacity() >= 1024);
+        let storage = (tree.hashes.as_ptr(), tree.hashes.capacity());
+        for batch in elements.chunks(100) {
+            tree.add(batch);
+        }
+        assert_eq!((tree.hashes.as_ptr(), tree.hashes.capacity()), storage);
+        assert_eq!(tree.root(), MerkleTree::new(&elements).root());
+
+        // exceeding the capacity just reallocates
+        tree.add(&["hola".to_string()]);
+        assert_eq!(tree.len(), 1025);
+        assert_eq!(tree.root(), MerkleTree::new(&[elements, vec!["hola".to_string()]].concat()).root());
+    }
+
+    #[test]
+    fn reserve_makes_room_for_additional_leaves() {
+        let elements = numbered_elements(77);
+        let mut tree = MerkleTree::new_retaining(&elements[..5]);
+        tree.reserve(72);
+        assert!(tree.capacity() >= 77);
+        let storage = tree.hashes.as_ptr();
+        tree.add(&elements[5..40]);
+        tree.add(&elements[40..]);
+        assert_eq!(tree.hashes.as_ptr(), storage);
+        assert_eq!(tree.root(), MerkleTree::new(&elements).root());
+    }
+
+    #[test]
+    fn proofs_where_selects_the_matching_leaves() {
+        let tree = MerkleTree::new_retaining(&numbered_elements(13));
+        assert!(tree.proofs_where(|_, _| false).is_empty());
+
+        let all = tree.proofs_where(|_, _| true);
+        assert_eq!(all.iter().map(|(i, _)| *i).collect::<Vec<_>>(), (0..13).collect::<Vec<_>>());
+        assert_eq!(all.iter().map(|(_, p)| p.clone()).collect::<Vec<_>>(), tree.proofs_for_all());
+
+        // retained elements are what the predicate sees
+        let scattered = tree.proofs_where(|_, element| element.ends_with(b"1") || element == b"6");
+        assert_eq!(scattered.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![1, 6, 11]);
+        for (index, proof) in all.iter().chain(&scattered) {
+            assert_eq!(proof.siblings, tree.proof(*index));
+            assert!(proof.verify(&tree.root(), &tree.leaf_hash(*index).unwrap()));
+        }
+    }
+
+    #[test]
+    fn proofs_where_sees_leaf_hashes_without_retained_elements() {
+        let tree = MerkleTree::with_arity(&numbered_elements(9), 3).unwrap();
+        let target = hash("4".to_string());
+        let matches = tree.proofs_where(|_, leaf| leaf == target.as_slice());
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].0, 4);
+        assert!(matches[0].1.verify(&tree.root(), &LeafHash::try_from(target.as_slice()).unwrap()));
+    }
+
+    #[test]
+    fn iter_leaves_yields_leaf_hashes_in_index_order() {
+        let tree = MerkleTree::new(&["hola".to_string(), "moikka".to_string()]);
+        let leaves: Vec<&[u8]> = tree.iter_leaves().collect();
+        assert_eq!(leaves, vec![hash("hola".to_string()).as_slice(), hash("moikka".to_string()).as_slice()]);
+    }
+
+    #[test]
+    fn consuming_the_tree_yields_only_the_leaf_hashes() {
+        let tree = MerkleTree::new(&["hola".to_string(), "moikka".to_string(), "heippa".to_string(), "ahoj".to_string()]);
+        let leaves = tree.leaves().to_vec();
+        let root = tree.root().to_vec();
+        let iter = tree.into_iter();
+        assert_eq!(iter.len(), 4);
+        let consumed: Vec<Vec<u8>> = iter.collect();
+        assert_eq!(consumed, leaves);
+        assert!(!consumed.contains(&root));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,178 @@
+use super::{MerkleTree, DEFAULT_ARITY};
+use crate::error::TreeBuildError;
+use crate::scheme::{ByteOrder, HashFunction, HashScheme, PairOrder, TreeHasher};
+use std::io::{self, Read};
+
+// Builds a tree one leaf at a time, leaves are hashed as they come in so elements read
+// from a stream never have to be in memory whole. Same tree as the constructors taking
+// all the elements with the same scheme and hash function
+#[derive(Debug, Clone, Default)]
+pub struct MerkleTreeBuilder {
+  hasher: TreeHasher,
+  leaves: Vec<Vec<u8>>
+}
+
+impl MerkleTreeBuilder {
+  #[must_use]
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  #[must_use]
+  pub fn with_scheme(mut self, scheme: HashScheme) -> Self {
+    self.hasher.scheme = scheme;
+    self
+  }
+
+  #[must_use]
+  pub fn with_hash_function(mut self, function: HashFunction) -> Self {
+    self.hasher.function = function;
+    self
+  }
+
+  // Order of the lengths framing V1 leaves and of the integers of `MerkleTree::to_bytes`
+  #[must_use]
+  pub fn with_byte_order(mut self, byte_order: ByteOrder) -> Self {
+    self.hasher.byte_order = byte_order;
+    self
+  }
+
+  // `PairOrder::Sorted` with Keccak-256 makes the trees on-chain verifiers expect
+  #[must_use]
+  pub fn with_pair_order(mut self, pair_order: PairOrder) -> Self {
+    self.hasher.pair_order = pair_order;
+    self
+  }
+
+  pub fn push(&mut self, element: impl AsRef<[u8]>) {
+    self.leaves.push(self.hasher.leaf(element.as_ref()));
+  }
+
+  // `push` of an element that may have failed to arrive, the error comes back with the
+  // index the element would have had and nothing is pushed
+  pub fn try_push<E>(&mut self, element: Result<impl AsRef<[u8]>, E>) -> Result<(), TreeBuildError<E>> {
+    let element = element.map_err(|error| TreeBuildError { index: self.len(), error })?;
+    self.push(element);
+    Ok(())
+  }
+
+  // Leaf over everything `reader` holds. V1 trees frame leaves with their length, they
+  // need `push_sized_leaf_from_reader`
+  pub fn push_leaf_from_reader(&mut self, reader: impl Read) -> io::Result<()> {
+    self.leaves.push(self.hasher.leaf_from_reader(reader, None)?);
+    Ok(())
+  }
+
+  // Leaf over the `len` bytes `reader` holds, an error if it holds another amount
+  pub fn push_sized_leaf_from_reader(&mut self, reader: impl Read, len: u64) -> io::Result<()> {
+    self.leaves.push(self.hasher.leaf_from_reader(reader, Some(len))?);
+    Ok(())
+  }
+
+  #[must_use]
+  pub fn len(&self) -> usize {
+    self.leaves.len()
+  }
+
+  #[must_use]
+  pub fn is_empty(&self) -> bool {
+    self.leaves.is_empty()
+  }
+
+  #[must_use]
+  pub fn build(self) -> MerkleTree {
+    let count = self.leaves.len();
+    let mut tree = MerkleTree::with_layout(MerkleTree::build_hashes(self.leaves, DEFAULT_ARITY, self.hasher), count, DEFAULT_ARITY);
+    tree.set_hasher(self.hasher);
+    tree
+  }
+}
+
+impl MerkleTree {
+  // `new` over elements that may fail to arrive, like lines read from a file. Each one is
+  // hashed as it comes, the first error stops the iterator and drops what was built
+  pub fn try_from_iter<E>(elements: impl IntoIterator<Item = Result<impl AsRef<[u8]>, E>>) -> Result<Self, TreeBuildError<E>> {
+    let mut builder = MerkleTreeBuilder::new();
+    for element in elements {
+      builder.try_push(element)?;
+    }
+    Ok(builder.build())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::TreeBuildError;
+    use crate::merkle_tree::*;
+    use crate::scheme::{HashFunction, HashScheme};
+    use std::cell::Cell;
+    use std::io::{self, BufRead, Cursor};
+
+    fn large(len: usize) -> String {
+        (0..len).map(|i| char::from(b'a' + (i % 26) as u8)).collect()
+    }
+
+    #[test]
+    fn streamed_elements_hash_like_the_whole_bytes() {
+        let element = large(3 * 1024 * 1024 + 17);
+        assert_eq!(hash_reader(Cursor::new(element.as_bytes())).unwrap().to_vec(), hash(element.clone()));
+        assert_eq!(hash_reader(io::empty()).unwrap().to_vec(), hash(String::new()));
+
+        let elements = vec!["small".to_string(), element, large(100)];
+        let mut builder = MerkleTreeBuilder::new();
+        builder.push(&elements[0]);
+        builder.push_leaf_from_reader(Cursor::new(elements[1].as_bytes())).unwrap();
+        builder.push_leaf_from_reader(elements[2].as_bytes()).unwrap();
+        assert_eq!(builder.len(), 3);
+        assert_eq!(builder.build().root(), MerkleTree::new(&elements).root());
+    }
+
+    #[test]
+    fn framed_leaves_need_their_length() {
+        let elements = vec![large(200_000), large(5)];
+        let mut builder = MerkleTreeBuilder::new().with_scheme(HashScheme::V1).with_hash_function(HashFunction::Sha256);
+        assert_eq!(builder.push_leaf_from_reader(elements[0].as_bytes()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(builder.push_sized_leaf_from_reader(elements[0].as_bytes(), 10).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(builder.is_empty());
+        for element in &elements {
+            builder.push_sized_leaf_from_reader(element.as_bytes(), element.len() as u64).unwrap();
+        }
+        let tree = builder.build();
+        let expected = MerkleTree::with_scheme(&elements, HashScheme::V1);
+        assert_eq!(tree.hash_function(), HashFunction::Sha256);
+        assert_ne!(tree.root(), expected.root());
+        let mut builder = MerkleTreeBuilder::new().with_scheme(HashScheme::V1);
+        builder.push(&elements[0]);
+        builder.push_sized_leaf_from_reader(elements[1].as_bytes(), 5).unwrap();
+        assert_eq!(builder.build().root(), expected.root());
+    }
+
+    #[test]
+    fn the_first_failed_element_stops_the_build() {
+        let pulled = Cell::new(0);
+        let elements = (0..10).map(|i| {
+            pulled.set(pulled.get() + 1);
+            if i == 5 { Err(io::Error::other("disk gone")) } else { Ok(format!("line {i}")) }
+        });
+        let error = MerkleTree::try_from_iter(elements).err().unwrap();
+        assert_eq!(error.index, 5);
+        assert_eq!(error.error.to_string(), "disk gone");
+        assert_eq!(error.to_string(), "element 5 could not be read: disk gone");
+        assert_eq!(pulled.get(), 6);
+
+        let mut builder = MerkleTreeBuilder::new();
+        builder.try_push(Ok::<_, ()>("first")).unwrap();
+        assert_eq!(builder.try_push(Err::<&str, _>('x')), Err(TreeBuildError { index: 1, error: 'x' }));
+        assert_eq!(builder.len(), 1);
+    }
+
+    #[test]
+    fn elements_that_all_arrive_build_the_tree_of_new() {
+        let text = (0..9).map(|i| format!("line {i}\n")).collect::<String>();
+        let mut tree = MerkleTree::try_from_iter(Cursor::new(text.as_bytes()).lines()).unwrap();
+        let elements: Vec<String> = (0..9).map(|i| format!("line {i}")).collect();
+        assert_eq!(tree.get_hashes(), MerkleTree::new(&elements).get_hashes());
+        assert_eq!(MerkleTree::try_from_iter(Vec::<Result<Vec<u8>, ()>>::new()).ok().map(|t| t.root()), Some(EMPTY_ROOT));
+    }
+}
@@ -0,0 +1,269 @@
+use super::{MerkleTree, TreeEvent};
+use crate::error::MerkleTreeError;
+use crate::hash::LeafHash;
+
+// Handle of an open checkpoint, see `MerkleTree::checkpoint`. Ids are never reused, a
+// handle stays invalid once its checkpoint is rolled back or committed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointId(usize);
+
+// What it takes to revert one mutation, only recorded while a checkpoint is open
+#[derive(Clone)]
+pub(super) enum Undo {
+  // number of leaves appended at the end
+  Append(usize),
+  Update { index: usize, leaf: Vec<u8>, element: Option<Vec<u8>> },
+  Remove { index: usize, leaf: Vec<u8>, element: Option<Vec<u8>>, meta: Option<u64> },
+  // leaf placed at `index` by a sorted tree
+  Insert { index: usize },
+  // elements retained until the tree stopped retaining them
+  Elements(Vec<Vec<u8>>),
+}
+
+impl MerkleTree {
+  // Starts recording the mutations so they can be undone with `rollback`.
+  // Checkpoints nest, the newest one is closed first
+  pub fn checkpoint(&mut self) -> CheckpointId {
+    let id = CheckpointId(self.next_checkpoint);
+    self.next_checkpoint += 1;
+    self.checkpoints.push((id, self.journal.len()));
+    id
+  }
+
+  // Reverts the tree to its state when `id` was taken, newer checkpoints are discarded.
+  // Observers see the leaf events that undo each mutation
+  pub fn rollback(&mut self, id: CheckpointId) -> Result<(), MerkleTreeError> {
+    let start = self.close(id)?;
+    let old_root = self.root();
+    let observed = self.observer.is_some();
+    let mut events = vec![];
+    let mut leaves = self.take_leaves();
+    for undo in self.journal.drain(start..).rev() {
+      if observed {
+        events.extend(undo_events(&undo, &leaves));
+      }
+      match undo {
+        Undo::Append(count) => {
+          leaves.truncate(leaves.len() - count);
+          if let Some(elements) = self.elements.as_mut() {
+            elements.truncate(leaves.len());
+          }
+          if let Some(slots) = self.meta.as_mut() {
+            slots.truncate(leaves.len());
+          }
+        },
+        Undo::Update { index, leaf, element } => {
+          leaves[index] = leaf;
+          if let (Some(elements), Some(element)) = (self.elements.as_mut(), element) {
+            elements[index] = element;
+          }
+        },
+        Undo::Remove { index, leaf, element, meta } => {
+          if meta.is_some() || self.meta.is_some() {
+            self.meta.get_or_insert_with(|| vec![None; leaves.len()]).insert(index, meta);
+          }
+          leaves.insert(index, leaf);
+          if let (Some(elements), Some(element)) = (self.elements.as_mut(), element) {
+            elements.insert(index, element);
+          }
+        },
+        Undo::Insert { index } => {
+          leaves.remove(index);
+          if let Some(elements) = self.elements.as_mut() {
+            elements.remove(index);
+          }
+          if let Some(slots) = self.meta.as_mut() {
+            slots.remove(index);
+          }
+        },
+        Undo::Elements(elements) => self.elements = Some(elements),
+      }
+    }
+    self.set_leaves(leaves);
+    self.notify(old_root, |_| events);
+    Ok(())
+  }
+
+  // Keeps the mutations since `id` and closes it along with newer checkpoints.
+  // The undo data is dropped unless an older checkpoint still needs it
+  pub fn commit(&mut self, id: CheckpointId) -> Result<(), MerkleTreeError> {
+    self.close(id)?;
+    if self.checkpoints.is_empty() {
+      self.journal.clear();
+    }
+    Ok(())
+  }
+
+  // Journal position where the checkpoint starts
+  fn close(&mut self, id: CheckpointId) -> Result<usize, MerkleTreeError> {
+    let position = self.checkpoints.iter().position(|&(open, _)| open == id).ok_or(MerkleTreeError::UnknownCheckpoint)?;
+    let (_, start) = self.checkpoints[position];
+    self.checkpoints.truncate(position);
+    Ok(start)
+  }
+
+  pub(super) fn record(&mut self, undo: Undo) {
+    if !self.checkpoints.is_empty() {
+      self.journal.push(undo);
+    }
+  }
+
+  // Stops retaining elements, a rollback brings them back
+  pub(super) fn drop_elements(&mut self) {
+    if let Some(elements) = self.elements.take() {
+      self.record(Undo::Elements(elements));
+    }
+  }
+}
+
+// What reverting `undo` on `leaves` does, as observer events
+fn undo_events(undo: &Undo, leaves: &[Vec<u8>]) -> Vec<TreeEvent> {
+  let typed = |leaf: &[u8]| LeafHash::try_from(leaf).expect("leaves have the output length of the hash function");
+  match undo {
+    Undo::Append(count) => (leaves.len() - count..leaves.len()).rev().map(|index| TreeEvent::LeafRemoved { index }).collect(),
+    Undo::Update { index, leaf, .. } => vec![TreeEvent::LeafUpdated { index: *index, old: typed(&leaves[*index]), new: typed(leaf) }],
+    Undo::Remove { index, leaf, .. } => vec![TreeEvent::LeafInserted { index: *index, hash: typed(leaf) }],
+    Undo::Insert { index } => vec![TreeEvent::LeafRemoved { index: *index }],
+    Undo::Elements(_) => vec![],
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::*;
+    use crate::error::MerkleTreeError;
+
+    fn elements(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("element {i}")).collect()
+    }
+
+    #[test]
+    fn rollback_restores_the_tree_before_the_checkpoint() {
+        let mut tree = MerkleTree::new_retaining(&elements(6));
+        let before = tree.clone();
+        let id = tree.checkpoint();
+        tree.update(2, "updated").unwrap();
+        tree.push("pushed");
+        tree.remove(0).unwrap();
+        tree.add(&elements(3));
+        tree.update(6, "updated again").unwrap();
+        assert_ne!(tree.root(), before.root());
+
+        tree.rollback(id).unwrap();
+        assert_eq!(tree.root(), before.root());
+        assert_eq!(tree.elements(), before.elements());
+        for index in [0, 3, 5] {
+            assert_eq!(tree.proof(index), before.proof(index));
+            assert!(tree.verify(&before.proof(index), index));
+        }
+    }
+
+    #[test]
+    fn rollback_retains_the_elements_dropped_by_raw_leaves() {
+        let leaf = |element: &str| MerkleTree::new(&[element.to_string()]).leaf_hash(0).unwrap();
+        let mut tree = MerkleTree::new_retaining(&elements(4));
+        let id = tree.checkpoint();
+        tree.add_leaves(&[leaf("raw")]).unwrap();
+        assert_eq!(tree.elements(), None);
+        tree.rollback(id).unwrap();
+        assert_eq!(tree.elements().map(|e| e.len()), Some(4));
+        assert_eq!(tree.elements(), MerkleTree::new_retaining(&elements(4)).elements());
+
+        let id = tree.checkpoint();
+        tree.update_leaf(1, leaf("raw")).unwrap();
+        tree.push("pushed");
+        tree.rollback(id).unwrap();
+        assert_eq!(tree.elements(), MerkleTree::new_retaining(&elements(4)).elements());
+
+        let id = tree.checkpoint();
+        tree.merge(MerkleTree::new(&elements(2))).unwrap();
+        tree.rollback(id).unwrap();
+        assert_eq!(tree.elements(), MerkleTree::new_retaining(&elements(4)).elements());
+
+        let id = tree.checkpoint();
+        tree.push("pushed");
+        tree.replace_leaf_hashes(&[leaf("raw")]).unwrap();
+        assert_eq!(tree.elements(), None);
+        tree.rollback(id).unwrap();
+        assert_eq!(tree.elements(), MerkleTree::new_retaining(&elements(4)).elements());
+        assert_eq!(tree.root(), MerkleTree::new(&elements(4)).root());
+    }
+
+    #[test]
+    fn rolling_back_an_outer_checkpoint_discards_the_newer_ones() {
+        let mut tree = MerkleTree::new(&elements(5));
+        let before = tree.clone();
+        let outer = tree.checkpoint();
+        tree.update(1, "outer").unwrap();
+        let middle = tree.clone();
+        let inner = tree.checkpoint();
+        tree.remove(3).unwrap();
+        tree.rollback(inner).unwrap();
+        assert_eq!(tree.root(), middle.root());
+
+        let inner = tree.checkpoint();
+        tree.push("inner");
+        tree.rollback(outer).unwrap();
+        assert_eq!(tree.root(), before.root());
+        assert_eq!(tree.rollback(inner), Err(MerkleTreeError::UnknownCheckpoint));
+    }
+
+    #[test]
+    fn closed_checkpoints_stay_unknown_after_new_ones_open() {
+        let mut tree = MerkleTree::new(&elements(3));
+        let (outer, inner) = (tree.checkpoint(), tree.checkpoint());
+        tree.rollback(outer).unwrap();
+        let newer = tree.checkpoint();
+        tree.push("q");
+        let newest = tree.checkpoint();
+        assert_eq!(tree.rollback(inner), Err(MerkleTreeError::UnknownCheckpoint));
+        assert_eq!(tree.commit(outer), Err(MerkleTreeError::UnknownCheckpoint));
+        assert_eq!(tree.len(), 4);
+
+        tree.commit(newest).unwrap();
+        assert_eq!(tree.rollback(newest), Err(MerkleTreeError::UnknownCheckpoint));
+        tree.rollback(newer).unwrap();
+        assert_eq!(tree.rollback(newer), Err(MerkleTreeError::UnknownCheckpoint));
+        assert_eq!(tree.root(), MerkleTree::new(&elements(3)).root());
+    }
+
+    #[test]
+    fn commit_keeps_the_changes_and_drops_the_undo_data() {
+        let mut tree = MerkleTree::new(&elements(4));
+        let outer = tree.checkpoint();
+        tree.push("outer");
+        let inner = tree.checkpoint();
+        tree.update(0, "inner").unwrap();
+        tree.commit(inner).unwrap();
+        let committed = tree.clone();
+        assert_eq!(tree.journal.len(), 2);
+
+        // the outer checkpoint can still undo the committed inner changes
+        tree.rollback(outer).unwrap();
+        assert_eq!(tree.root(), MerkleTree::new(&elements(4)).root());
+
+        let mut tree = committed;
+        let id = tree.checkpoint();
+        tree.push("more");
+        tree.commit(id).unwrap();
+        tree.commit(outer).unwrap();
+        assert!(tree.journal.is_empty());
+        assert_eq!(tree.len(), 6);
+    }
+
+    #[test]
+    fn mutations_match_a_tree_built_from_scratch() {
+        let mut tree = MerkleTree::new(&elements(7));
+        tree.update(6, "updated").unwrap();
+        tree.remove(1).unwrap();
+        tree.push("pushed");
+        let mut expected = elements(7);
+        expected[6] = "updated".to_string();
+        expected.remove(1);
+        expected.push("pushed".to_string());
+        assert_eq!(tree.root(), MerkleTree::new(&expected).root());
+        assert_eq!(tree.update(7, "missing"), Err(MerkleTreeError::IndexOutOfBounds { index: 7, len: 7 }));
+        assert!(tree.journal.is_empty());
+    }
+}
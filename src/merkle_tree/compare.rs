@@ -0,0 +1,178 @@
+use super::MerkleTree;
+use crate::codec;
+use crate::hash::{LeafHash, Root};
+use crate::json::Json;
+use std::ops::Range;
+
+// A leaf both trees have but with other hashes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafDifference {
+  pub index: usize,
+  pub left: LeafHash,
+  pub right: LeafHash
+}
+
+// How two trees differ, see `MerkleTree::compare`. `left` is the tree compared, `right`
+// the one it is compared with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComparisonReport {
+  pub left_root: Root,
+  pub right_root: Root,
+  pub left_leaf_count: usize,
+  pub right_leaf_count: usize,
+  // In index order, over the leaves both trees have
+  pub changed: Vec<LeafDifference>,
+  // Leaf indices past the end of the other tree, one of the two is always empty
+  pub only_in_left: Range<usize>,
+  pub only_in_right: Range<usize>,
+  // Nodes with other hashes at each position both trees have, level 0 being the leaves.
+  // Trees of different arities only compare their leaves
+  pub level_mismatches: Vec<usize>
+}
+
+impl ComparisonReport {
+  #[must_use]
+  pub fn is_equal(&self) -> bool {
+    self.left_root == self.right_root && self.left_leaf_count == self.right_leaf_count
+  }
+
+  // One JSON object with the fields in declaration order, ranges as {"start":…,"end":…}
+  #[must_use]
+  pub fn to_json(&self) -> String {
+    let range = |range: &Range<usize>| Json::object([("start", range.start.into()), ("end", range.end.into())]);
+    Json::object([
+      ("left_root", codec::bytes_to_json(self.left_root.as_bytes())),
+      ("right_root", codec::bytes_to_json(self.right_root.as_bytes())),
+      ("left_leaf_count", self.left_leaf_count.into()),
+      ("right_leaf_count", self.right_leaf_count.into()),
+      ("changed", Json::Array(self.changed.iter().map(|difference| Json::object([
+        ("index", difference.index.into()),
+        ("left", codec::bytes_to_json(difference.left.as_bytes())),
+        ("right", codec::bytes_to_json(difference.right.as_bytes())),
+      ])).collect())),
+      ("only_in_left", range(&self.only_in_left)),
+      ("only_in_right", range(&self.only_in_right)),
+      ("level_mismatches", Json::Array(self.level_mismatches.iter().map(|&count| count.into()).collect())),
+    ]).to_string()
+  }
+}
+
+impl MerkleTree {
+  // Compares the trees top down from the highest level both have, so subtrees with the
+  // same hash over the same leaves are skipped. `other` can come from anywhere, a tree
+  // loaded with `from_bytes` or `import_nodes` from cold storage for instance
+  #[must_use]
+  pub fn compare(&self, other: &MerkleTree) -> ComparisonReport {
+    let (left, right) = (self.layout(), other.layout());
+    let top = if self.arity == other.arity { left.height().min(right.height()) } else { 0 };
+    let size = |level| left.level_size(level).unwrap_or(0).min(right.level_size(level).unwrap_or(0));
+    let mut level_mismatches = vec![0; top + 1];
+    let mut changed = vec![];
+    let mut pending: Vec<usize> = (0..size(top)).collect();
+    for level in (0..=top).rev() {
+      let span = self.arity.checked_pow(level as u32).unwrap_or(usize::MAX);
+      let mut next = vec![];
+      for index in pending {
+        let a = left.node_index(level, index).map(|n| self.hashes[n].as_slice());
+        let b = right.node_index(level, index).map(|n| other.hashes[n].as_slice());
+        let end = (index + 1).saturating_mul(span);
+        if a == b && end.min(self.count) == end.min(other.count) {
+          continue;
+        }
+        if a != b {
+          level_mismatches[level] += 1;
+        }
+        if level == 0 {
+          let hash = |node: Option<&[u8]>| LeafHash::try_from(node.expect("both trees have the leaf")).expect("leaves are hashes");
+          changed.push(LeafDifference { index, left: hash(a), right: hash(b) });
+        } else {
+          next.extend(index * self.arity..((index + 1) * self.arity).min(size(level - 1)));
+        }
+      }
+      pending = next;
+    }
+    ComparisonReport {
+      left_root: self.root(),
+      right_root: other.root(),
+      left_leaf_count: self.count,
+      right_leaf_count: other.count,
+      changed,
+      only_in_left: other.count.min(self.count)..self.count,
+      only_in_right: self.count.min(other.count)..other.count,
+      level_mismatches
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::{LeafDifference, MerkleTree};
+    use crate::scheme::TreeConfig;
+
+    fn elements(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("leaf {i}")).collect()
+    }
+
+    #[test]
+    fn equal_trees_and_a_single_change() {
+        let tree = MerkleTree::new(&elements(8));
+        let report = tree.compare(&tree.clone());
+        assert!(report.is_equal());
+        assert!(report.changed.is_empty() && report.only_in_left.is_empty() && report.only_in_right.is_empty());
+        assert_eq!(report.level_mismatches, [0, 0, 0, 0]);
+
+        let mut changed = tree.clone();
+        changed.update(5, "other").unwrap();
+        let report = tree.compare(&changed);
+        assert!(!report.is_equal());
+        assert_eq!(report.changed, [LeafDifference { index: 5, left: tree.leaf_hash(5).unwrap(), right: changed.leaf_hash(5).unwrap() }]);
+        // one node per level on the path of the leaf
+        assert_eq!(report.level_mismatches, [1, 1, 1, 1]);
+        assert_eq!(report.to_json(), format!(
+            r#"{{"left_root":"{}","right_root":"{}","left_leaf_count":8,"right_leaf_count":8,"changed":[{{"index":5,"left":"{}","right":"{}"}}],"only_in_left":{{"start":8,"end":8}},"only_in_right":{{"start":8,"end":8}},"level_mismatches":[1,1,1,1]}}"#,
+            tree.root(), changed.root(), tree.leaf_hash(5).unwrap(), changed.leaf_hash(5).unwrap()));
+    }
+
+    #[test]
+    fn many_changes_against_a_restored_snapshot() {
+        let tree = MerkleTree::new(&elements(13));
+        let mut nodes = vec![];
+        tree.export_nodes(|level, index, hash| {
+            nodes.push((level, index, hash.to_vec()));
+            Ok::<_, ()>(())
+        }).unwrap();
+        let snapshot = MerkleTree::import_nodes(TreeConfig::default(), nodes).unwrap();
+        let mut current = tree.clone();
+        for index in [0, 1, 6, 12] {
+            current.update(index, &format!("changed {index}")).unwrap();
+        }
+        let report = current.compare(&snapshot);
+        assert_eq!(report.changed.iter().map(|d| d.index).collect::<Vec<_>>(), [0, 1, 6, 12]);
+        assert!(report.changed.iter().all(|d| d.left == current.leaf_hash(d.index).unwrap() && d.right == tree.leaf_hash(d.index).unwrap()));
+        assert_eq!(report.level_mismatches, [4, 3, 3, 2, 1]);
+        assert_eq!(MerkleTree::from_bytes(&tree.to_bytes()).unwrap().compare(&current).changed.len(), 4);
+    }
+
+    #[test]
+    fn trees_of_different_lengths() {
+        let short = MerkleTree::new(&elements(5));
+        let mut long = MerkleTree::new(&elements(11));
+        let report = short.compare(&long);
+        assert!(report.changed.is_empty());
+        assert_eq!((report.only_in_left, report.only_in_right), (5..5, 5..11));
+        // the subtree of the first four leaves is shared, the promoted fifth leaf is not
+        assert_eq!(report.level_mismatches, [0, 1, 1, 1]);
+
+        long.update(2, "other").unwrap();
+        let report = long.compare(&short);
+        assert_eq!(report.changed.iter().map(|d| d.index).collect::<Vec<_>>(), [2]);
+        assert_eq!((report.only_in_left.clone(), report.only_in_right.clone()), (5..11, 5..5));
+        assert!(report.to_json().contains(r#""only_in_left":{"start":5,"end":11},"only_in_right":{"start":5,"end":5}"#));
+
+        let wide = MerkleTree::with_arity(&elements(5), 4).unwrap();
+        let report = short.compare(&wide);
+        assert!(report.changed.is_empty() && report.level_mismatches == [0]);
+        assert!(!report.is_equal());
+    }
+}
@@ -0,0 +1,536 @@
+use super::{MerkleTree, DEFAULT_ARITY};
+use crate::codec::{self, Reader};
+use crate::error::MerkleTreeError;
+use crate::json::Json;
+use crate::scheme::{ByteOrder, HashFunction, HashScheme, PairOrder, TreeHasher};
+use crate::tree_math::Layout;
+
+// First byte of the binary encoding, bumped whenever the layout changes
+const FORMAT_VERSION: u8 = 1;
+
+// Tags of the optional sections after the elements, in the order they are written
+const META_SECTION: u8 = 2;
+pub(super) const INDEX_SECTION: u8 = 1;
+
+// Nodes `to_json_tree` writes and `from_json_tree` reads at most. Nested objects take
+// many times the bytes of the leaves alone
+pub const MAX_JSON_TREE_NODES: usize = 1 << 20;
+
+// Only the leaves and the configuration are written, the upper levels are rebuilt on
+// load. Checkpoints are not part of the encoding
+impl MerkleTree {
+  // `{"arity":…,"scheme":…,"hash":…,"sorted":…,"leaves":["0x…",…]}` plus "elements" when they
+  // are retained, as hex strings since they may hold any bytes, "meta" when leaves carry
+  // metadata, as decimal strings or null since JSON numbers do not hold every u64,
+  // "byte_order" for big endian trees and "pair_order" for trees hashing pairs sorted
+  #[must_use]
+  pub fn to_json(&self) -> String {
+    let mut fields = vec![
+      ("arity", self.arity.into()),
+      ("scheme", self.scheme.name().into()),
+      ("hash", self.function.name().into()),
+      ("sorted", self.sorted.into()),
+      ("leaves", Json::Array(self.leaves().iter().map(|l| codec::bytes_to_json(l)).collect())),
+    ];
+    if let Some(elements) = &self.elements {
+      fields.push(("elements", Json::Array(elements.iter().map(|e| codec::bytes_to_json(e)).collect())));
+    }
+    if let Some(meta) = &self.meta {
+      fields.push(("meta", Json::Array(meta.iter().map(|m| m.map_or(Json::Null, |m| m.to_string().into())).collect())));
+    }
+    if self.byte_order != ByteOrder::default() {
+      fields.push(("byte_order", self.byte_order.name().into()));
+    }
+    if self.pair_order != PairOrder::default() {
+      fields.push(("pair_order", self.pair_order.name().into()));
+    }
+    Json::object(fields).to_string()
+  }
+
+  // Hashes and elements may be hex strings or arrays of bytes, missing configuration
+  // fields take the defaults of `new`
+  pub fn from_json(text: &str) -> Result<Self, MerkleTreeError> {
+    let json = Json::parse(text).map_err(codec::invalid)?;
+    let (arity, hasher) = config_from_json(&json, "hash")?;
+    let sorted = match json.get("sorted") {
+      Some(sorted) => sorted.as_bool().ok_or_else(|| codec::invalid("sorted must be a boolean"))?,
+      None => false
+    };
+    let leaves = codec::list_from_json("leaves", json.get("leaves"))?;
+    let elements = json.get("elements").map(|e| codec::list_from_json("elements", Some(e))).transpose()?;
+    let meta = json.get("meta").map(meta_from_json).transpose()?;
+    let mut tree = Self::from_parts(leaves, elements, arity, hasher, sorted)?;
+    tree.set_meta(meta)?;
+    Ok(tree)
+  }
+
+  // Every node from the root down as `{"hash":"0x…","children":[…]}`, for tools drawing
+  // the tree. Leaves have their "index" instead of children, promoted nodes their single
+  // child. The root also has "arity", "scheme", "hash_function", "byte_order" and
+  // "pair_order" for trees that are not binary little endian SHA3-256 with the legacy
+  // scheme and pairs in order
+  pub fn to_json_tree(&self) -> Result<String, MerkleTreeError> {
+    let layout = self.layout();
+    if layout.node_count() > MAX_JSON_TREE_NODES {
+      return Err(MerkleTreeError::TooManyNodes { limit: MAX_JSON_TREE_NODES });
+    }
+    let mut root = match self.count {
+      0 => Json::object([("hash", codec::bytes_to_json(self.root().as_bytes())), ("children", Json::Array(vec![]))]),
+      _ => self.json_node(&layout, 0)
+    };
+    if let Json::Object(fields) = &mut root {
+      if self.arity != DEFAULT_ARITY {
+        fields.push(("arity".to_string(), self.arity.into()));
+      }
+      if self.scheme != HashScheme::Legacy {
+        fields.push(("scheme".to_string(), self.scheme.name().into()));
+      }
+      if self.function != HashFunction::default() {
+        fields.push(("hash_function".to_string(), self.function.name().into()));
+      }
+      if self.byte_order != ByteOrder::default() {
+        fields.push(("byte_order".to_string(), self.byte_order.name().into()));
+      }
+      if self.pair_order != PairOrder::default() {
+        fields.push(("pair_order".to_string(), self.pair_order.name().into()));
+      }
+    }
+    Ok(root.to_string())
+  }
+
+  // Reads `to_json_tree` back. The tree is rebuilt from the leaves and every node of the
+  // document has to be the node of the rebuilt tree at its place
+  pub fn from_json_tree(text: &str) -> Result<Self, MerkleTreeError> {
+    let json = Json::parse(text).map_err(codec::invalid)?;
+    let (arity, hasher) = config_from_json(&json, "hash_function")?;
+    let mut leaves = vec![];
+    json_tree_leaves(&json, &mut leaves, &mut 0)?;
+    let tree = Self::from_parts(leaves, None, arity, hasher, false)?;
+    let hash = codec::bytes_from_json("hash", json.get("hash"))?;
+    if tree.is_empty() {
+      if hash != tree.root().as_bytes() {
+        return Err(codec::invalid("the root of an empty tree does not match"));
+      }
+      return Ok(tree);
+    }
+    tree.check_json_node(&tree.layout(), 0, &json)?;
+    Ok(tree)
+  }
+
+  fn json_node(&self, layout: &Layout, node: usize) -> Json {
+    let hash = ("hash", codec::bytes_to_json(&self.hashes[node]));
+    match layout.children(node) {
+      Some(children) => Json::object([hash, ("children", Json::Array(children.map(|child| self.json_node(layout, child)).collect()))]),
+      None => Json::object([hash, ("index", (node - self.leaf_offset()).into())]),
+    }
+  }
+
+  fn check_json_node(&self, layout: &Layout, node: usize, json: &Json) -> Result<(), MerkleTreeError> {
+    if codec::bytes_from_json("hash", json.get("hash"))? != self.hashes[node] {
+      return Err(codec::invalid("a node hash does not match its children"));
+    }
+    let Some(children) = layout.children(node) else {
+      return Ok(());
+    };
+    let listed = json.get("children").and_then(Json::as_array).unwrap_or_default();
+    if listed.len() != children.len() {
+      return Err(codec::invalid(format!("a node has {} children, expected {}", listed.len(), children.len())));
+    }
+    children.zip(listed).try_for_each(|(child, json)| self.check_json_node(layout, child, json))
+  }
+
+  // Format version, arity as u64, scheme, byte order and hash function id, sorted flag, the
+  // leaves as their hash length, count and raw bytes, then a flag for retained elements
+  // followed by their count and each element as its u64 length and bytes. Leaves with
+  // metadata add a section of its tag, the leaf count and a flag per leaf, followed by the
+  // u64 when it is set. Integers are in the byte order of the tree. The leaf index is left
+  // out, see `to_bytes_with_index`
+  #[must_use]
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let element_bytes = self.elements.iter().flatten().map(|e| 8 + e.len()).sum::<usize>();
+    let mut out = Vec::with_capacity(29 + self.count * self.function.output_len() + element_bytes);
+    let order = self.byte_order;
+    out.push(FORMAT_VERSION);
+    codec::put_u64(&mut out, self.arity, order);
+    out.push(self.hasher().id());
+    out.push(u8::from(self.sorted));
+    codec::put_hashes(&mut out, self.leaves(), order).expect("leaves all have the same length");
+    out.push(u8::from(self.elements.is_some()));
+    if let Some(elements) = &self.elements {
+      codec::put_u64(&mut out, elements.len(), order);
+      for element in elements {
+        codec::put_u64(&mut out, element.len(), order);
+        out.extend_from_slice(element);
+      }
+    }
+    if let Some(meta) = &self.meta {
+      out.push(META_SECTION);
+      codec::put_u64(&mut out, meta.len(), order);
+      for value in meta {
+        out.push(u8::from(value.is_some()));
+        if let Some(value) = value {
+          out.extend_from_slice(&order.u64_bytes(*value));
+        }
+      }
+    }
+    out
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleTreeError> {
+    let mut reader = Reader::new(bytes);
+    let version = reader.u8()?;
+    if version != FORMAT_VERSION {
+      return Err(codec::invalid(format!("unsupported format version {version}")));
+    }
+    // the id comes after the arity it gives the order of
+    let hasher = TreeHasher::from_id(reader.peek(8)?).ok_or_else(|| codec::invalid("unknown scheme id"))?;
+    reader.order = hasher.byte_order;
+    let arity = reader.usize()?;
+    reader.u8()?;
+    let sorted = flag(&mut reader)?;
+    let leaves = reader.hashes_checked(|len, count| match count {
+      0 => Ok(()),
+      _ if len == hasher.output_len() => Ok(()),
+      _ => Err(MerkleTreeError::InvalidHashLength { expected: hasher.output_len(), got: len }),
+    })?;
+    let elements = if flag(&mut reader)? {
+      let count = reader.usize()?;
+      let mut elements = Vec::with_capacity(count.min(leaves.len()));
+      for _ in 0..count {
+        let len = reader.usize()?;
+        elements.push(reader.take(len)?.to_vec());
+      }
+      Some(elements)
+    } else {
+      None
+    };
+    let meta = if reader.peek(0).ok() == Some(META_SECTION) {
+      reader.u8()?;
+      let count = reader.usize()?;
+      if count != leaves.len() {
+        return Err(codec::invalid("metadata and leaves differ in length"));
+      }
+      let mut meta = Vec::with_capacity(count);
+      for _ in 0..count {
+        meta.push(flag(&mut reader)?.then(|| reader.u64()).transpose()?);
+      }
+      Some(meta)
+    } else {
+      None
+    };
+    // only `to_bytes_with_index` writes anything after the metadata
+    let index = if reader.is_empty() {
+      None
+    } else {
+      if reader.u8()? != INDEX_SECTION {
+        return Err(codec::invalid("expected a leaf index"));
+      }
+      let count = reader.usize()?;
+      if count > leaves.len() {
+        return Err(codec::invalid("the index lists more leaves than the tree has"));
+      }
+      Some((0..count).map(|_| reader.usize()).collect::<Result<Vec<_>, _>>()?)
+    };
+    reader.finish()?;
+    let mut tree = Self::from_parts(leaves, elements, arity, hasher, sorted)?;
+    tree.set_meta(meta)?;
+    if let Some(firsts) = index {
+      tree.restore_index(&firsts)?;
+    }
+    Ok(tree)
+  }
+
+  fn from_parts(leaves: Vec<Vec<u8>>, elements: Option<Vec<Vec<u8>>>, arity: usize, hasher: TreeHasher, sorted: bool) -> Result<Self, MerkleTreeError> {
+    if arity < 2 {
+      return Err(MerkleTreeError::InvalidArity(arity));
+    }
+    if let Some(leaf) = leaves.iter().find(|l| l.len() != hasher.output_len()) {
+      return Err(MerkleTreeError::InvalidHashLength { expected: hasher.output_len(), got: leaf.len() });
+    }
+    if elements.as_ref().is_some_and(|e| e.len() != leaves.len()) {
+      return Err(codec::invalid("elements and leaves differ in length"));
+    }
+    if sorted && !leaves.is_sorted() {
+      return Err(codec::invalid("leaves of a sorted tree are out of order"));
+    }
+    let count = leaves.len();
+    let hashes = Self::build_hashes(leaves, arity, hasher);
+    let mut tree = Self::with_layout(hashes, count, arity);
+    tree.set_hasher(hasher);
+    tree.sorted = sorted;
+    tree.elements = elements;
+    Ok(tree)
+  }
+
+  fn set_meta(&mut self, meta: Option<Vec<Option<u64>>>) -> Result<(), MerkleTreeError> {
+    if meta.as_ref().is_some_and(|m| m.len() != self.count) {
+      return Err(codec::invalid("metadata and leaves differ in length"));
+    }
+    self.meta = meta;
+    Ok(())
+  }
+}
+
+// Arity and hasher of a JSON document, the defaults of `new` for missing fields
+pub(super) fn config_from_json(json: &Json, function_field: &str) -> Result<(usize, TreeHasher), MerkleTreeError> {
+  let arity = match json.get("arity") {
+    Some(arity) => codec::usize_from_json("arity", Some(arity))?,
+    None => DEFAULT_ARITY
+  };
+  let scheme = match json.get("scheme") {
+    Some(name) => name.as_str().and_then(HashScheme::from_name)
+      .ok_or_else(|| codec::invalid("scheme must be \"legacy\" or \"v1\""))?,
+    None => HashScheme::Legacy
+  };
+  let function = match json.get(function_field) {
+    Some(name) => name.as_str().and_then(HashFunction::from_name)
+      .ok_or_else(|| codec::invalid("unknown hash function"))?,
+    None => HashFunction::default()
+  };
+  codec::check_padding(json.get("padding"))?;
+  let hasher = TreeHasher::new(scheme, function)
+    .with_byte_order(codec::byte_order_from_json(json.get("byte_order"))?)
+    .with_pair_order(codec::pair_order_from_json(json.get("pair_order"))?);
+  Ok((arity, hasher))
+}
+
+// Leaves of a `to_json_tree` document in order, counting the nodes on the way
+fn json_tree_leaves(json: &Json, leaves: &mut Vec<Vec<u8>>, nodes: &mut usize) -> Result<(), MerkleTreeError> {
+  *nodes += 1;
+  if *nodes > MAX_JSON_TREE_NODES {
+    return Err(MerkleTreeError::TooManyNodes { limit: MAX_JSON_TREE_NODES });
+  }
+  match (json.get("index"), json.get("children").map(Json::as_array)) {
+    (Some(index), None) => {
+      if codec::usize_from_json("index", Some(index))? != leaves.len() {
+        return Err(codec::invalid("leaves are out of order"));
+      }
+      leaves.push(codec::bytes_from_json("hash", json.get("hash"))?);
+      Ok(())
+    },
+    (None, Some(Some(children))) => children.iter().try_for_each(|child| json_tree_leaves(child, leaves, nodes)),
+    _ => Err(codec::invalid("a node needs either an index or a list of children")),
+  }
+}
+
+fn meta_from_json(json: &Json) -> Result<Vec<Option<u64>>, MerkleTreeError> {
+  let values = json.as_array().ok_or_else(|| codec::invalid("meta must be an array"))?;
+  values.iter().map(|value| match value {
+    Json::Null => Ok(None),
+    value => value.as_str().and_then(|s| s.parse().ok()).map(Some)
+      .ok_or_else(|| codec::invalid("meta values must be decimal strings or null"))
+  }).collect()
+}
+
+fn flag(reader: &mut Reader) -> Result<bool, MerkleTreeError> {
+  match reader.u8()? {
+    0 => Ok(false),
+    1 => Ok(true),
+    other => Err(codec::invalid(format!("expected a flag, got {other}")))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::MerkleTree;
+    use crate::error::MerkleTreeError;
+    use crate::hash::LeafHash;
+    use crate::merkle_tree::{hash_node, MerkleTreeBuilder, MAX_JSON_TREE_NODES};
+    use crate::proof::{InclusionProof, MerkleProof};
+    use crate::scheme::{ByteOrder, HashFunction, HashScheme, PairOrder, TreeConfig};
+    use crate::hex;
+    use hex_literal::hex;
+
+    fn elements() -> Vec<String> {
+        (0..5).map(|i| format!("element {i}")).collect()
+    }
+
+    #[test]
+    fn json_round_trip_writes_hashes_as_hex_strings() {
+        let tree = MerkleTree::new_retaining(&elements());
+        let json = tree.to_json();
+        assert!(json.contains(r#""leaves":["0x"#));
+        let loaded = MerkleTree::from_json(&json).unwrap();
+        assert_eq!(loaded.root(), tree.root());
+        assert_eq!(loaded.elements(), tree.elements());
+
+        let proof = tree.inclusion_proof(3).unwrap();
+        let proof_json = proof.proof.to_json();
+        assert!(proof_json.starts_with(r#"{"index":3,"leaf_count":5,"siblings":["0x"#));
+        assert_eq!(MerkleProof::from_json(&proof_json).unwrap(), proof.proof);
+    }
+
+    #[test]
+    fn binary_round_trip_stays_compact() {
+        for tree in [MerkleTree::new_retaining(&elements()), MerkleTree::with_scheme(&elements(), HashScheme::V1), MerkleTree::new(&[])] {
+            let bytes = tree.to_bytes();
+            let loaded = MerkleTree::from_bytes(&bytes).unwrap();
+            assert_eq!(loaded.root(), tree.root());
+            assert_eq!(loaded.scheme(), tree.scheme());
+            assert!(bytes.len() < tree.to_json().len());
+        }
+        // header, leaf hash length and count, raw leaves, elements flag
+        assert_eq!(MerkleTree::new(&elements()).to_bytes().len(), 11 + 9 + 5 * 32 + 1);
+        let proof = MerkleTree::new(&elements()).inclusion_proof(3).unwrap();
+        let bytes = proof.to_bytes().unwrap();
+        // leaf, three integers, the scheme and three siblings
+        assert_eq!(bytes.len(), 9 + 32 + 3 * 8 + 1 + 9 + 3 * 32);
+        assert_eq!(InclusionProof::from_bytes(&bytes).unwrap(), proof);
+        assert!(InclusionProof::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(MerkleProof::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+    }
+
+    #[test]
+    fn hash_frames_of_zero_byte_hashes_are_rejected_before_allocating() {
+        // header, 2^36 leaves of no bytes, retained elements of count 0
+        let mut bytes = MerkleTree::new(&[]).to_bytes()[..11].to_vec();
+        bytes.push(0);
+        bytes.extend_from_slice(&(1u64 << 36).to_le_bytes());
+        bytes.push(1);
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        assert_eq!(bytes.len(), 29);
+        assert_eq!(MerkleTree::from_bytes(&bytes).err(), Some(MerkleTreeError::InvalidHashLength { expected: 32, got: 0 }));
+
+        // the frame reader refuses them whatever reads it
+        let mut proof = MerkleTree::new(&elements()).inclusion_proof(3).unwrap().proof.to_bytes().unwrap()[..3 * 8 + 1].to_vec();
+        proof.push(0);
+        proof.extend_from_slice(&2u64.to_le_bytes());
+        assert!(matches!(MerkleProof::from_bytes(&proof), Err(MerkleTreeError::InvalidEncoding(_))));
+    }
+
+    #[test]
+    fn base64_proofs_round_trip_and_need_no_escaping() {
+        let tree = MerkleTree::with_hash_function(&elements(), HashFunction::Keccak256);
+        for index in 0..5 {
+            let proof = tree.inclusion_proof(index).unwrap();
+            let text = proof.to_base64().unwrap();
+            assert_eq!(InclusionProof::from_base64(&text).unwrap(), proof);
+            assert_eq!(MerkleProof::from_base64(&proof.proof.to_base64().unwrap()).unwrap(), proof.proof);
+            assert!(text.len() < hex::encode(&proof.to_bytes().unwrap()).len());
+
+            // percent-encoding leaves the unreserved characters of RFC 3986 alone
+            let percent_encoded: String = text.bytes().map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => char::from(b).to_string(),
+                _ => format!("%{b:02X}"),
+            }).collect();
+            assert_eq!(percent_encoded, text);
+            let query = format!("https://example.com/verify?proof={percent_encoded}&root={}", tree.root());
+            assert_eq!(InclusionProof::from_base64(query.split_once("proof=").unwrap().1.split('&').next().unwrap()).unwrap(), proof);
+
+            let padded = format!("{text}{}", "=".repeat((4 - text.len() % 4) % 4));
+            assert_eq!(InclusionProof::from_base64(&padded).unwrap(), proof);
+        }
+        let text = tree.inclusion_proof(0).unwrap().to_base64().unwrap();
+        for bad in [format!("{text}AA"), format!("{text}!"), text.replace(&text[..1], "+"), text[..text.len() - 1].to_string()] {
+            assert!(InclusionProof::from_base64(&bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn encodings_follow_the_byte_order() {
+        let proof = |byte_order| MerkleProof { index: 1, leaf_count: 3, siblings: vec![vec![0xaa; 32]], arity: 2, scheme: HashScheme::Legacy, function: HashFunction::Sha3_256, byte_order, pair_order: PairOrder::InOrder };
+        let little = proof(ByteOrder::LittleEndian).to_bytes().unwrap();
+        let big = proof(ByteOrder::BigEndian).to_bytes().unwrap();
+        assert_eq!(little[..34], hex!("0100000000000000 0300000000000000 0200000000000000 00 20 0100000000000000"));
+        assert_eq!(big[..34], hex!("0000000000000001 0000000000000003 0000000000000002 08 20 0000000000000001"));
+        assert_eq!(little[34..], big[34..]);
+        assert_eq!(MerkleProof::from_bytes(&big).unwrap(), proof(ByteOrder::BigEndian));
+        assert_eq!(MerkleProof::from_bytes(&little).unwrap(), proof(ByteOrder::LittleEndian));
+        assert_ne!(proof(ByteOrder::BigEndian), proof(ByteOrder::LittleEndian));
+        // read as little endian the big endian integers run far past the input
+        let mut relabeled = big.clone();
+        relabeled[24] = 0;
+        assert!(MerkleProof::from_bytes(&relabeled).is_err());
+
+        let mut builder = MerkleTreeBuilder::new().with_scheme(HashScheme::V1).with_byte_order(ByteOrder::BigEndian);
+        elements().iter().for_each(|e| builder.push(e));
+        let tree = builder.build();
+        assert_eq!(tree.config(), TreeConfig { scheme: HashScheme::V1, byte_order: ByteOrder::BigEndian, ..TreeConfig::default() });
+        let bytes = tree.to_bytes();
+        assert_eq!(bytes[..10], hex!("01 0000000000000002 09"));
+        for loaded in [MerkleTree::from_bytes(&bytes).unwrap(), MerkleTree::from_json(&tree.to_json()).unwrap(), MerkleTree::from_json_tree(&tree.to_json_tree().unwrap()).unwrap()] {
+            assert_eq!((loaded.root(), loaded.config()), (tree.root(), tree.config()));
+        }
+        assert!(tree.to_json().contains(r#""byte_order":"big-endian""#));
+        assert!(MerkleTree::from_json(&tree.to_json().replace("big-endian", "middle-endian")).is_err());
+
+        let proof = tree.inclusion_proof(3).unwrap();
+        assert_eq!(proof.proof.byte_order, ByteOrder::BigEndian);
+        assert_eq!(InclusionProof::from_bytes(&proof.to_bytes().unwrap()).unwrap(), proof);
+        assert_eq!(InclusionProof::from_json(&proof.to_json()).unwrap(), proof);
+        assert_eq!(proof.to_bytes().unwrap().len(), proof.serialized_size());
+
+        // a verifier expecting little endian proofs is told, not handed a wrong root
+        let little_endian = TreeConfig { byte_order: ByteOrder::LittleEndian, ..tree.config() };
+        assert!(proof.verify(&tree.root()));
+        assert_eq!(proof.verify_as(&little_endian, &tree.root()), Err(MerkleTreeError::ConfigMismatch { expected: little_endian, got: tree.config() }));
+        assert_eq!(proof.verify_as(&tree.config(), &tree.root()), Ok(true));
+        assert!(tree.config().to_string().ends_with("with the v1 scheme and big-endian integers"));
+    }
+
+    #[test]
+    fn json_trees_round_trip() {
+        let numbered = |count: usize| (0..count).map(|i| i.to_string()).collect::<Vec<_>>();
+        for count in [0, 1, 2, 5, 8] {
+            for tree in [MerkleTree::new(&numbered(count)), MerkleTree::with_arity(&numbered(count), 3).unwrap(), MerkleTree::with_hash_function(&numbered(count), HashFunction::Keccak256)] {
+                let json = tree.to_json_tree().unwrap();
+                let loaded = MerkleTree::from_json_tree(&json).unwrap();
+                assert_eq!((loaded.root(), loaded.len(), loaded.arity()), (tree.root(), count, tree.arity()));
+                assert_eq!(loaded.hash_function(), tree.hash_function());
+            }
+        }
+        let tree = MerkleTree::new(&numbered(3));
+        let (root, leaves) = (tree.root(), tree.leaves());
+        let hex = |bytes: &[u8]| crate::hex::encode(bytes);
+        let node = tree.node(1).unwrap();
+        // the third leaf is promoted, its parent has it as its only child
+        assert_eq!(tree.to_json_tree().unwrap(), format!(
+            r#"{{"hash":"{}","children":[{{"hash":"{}","children":[{{"hash":"{}","index":0}},{{"hash":"{}","index":1}}]}},{{"hash":"{}","children":[{{"hash":"{}","index":2}}]}}]}}"#,
+            root, hex(node), hex(&leaves[0]), hex(&leaves[1]), hex(&leaves[2]), hex(&leaves[2])
+        ));
+        assert!(MerkleTree::new_sorted(&numbered(3)).to_json_tree().unwrap().starts_with(r#"{"hash""#));
+    }
+
+    #[test]
+    fn malformed_json_trees_are_rejected() {
+        let tree = MerkleTree::new(&(0..4).map(|i| i.to_string()).collect::<Vec<_>>());
+        let json = tree.to_json_tree().unwrap();
+        let leaves: Vec<String> = tree.leaves().iter().map(|l| crate::hex::encode(l)).collect();
+        let node = crate::hex::encode(tree.node(1).unwrap());
+        for (tampered, reason) in [
+            (json.replace(&node, &leaves[0]), "does not match"),
+            (json.replace(&leaves[3], &leaves[2]), "does not match"),
+            (json.replace(r#""index":1"#, r#""index":2"#), "out of order"),
+            (json.replacen(r#","index":0"#, "", 1), "either an index"),
+            (json.replacen(r#""children":["#, r#""children":[{"hash":"0x00","children":[]},"#, 1), "children"),
+            (json.replacen(r#""index":0"#, r#""index":0,"children":[]"#, 1), "either an index"),
+            (format!("{}{}", "[".repeat(600), "]".repeat(600)), "nesting"),
+        ] {
+            match MerkleTree::from_json_tree(&tampered) {
+                Err(MerkleTreeError::InvalidEncoding(e)) => assert!(e.contains(reason), "{e}"),
+                other => panic!("{:?} for {tampered}", other.map(|tree| tree.root())),
+            }
+        }
+        let leaves: Vec<LeafHash> = (0..(1u32 << 19) + 1).map(|i| LeafHash::try_from(hash_node(&[&i.to_le_bytes()]).as_slice()).unwrap()).collect();
+        let large = MerkleTree::from_leaves(&leaves).unwrap();
+        assert_eq!(large.to_json_tree(), Err(MerkleTreeError::TooManyNodes { limit: MAX_JSON_TREE_NODES }));
+    }
+
+    #[test]
+    fn json_may_mix_hex_strings_and_byte_arrays() {
+        let tree = MerkleTree::new(&elements());
+        let leaves = tree.leaves();
+        let as_array = |bytes: &[u8]| format!("[{}]", bytes.iter().map(u8::to_string).collect::<Vec<_>>().join(","));
+        let json = format!(
+            r#"{{"leaves":[{},"{}",{},"{}",{}]}}"#,
+            as_array(&leaves[0]), crate::hex::encode(&leaves[1]), as_array(&leaves[2]), crate::hex::encode(&leaves[3]), as_array(&leaves[4])
+        );
+        assert_eq!(MerkleTree::from_json(&json).unwrap().root(), tree.root());
+
+        let proof = tree.inclusion_proof(0).unwrap();
+        let mixed = proof.to_json().replacen(&format!(r#""{}""#, proof.leaf), &as_array(proof.leaf.as_bytes()), 1);
+        assert_eq!(InclusionProof::from_json(&mixed).unwrap(), proof);
+        let out_of_range = mixed.replacen('[', "[256,", 1);
+        assert!(InclusionProof::from_json(&out_of_range).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+// One root committing to the files of a directory. Every file becomes the leaf
+// H(relative path || file root), with paths '/' separated and the leaves sorted by
+// path, so the same directory gives the same root on every machine
+use super::stream::read_full;
+use super::MerkleTree;
+use crate::hash::{LeafHash, Root};
+use crate::proof::InclusionProof;
+use crate::root_hasher::MerkleRootHasher;
+use sha3::{Digest, Sha3_256};
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+  #[default]
+  Skip,
+  Error
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DirectoryOptions {
+  // Files are hashed whole when None, otherwise the file root is the root of the tree
+  // over chunks of this many bytes
+  pub chunk_size: Option<usize>,
+  pub symlinks: SymlinkPolicy,
+  // Globs over the relative paths, `*` and `?` stay within a path segment and `**`
+  // spans segments. An excluded directory is not walked
+  pub exclude: Vec<String>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLeaf {
+  pub path: String,
+  pub file_root: Root,
+  pub len: u64
+}
+
+impl FileLeaf {
+  #[must_use]
+  pub fn leaf_hash(&self) -> LeafHash {
+    let mut hasher = Sha3_256::default();
+    hasher.update(self.path.as_bytes());
+    hasher.update(self.file_root.as_bytes());
+    LeafHash::new(hasher.finalize().into())
+  }
+}
+
+#[derive(Clone)]
+pub struct DirectoryCommitment {
+  pub tree: MerkleTree,
+  // in leaf order
+  pub files: Vec<FileLeaf>
+}
+
+impl DirectoryCommitment {
+  #[must_use]
+  pub fn root(&self) -> Root {
+    self.tree.root()
+  }
+
+  #[must_use]
+  pub fn file(&self, path: &str) -> Option<&FileLeaf> {
+    self.position(path).map(|i| &self.files[i])
+  }
+
+  #[must_use]
+  pub fn proof_for_path(&self, path: &str) -> Option<InclusionProof> {
+    self.position(path).and_then(|i| self.tree.inclusion_proof(i).ok())
+  }
+
+  fn position(&self, path: &str) -> Option<usize> {
+    self.files.binary_search_by(|f| f.path.as_str().cmp(path)).ok()
+  }
+}
+
+#[derive(Debug)]
+pub enum DirectoryError {
+  Io { path: PathBuf, error: io::Error },
+  // Found while walking with `SymlinkPolicy::Error`
+  Symlink(PathBuf),
+  // The relative path has no UTF-8 form, so it has no portable leaf
+  NonUtf8Path(PathBuf),
+  InvalidChunkSize
+}
+
+impl fmt::Display for DirectoryError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Io { path, error } => write!(f, "{}: {error}", path.display()),
+      Self::Symlink(path) => write!(f, "{} is a symbolic link", path.display()),
+      Self::NonUtf8Path(path) => write!(f, "{} is not valid UTF-8", path.display()),
+      Self::InvalidChunkSize => write!(f, "the chunk size must be at least one byte"),
+    }
+  }
+}
+
+impl std::error::Error for DirectoryError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Io { error, .. } => Some(error),
+      _ => None,
+    }
+  }
+}
+
+pub fn hash_directory(path: impl AsRef<Path>, options: &DirectoryOptions) -> Result<DirectoryCommitment, DirectoryError> {
+  if options.chunk_size == Some(0) {
+    return Err(DirectoryError::InvalidChunkSize);
+  }
+  let mut files = vec![];
+  walk(path.as_ref(), "", options, &mut files)?;
+  files.sort_by(|a, b| a.path.cmp(&b.path));
+  let leaves: Vec<LeafHash> = files.iter().map(FileLeaf::leaf_hash).collect();
+  let tree = MerkleTree::from_leaves(&leaves).expect("file leaves are SHA3-256 hashes");
+  Ok(DirectoryCommitment { tree, files })
+}
+
+fn walk(dir: &Path, prefix: &str, options: &DirectoryOptions, files: &mut Vec<FileLeaf>) -> Result<(), DirectoryError> {
+  for entry in fs::read_dir(dir).map_err(io_error(dir))? {
+    let entry = entry.map_err(io_error(dir))?;
+    let path = entry.path();
+    let name = entry.file_name().into_string().map_err(|_| DirectoryError::NonUtf8Path(path.clone()))?;
+    let relative = if prefix.is_empty() { name } else { format!("{prefix}/{name}") };
+    if options.exclude.iter().any(|glob| glob_matches(glob, &relative)) {
+      continue;
+    }
+    let kind = entry.file_type().map_err(io_error(&path))?;
+    if kind.is_symlink() {
+      match options.symlinks {
+        SymlinkPolicy::Skip => continue,
+        SymlinkPolicy::Error => return Err(DirectoryError::Symlink(path)),
+      }
+    }
+    if kind.is_dir() {
+      walk(&path, &relative, options, files)?;
+    } else if kind.is_file() {
+      let (file_root, len) = hash_file(&path, options.chunk_size).map_err(io_error(&path))?;
+      files.push(FileLeaf { path: relative, file_root, len });
+    }
+  }
+  Ok(())
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> DirectoryError + '_ {
+  move |error| DirectoryError::Io { path: path.to_path_buf(), error }
+}
+
+fn hash_file(path: &Path, chunk_size: Option<usize>) -> io::Result<(Root, u64)> {
+  let mut file = File::open(path)?;
+  let mut len = 0;
+  let Some(chunk_size) = chunk_size else {
+    let mut hasher = Sha3_256::default();
+    len = io::copy(&mut file, &mut hasher)?;
+    return Ok((Root::new(hasher.finalize().into()), len));
+  };
+  let mut hasher = MerkleRootHasher::new();
+  let mut chunk = vec![0; chunk_size];
+  loop {
+    let read = read_full(&mut file, &mut chunk)?;
+    if read == 0 && len > 0 {
+      break;
+    }
+    // an empty file is a single empty chunk
+    hasher.update(&chunk[..read]);
+    len += read as u64;
+    if read < chunk_size {
+      break;
+    }
+  }
+  Ok((hasher.finalize(), len))
+}
+
+fn glob_matches(glob: &str, path: &str) -> bool {
+  fn matches(glob: &[u8], path: &[u8]) -> bool {
+    match glob {
+      [] => path.is_empty(),
+      [b'*', b'*'] => true,
+      [b'*', b'*', rest @ ..] => {
+        let rest = rest.strip_prefix(b"/").unwrap_or(rest);
+        (0..=path.len()).any(|i| (i == 0 || path[i - 1] == b'/') && matches(rest, &path[i..]))
+      }
+      [b'*', rest @ ..] => (0..=path.len()).take_while(|&i| i == 0 || path[i - 1] != b'/').any(|i| matches(rest, &path[i..])),
+      [b'?', rest @ ..] => path.first().is_some_and(|&c| c != b'/') && matches(rest, &path[1..]),
+      [c, rest @ ..] => path.first() == Some(c) && matches(rest, &path[1..]),
+    }
+  }
+  matches(glob.as_bytes(), path.as_bytes())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::fs::{glob_matches, hash_directory, DirectoryError, DirectoryOptions, SymlinkPolicy};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("merkle-tree-fs-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::write(dir.join("README"), "hola").unwrap();
+        fs::write(dir.join("src/lib.rs"), "pub mod nested;").unwrap();
+        fs::write(dir.join("src/nested/mod.rs"), vec![7; 5000]).unwrap();
+        fs::write(dir.join("src/empty"), "").unwrap();
+        fs::write(dir.join("target/out.bin"), "build output").unwrap();
+        dir
+    }
+
+    #[test]
+    fn touching_one_file_changes_exactly_one_leaf() {
+        let dir = fixture("touch");
+        let options = DirectoryOptions { chunk_size: Some(1024), ..DirectoryOptions::default() };
+        let before = hash_directory(&dir, &options).unwrap();
+        let paths: Vec<&str> = before.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["README", "src/empty", "src/lib.rs", "src/nested/mod.rs", "target/out.bin"]);
+        assert_eq!(before.file("src/nested/mod.rs").unwrap().len, 5000);
+        assert_eq!(hash_directory(&dir, &options).unwrap().root(), before.root());
+
+        fs::write(dir.join("src/lib.rs"), "pub mod nested; // touched").unwrap();
+        let after = hash_directory(&dir, &options).unwrap();
+        assert_ne!(after.root(), before.root());
+        let changed: Vec<usize> = (0..5).filter(|&i| after.tree.leaves()[i] != before.tree.leaves()[i]).collect();
+        assert_eq!(changed, [2]);
+
+        let proof = after.proof_for_path("src/lib.rs").unwrap();
+        assert_eq!(proof.leaf, after.file("src/lib.rs").unwrap().leaf_hash());
+        assert!(proof.verify(&after.root()));
+        assert!(after.proof_for_path("missing").is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn options_change_what_gets_committed() {
+        let dir = fixture("options");
+        let whole = hash_directory(&dir, &DirectoryOptions::default()).unwrap();
+        let chunked = hash_directory(&dir, &DirectoryOptions { chunk_size: Some(1024), ..DirectoryOptions::default() }).unwrap();
+        assert_ne!(whole.root(), chunked.root());
+        // a file within a single chunk has the same root either way
+        assert_eq!(whole.file("README").unwrap().file_root, chunked.file("README").unwrap().file_root);
+
+        let excluded = hash_directory(&dir, &DirectoryOptions { exclude: vec!["target".into(), "**/*.rs".into()], ..DirectoryOptions::default() }).unwrap();
+        let paths: Vec<&str> = excluded.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["README", "src/empty"]);
+        assert!(matches!(hash_directory(&dir, &DirectoryOptions { chunk_size: Some(0), ..DirectoryOptions::default() }), Err(DirectoryError::InvalidChunkSize)));
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("README"), dir.join("link")).unwrap();
+            assert_eq!(hash_directory(&dir, &DirectoryOptions::default()).unwrap().root(), whole.root());
+            let strict = DirectoryOptions { symlinks: SymlinkPolicy::Error, ..DirectoryOptions::default() };
+            assert!(matches!(hash_directory(&dir, &strict), Err(DirectoryError::Symlink(_))));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn globs_respect_path_segments() {
+        assert!(glob_matches("*.rs", "lib.rs"));
+        assert!(!glob_matches("*.rs", "src/lib.rs"));
+        assert!(glob_matches("**/*.rs", "src/lib.rs"));
+        assert!(glob_matches("**/*.rs", "lib.rs"));
+        assert!(glob_matches("src/**", "src/nested/mod.rs"));
+        assert!(glob_matches("src/?ib.rs", "src/lib.rs"));
+        assert!(!glob_matches("src", "src2"));
+    }
+}
@@ -0,0 +1,167 @@
+use super::encoding::INDEX_SECTION;
+use super::MerkleTree;
+use crate::codec;
+use crate::error::MerkleTreeError;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// Position of the first leaf with each hash. It is built by the first lookup that needs
+// it, so a tree loaded only for its root or for proofs by index never pays for it, and
+// every change to the leaves drops it
+#[derive(Debug, Clone, Default)]
+pub(super) struct LeafIndex(OnceLock<HashMap<Vec<u8>, usize>>);
+
+impl LeafIndex {
+  fn get_or_build(&self, leaves: &[Vec<u8>]) -> &HashMap<Vec<u8>, usize> {
+    self.0.get_or_init(|| {
+      let mut positions = HashMap::with_capacity(leaves.len());
+      for (index, leaf) in leaves.iter().enumerate() {
+        positions.entry(leaf.clone()).or_insert(index);
+      }
+      positions
+    })
+  }
+}
+
+impl MerkleTree {
+  // Index of the first leaf holding `element`
+  #[must_use]
+  pub fn index_of(&self, element: &str) -> Option<usize> {
+    self.position_of_leaf(&self.hasher().leaf(element.as_bytes()))
+  }
+
+  #[must_use]
+  pub fn contains(&self, element: &str) -> bool {
+    self.index_of(element).is_some()
+  }
+
+  // Whether the leaf index is in memory right now. Sorted trees search their leaves
+  // and never build one
+  #[must_use]
+  pub fn is_index_built(&self) -> bool {
+    self.index.0.get().is_some()
+  }
+
+  // `to_bytes` followed by the leaf index, built first if it is not yet, so that
+  // `from_bytes` hands back a tree whose first lookup is already answered from memory.
+  // The stored index is checked against the leaves on load
+  #[must_use]
+  pub fn to_bytes_with_index(&self) -> Vec<u8> {
+    let mut out = self.to_bytes();
+    out.push(INDEX_SECTION);
+    let mut firsts: Vec<usize> = self.index.get_or_build(self.leaves()).values().copied().collect();
+    firsts.sort_unstable();
+    codec::put_u64(&mut out, firsts.len(), self.byte_order);
+    for index in firsts {
+      codec::put_u64(&mut out, index, self.byte_order);
+    }
+    out
+  }
+
+  // First leaf with the hash `leaf` in an unsorted tree
+  pub(super) fn indexed_position(&self, leaf: &[u8]) -> Option<usize> {
+    self.index.get_or_build(self.leaves()).get(leaf).copied()
+  }
+
+  pub(super) fn invalidate_index(&mut self) {
+    self.index = LeafIndex::default();
+  }
+
+  // Installs an index read back from `to_bytes_with_index`, it has to list exactly the
+  // first position of every distinct leaf
+  pub(super) fn restore_index(&mut self, firsts: &[usize]) -> Result<(), MerkleTreeError> {
+    let leaves = self.leaves();
+    let mut positions = HashMap::with_capacity(firsts.len());
+    for &index in firsts {
+      let leaf = leaves.get(index).ok_or_else(|| codec::invalid(format!("indexed leaf {index} is out of bounds")))?;
+      if positions.insert(leaf.clone(), index).is_some() {
+        return Err(codec::invalid(format!("the index lists leaf {index} twice")));
+      }
+    }
+    if leaves.iter().enumerate().any(|(i, leaf)| positions.get(leaf).is_none_or(|&first| first > i)) {
+      return Err(codec::invalid("the index does not match the leaves"));
+    }
+    self.index = LeafIndex(OnceLock::from(positions));
+    Ok(())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::MerkleTree;
+
+    fn elements() -> Vec<String> {
+        ["a", "b", "c", "b", "d", "e", "f"].iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn loaded_trees_build_the_index_on_the_first_lookup() {
+        let tree = MerkleTree::new(&elements());
+        let loaded = MerkleTree::from_bytes(&tree.to_bytes()).unwrap();
+        assert_eq!(loaded.root(), tree.root());
+        assert_eq!(loaded.proof(4), tree.proof(4));
+        assert!(!loaded.is_index_built());
+
+        assert_eq!(loaded.index_of("b"), Some(1));
+        assert!(loaded.is_index_built());
+        assert!(loaded.contains("f") && !loaded.contains("g"));
+        let (index, proof) = loaded.proof_for("d").unwrap();
+        assert_eq!((index, proof), (4, tree.proof(4)));
+
+        // sorted trees binary search their leaves instead
+        let sorted = MerkleTree::new_sorted(&elements());
+        assert!(sorted.contains("c"));
+        assert!(!sorted.is_index_built());
+    }
+
+    #[test]
+    fn mutations_keep_lookups_consistent() {
+        let mut tree = MerkleTree::new(&elements());
+        assert_eq!(tree.index_of("b"), Some(1));
+
+        tree.update(1, "g").unwrap();
+        assert!(!tree.is_index_built());
+        assert_eq!(tree.index_of("b"), Some(3));
+        assert_eq!(tree.index_of("g"), Some(1));
+
+        tree.remove(0).unwrap();
+        assert_eq!((tree.index_of("a"), tree.index_of("g"), tree.index_of("f")), (None, Some(0), Some(5)));
+
+        let checkpoint = tree.checkpoint();
+        tree.push("a");
+        assert_eq!(tree.index_of("a"), Some(6));
+        tree.rollback(checkpoint).unwrap();
+        assert_eq!(tree.index_of("a"), None);
+
+        tree.retain(|i, _| i != 0);
+        tree.replace_leaves(&["x", "b"]);
+        assert_eq!((tree.index_of("x"), tree.index_of("b"), tree.index_of("g")), (Some(0), Some(1), None));
+
+        let rebuilt = MerkleTree::new(&["x".to_string(), "b".to_string()]);
+        for element in ["x", "b", "d"] {
+            assert_eq!(tree.index_of(element), rebuilt.index_of(element));
+        }
+    }
+
+    #[test]
+    fn the_index_is_persisted_only_on_request() {
+        let tree = MerkleTree::new_retaining(&elements());
+        let bytes = tree.to_bytes_with_index();
+        assert_eq!(bytes.len(), tree.to_bytes().len() + 1 + 8 + 6 * 8);
+        let loaded = MerkleTree::from_bytes(&bytes).unwrap();
+        assert!(loaded.is_index_built());
+        assert_eq!((loaded.index_of("b"), loaded.index_of("f")), (Some(1), Some(6)));
+        assert_eq!(loaded.to_bytes(), tree.to_bytes());
+
+        // an index that disagrees with the leaves is rejected, not trusted
+        let plain = tree.to_bytes().len();
+        let mut wrong = bytes.clone();
+        wrong[plain + 9 + 2 * 8] = 3;
+        assert!(MerkleTree::from_bytes(&wrong).is_err());
+        let mut missing = bytes[..bytes.len() - 8].to_vec();
+        missing[plain + 1] = 5;
+        assert!(MerkleTree::from_bytes(&missing).is_err());
+        assert!(MerkleTree::from_bytes(&[tree.to_bytes().as_slice(), &[0]].concat()).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use super::MerkleTree;
+
+// Leaves may carry an opaque u64 next to their hash, an expiry time for instance. It is
+// not hashed, so the root and the proofs are the same with or without it. Trees that
+// never got any keep no storage for it
+impl MerkleTree {
+  // `push` keeping `meta` with the new leaf
+  pub fn push_with_meta(&mut self, element: &str, meta: u64) {
+    self.add_with_slots(&[element.to_string()], vec![Some(meta)]);
+  }
+
+  // `add` keeping the metadata paired with each element
+  pub fn add_with_meta(&mut self, elements: &[(String, u64)]) {
+    let (elements, meta): (Vec<String>, Vec<Option<u64>>) = elements.iter().map(|(e, m)| (e.clone(), Some(*m))).unzip();
+    self.add_with_slots(&elements, meta);
+  }
+
+  // Metadata of the leaf at `index`, none for leaves added without it
+  #[must_use]
+  pub fn leaf_meta(&self, index: usize) -> Option<u64> {
+    *self.meta.as_ref()?.get(index)?
+  }
+
+  // Removes every leaf whose metadata is below `now` and lays the tree out once for all
+  // of them. Leaves without metadata never expire. Returns the indices the removed
+  // leaves had, in ascending order
+  pub fn prune_expired(&mut self, now: u64) -> Vec<usize> {
+    let keep: Vec<bool> = (0..self.count).map(|i| self.leaf_meta(i).is_none_or(|expiry| expiry >= now)).collect();
+    let removed = (0..self.count).filter(|&i| !keep[i]).collect();
+    self.retain_flagged(&keep);
+    removed
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::MerkleTree;
+
+    fn entries() -> Vec<(String, u64)> {
+        (0..9).map(|i| (format!("entry {i}"), [30, 10, 50, 20, 10, 40, 60, 20, 30][i])).collect()
+    }
+
+    #[test]
+    fn metadata_is_kept_next_to_the_leaves() {
+        let mut tree = MerkleTree::new(&["plain".to_string()]);
+        tree.add_with_meta(&entries()[..3]);
+        tree.push("no expiry");
+        tree.push_with_meta("late", 70);
+        let elements: Vec<String> = ["plain", "entry 0", "entry 1", "entry 2", "no expiry", "late"].iter().map(|e| e.to_string()).collect();
+        // not hashed into the leaves
+        assert_eq!(tree.root(), MerkleTree::new(&elements).root());
+        let meta: Vec<_> = (0..7).map(|i| tree.leaf_meta(i)).collect();
+        assert_eq!(meta, [None, Some(30), Some(10), Some(50), None, Some(70), None]);
+
+        tree.update(1, "entry 0 again").unwrap();
+        tree.remove(2).unwrap();
+        assert_eq!((tree.leaf_meta(1), tree.leaf_meta(2)), (Some(30), Some(50)));
+        let checkpoint = tree.checkpoint();
+        tree.retain(|i, _| i == 0);
+        tree.rollback(checkpoint).unwrap();
+        assert_eq!((tree.leaf_meta(2), tree.leaf_meta(4)), (Some(50), Some(70)));
+
+        let subtree = tree.subtree(2..5).unwrap();
+        assert_eq!((subtree.leaf_meta(0), subtree.leaf_meta(2)), (Some(50), Some(70)));
+        let mut merged = MerkleTree::new(&elements[..1]);
+        merged.merge(subtree).unwrap();
+        assert_eq!((merged.leaf_meta(0), merged.leaf_meta(1), merged.leaf_meta(3)), (None, Some(50), Some(70)));
+
+        // sorted trees move the metadata along with the leaves
+        let mut sorted = MerkleTree::new_sorted(&[]);
+        sorted.add_with_meta(&entries());
+        for (i, (element, meta)) in entries().iter().enumerate() {
+            let index = sorted.index_of(element).unwrap();
+            assert_eq!(sorted.leaf_meta(index), Some(*meta), "entry {i}");
+        }
+    }
+
+    #[test]
+    fn pruning_matches_a_rebuild_without_the_expired_leaves() {
+        let mut tree = MerkleTree::new(&["no expiry".to_string()]);
+        tree.add_with_meta(&entries());
+        let removed = tree.prune_expired(30);
+        assert_eq!(removed, [2, 4, 5, 8]);
+
+        let mut kept = vec!["no expiry".to_string()];
+        kept.extend(entries().into_iter().filter(|(_, expiry)| *expiry >= 30).map(|(element, _)| element));
+        let rebuilt = MerkleTree::new(&kept);
+        assert_eq!(tree.root(), rebuilt.root());
+        assert_eq!(tree.get_hashes(), rebuilt.clone().get_hashes());
+        let meta: Vec<_> = (0..tree.len()).map(|i| tree.leaf_meta(i)).collect();
+        assert_eq!(meta, [None, Some(30), Some(50), Some(40), Some(60), Some(30)]);
+
+        assert!(tree.prune_expired(30).is_empty());
+        assert_eq!(tree.prune_expired(u64::MAX).len(), 5);
+        assert_eq!(tree.root(), MerkleTree::new(&kept[..1]).root());
+    }
+
+    #[test]
+    fn metadata_survives_round_trips() {
+        let mut tree = MerkleTree::new(&["no expiry".to_string()]);
+        tree.add_with_meta(&entries());
+        tree.push_with_meta("largest", u64::MAX);
+        for loaded in [MerkleTree::from_bytes(&tree.to_bytes()).unwrap(), MerkleTree::from_bytes(&tree.to_bytes_with_index()).unwrap(), MerkleTree::from_json(&tree.to_json()).unwrap()] {
+            assert_eq!(loaded.root(), tree.root());
+            assert!((0..tree.len() + 1).all(|i| loaded.leaf_meta(i) == tree.leaf_meta(i)));
+        }
+
+        // trees without metadata encode as they did before it existed
+        let plain = MerkleTree::new(&["no expiry".to_string()]);
+        assert_eq!(plain.to_bytes().len(), 11 + 9 + 32 + 1);
+        assert!(!plain.to_json().contains("meta"));
+        let mut appended = plain.clone();
+        appended.push("entry");
+        assert_eq!(appended.to_bytes(), MerkleTree::new(&["no expiry".to_string(), "entry".to_string()]).to_bytes());
+        assert_eq!(tree.to_bytes().len(), appended.to_bytes().len() + 9 * 32 + 1 + 8 + 1 + 10 * 9);
+    }
+}
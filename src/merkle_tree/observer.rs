@@ -0,0 +1,134 @@
+use super::MerkleTree;
+use crate::hash::{LeafHash, Root};
+
+// A change to the leaves or the root, see `MerkleTree::on_change`. Replaying the
+// leaf events in order on a copy of the leaves gives the leaves of the tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeEvent {
+  LeafAppended { index: usize, hash: LeafHash },
+  // the leaf was placed at `index` by a sorted tree or a rollback, the following
+  // leaves shift to the right
+  LeafInserted { index: usize, hash: LeafHash },
+  LeafUpdated { index: usize, old: LeafHash, new: LeafHash },
+  // the following leaves shift to the left
+  LeafRemoved { index: usize },
+  RootChanged { old: Root, new: Root },
+}
+
+pub(super) type Observer = Box<dyn FnMut(TreeEvent) + Send + Sync>;
+
+impl MerkleTree {
+  // Registers `f` to receive the events of every later mutation, replacing any previous
+  // observer. The events of a mutation are delivered once it is done, leaf events in the
+  // order they apply and then `RootChanged` if the root moved. Clones start without one
+  pub fn on_change(&mut self, f: impl FnMut(TreeEvent) + Send + Sync + 'static) {
+    self.observer = Some(Box::new(f));
+  }
+
+  pub fn clear_observer(&mut self) {
+    self.observer = None;
+  }
+
+  // The events are only built when somebody listens
+  pub(super) fn notify(&mut self, old_root: Root, events: impl FnOnce(&Self) -> Vec<TreeEvent>) {
+    if self.observer.is_none() {
+      return;
+    }
+    let events = events(self);
+    let new_root = self.root();
+    let observer = self.observer.as_mut().expect("checked above");
+    events.into_iter().for_each(&mut *observer);
+    if new_root != old_root {
+      observer(TreeEvent::RootChanged { old: old_root, new: new_root });
+    }
+  }
+
+  // Events of leaves appended from `start` on
+  pub(super) fn appended_since(&self, start: usize) -> Vec<TreeEvent> {
+    (start..self.count).map(|index| TreeEvent::LeafAppended { index, hash: self.leaf_hash(index).expect("appended leaf") }).collect()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::hash::LeafHash;
+    use crate::merkle_tree::*;
+    use std::sync::{Arc, Mutex};
+
+    fn observed(tree: &mut MerkleTree) -> Arc<Mutex<Vec<TreeEvent>>> {
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink = Arc::clone(&events);
+        tree.on_change(move |event| sink.lock().unwrap().push(event));
+        events
+    }
+
+    fn leaf(element: &str) -> LeafHash {
+        LeafHash::try_from(hash(element.to_string()).as_slice()).unwrap()
+    }
+
+    #[test]
+    fn scripted_mutations_emit_the_exact_event_sequence() {
+        let mut tree = MerkleTree::new(&["a".to_string(), "b".to_string()]);
+        let events = observed(&mut tree);
+        let roots = |elements: &[&str]| MerkleTree::new(&elements.iter().map(|e| e.to_string()).collect::<Vec<_>>()).root();
+
+        tree.push("c");
+        tree.add(&["d".to_string(), "e".to_string()]);
+        tree.update(1, "B").unwrap();
+        tree.remove(0).unwrap();
+        // an update to the same element leaves the root alone
+        tree.update(0, "B").unwrap();
+        assert!(tree.remove(9).is_err());
+
+        assert_eq!(*events.lock().unwrap(), vec![
+            TreeEvent::LeafAppended { index: 2, hash: leaf("c") },
+            TreeEvent::RootChanged { old: roots(&["a", "b"]), new: roots(&["a", "b", "c"]) },
+            TreeEvent::LeafAppended { index: 3, hash: leaf("d") },
+            TreeEvent::LeafAppended { index: 4, hash: leaf("e") },
+            TreeEvent::RootChanged { old: roots(&["a", "b", "c"]), new: roots(&["a", "b", "c", "d", "e"]) },
+            TreeEvent::LeafUpdated { index: 1, old: leaf("b"), new: leaf("B") },
+            TreeEvent::RootChanged { old: roots(&["a", "b", "c", "d", "e"]), new: roots(&["a", "B", "c", "d", "e"]) },
+            TreeEvent::LeafRemoved { index: 0 },
+            TreeEvent::RootChanged { old: roots(&["a", "B", "c", "d", "e"]), new: roots(&["B", "c", "d", "e"]) },
+            TreeEvent::LeafUpdated { index: 0, old: leaf("B"), new: leaf("B") },
+        ]);
+    }
+
+    #[test]
+    fn replaying_events_mirrors_the_leaves() {
+        let mut tree = MerkleTree::new_sorted(&["m".to_string(), "x".to_string()]);
+        let mut mirror: Vec<LeafHash> = (0..tree.len()).map(|i| tree.leaf_hash(i).unwrap()).collect();
+        let events = observed(&mut tree);
+        tree.add(&["a".to_string(), "z".to_string()]);
+        tree.update(0, "q").unwrap();
+        let checkpoint = tree.checkpoint();
+        tree.push("b");
+        tree.remove(1).unwrap();
+        tree.rollback(checkpoint).unwrap();
+        tree.merge(MerkleTree::new(&["c".to_string()])).unwrap();
+
+        let mut last_root = None;
+        for event in events.lock().unwrap().drain(..) {
+            match event {
+                TreeEvent::LeafAppended { index, hash } => {
+                    assert_eq!(index, mirror.len());
+                    mirror.push(hash);
+                },
+                TreeEvent::LeafInserted { index, hash } => mirror.insert(index, hash),
+                TreeEvent::LeafUpdated { index, new, .. } => mirror[index] = new,
+                TreeEvent::LeafRemoved { index } => { mirror.remove(index); },
+                TreeEvent::RootChanged { new, .. } => last_root = Some(new),
+            }
+        }
+        assert_eq!(mirror, (0..tree.len()).map(|i| tree.leaf_hash(i).unwrap()).collect::<Vec<_>>());
+        assert_eq!(last_root, Some(tree.root()));
+
+        // clones do not share the observer
+        let mut clone = tree.clone();
+        clone.push("d");
+        tree.clear_observer();
+        tree.push("d");
+        assert!(events.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+use super::{MerkleTree, DEFAULT_ARITY};
+use crate::error::MerkleTreeError;
+use crate::hash::{LeafHash, Root};
+use crate::scheme::{HashFunction, HashScheme, TreeConfig, TreeHasher};
+use crate::tree_math::DepthLimit;
+
+// Roots of empty subtrees of every height, grown on demand. Height 0 is the empty
+// slot, a hash of zero bytes, and height h is the parent of two empty subtrees of
+// height h - 1. Heights past the depth limit are refused rather than grown to
+#[derive(Debug, Clone)]
+pub struct ZeroHashes {
+  hasher: TreeHasher,
+  hashes: Vec<Vec<u8>>,
+  limit: DepthLimit
+}
+
+impl ZeroHashes {
+  #[must_use]
+  pub fn new(scheme: HashScheme, function: HashFunction) -> Self {
+    Self::for_hasher(TreeHasher::new(scheme, function))
+  }
+
+  pub(crate) fn for_hasher(hasher: TreeHasher) -> Self {
+    Self { hasher, hashes: vec![vec![0; hasher.output_len()]], limit: DepthLimit::default() }
+  }
+
+  // For sparse trees deeper than `MAX_SUPPORTED_DEPTH`
+  #[must_use]
+  pub fn with_depth_limit(self, limit: DepthLimit) -> Self {
+    Self { limit, ..self }
+  }
+
+  // Empty subtree roots of the heights 0 to `depth`
+  pub fn zero_hashes(&mut self, depth: usize) -> Result<&[Vec<u8>], MerkleTreeError> {
+    self.limit.check(depth)?;
+    while self.hashes.len() <= depth {
+      let below = self.hashes.last().expect("height 0 is always there");
+      let node = self.hasher.node(&[below.as_slice(), below.as_slice()]);
+      self.hashes.push(node);
+    }
+    Ok(&self.hashes[..=depth])
+  }
+}
+
+type Level = Vec<Vec<u8>>;
+
+// Counted roots hash this ahead of the padded root and the leaf count, so one is never
+// taken for a node or a leaf
+const COUNTED_DOMAIN: &[u8] = b"merkle-tree padded count v1\0";
+
+// Binary trees padded with empty slots to 2^depth leaves, as fixed-depth commitments
+// use. Only the real leaves are hashed, the empty part of every level comes from
+// `ZeroHashes`, so the cost follows the number of leaves and not the capacity. Depths
+// are held to `MAX_SUPPORTED_DEPTH`, a tree has fewer leaves than that many levels hold.
+//
+// Empty slots hold the zero hash. No leaf of data hashes to it, the empty element
+// included, but `from_leaves` takes any hash, so the counted root also commits to the
+// number of real leaves and tells a real leaf from padding at any index
+impl MerkleTree {
+  pub fn padded_root(&self, depth: usize) -> Result<Root, MerkleTreeError> {
+    let (levels, zeros) = self.padded_levels(depth)?;
+    let root = levels[depth].first().unwrap_or(&zeros[depth]);
+    Root::try_from(root.as_slice())
+  }
+
+  // Siblings of the leaf at `index` in the padded tree, `depth` of them. They verify
+  // against `padded_root` as a proof in a tree of 2^depth leaves
+  pub fn padded_proof(&self, index: usize, depth: usize) -> Result<Vec<Vec<u8>>, MerkleTreeError> {
+    if index >= self.count {
+      return Err(MerkleTreeError::IndexOutOfBounds { index, len: self.count });
+    }
+    self.slot_proof(index, depth)
+  }
+
+  // `padded_root` bound to the number of real leaves, see `verify_padded_proof`
+  pub fn padded_counted_root(&self, depth: usize) -> Result<Root, MerkleTreeError> {
+    let root = self.padded_root(depth)?;
+    Root::try_from(counted(self.hasher(), root.as_bytes(), self.count).as_slice())
+  }
+
+  // Siblings of the empty slot at `index`, past the real leaves, proving it is padding
+  pub fn padding_proof(&self, index: usize, depth: usize) -> Result<Vec<Vec<u8>>, MerkleTreeError> {
+    let slots = 1usize.checked_shl(depth as u32).unwrap_or(usize::MAX);
+    if index < self.count {
+      return Err(MerkleTreeError::NotPadding { index, leaf_count: self.count });
+    }
+    if index >= slots {
+      return Err(MerkleTreeError::IndexOutOfBounds { index, len: slots });
+    }
+    self.slot_proof(index, depth)
+  }
+
+  fn slot_proof(&self, index: usize, depth: usize) -> Result<Vec<Vec<u8>>, MerkleTreeError> {
+    let (levels, zeros) = self.padded_levels(depth)?;
+    Ok((0..depth).map(|height| {
+      let sibling = (index >> height) ^ 1;
+      levels[height].get(sibling).unwrap_or(&zeros[height]).clone()
+    }).collect())
+  }
+
+  // Nodes of every level that cover at least one real leaf, from the leaves up, and the
+  // empty subtree roots
+  fn padded_levels(&self, depth: usize) -> Result<(Vec<Level>, Vec<Vec<u8>>), MerkleTreeError> {
+    if self.arity != DEFAULT_ARITY {
+      return Err(MerkleTreeError::Incompatible(format!("padded trees are binary, this tree has arity {}", self.arity)));
+    }
+    DepthLimit::default().check(depth)?;
+    if 1usize.checked_shl(depth as u32).is_some_and(|slots| self.count > slots) {
+      return Err(MerkleTreeError::DepthTooSmall { depth, len: self.count });
+    }
+    let hasher = self.hasher();
+    let zeros = ZeroHashes::for_hasher(hasher).zero_hashes(depth)?.to_vec();
+    let mut levels = vec![self.leaves().to_vec()];
+    for zero in &zeros[..depth] {
+      let level = levels.last().expect("the leaves are the first level");
+      let parents = level.chunks(2).map(|pair| match pair {
+        [left, right] => hasher.node(&[left.as_slice(), right.as_slice()]),
+        [last] => hasher.node(&[last.as_slice(), zero.as_slice()]),
+        _ => unreachable!("chunks of two")
+      }).collect();
+      levels.push(parents);
+    }
+    Ok((levels, zeros))
+  }
+}
+
+fn counted(hasher: TreeHasher, root: &[u8], leaf_count: usize) -> Vec<u8> {
+  hasher.function.digest(&[COUNTED_DOMAIN, root, &hasher.byte_order.u64_bytes(leaf_count as u64)])
+}
+
+// Checks a proof of the slot at `index` against the `padded_counted_root` of a tree of
+// `leaf_count` real leaves, its depth being the proof length. A leaf proves a real leaf,
+// which needs `index < leaf_count`. None proves padding, which needs the slot past them
+#[must_use]
+pub fn verify_padded_proof(config: &TreeConfig, root: &Root, leaf: Option<&LeafHash>, index: usize, leaf_count: usize, proof: &[Vec<u8>]) -> bool {
+  let hasher = TreeHasher::of(*config);
+  let slots = 1usize.checked_shl(proof.len() as u32);
+  if config.arity != DEFAULT_ARITY || DepthLimit::default().check(proof.len()).is_err() || slots.is_some_and(|slots| leaf_count > slots || index >= slots) {
+    return false;
+  }
+  let mut hash = match leaf {
+    Some(leaf) if index < leaf_count => leaf.to_vec(),
+    None if index >= leaf_count => vec![0; hasher.output_len()],
+    _ => return false,
+  };
+  if hash.len() != hasher.output_len() || proof.iter().any(|sibling| sibling.len() != hash.len()) {
+    return false;
+  }
+  for (height, sibling) in proof.iter().enumerate() {
+    hash = if index >> height & 1 == 0 { hasher.node(&[&hash, sibling]) } else { hasher.node(&[sibling, &hash]) };
+  }
+  counted(hasher, &hash, leaf_count) == root.as_bytes()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::hash::LeafHash;
+    use crate::merkle_tree::*;
+    use crate::scheme::{digest_count, HashFunction, HashScheme};
+    use crate::tree_math::{DepthLimit, HARD_MAX_DEPTH, MAX_SUPPORTED_DEPTH};
+
+    fn elements(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("element {i}")).collect()
+    }
+
+    // the padded tree built leaf by leaf, empty slots included
+    fn materialized(tree: &MerkleTree, depth: usize) -> MerkleTree {
+        let empty = LeafHash::try_from(&[0; 32][..]).unwrap();
+        let mut leaves: Vec<LeafHash> = (0..tree.len()).map(|i| tree.leaf_hash(i).unwrap()).collect();
+        leaves.resize(1 << depth, empty);
+        MerkleTree::from_leaves(&leaves).unwrap()
+    }
+
+    #[test]
+    fn padded_roots_match_the_materialized_tree() {
+        for depth in 0..=5 {
+            for count in 0..=1usize << depth {
+                let tree = MerkleTree::new(&elements(count));
+                let full = materialized(&tree, depth);
+                assert_eq!(tree.padded_root(depth).unwrap(), full.root());
+                for index in 0..count {
+                    let proof = tree.padded_proof(index, depth).unwrap();
+                    assert_eq!(proof, full.proof(index));
+                    assert!(verify_proof(&full.root(), &tree.leaf_hash(index).unwrap(), index, 1 << depth, &proof));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deep_padding_only_costs_the_real_leaves() {
+        let tree = MerkleTree::new(&elements(3));
+        let before = digest_count();
+        let root = tree.padded_root(32).unwrap();
+        let proof = tree.padded_proof(2, 32).unwrap();
+        // each call grows the 32 zero hashes and the nodes over the leaves, two on the
+        // first level and one on each of the 31 above
+        assert_eq!(digest_count() - before, 2 * (32 + 2 + 31));
+        assert_eq!(proof.len(), 32);
+        assert!(verify_proof(&root, &tree.leaf_hash(2).unwrap(), 2, 1 << 32, &proof));
+
+        let mut zeros = ZeroHashes::new(HashScheme::Legacy, HashFunction::Sha3_256);
+        let hashes = zeros.zero_hashes(32).unwrap().to_vec();
+        assert_eq!(hashes[0], vec![0; 32]);
+        assert_eq!(hashes[3], hash_node(&[&hashes[2], &hashes[2]]));
+        assert_eq!(MerkleTree::new(&[]).padded_root(32).unwrap().as_bytes(), hashes[32].as_slice());
+
+        assert_eq!(tree.padded_root(1).err(), Some(MerkleTreeError::DepthTooSmall { depth: 1, len: 3 }));
+        assert!(MerkleTree::with_arity(&elements(3), 4).unwrap().padded_root(4).is_err());
+    }
+
+    #[test]
+    fn empty_elements_are_real_leaves_not_padding() {
+        for scheme in [HashScheme::Legacy, HashScheme::V1] {
+            let zero = ZeroHashes::new(scheme, HashFunction::Sha3_256).zero_hashes(0).unwrap()[0].clone();
+            assert_ne!(scheme.hash_leaf(b""), zero);
+            let tree = MerkleTree::with_scheme(&["", "a", ""].map(String::from), scheme);
+            let (index, proof) = tree.proof_for("").unwrap();
+            assert_eq!(index, 0);
+            assert!(tree.verify(&proof, index));
+
+            let (config, root, empty) = (tree.config(), tree.padded_counted_root(3).unwrap(), tree.leaf_hash(2).unwrap());
+            assert_ne!(root, tree.padded_root(3).unwrap());
+            let proof = tree.padded_proof(2, 3).unwrap();
+            assert!(verify_padded_proof(&config, &root, Some(&empty), 2, 3, &proof));
+            // the slot is not padding, and the count is committed
+            assert!(!verify_padded_proof(&config, &root, None, 2, 3, &proof));
+            assert!(!verify_padded_proof(&config, &root, Some(&empty), 2, 2, &proof));
+            assert!(!verify_padded_proof(&config, &root, Some(&empty), 2, 4, &proof));
+            for index in 3..8 {
+                let padding = tree.padding_proof(index, 3).unwrap();
+                assert!(verify_padded_proof(&config, &root, None, index, 3, &padding), "slot {index}");
+                assert!(!verify_padded_proof(&config, &root, Some(&empty), index, 3, &padding));
+            }
+            assert_eq!(tree.padding_proof(2, 3).err(), Some(MerkleTreeError::NotPadding { index: 2, leaf_count: 3 }));
+            assert_eq!(tree.padding_proof(8, 3).err(), Some(MerkleTreeError::IndexOutOfBounds { index: 8, len: 8 }));
+        }
+
+        // a real leaf with the padding hash is told apart by the count alone
+        let zero = LeafHash::try_from(&[0; 32][..]).unwrap();
+        let (two, one) = (MerkleTree::from_leaves(&[zero, zero]).unwrap(), MerkleTree::from_leaves(&[zero]).unwrap());
+        assert_eq!(two.padded_root(2).unwrap(), one.padded_root(2).unwrap());
+        assert_ne!(two.padded_counted_root(2).unwrap(), one.padded_counted_root(2).unwrap());
+        let config = two.config();
+        assert!(verify_padded_proof(&config, &two.padded_counted_root(2).unwrap(), Some(&zero), 1, 2, &two.padded_proof(1, 2).unwrap()));
+        assert!(!verify_padded_proof(&config, &one.padded_counted_root(2).unwrap(), Some(&zero), 1, 1, &one.padding_proof(1, 2).unwrap()));
+        assert!(verify_padded_proof(&config, &one.padded_counted_root(2).unwrap(), None, 1, 1, &one.padding_proof(1, 2).unwrap()));
+
+        // a legacy tree of one empty element has the empty root, padded or counted it does
+        // not, at any depth
+        let (single, none) = (MerkleTree::new(&[String::new()]), MerkleTree::new(&[]));
+        assert_eq!(single.root(), none.root());
+        for depth in 0..3 {
+            assert_ne!(single.padded_root(depth).unwrap(), none.padded_root(depth).unwrap());
+            assert_ne!(single.padded_counted_root(depth).unwrap(), none.padded_counted_root(depth).unwrap());
+        }
+        assert!(single.padded_proof(0, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn depths_past_the_limit_are_refused() {
+        let tree = MerkleTree::new(&elements(3));
+        let too_deep = |requested| Some(MerkleTreeError::DepthTooLarge { requested, max: MAX_SUPPORTED_DEPTH });
+        assert_eq!(tree.padded_root(MAX_SUPPORTED_DEPTH + 1).err(), too_deep(65));
+        assert_eq!(tree.padded_proof(0, 10_000).err(), too_deep(10_000));
+        assert_eq!(tree.padded_root(usize::MAX).err(), too_deep(usize::MAX));
+        // the deepest supported padding, wider than usize can count
+        let mut zeros = ZeroHashes::new(HashScheme::Legacy, HashFunction::Sha3_256);
+        assert_eq!(tree.padded_proof(2, MAX_SUPPORTED_DEPTH).unwrap().len(), 64);
+        let below = tree.padded_root(63).unwrap();
+        assert_eq!(tree.padded_root(MAX_SUPPORTED_DEPTH).unwrap().as_bytes(), hash_node(&[below.as_bytes(), &zeros.zero_hashes(63).unwrap()[63]]));
+
+        assert_eq!(zeros.zero_hashes(10_000).err(), too_deep(10_000));
+        let mut sparse = ZeroHashes::new(HashScheme::Legacy, HashFunction::Sha3_256).with_depth_limit(DepthLimit::new(HARD_MAX_DEPTH).unwrap());
+        let hashes = sparse.zero_hashes(256).unwrap().to_vec();
+        assert_eq!(hashes.len(), 257);
+        assert_eq!(hashes[64], zeros.zero_hashes(64).unwrap()[64]);
+        assert_eq!(sparse.zero_hashes(257).err(), Some(MerkleTreeError::DepthTooLarge { requested: 257, max: 256 }));
+        assert_eq!(DepthLimit::new(HARD_MAX_DEPTH + 1).err(), Some(MerkleTreeError::DepthTooLarge { requested: 257, max: HARD_MAX_DEPTH }));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::hex;
+use crate::tree_math::Layout;
+use std::collections::HashMap;
+
+// Checks of the `paranoid` feature over the node storage of a whole tree. Either finding
+// means a broken hash function or, far more likely, a bug in domain separation or in how
+// the nodes are indexed. Promoted nodes are copies of their child and are not compared
+pub(crate) fn check_nodes(hashes: &[Vec<u8>], leaf_count: usize, arity: usize) {
+  let layout = Layout::of(leaf_count, arity);
+  // every hashed parent, with its level and index on it
+  let mut parents: HashMap<&[u8], (usize, usize)> = HashMap::new();
+  for level in 1..=layout.height() {
+    let offset = layout.level_offset(level).expect("levels of the tree");
+    for index in 0..layout.level_size(level).expect("levels of the tree") {
+      let children = layout.children(offset + index).expect("interior nodes have children");
+      if children.len() == 1 {
+        continue;
+      }
+      let node = &hashes[offset + index];
+      if let Some(&(other_level, other_index)) = parents.get(node.as_slice()) {
+        let other = layout.node_index(other_level, other_index).expect("a recorded node");
+        if hashes[layout.children(other).expect("interior nodes have children")] != hashes[children] {
+          panic!("nodes ({other_level}, {other_index}) and ({level}, {index}) hash different children to the same parent {}", hex::encode(node));
+        }
+      } else {
+        parents.insert(node, (level, index));
+      }
+    }
+  }
+  for index in 0..leaf_count {
+    let leaf = &hashes[layout.node_index(0, index).expect("leaves of the tree")];
+    if let Some((level, other)) = parents.get(leaf.as_slice()) {
+      panic!("leaf {index} equals the interior node ({level}, {other}) {}", hex::encode(leaf));
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::paranoid::check_nodes;
+    use crate::merkle_tree::{hash_node, MerkleTree};
+    use crate::hash::LeafHash;
+    use crate::tree_math::Layout;
+    use std::panic;
+
+    fn leaves(count: u32) -> Vec<LeafHash> {
+        (0..count).map(|i| LeafHash::try_from(hash_node(&[&i.to_le_bytes()]).as_slice()).unwrap()).collect()
+    }
+
+    fn panic_message(build: impl FnOnce() + panic::UnwindSafe) -> String {
+        let payload = panic::catch_unwind(build).expect_err("the check should have failed");
+        payload.downcast_ref::<String>().cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn a_toy_hash_of_one_byte_collides() {
+        // binary SHA3-256 truncated to its first byte, 64 parents of 256 possible values
+        let toy = |pieces: &[&[u8]]| hash_node(pieces)[..1].to_vec();
+        let count = 65;
+        let layout = Layout::binary(count);
+        let mut hashes = vec![vec![]; layout.node_count()];
+        for level in 0..=layout.height() {
+            let offset = layout.level_offset(level).unwrap();
+            for index in 0..layout.level_size(level).unwrap() {
+                hashes[offset + index] = match layout.children(offset + index) {
+                    None => toy(&[&(index as u32).to_le_bytes()]),
+                    Some(children) if children.len() == 1 => hashes[children.start].clone(),
+                    Some(children) => toy(&hashes[children].iter().map(Vec::as_slice).collect::<Vec<_>>()),
+                };
+            }
+        }
+        let message = panic_message(|| check_nodes(&hashes, count, 2));
+        // the coordinates are (level, index on the level)
+        assert!(message.starts_with("nodes (") && message.contains("hash different children to the same parent"), "{message}");
+    }
+
+    #[test]
+    fn builds_and_updates_are_checked() {
+        let mut leaves = leaves(8);
+        let tree = MerkleTree::from_leaves(&leaves).unwrap();
+        check_nodes(&tree.hashes, tree.count, tree.arity);
+        assert_eq!(tree.len(), 8);
+
+        // a leaf holding the parent of the first two is a leaf equal to an interior node
+        leaves[5] = LeafHash::try_from(hash_node(&[leaves[0].as_bytes(), leaves[1].as_bytes()]).as_slice()).unwrap();
+        let message = panic_message(|| drop(MerkleTree::from_leaves(&leaves)));
+        assert!(message.starts_with("leaf 5 equals the interior node (1, 0)"), "{message}");
+
+        // legacy leaves hash their bytes the way nodes hash their children
+        let mut tree = MerkleTree::new(&(0..4).map(|i| i.to_string()).collect::<Vec<_>>());
+        let children = [tree[0].to_vec(), tree[1].to_vec()].concat();
+        let root = tree.root();
+        let message = panic_message(panic::AssertUnwindSafe(|| drop(tree.update_all_if_root_matches(&root, &[(3, children)]))));
+        assert!(message.starts_with("leaf 3 equals the interior node (1, 0)"), "{message}");
+    }
+}
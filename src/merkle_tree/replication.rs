@@ -0,0 +1,123 @@
+use super::MerkleTree;
+use crate::error::MerkleTreeError;
+use crate::scheme::{TreeConfig, TreeHasher};
+use crate::tree_math::Layout;
+
+// Node by node copies of a tree, for stores that keep nodes keyed by (level, index)
+impl MerkleTree {
+  // Hands every node to `sink` once as (level, index, hash), level 0 being the leaves.
+  // Nodes come in storage order: the root first, then each level down to the leaves, left
+  // to right. The first error of the sink stops the walk and is returned
+  pub fn export_nodes<E>(&self, mut sink: impl FnMut(usize, usize, &[u8]) -> Result<(), E>) -> Result<(), E> {
+    let layout = self.layout();
+    for level in (0..=layout.height()).rev() {
+      let offset = layout.level_offset(level).unwrap_or(0);
+      for index in 0..layout.level_size(level).unwrap_or(0) {
+        sink(level, index, &self.hashes[offset + index])?;
+      }
+    }
+    Ok(())
+  }
+
+  // Tree of `config` from nodes in any order, every node of the tree exactly once. The
+  // tree is built again from the leaves so any node that does not match is an error
+  pub fn import_nodes(config: TreeConfig, nodes: impl IntoIterator<Item = (usize, usize, Vec<u8>)>) -> Result<Self, MerkleTreeError> {
+    if config.arity < 2 {
+      return Err(MerkleTreeError::InvalidArity(config.arity));
+    }
+    let mut nodes: Vec<_> = nodes.into_iter().collect();
+    nodes.sort_by_key(|&(level, index, _)| (level, index));
+    if let Some(pair) = nodes.windows(2).find(|pair| (pair[0].0, pair[0].1) == (pair[1].0, pair[1].1)) {
+      return Err(MerkleTreeError::InvalidEncoding(format!("node ({}, {}) is given twice", pair[0].0, pair[0].1)));
+    }
+    let leaf_count = nodes.iter().take_while(|&&(level, _, _)| level == 0).count();
+    let layout = Layout::of(leaf_count, config.arity);
+    if nodes.len() != layout.node_count() {
+      return Err(MerkleTreeError::InvalidEncoding(format!("{leaf_count} leaves make {} nodes, got {}", layout.node_count(), nodes.len())));
+    }
+    let hasher = TreeHasher::of(config);
+    if let Some((_, _, node)) = nodes.iter().find(|(_, _, node)| node.len() != hasher.output_len()) {
+      return Err(MerkleTreeError::InvalidHashLength { expected: hasher.output_len(), got: node.len() });
+    }
+    let leaves = nodes[..leaf_count].iter().map(|(_, _, leaf)| leaf.clone()).collect();
+    let mut tree = Self::with_layout(Self::build_hashes(leaves, config.arity, hasher), leaf_count, config.arity);
+    tree.set_hasher(hasher);
+    for (level, index, node) in &nodes {
+      if tree.node_at(*level, *index) != Some(node.as_slice()) {
+        return Err(MerkleTreeError::InvalidEncoding(format!("node ({level}, {index}) does not match the leaves")));
+      }
+    }
+    Ok(tree)
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::merkle_tree::*;
+    use crate::scheme::{HashFunction, TreeConfig};
+    use std::collections::BTreeMap;
+
+    fn export(tree: &MerkleTree) -> BTreeMap<(usize, usize), Vec<u8>> {
+        let mut store = BTreeMap::new();
+        tree.export_nodes(|level, index, hash| {
+            store.insert((level, index), hash.to_vec());
+            Ok::<_, ()>(())
+        }).unwrap();
+        store
+    }
+
+    fn import(config: TreeConfig, store: &BTreeMap<(usize, usize), Vec<u8>>) -> Result<MerkleTree, MerkleTreeError> {
+        MerkleTree::import_nodes(config, store.iter().map(|(&(level, index), hash)| (level, index, hash.clone())))
+    }
+
+    fn elements(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("element {i}")).collect()
+    }
+
+    #[test]
+    fn exported_nodes_rebuild_the_same_tree() {
+        for count in [0, 1, 2, 7, 16] {
+            let tree = MerkleTree::new(&elements(count));
+            let mut order = vec![];
+            tree.export_nodes(|level, index, _| {
+                order.push((level, index));
+                Ok::<_, ()>(())
+            }).unwrap();
+            assert_eq!(order.first().map(|&(level, _)| level), (count > 0).then(|| tree.layout().height()));
+            let store = export(&tree);
+            assert_eq!(store.len(), tree.layout().node_count());
+            let imported = import(tree.config(), &store).unwrap();
+            assert_eq!(imported.root(), tree.root());
+            assert_eq!(imported.leaves(), tree.leaves());
+        }
+        let wide = MerkleTree::with_hash_function(&elements(5), HashFunction::Sha3_512);
+        assert_eq!(import(wide.config(), &export(&wide)).unwrap().root(), wide.root());
+
+        // the sink decides when to stop
+        let mut seen = 0;
+        assert_eq!(MerkleTree::new(&elements(4)).export_nodes(|_, _, _| { seen += 1; if seen == 3 { Err("full") } else { Ok(()) } }), Err("full"));
+        assert_eq!(seen, 3);
+    }
+
+    #[test]
+    fn corrupted_or_incomplete_exports_are_rejected() {
+        let tree = MerkleTree::new(&elements(6));
+        let store = export(&tree);
+        for node in [(0, 3), (1, 1), (3, 0)] {
+            let mut corrupted = store.clone();
+            corrupted.get_mut(&node).unwrap()[0] ^= 1;
+            assert!(matches!(import(tree.config(), &corrupted), Err(MerkleTreeError::InvalidEncoding(_))));
+        }
+        let mut missing = store.clone();
+        missing.remove(&(1, 2));
+        assert!(import(tree.config(), &missing).is_err());
+        let mut short = store.clone();
+        short.get_mut(&(2, 0)).unwrap().pop();
+        assert_eq!(import(tree.config(), &short).err(), Some(MerkleTreeError::InvalidHashLength { expected: 32, got: 31 }));
+        let twice = store.iter().chain(store.iter().take(1)).map(|(&(l, i), h)| (l, i, h.clone()));
+        assert!(MerkleTree::import_nodes(tree.config(), twice).is_err());
+        assert!(import(TreeConfig { arity: 3, ..tree.config() }, &store).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use super::{MerkleTree, DEFAULT_ARITY};
+use crate::scheme::{Digester, TreeHasher};
+
+// Node buffers kept from one short-lived tree to the next. A tree built with `new_in` takes
+// them and `recycle` hands them back, so once the scratch has seen the largest tree of a
+// workload building another one does not allocate
+#[derive(Debug, Default)]
+pub struct MerkleTreeScratch {
+  // the storage of the next tree, nodes included
+  nodes: Vec<Vec<u8>>,
+  // node buffers a smaller tree did not need
+  spare: Vec<Vec<u8>>
+}
+
+impl MerkleTreeScratch {
+  #[must_use]
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl MerkleTree {
+  // Same tree as `new`, its nodes hashed into the buffers of `scratch`
+  #[must_use]
+  pub fn new_in(scratch: &mut MerkleTreeScratch, elements: &[String]) -> Self {
+    let (count, total) = (elements.len(), node_count(elements.len(), DEFAULT_ARITY));
+    let mut hashes = std::mem::take(&mut scratch.nodes);
+    if hashes.len() > total {
+      scratch.spare.extend(hashes.drain(total..));
+    }
+    while hashes.len() < total {
+      hashes.push(scratch.spare.pop().unwrap_or_default());
+    }
+    let hasher = TreeHasher::default();
+    let mut digester = Digester::new(hasher.function);
+    for (leaf, element) in hashes[total - count..].iter_mut().zip(elements) {
+      hasher.leaf_into(&mut digester, element.as_bytes(), leaf);
+    }
+    // levels are stored the root first, each one ends where the one below starts
+    let (mut end, mut size) = (total, count);
+    while size > 1 {
+      let parents = size.div_ceil(DEFAULT_ARITY);
+      let (upper, lower) = hashes.split_at_mut(end - size);
+      for (parent, group) in upper[end - size - parents..].iter_mut().zip(lower[..size].chunks(DEFAULT_ARITY)) {
+        match group {
+          [promoted] => parent.clone_from(promoted),
+          _ => hasher.node_into(&mut digester, group.iter().map(Vec::as_slice), parent),
+        }
+      }
+      end -= size;
+      size = parents;
+    }
+    #[cfg(feature = "paranoid")]
+    super::paranoid::check_nodes(&hashes, count, DEFAULT_ARITY);
+    Self::with_layout(hashes, count, DEFAULT_ARITY)
+  }
+
+  // Gives the node buffers of the tree back to `scratch`
+  pub fn recycle(mut self, scratch: &mut MerkleTreeScratch) {
+    if scratch.nodes.is_empty() {
+      scratch.nodes = std::mem::take(&mut self.hashes);
+    } else {
+      scratch.spare.append(&mut self.hashes);
+    }
+  }
+}
+
+// `Layout::node_count` without laying the levels out
+fn node_count(leaf_count: usize, arity: usize) -> usize {
+  let (mut total, mut size) = (leaf_count, leaf_count);
+  while size > 1 {
+    size = size.div_ceil(arity);
+    total += size;
+  }
+  total
+}
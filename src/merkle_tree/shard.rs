@@ -0,0 +1,241 @@
+use super::{MerkleTree, DEFAULT_ARITY};
+use crate::error::MerkleTreeError;
+use crate::hash::{LeafHash, Root};
+use crate::scheme::TreeHasher;
+use crate::tree_math::Layout;
+
+// Builds one shard of a tree too large for one machine. Shard `shard_index` holds the
+// leaves from `shard_index * shard_leaf_count` on, and with a power of two shard size
+// every shard is a subtree of the whole tree, so workers hash their shards apart and
+// `combine_shards` only hashes the levels above them. Trees are those of `MerkleTree::new`
+#[derive(Debug, Clone)]
+pub struct ShardBuilder {
+  shard_index: usize,
+  shard_leaf_count: usize,
+  leaves: Vec<Vec<u8>>
+}
+
+// The hashed shard a worker hands back, every node of its subtree. `frontier_nodes` are
+// its levels below the root from the leaves up, so the coordinator places them in the
+// tree as they are. A shard of one leaf has that leaf as its root and no levels below
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardResult {
+  pub shard_index: usize,
+  pub shard_leaf_count: usize,
+  pub subtree_root: Root,
+  pub frontier_nodes: Vec<Vec<Vec<u8>>>
+}
+
+impl ShardBuilder {
+  pub fn new(shard_index: usize, shard_leaf_count: usize) -> Result<Self, MerkleTreeError> {
+    if !shard_leaf_count.is_power_of_two() {
+      return Err(MerkleTreeError::InvalidShard { shard: shard_index, reason: format!("{shard_leaf_count} leaves is not a power of two") });
+    }
+    if shard_index.checked_mul(shard_leaf_count).is_none() {
+      return Err(MerkleTreeError::InvalidShard { shard: shard_index, reason: "its first leaf index does not fit in usize".to_string() });
+    }
+    Ok(Self { shard_index, shard_leaf_count, leaves: Vec::with_capacity(shard_leaf_count) })
+  }
+
+  // Index in the whole tree of the first leaf of the shard
+  #[must_use]
+  pub fn first_leaf(&self) -> usize {
+    self.shard_index * self.shard_leaf_count
+  }
+
+  pub fn push(&mut self, element: &str) -> Result<(), MerkleTreeError> {
+    self.push_hashed(TreeHasher::default().leaf(element.as_bytes()))
+  }
+
+  pub fn push_leaf(&mut self, leaf: LeafHash) -> Result<(), MerkleTreeError> {
+    if leaf.len() != TreeHasher::default().output_len() {
+      return Err(MerkleTreeError::InvalidHashLength { expected: TreeHasher::default().output_len(), got: leaf.len() });
+    }
+    self.push_hashed(leaf.to_vec())
+  }
+
+  fn push_hashed(&mut self, leaf: Vec<u8>) -> Result<(), MerkleTreeError> {
+    if self.leaves.len() == self.shard_leaf_count {
+      return Err(MerkleTreeError::InvalidShard { shard: self.shard_index, reason: format!("it is full at {} leaves", self.shard_leaf_count) });
+    }
+    self.leaves.push(leaf);
+    Ok(())
+  }
+
+  // Hashes the shard. Only the last shard of a tree may be left short
+  pub fn finish(self) -> Result<ShardResult, MerkleTreeError> {
+    if self.leaves.is_empty() {
+      return Err(MerkleTreeError::InvalidShard { shard: self.shard_index, reason: "it has no leaves".to_string() });
+    }
+    let count = self.leaves.len();
+    let tree = MerkleTree::with_layout(MerkleTree::build_hashes(self.leaves, DEFAULT_ARITY, TreeHasher::default()), count, DEFAULT_ARITY);
+    let layout = tree.layout();
+    let frontier_nodes = (0..layout.height()).map(|level| {
+      let offset = layout.level_offset(level).expect("levels below the root exist");
+      tree.hashes[offset..offset + layout.level_size(level).expect("levels below the root exist")].to_vec()
+    }).collect();
+    Ok(ShardResult { shard_index: self.shard_index, shard_leaf_count: self.shard_leaf_count, subtree_root: tree.root(), frontier_nodes })
+  }
+}
+
+impl ShardResult {
+  // Leaves the shard holds, the shard size for every shard but the last
+  #[must_use]
+  pub fn leaf_count(&self) -> usize {
+    self.frontier_nodes.first().map_or(1, Vec::len)
+  }
+
+  // Levels and sizes of a subtree of that many leaves, and the root over the top level. The
+  // nodes further down are trusted as the worker sent them
+  fn check(&self) -> Result<(), MerkleTreeError> {
+    let invalid = |reason: String| MerkleTreeError::InvalidShard { shard: self.shard_index, reason };
+    let layout = Layout::binary(self.leaf_count());
+    if self.leaf_count() > self.shard_leaf_count {
+      return Err(invalid(format!("it has {} leaves, more than the shard size {}", self.leaf_count(), self.shard_leaf_count)));
+    }
+    if self.frontier_nodes.len() != layout.height() {
+      return Err(invalid(format!("{} leaves make {} levels below the root, got {}", self.leaf_count(), layout.height(), self.frontier_nodes.len())));
+    }
+    for (level, nodes) in self.frontier_nodes.iter().enumerate() {
+      if Some(nodes.len()) != layout.level_size(level) {
+        return Err(invalid(format!("level {level} has {} nodes, expected {}", nodes.len(), layout.level_size(level).unwrap_or(0))));
+      }
+      let expected = TreeHasher::default().output_len();
+      if let Some(node) = nodes.iter().find(|n| n.len() != expected) {
+        return Err(MerkleTreeError::InvalidHashLength { expected, got: node.len() });
+      }
+    }
+    if let Some(top) = self.frontier_nodes.last() {
+      let over_top = TreeHasher::default().node(&top.iter().map(Vec::as_slice).collect::<Vec<_>>());
+      if over_top != self.subtree_root.as_bytes() {
+        return Err(invalid("its root is not the hash of its top level".to_string()));
+      }
+    }
+    Ok(())
+  }
+}
+
+// The whole tree from the results of every shard, in any order. Shards have to share one
+// size, start at 0 without gaps, be given once and be full except for the last. The shard
+// nodes are copied, only the levels above the shard roots are hashed
+pub fn combine_shards(mut results: Vec<ShardResult>) -> Result<MerkleTree, MerkleTreeError> {
+  results.sort_by_key(|r| r.shard_index);
+  let Some(first) = results.first() else {
+    return Err(MerkleTreeError::Empty);
+  };
+  let shard_leaf_count = first.shard_leaf_count;
+  for (position, result) in results.iter().enumerate() {
+    if result.shard_index > position {
+      return Err(MerkleTreeError::MissingShard(position));
+    }
+    if result.shard_index < position {
+      return Err(MerkleTreeError::InvalidShard { shard: result.shard_index, reason: "it is given more than once".to_string() });
+    }
+    if result.shard_leaf_count != shard_leaf_count || !shard_leaf_count.is_power_of_two() {
+      return Err(MerkleTreeError::InvalidShard { shard: position, reason: format!("its size {} is not the power of two size {shard_leaf_count} of shard 0", result.shard_leaf_count) });
+    }
+    result.check()?;
+    if position + 1 < results.len() && result.leaf_count() != shard_leaf_count {
+      return Err(MerkleTreeError::InvalidShard { shard: position, reason: format!("only the last shard may have fewer than {shard_leaf_count} leaves") });
+    }
+  }
+  let shard_height = shard_leaf_count.trailing_zeros() as usize;
+  let leaf_count = (results.len() - 1) * shard_leaf_count + results.last().expect("checked above").leaf_count();
+  let layout = Layout::binary(leaf_count);
+  let mut hashes = vec![vec![]; layout.node_count()];
+  let roots: Vec<Vec<u8>> = results.iter().map(|r| r.subtree_root.as_bytes().to_vec()).collect();
+  let mut place = |level: usize, nodes: &mut dyn Iterator<Item = &Vec<u8>>| {
+    let offset = layout.level_offset(level).expect("the level is in the tree");
+    for (slot, node) in hashes[offset..].iter_mut().zip(nodes) {
+      slot.clone_from(node);
+    }
+  };
+  // a short last shard reaches its root below the shard height, its root is promoted from there
+  for level in 0..=shard_height.min(layout.height()) {
+    place(level, &mut results.iter().zip(&roots).flat_map(|(r, root)| r.frontier_nodes.get(level).map_or(std::slice::from_ref(root), Vec::as_slice)));
+  }
+  let above = Layout::binary(roots.len());
+  let top = MerkleTree::build_hashes(roots.clone(), DEFAULT_ARITY, TreeHasher::default());
+  for level in 1..=above.height() {
+    let offset = above.level_offset(level).expect("the level is in the top tree");
+    place(shard_height + level, &mut top[offset..offset + above.level_size(level).expect("the level is in the top tree")].iter());
+  }
+  Ok(MerkleTree::with_layout(hashes, leaf_count, DEFAULT_ARITY))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::merkle_tree::{combine_shards, verify_proof, MerkleTree, ShardBuilder, ShardResult};
+
+    fn elements(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("entry {i}")).collect()
+    }
+
+    fn shards(elements: &[String], shard_leaf_count: usize) -> Vec<ShardResult> {
+        elements.chunks(shard_leaf_count).enumerate().map(|(index, chunk)| {
+            let mut builder = ShardBuilder::new(index, shard_leaf_count).unwrap();
+            assert_eq!(builder.first_leaf(), index * shard_leaf_count);
+            for element in chunk {
+                builder.push(element).unwrap();
+            }
+            builder.finish().unwrap()
+        }).collect()
+    }
+
+    #[test]
+    fn combined_shards_are_the_monolithic_tree() {
+        let elements = elements(64);
+        let whole = MerkleTree::new(&elements);
+        let mut results = shards(&elements, 16);
+        results.reverse();
+        let combined = combine_shards(results).unwrap();
+        assert_eq!(combined.root(), whole.root());
+        for index in [0, 15, 16, 33, 63] {
+            assert_eq!(combined.proof(index), whole.proof(index));
+            let leaf = combined.leaf_hash(index).unwrap();
+            assert!(verify_proof(&whole.root(), &leaf, index, 64, &combined.proof(index)));
+        }
+        assert_eq!(combined.clone().get_hashes(), whole.clone().get_hashes());
+
+        // a short last shard, and shards of one leaf
+        for (count, size) in [(50, 16), (49, 16), (5, 1), (17, 16), (3, 4), (1, 1)] {
+            let elements = self::elements(count);
+            let combined = combine_shards(shards(&elements, size)).unwrap();
+            assert_eq!(combined.clone().get_hashes(), MerkleTree::new(&elements).get_hashes(), "{count} leaves in shards of {size}");
+        }
+    }
+
+    #[test]
+    fn misaligned_or_missing_shards_are_rejected() {
+        assert!(matches!(ShardBuilder::new(0, 12), Err(MerkleTreeError::InvalidShard { shard: 0, .. })));
+        let mut full = ShardBuilder::new(1, 2).unwrap();
+        full.push("a").unwrap();
+        full.push("b").unwrap();
+        assert!(full.push("c").is_err());
+        assert!(ShardBuilder::new(3, 4).unwrap().finish().is_err());
+
+        let results = shards(&elements(64), 16);
+        let mut missing = results.clone();
+        missing.remove(2);
+        assert_eq!(combine_shards(missing).err(), Some(MerkleTreeError::MissingShard(2)));
+        assert_eq!(combine_shards(results[1..].to_vec()).err(), Some(MerkleTreeError::MissingShard(0)));
+        let mut twice = results.clone();
+        twice.push(results[1].clone());
+        assert!(matches!(combine_shards(twice), Err(MerkleTreeError::InvalidShard { shard: 1, .. })));
+        assert_eq!(combine_shards(vec![]).err(), Some(MerkleTreeError::Empty));
+
+        // shards of another size, a short shard before the last, a tampered root
+        let mut mixed = shards(&elements(48), 16);
+        mixed.push(shards(&elements(64), 8)[6].clone());
+        mixed[3].shard_index = 3;
+        assert!(matches!(combine_shards(mixed), Err(MerkleTreeError::InvalidShard { shard: 3, .. })));
+        let mut short = results.clone();
+        short[1] = shards(&elements(30), 16)[1].clone();
+        assert!(matches!(combine_shards(short), Err(MerkleTreeError::InvalidShard { shard: 1, .. })));
+        let mut tampered = results.clone();
+        tampered[2].frontier_nodes[3][0][0] ^= 1;
+        assert!(matches!(combine_shards(tampered), Err(MerkleTreeError::InvalidShard { shard: 2, .. })));
+    }
+}
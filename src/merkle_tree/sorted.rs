@@ -0,0 +1,142 @@
+use super::{MerkleTree, DEFAULT_ARITY};
+use super::checkpoint::Undo;
+use super::TreeEvent;
+use crate::hash::LeafHash;
+use crate::scheme::TreeHasher;
+
+impl MerkleTree {
+  // Tree over the leaf hashes in ascending order, so any ordering of the same elements
+  // gives the same root. `push`/`add`/`update` keep the leaves sorted, which costs O(n)
+  // per inserted leaf because the following leaves shift
+  #[must_use]
+  pub fn new_sorted(elements: &[String]) -> Self {
+    let mut leaves = Self::hash_elements(elements, TreeHasher::default());
+    leaves.sort();
+    let hashes = Self::build_hashes(leaves, DEFAULT_ARITY, TreeHasher::default());
+    let mut tree = Self::with_layout(hashes, elements.len(), DEFAULT_ARITY);
+    tree.sorted = true;
+    tree
+  }
+
+  #[must_use]
+  pub fn is_sorted(&self) -> bool {
+    self.sorted
+  }
+
+  // Places every leaf at its sorted position and lays the tree out once. Retained elements
+  // are dropped when the new ones are unknown, leaves past the end of `new_meta` get none
+  pub(super) fn insert_sorted(&mut self, new_leaves: Vec<Vec<u8>>, new_elements: Option<Vec<Vec<u8>>>, new_meta: Vec<Option<u64>>) {
+    let old_root = self.root();
+    let mut events = vec![];
+    let mut leaves = self.take_leaves();
+    if new_elements.is_none() {
+      self.drop_elements();
+    }
+    if new_meta.iter().any(Option::is_some) {
+      self.meta.get_or_insert_with(|| vec![None; leaves.len()]);
+    }
+    let new_elements = new_elements.into_iter().flatten().map(Some).chain(std::iter::repeat(None));
+    let new_meta = new_meta.into_iter().chain(std::iter::repeat(None));
+    for ((leaf, element), meta) in new_leaves.into_iter().zip(new_elements).zip(new_meta) {
+      let index = leaves.partition_point(|l| *l <= leaf);
+      if self.observer.is_some() {
+        events.push(TreeEvent::LeafInserted { index, hash: LeafHash::try_from(leaf.as_slice()).expect("leaves have the output length of the hash function") });
+      }
+      leaves.insert(index, leaf);
+      if let (Some(elements), Some(element)) = (self.elements.as_mut(), element) {
+        elements.insert(index, element);
+      }
+      if let Some(slots) = self.meta.as_mut() {
+        slots.insert(index, meta);
+      }
+      self.record(Undo::Insert { index });
+    }
+    self.set_leaves(leaves);
+    self.notify(old_root, |_| events);
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::*;
+
+    // Deterministic Fisher-Yates shuffle driven by a xorshift generator
+    fn shuffled(elements: &[String], mut seed: u64) -> Vec<String> {
+        let mut shuffled = elements.to_vec();
+        for i in (1..shuffled.len()).rev() {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            shuffled.swap(i, (seed % (i as u64 + 1)) as usize);
+        }
+        shuffled
+    }
+
+    fn multiset() -> Vec<String> {
+        let mut elements: Vec<String> = (0..11).map(|i| format!("event {i}")).collect();
+        elements.push("event 3".to_string());
+        elements
+    }
+
+    #[test]
+    fn interleavings_of_the_same_multiset_converge_to_one_root() {
+        let elements = multiset();
+        let expected = MerkleTree::new_sorted(&elements);
+        for seed in 1..=10 {
+            let order = shuffled(&elements, seed);
+            let mut by_push = MerkleTree::new_sorted(&[]);
+            for element in &order {
+                by_push.push(element);
+            }
+            assert_eq!(by_push.root(), expected.root());
+
+            let mut by_batches = MerkleTree::new_sorted(&order[..4]);
+            by_batches.add(&order[4..9]);
+            by_batches.add(&order[9..]);
+            assert_eq!(by_batches.root(), expected.root());
+        }
+        assert_ne!(expected.root(), MerkleTree::new(&elements).root());
+    }
+
+    #[test]
+    fn membership_proofs_of_a_sorted_tree_verify() {
+        let elements = multiset();
+        let mut tree = MerkleTree::new_sorted(&shuffled(&elements, 42));
+        tree.update(0, "replacement").unwrap();
+        assert!(tree.leaves().windows(2).all(|w| w[0] <= w[1]));
+        // the update replaced whichever leaf sorted first
+        let mut found = 0;
+        for element in elements.iter().map(String::as_str).chain(["replacement"]) {
+            if let Some((index, proof)) = tree.proof_for(element) {
+                assert_eq!(tree[index], hash(element.to_string()));
+                assert!(tree.verify(&proof, index));
+                found += 1;
+            }
+        }
+        assert_eq!(found, elements.len());
+        assert!(tree.proof_for("replacement").is_some());
+        assert!(tree.proof_for("event 11").is_none());
+    }
+
+    #[test]
+    fn rollback_undoes_sorted_inserts() {
+        let mut tree = MerkleTree::new_sorted(&multiset());
+        let before = tree.clone();
+        let id = tree.checkpoint();
+        tree.add(&["b".to_string(), "a".to_string()]);
+        tree.push("c");
+        tree.rollback(id).unwrap();
+        assert_eq!(tree.root(), before.root());
+    }
+
+    #[test]
+    fn default_trees_keep_the_insertion_order() {
+        let elements = multiset();
+        let tree = MerkleTree::new(&elements);
+        assert!(!tree.is_sorted());
+        let (index, proof) = tree.proof_for("event 3").unwrap();
+        assert_eq!(index, 3);
+        assert!(tree.verify(&proof, index));
+    }
+}
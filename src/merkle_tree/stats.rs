@@ -0,0 +1,112 @@
+use super::MerkleTree;
+use crate::json::Json;
+use crate::scheme::HashScheme;
+use std::mem::size_of;
+
+// Size and configuration figures of a tree, see `MerkleTree::stats`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeStats {
+  pub leaf_count: usize,
+  pub node_count: usize,
+  // number of levels above the leaves
+  pub depth: usize,
+  pub hash_len: usize,
+  pub arity: usize,
+  pub scheme: HashScheme,
+  pub sorted: bool,
+  pub retains_elements: bool,
+  // approximate heap bytes, allocated capacity included
+  pub node_bytes: usize,
+  pub element_bytes: usize
+}
+
+impl TreeStats {
+  // One flat JSON object, ready for logs and metrics
+  #[must_use]
+  pub fn to_json(&self) -> String {
+    Json::object([
+      ("leaf_count", self.leaf_count.into()),
+      ("node_count", self.node_count.into()),
+      ("depth", self.depth.into()),
+      ("hash_len", self.hash_len.into()),
+      ("arity", self.arity.into()),
+      ("scheme", self.scheme.name().into()),
+      ("sorted", self.sorted.into()),
+      ("retains_elements", self.retains_elements.into()),
+      ("node_bytes", self.node_bytes.into()),
+      ("element_bytes", self.element_bytes.into()),
+    ]).to_string()
+  }
+}
+
+impl MerkleTree {
+  #[must_use]
+  pub fn stats(&self) -> TreeStats {
+    TreeStats {
+      leaf_count: self.count,
+      node_count: self.hashes.len(),
+      depth: self.layout().height(),
+      hash_len: self.function.output_len(),
+      arity: self.arity,
+      scheme: self.scheme,
+      sorted: self.sorted,
+      retains_elements: self.elements.is_some(),
+      node_bytes: heap_bytes(&self.hashes),
+      element_bytes: self.elements.as_ref().map_or(0, heap_bytes)
+    }
+  }
+}
+
+fn heap_bytes(buffers: &Vec<Vec<u8>>) -> usize {
+  buffers.capacity() * size_of::<Vec<u8>>() + buffers.iter().map(Vec::capacity).sum::<usize>()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::MerkleTree;
+
+    fn elements(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("element {i}")).collect()
+    }
+
+    #[test]
+    fn node_count_and_depth_of_power_of_two_trees() {
+        for depth in 0..8 {
+            let count = 1 << depth;
+            let stats = MerkleTree::new(&elements(count)).stats();
+            assert_eq!(stats.leaf_count, count);
+            assert_eq!(stats.node_count, 2 * count - 1);
+            assert_eq!(stats.depth, depth);
+            assert_eq!(stats.hash_len, 32);
+            assert!(stats.node_bytes >= stats.node_count * 32);
+        }
+    }
+
+    #[test]
+    fn unbalanced_trees_round_the_depth_up() {
+        for count in [3, 5, 6, 7, 9, 100] {
+            let stats = MerkleTree::new(&elements(count)).stats();
+            assert_eq!(stats.depth, count.next_power_of_two().trailing_zeros() as usize);
+            assert!(stats.node_count >= 2 * count - 1);
+        }
+        assert_eq!(MerkleTree::new(&[]).stats().depth, 0);
+    }
+
+    #[test]
+    fn retained_elements_add_to_the_byte_estimate() {
+        let plain = MerkleTree::new(&elements(16)).stats();
+        let retaining = MerkleTree::new_retaining(&elements(16)).stats();
+        assert_eq!(plain.element_bytes, 0);
+        assert!(!plain.retains_elements);
+        assert!(retaining.retains_elements);
+        assert!(retaining.element_bytes > elements(16).iter().map(String::len).sum());
+        assert_eq!(retaining.node_bytes, plain.node_bytes);
+    }
+
+    #[test]
+    fn stats_render_as_json() {
+        let json = MerkleTree::with_arity(&elements(4), 4).unwrap().stats().to_json();
+        assert!(json.starts_with(r#"{"leaf_count":4,"node_count":5,"depth":1,"hash_len":32,"arity":4,"scheme":"legacy","sorted":false"#));
+    }
+}
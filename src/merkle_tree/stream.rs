@@ -0,0 +1,206 @@
+use super::{MerkleTree, DEFAULT_ARITY};
+use crate::error::MerkleTreeError;
+use crate::hash::{LeafHash, Root};
+use crate::proof::MerkleProof;
+use crate::scheme::{HashScheme, TreeHasher};
+use std::io::{self, Read, Write};
+
+// What checking pieces of chunked data takes without the data or the tree, the chunk
+// size, the length of the data and the root over its chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkedCommitment {
+  pub chunk_size: usize,
+  pub total_len: u64,
+  pub root: Root
+}
+
+impl ChunkedCommitment {
+  // Whether `piece` is the chunk starting at byte `offset` according to `proof`. Offsets
+  // between chunk boundaries and pieces that are not exactly one chunk are errors, a
+  // proof of another chunk or another tree only fails to verify
+  pub fn verify_piece(&self, offset: u64, piece: &[u8], proof: &MerkleProof) -> Result<bool, MerkleTreeError> {
+    let chunk_size = self.chunk_size as u64;
+    if chunk_size == 0 {
+      return Err(MerkleTreeError::InvalidChunkSize);
+    }
+    if !offset.is_multiple_of(chunk_size) {
+      return Err(MerkleTreeError::MisalignedOffset { offset, chunk_size: self.chunk_size });
+    }
+    let (index, len) = (offset / chunk_size, self.total_len.div_ceil(chunk_size));
+    if index >= len {
+      return Err(MerkleTreeError::IndexOutOfBounds { index: index as usize, len: len as usize });
+    }
+    // only the last chunk may be shorter
+    let expected = (self.total_len - offset).min(chunk_size) as usize;
+    if piece.len() != expected {
+      return Err(MerkleTreeError::InvalidPieceLength { offset, expected, got: piece.len() });
+    }
+    if proof.index as u64 != index || proof.leaf_count as u64 != len {
+      return Ok(false);
+    }
+    let leaf = LeafHash::try_from(HashScheme::Legacy.hash_leaf(piece).as_slice())?;
+    Ok(proof.verify(&self.root, &leaf))
+  }
+}
+
+impl MerkleTree {
+  // Tree over the chunks of `reader`, every `chunk_size` bytes become a leaf and the
+  // last chunk may be shorter. No input gives the empty tree
+  pub fn from_chunked_reader(reader: impl Read, chunk_size: usize) -> io::Result<Self> {
+    Self::from_chunked_reader_with_commitment(reader, chunk_size).map(|(tree, _)| tree)
+  }
+
+  // Same tree as `from_chunked_reader` along with the commitment to hand to whoever
+  // checks pieces of the data
+  pub fn from_chunked_reader_with_commitment(mut reader: impl Read, chunk_size: usize) -> io::Result<(Self, ChunkedCommitment)> {
+    if chunk_size == 0 {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, MerkleTreeError::InvalidChunkSize));
+    }
+    let mut leaves = vec![];
+    let mut chunk = vec![0; chunk_size];
+    let mut total_len = 0;
+    loop {
+      let read = read_full(&mut reader, &mut chunk)?;
+      total_len += read as u64;
+      if read > 0 {
+        leaves.push(HashScheme::Legacy.hash_leaf(&chunk[..read]));
+      }
+      if read < chunk_size {
+        break;
+      }
+    }
+    let count = leaves.len();
+    let tree = Self::with_layout(Self::build_hashes(leaves, DEFAULT_ARITY, TreeHasher::default()), count, DEFAULT_ARITY);
+    let commitment = ChunkedCommitment { chunk_size, total_len, root: tree.root() };
+    Ok((tree, commitment))
+  }
+}
+
+// Builds the tree of a byte stream as it is written, each full chunk is hashed into a
+// leaf right away so only one chunk is buffered. Same tree as `from_chunked_reader`
+pub struct MerkleWriter {
+  chunk_size: usize,
+  buffer: Vec<u8>,
+  leaves: Vec<Vec<u8>>
+}
+
+impl MerkleWriter {
+  pub fn new(chunk_size: usize) -> Result<Self, MerkleTreeError> {
+    if chunk_size == 0 {
+      return Err(MerkleTreeError::InvalidChunkSize);
+    }
+    Ok(Self { chunk_size, buffer: Vec::with_capacity(chunk_size), leaves: vec![] })
+  }
+
+  // Hashes the final partial chunk and builds the tree
+  #[must_use]
+  pub fn finalize(mut self) -> MerkleTree {
+    if !self.buffer.is_empty() {
+      self.leaves.push(HashScheme::Legacy.hash_leaf(&self.buffer));
+    }
+    let count = self.leaves.len();
+    MerkleTree::with_layout(MerkleTree::build_hashes(self.leaves, DEFAULT_ARITY, TreeHasher::default()), count, DEFAULT_ARITY)
+  }
+}
+
+impl Write for MerkleWriter {
+  fn write(&mut self, mut data: &[u8]) -> io::Result<usize> {
+    let written = data.len();
+    while !data.is_empty() {
+      let take = data.len().min(self.chunk_size - self.buffer.len());
+      self.buffer.extend_from_slice(&data[..take]);
+      data = &data[take..];
+      if self.buffer.len() == self.chunk_size {
+        self.leaves.push(HashScheme::Legacy.hash_leaf(&self.buffer));
+        self.buffer.clear();
+      }
+    }
+    Ok(written)
+  }
+
+  // A partial chunk stays buffered, it only becomes a leaf in `finalize`
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+// Fills `buf` unless the input ends first, returns how much was read
+pub(super) fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+  let mut filled = 0;
+  while filled < buf.len() {
+    match reader.read(&mut buf[filled..]) {
+      Ok(0) => break,
+      Ok(n) => filled += n,
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+      Err(e) => return Err(e),
+    }
+  }
+  Ok(filled)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::merkle_tree::*;
+    use std::fs::{self, File};
+    use std::io::{self, Write};
+
+    #[test]
+    fn writing_through_the_writer_equals_reading_in_chunks() {
+        let path = std::env::temp_dir().join(format!("merkle-tree-stream-{}", std::process::id()));
+        for len in [0usize, 1, 4096, 4097, 10_000, 3 * 4096] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            fs::write(&path, &data).unwrap();
+            let mut writer = MerkleWriter::new(4096).unwrap();
+            io::copy(&mut File::open(&path).unwrap(), &mut writer).unwrap();
+            writer.flush().unwrap();
+            let written = writer.finalize();
+            let read = MerkleTree::from_chunked_reader(File::open(&path).unwrap(), 4096).unwrap();
+            assert_eq!(written.root(), read.root());
+            assert_eq!(written.len(), len.div_ceil(4096));
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn ragged_writes_fill_the_same_chunks() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        let mut writer = MerkleWriter::new(64).unwrap();
+        for piece in data.chunks(37) {
+            writer.write_all(piece).unwrap();
+            writer.flush().unwrap();
+        }
+        let expected = MerkleTree::from_chunked_reader(data.as_slice(), 64).unwrap();
+        assert_eq!(writer.finalize().root(), expected.root());
+        assert_eq!(expected.len(), 16);
+        assert_eq!(MerkleWriter::new(0).err(), Some(MerkleTreeError::InvalidChunkSize));
+        assert_eq!(MerkleTree::from_chunked_reader(data.as_slice(), 0).err().map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn pieces_verify_by_byte_offset() {
+        // five full chunks of 1000 bytes and a final one of 234
+        let path = std::env::temp_dir().join(format!("merkle-tree-pieces-{}", std::process::id()));
+        let data: Vec<u8> = (0..5234u32).map(|i| (i * 13 % 256) as u8).collect();
+        fs::write(&path, &data).unwrap();
+        let (tree, commitment) = MerkleTree::from_chunked_reader_with_commitment(File::open(&path).unwrap(), 1000).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(commitment, ChunkedCommitment { chunk_size: 1000, total_len: 5234, root: tree.root() });
+        let proof = |index: usize| tree.inclusion_proof(index).unwrap().proof;
+
+        for (offset, piece) in [(0, &data[..1000]), (3000, &data[3000..4000]), (5000, &data[5000..])] {
+            assert_eq!(commitment.verify_piece(offset as u64, piece, &proof(offset / 1000)), Ok(true));
+        }
+        let mut corrupted = data[3000..4000].to_vec();
+        corrupted[10] ^= 1;
+        assert_eq!(commitment.verify_piece(3000, &corrupted, &proof(3)), Ok(false));
+        assert_eq!(commitment.verify_piece(3000, &data[3000..4000], &proof(2)), Ok(false));
+
+        assert_eq!(commitment.verify_piece(1500, &data[1500..2500], &proof(1)), Err(MerkleTreeError::MisalignedOffset { offset: 1500, chunk_size: 1000 }));
+        // spanning into the next chunk, or short of the chunk end
+        assert_eq!(commitment.verify_piece(1000, &data[1000..2500], &proof(1)), Err(MerkleTreeError::InvalidPieceLength { offset: 1000, expected: 1000, got: 1500 }));
+        assert_eq!(commitment.verify_piece(5000, &data[5000..5100], &proof(5)), Err(MerkleTreeError::InvalidPieceLength { offset: 5000, expected: 234, got: 100 }));
+        assert_eq!(commitment.verify_piece(6000, &[], &proof(5)), Err(MerkleTreeError::IndexOutOfBounds { index: 6, len: 6 }));
+    }
+}
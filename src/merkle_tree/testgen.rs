@@ -0,0 +1,374 @@
+// Conformance vectors shared with other implementations of the trees and their proofs.
+// `generate` draws trees and proofs from a seed, `run_conformance` checks a vectors file,
+// made here or elsewhere, against this crate
+use super::encoding::config_from_json;
+use super::MerkleTree;
+use crate::codec;
+use crate::error::MerkleTreeError;
+use crate::hash::{LeafHash, Root};
+use crate::json::Json;
+use crate::proof::MerkleProof;
+use crate::scheme::{ByteOrder, HashFunction, HashScheme, Padding, PairOrder, TreeConfig, TreeHasher};
+use std::fmt;
+
+// Trees of every size under every config, each with `proofs_per_tree` proofs of random
+// leaves and a corrupted copy of each
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestSpec {
+  pub sizes: Vec<usize>,
+  // every arity has to be at least 2
+  pub configs: Vec<TreeConfig>,
+  pub proofs_per_tree: usize,
+  // elements are random bytes of up to this length
+  pub max_element_len: usize
+}
+
+impl Default for TestSpec {
+  fn default() -> Self {
+    let base = TreeConfig::default();
+    Self {
+      sizes: vec![0, 1, 2, 3, 5, 8, 13, 33],
+      configs: vec![
+        base,
+        TreeConfig { scheme: HashScheme::V1, ..base },
+        TreeConfig { arity: 3, ..base },
+        TreeConfig { function: HashFunction::Keccak256, ..base },
+        TreeConfig { function: HashFunction::Keccak256, pair_order: PairOrder::Sorted, ..base },
+        TreeConfig { arity: 4, scheme: HashScheme::V1, function: HashFunction::Sha3_512, byte_order: ByteOrder::BigEndian, ..base },
+      ],
+      proofs_per_tree: 3,
+      max_element_len: 64
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceVectors {
+  pub seed: u64,
+  pub trees: Vec<TreeVector>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeVector {
+  pub config: TreeConfig,
+  pub elements: Vec<Vec<u8>>,
+  pub root: Root,
+  pub proofs: Vec<ProofVector>
+}
+
+// A proof and whether it verifies against the root of its tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofVector {
+  pub leaf: LeafHash,
+  pub proof: MerkleProof,
+  // None for proofs as the tree makes them
+  pub corruption: Option<Corruption>,
+  pub valid: bool
+}
+
+// What was changed in a proof the tree made
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corruption {
+  // a bit of one sibling flipped
+  Sibling,
+  // a bit of the leaf flipped
+  Leaf,
+  // the next index, wrapping around
+  Index,
+  // the last sibling dropped
+  MissingSibling,
+}
+
+impl Corruption {
+  pub const ALL: [Self; 4] = [Self::Sibling, Self::Leaf, Self::Index, Self::MissingSibling];
+
+  #[must_use]
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Sibling => "sibling",
+      Self::Leaf => "leaf",
+      Self::Index => "index",
+      Self::MissingSibling => "missing_sibling",
+    }
+  }
+
+  #[must_use]
+  pub fn from_name(name: &str) -> Option<Self> {
+    Self::ALL.into_iter().find(|c| c.name() == name)
+  }
+
+  fn applies_to(self, proof: &MerkleProof) -> bool {
+    match self {
+      Self::Sibling | Self::MissingSibling => !proof.siblings.is_empty(),
+      Self::Leaf => true,
+      Self::Index => proof.leaf_count > 1,
+    }
+  }
+
+  fn apply(self, leaf: &mut LeafHash, proof: &mut MerkleProof, rng: &mut SplitMix64) {
+    match self {
+      Self::Sibling => {
+        let sibling = rng.below(proof.siblings.len());
+        let byte = rng.below(proof.siblings[sibling].len());
+        proof.siblings[sibling][byte] ^= 1 << rng.below(8);
+      },
+      Self::Leaf => {
+        let mut bytes = leaf.to_vec();
+        let byte = rng.below(bytes.len());
+        bytes[byte] ^= 1 << rng.below(8);
+        *leaf = LeafHash::try_from(bytes.as_slice()).expect("same length as before");
+      },
+      Self::Index => proof.index = (proof.index + 1) % proof.leaf_count,
+      Self::MissingSibling => {
+        proof.siblings.pop();
+      },
+    }
+  }
+}
+
+// Everything that was drawn comes from the seed, the same seed and spec give the same
+// vectors down to the byte
+//
+// # Panics
+//
+// On a config of the spec with an arity below 2
+#[must_use]
+pub fn generate(seed: u64, spec: TestSpec) -> ConformanceVectors {
+  let mut rng = SplitMix64(seed);
+  let mut trees = vec![];
+  for &config in &spec.configs {
+    for &size in &spec.sizes {
+      let elements: Vec<Vec<u8>> = (0..size).map(|_| {
+        let len = rng.below(spec.max_element_len + 1);
+        (0..len).map(|_| rng.next_u64() as u8).collect()
+      }).collect();
+      let tree = tree_of(config, &elements).expect("TestSpec configs need an arity of at least 2");
+      let root = tree.root();
+      let mut proofs = vec![];
+      let draws = if size == 0 { 0 } else { spec.proofs_per_tree };
+      for _ in 0..draws {
+        let proof = tree.inclusion_proof(rng.below(size)).expect("the index is in range");
+        let (mut leaf, mut corrupted) = (proof.leaf, proof.proof.clone());
+        let applicable: Vec<Corruption> = Corruption::ALL.into_iter().filter(|c| c.applies_to(&proof.proof)).collect();
+        let corruption = applicable[rng.below(applicable.len())];
+        corruption.apply(&mut leaf, &mut corrupted, &mut rng);
+        let valid = corrupted.verify(&root, &leaf);
+        proofs.push(ProofVector { leaf: proof.leaf, proof: proof.proof, corruption: None, valid: true });
+        proofs.push(ProofVector { leaf, proof: corrupted, corruption: Some(corruption), valid });
+      }
+      trees.push(TreeVector { config, elements, root, proofs });
+    }
+  }
+  ConformanceVectors { seed, trees }
+}
+
+// Where this crate disagrees with a vector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+  pub tree: usize,
+  // None when the tree itself disagrees
+  pub proof: Option<usize>,
+  pub reason: String
+}
+
+impl fmt::Display for Mismatch {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.proof {
+      Some(proof) => write!(f, "tree {} proof {}: {}", self.tree, proof, self.reason),
+      None => write!(f, "tree {}: {}", self.tree, self.reason),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConformanceReport {
+  // roots and proofs compared
+  pub checked: usize,
+  pub mismatches: Vec<Mismatch>
+}
+
+impl ConformanceReport {
+  #[must_use]
+  pub fn passed(&self) -> bool {
+    self.mismatches.is_empty()
+  }
+}
+
+// Rebuilds every tree of `vectors` and checks its root, verifies every proof against it
+// and checks the uncorrupted ones are the proofs this crate makes
+#[must_use]
+pub fn run_conformance(vectors: &ConformanceVectors) -> ConformanceReport {
+  let mut report = ConformanceReport::default();
+  for (index, vector) in vectors.trees.iter().enumerate() {
+    let mismatch = |proof, reason: String| Mismatch { tree: index, proof, reason };
+    report.checked += 1;
+    let tree = match tree_of(vector.config, &vector.elements) {
+      Ok(tree) => tree,
+      Err(e) => {
+        report.mismatches.push(mismatch(None, e.to_string()));
+        continue;
+      }
+    };
+    if tree.root() != vector.root {
+      report.mismatches.push(mismatch(None, format!("the root is {}, expected {}", tree.root(), vector.root)));
+    }
+    for (i, proof) in vector.proofs.iter().enumerate() {
+      report.checked += 1;
+      let valid = proof.proof.verify(&vector.root, &proof.leaf);
+      if valid != proof.valid {
+        report.mismatches.push(mismatch(Some(i), format!("the proof verifies as {valid}, expected {}", proof.valid)));
+      }
+      if proof.corruption.is_none() && tree.inclusion_proof(proof.proof.index).ok().is_none_or(|made| made.proof != proof.proof || made.leaf != proof.leaf) {
+        report.mismatches.push(mismatch(Some(i), format!("the tree makes another proof of leaf {}", proof.proof.index)));
+      }
+    }
+  }
+  report
+}
+
+// `MerkleTree` over raw elements under any config
+fn tree_of(config: TreeConfig, elements: &[Vec<u8>]) -> Result<MerkleTree, MerkleTreeError> {
+  if config.arity < 2 {
+    return Err(MerkleTreeError::InvalidArity(config.arity));
+  }
+  let hasher = TreeHasher::of(config);
+  let leaves = hasher.leaves(&elements.iter().map(Vec::as_slice).collect::<Vec<_>>());
+  let mut tree = MerkleTree::with_layout(MerkleTree::build_hashes(leaves, config.arity, hasher), elements.len(), config.arity);
+  tree.set_hasher(hasher);
+  Ok(tree)
+}
+
+// `{"seed":"…","trees":[{"arity":…,"scheme":…,"hash":…,"byte_order":…,"pair_order":…,
+// "padding":…,"elements":["0x…",…],"root":"0x…","proofs":[{"leaf":"0x…","proof":{…},
+// "corruption":…,"valid":…},…]},…]}`. The seed is a decimal string since JSON numbers do
+// not hold every u64, proofs are in the format of `MerkleProof::to_json` and "corruption"
+// is null or the name of one
+impl ConformanceVectors {
+  #[must_use]
+  pub fn to_json(&self) -> String {
+    let trees = self.trees.iter().map(|tree| Json::object([
+      ("arity", tree.config.arity.into()),
+      ("scheme", tree.config.scheme.name().into()),
+      ("hash", tree.config.function.name().into()),
+      ("byte_order", tree.config.byte_order.name().into()),
+      ("pair_order", tree.config.pair_order.name().into()),
+      ("padding", tree.config.padding.name().into()),
+      ("elements", Json::Array(tree.elements.iter().map(|e| codec::bytes_to_json(e)).collect())),
+      ("root", codec::bytes_to_json(tree.root.as_bytes())),
+      ("proofs", Json::Array(tree.proofs.iter().map(|proof| Json::object([
+        ("leaf", codec::bytes_to_json(proof.leaf.as_bytes())),
+        ("proof", Json::parse(&proof.proof.to_json()).expect("proofs write valid JSON")),
+        ("corruption", proof.corruption.map_or(Json::Null, |c| c.name().into())),
+        ("valid", proof.valid.into()),
+      ])).collect())),
+    ])).collect();
+    Json::object([("seed", self.seed.to_string().into()), ("trees", Json::Array(trees))]).to_string()
+  }
+
+  pub fn from_json(text: &str) -> Result<Self, MerkleTreeError> {
+    let json = Json::parse(text).map_err(codec::invalid)?;
+    let seed = json.get("seed").and_then(Json::as_str).and_then(|s| s.parse().ok())
+      .ok_or_else(|| codec::invalid("seed must be a decimal string"))?;
+    let trees = json.get("trees").and_then(Json::as_array).ok_or_else(|| codec::invalid("trees must be an array"))?;
+    Ok(Self { seed, trees: trees.iter().map(tree_from_json).collect::<Result<_, _>>()? })
+  }
+}
+
+fn tree_from_json(json: &Json) -> Result<TreeVector, MerkleTreeError> {
+  let (arity, hasher) = config_from_json(json, "hash")?;
+  let TreeHasher { scheme, function, byte_order, pair_order } = hasher;
+  let proofs = json.get("proofs").and_then(Json::as_array).ok_or_else(|| codec::invalid("proofs must be an array"))?;
+  Ok(TreeVector {
+    config: TreeConfig { arity, scheme, function, byte_order, pair_order, padding: Padding::PromoteOdd },
+    elements: codec::list_from_json("elements", json.get("elements"))?,
+    root: Root::try_from(codec::bytes_from_json("root", json.get("root"))?.as_slice())?,
+    proofs: proofs.iter().map(proof_from_json).collect::<Result<_, _>>()?
+  })
+}
+
+fn proof_from_json(json: &Json) -> Result<ProofVector, MerkleTreeError> {
+  let corruption = match json.get("corruption") {
+    None | Some(Json::Null) => None,
+    Some(name) => Some(name.as_str().and_then(Corruption::from_name).ok_or_else(|| codec::invalid("unknown corruption"))?),
+  };
+  Ok(ProofVector {
+    leaf: LeafHash::try_from(codec::bytes_from_json("leaf", json.get("leaf"))?.as_slice())?,
+    proof: MerkleProof::from_json(&json.get("proof").ok_or_else(|| codec::invalid("missing proof"))?.to_string())?,
+    corruption,
+    valid: json.get("valid").and_then(Json::as_bool).ok_or_else(|| codec::invalid("valid must be a boolean"))?
+  })
+}
+
+// SplitMix64, small and with a fixed output for every seed on every platform
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+  fn next_u64(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+  }
+
+  fn below(&mut self, n: usize) -> usize {
+    (self.next_u64() % n as u64) as usize
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::testgen::{generate, run_conformance, ConformanceVectors, Corruption, TestSpec};
+    use crate::scheme::{HashFunction, PairOrder};
+    use hex_literal::hex;
+
+    #[test]
+    fn generation_is_byte_stable_for_a_seed() {
+        let spec = TestSpec::default();
+        let json = generate(7, spec.clone()).to_json();
+        assert_eq!(json, generate(7, spec.clone()).to_json());
+        assert_ne!(json, generate(8, spec.clone()).to_json());
+        // pinned so a change to the generator or to any root shows up here
+        assert_eq!(HashFunction::Sha3_256.digest(&[json.as_bytes()]), hex!("f10558603b12319ad4a15b2b81c27b2e85f9a4295d087a1fc1b5cffaba6ea956"));
+        assert_eq!(ConformanceVectors::from_json(&json).unwrap(), generate(7, spec));
+    }
+
+    #[test]
+    fn generated_vectors_pass_conformance() {
+        for seed in [0, 1, u64::MAX] {
+            let vectors = generate(seed, TestSpec::default());
+            let report = run_conformance(&ConformanceVectors::from_json(&vectors.to_json()).unwrap());
+            assert!(report.passed(), "{:?}", report.mismatches);
+            assert_eq!(report.checked, 48 + 42 * 3 * 2);
+            let corrupted: Vec<_> = vectors.trees.iter().flat_map(|t| &t.proofs).filter(|p| p.corruption.is_some()).collect();
+            // sorted pairs do not commit to positions, a proof moved to another index may still verify
+            assert!(corrupted.iter().all(|p| !p.valid || p.corruption == Some(Corruption::Index) && p.proof.pair_order == PairOrder::Sorted));
+            for corruption in Corruption::ALL {
+                assert!(corrupted.iter().any(|p| p.corruption == Some(corruption)));
+            }
+        }
+    }
+
+    #[test]
+    fn disagreements_are_reported() {
+        // one tree of each config
+        let mut vectors = generate(3, TestSpec { sizes: vec![4], ..TestSpec::default() });
+        vectors.trees[0].elements[0].push(0);
+        vectors.trees[1].proofs[1].valid = true;
+        vectors.trees[2].proofs[0].proof.siblings[0][0] ^= 1;
+        vectors.trees[3].config.arity = 1;
+        let report = run_conformance(&vectors);
+        assert!(!report.passed());
+        let found: Vec<_> = report.mismatches.iter().map(|m| (m.tree, m.proof)).collect();
+        assert_eq!(found[0], (0, None));
+        assert!(report.mismatches[0].to_string().starts_with("tree 0: the root is"));
+        // the proof of a changed tree no longer is the proof the tree makes
+        assert!(found.contains(&(0, Some(0))));
+        assert!(found.contains(&(1, Some(1))));
+        assert_eq!(found.iter().filter(|&&m| m == (2, Some(0))).count(), 2);
+        assert!(found.contains(&(3, None)));
+        assert!(found.iter().all(|&(tree, _)| tree != 4));
+        assert!(ConformanceVectors::from_json(r#"{"seed":7,"trees":[]}"#).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use super::MerkleTree;
+use crate::error::MerkleTreeError;
+use crate::hash::LeafHash;
+use crate::proof::MerkleProof;
+use crate::scheme::HashFunction;
+
+// Words of the Solidity ABI
+const WORD: usize = 32;
+
+// How the fields of a row become the bytes of its leaf
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TupleEncoding {
+  // Fixed width fields as they are, integers big endian and variable fields behind their
+  // length as a u64 big endian. The leaf is hashed once with SHA3-256
+  Packed,
+  // `abi.encode` of the fields, hashed twice with Keccak-256 as OpenZeppelin's
+  // StandardMerkleTree does
+  Abi,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+  Fixed(Vec<u8>),
+  Address([u8; 20]),
+  U64(u64),
+  U128(u128),
+  Bytes(Vec<u8>),
+}
+
+// Row of typed fields such as `(address, amount)`, built field by field in order. The
+// order is part of the encoding, swapping two fields gives another leaf
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TupleLeaf {
+  encoding: TupleEncoding,
+  fields: Vec<Field>
+}
+
+impl TupleLeaf {
+  #[must_use]
+  pub fn new() -> Self {
+    Self { encoding: TupleEncoding::Packed, fields: vec![] }
+  }
+
+  // Row encoded the OpenZeppelin way, see `TupleEncoding::Abi`
+  #[must_use]
+  pub fn abi() -> Self {
+    Self { encoding: TupleEncoding::Abi, fields: vec![] }
+  }
+
+  // `bytesN` in the ABI, at most 32 bytes there
+  #[must_use]
+  pub fn fixed_bytes(self, bytes: &[u8]) -> Self {
+    self.field(Field::Fixed(bytes.to_vec()))
+  }
+
+  #[must_use]
+  pub fn address(self, address: [u8; 20]) -> Self {
+    self.field(Field::Address(address))
+  }
+
+  #[must_use]
+  pub fn u64(self, value: u64) -> Self {
+    self.field(Field::U64(value))
+  }
+
+  // `uint256` in the ABI
+  #[must_use]
+  pub fn u128(self, value: u128) -> Self {
+    self.field(Field::U128(value))
+  }
+
+  #[must_use]
+  pub fn bytes(self, bytes: &[u8]) -> Self {
+    self.field(Field::Bytes(bytes.to_vec()))
+  }
+
+  // Same encoding as `bytes` over the UTF-8 of `value`
+  #[must_use]
+  pub fn string(self, value: &str) -> Self {
+    self.bytes(value.as_bytes())
+  }
+
+  #[must_use]
+  pub fn encoding(&self) -> TupleEncoding {
+    self.encoding
+  }
+
+  fn field(mut self, field: Field) -> Self {
+    self.fields.push(field);
+    self
+  }
+
+  // Canonical bytes of the row, only fixed bytes longer than a word fail to encode for the ABI
+  pub fn encode(&self) -> Result<Vec<u8>, MerkleTreeError> {
+    match self.encoding {
+      TupleEncoding::Packed => Ok(self.packed()),
+      TupleEncoding::Abi => self.abi_encoded(),
+    }
+  }
+
+  pub fn leaf_hash(&self) -> Result<LeafHash, MerkleTreeError> {
+    let encoded = self.encode()?;
+    let leaf = match self.encoding {
+      TupleEncoding::Packed => HashFunction::Sha3_256.digest(&[&encoded]),
+      TupleEncoding::Abi => {
+        let inner = HashFunction::Keccak256.digest(&[&encoded]);
+        HashFunction::Keccak256.digest(&[&inner])
+      }
+    };
+    LeafHash::try_from(leaf.as_slice())
+  }
+
+  fn packed(&self) -> Vec<u8> {
+    let mut out = vec![];
+    for field in &self.fields {
+      match field {
+        Field::Fixed(bytes) => out.extend_from_slice(bytes),
+        Field::Address(address) => out.extend_from_slice(address),
+        Field::U64(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Field::U128(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Field::Bytes(bytes) => {
+          out.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+          out.extend_from_slice(bytes);
+        }
+      }
+    }
+    out
+  }
+
+  // One head word per field, variable fields put the offset of their data there and
+  // their length and padded data in the tail
+  fn abi_encoded(&self) -> Result<Vec<u8>, MerkleTreeError> {
+    let mut head = Vec::with_capacity(self.fields.len() * WORD);
+    let mut tail = vec![];
+    for field in &self.fields {
+      match field {
+        Field::Fixed(bytes) if bytes.len() > WORD => {
+          return Err(MerkleTreeError::InvalidEncoding(format!("bytes{} is not an ABI type", bytes.len())));
+        },
+        Field::Fixed(bytes) => head.extend_from_slice(&right_padded(bytes)),
+        Field::Address(address) => head.extend_from_slice(&left_padded(address)),
+        Field::U64(value) => head.extend_from_slice(&left_padded(&value.to_be_bytes())),
+        Field::U128(value) => head.extend_from_slice(&left_padded(&value.to_be_bytes())),
+        Field::Bytes(bytes) => {
+          head.extend_from_slice(&left_padded(&((self.fields.len() * WORD + tail.len()) as u64).to_be_bytes()));
+          tail.extend_from_slice(&left_padded(&(bytes.len() as u64).to_be_bytes()));
+          for chunk in bytes.chunks(WORD) {
+            tail.extend_from_slice(&right_padded(chunk));
+          }
+        }
+      }
+    }
+    head.extend_from_slice(&tail);
+    Ok(head)
+  }
+}
+
+impl Default for TupleLeaf {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+fn left_padded(bytes: &[u8]) -> [u8; WORD] {
+  let mut word = [0; WORD];
+  word[WORD - bytes.len()..].copy_from_slice(bytes);
+  word
+}
+
+fn right_padded(bytes: &[u8]) -> [u8; WORD] {
+  let mut word = [0; WORD];
+  word[..bytes.len()].copy_from_slice(bytes);
+  word
+}
+
+impl MerkleTree {
+  // Tree over the leaf hashes of `rows`, which all use the same encoding. ABI rows give a
+  // Keccak-256 tree hashing each pair sorted, whose proofs OpenZeppelin's `MerkleProof`
+  // verifies. The leaves stay in the order of `rows`, `StandardMerkleTree` sorts them
+  pub fn from_tuples(rows: &[TupleLeaf]) -> Result<Self, MerkleTreeError> {
+    let encoding = rows.first().map_or(TupleEncoding::Packed, TupleLeaf::encoding);
+    if rows.iter().any(|row| row.encoding != encoding) {
+      return Err(MerkleTreeError::Incompatible("rows with different encodings".to_string()));
+    }
+    let leaves = rows.iter().map(TupleLeaf::leaf_hash).collect::<Result<Vec<_>, _>>()?;
+    match encoding {
+      TupleEncoding::Packed => Self::from_leaves(&leaves),
+      TupleEncoding::Abi => Self::from_sorted_pair_leaves(&leaves),
+    }
+  }
+
+  // Proof of the first leaf encoding `row`
+  #[must_use]
+  pub fn proof_for_tuple(&self, row: &TupleLeaf) -> Option<MerkleProof> {
+    self.proof_by_leaf_hash(&row.leaf_hash().ok()?)
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use sha3::{Digest, Keccak256};
+    use crate::error::MerkleTreeError;
+    use crate::merkle_tree::*;
+
+    fn claims(encoded: fn() -> TupleLeaf) -> Vec<TupleLeaf> {
+        (0..5u8).map(|i| encoded().address([i; 20]).u128(1000 * u128::from(i) + 1)).collect()
+    }
+
+    #[test]
+    fn rows_encode_canonically_in_field_order() {
+        let row = TupleLeaf::new().address([0x11; 20]).u64(7).string("claim");
+        assert_eq!(row.encode().unwrap(), [
+            &[0x11; 20][..], &hex!("0000000000000007"), &hex!("0000000000000005"), b"claim"
+        ].concat());
+        assert_eq!(row.leaf_hash(), TupleLeaf::new().address([0x11; 20]).u64(7).string("claim").leaf_hash());
+
+        let tree = MerkleTree::from_tuples(&claims(TupleLeaf::new)).unwrap();
+        let swapped: Vec<TupleLeaf> = (0..5u8).map(|i| TupleLeaf::new().u128(1000 * u128::from(i) + 1).address([i; 20])).collect();
+        assert_ne!(MerkleTree::from_tuples(&swapped).unwrap().root(), tree.root());
+        // a variable field ends where its length says, the next field cannot shift into it
+        assert_ne!(TupleLeaf::new().string("ab").string("c").encode(), TupleLeaf::new().string("a").string("bc").encode());
+
+        let row = &claims(TupleLeaf::new)[3];
+        let proof = tree.proof_for_tuple(row).unwrap();
+        assert_eq!(proof.index, 3);
+        assert!(proof.verify(&tree.root(), &row.leaf_hash().unwrap()));
+        assert!(tree.proof_for_tuple(&TupleLeaf::new().address([3; 20]).u128(3002)).is_none());
+    }
+
+    #[test]
+    fn abi_rows_hash_like_the_standard_merkle_tree() {
+        // the leaf of ["0x1111111111111111111111111111111111111111", "5000000000000000000"]
+        // as `(address, uint256)`: keccak256(keccak256(abi.encode(values)))
+        let row = TupleLeaf::abi().address([0x11; 20]).u128(5_000_000_000_000_000_000);
+        let encoded = [&[0; 12][..], &[0x11; 20], &[0; 24], &hex!("4563918244f40000")].concat();
+        assert_eq!(row.encode().unwrap(), encoded);
+        let expected = Keccak256::digest(Keccak256::digest(&encoded));
+        assert_eq!(row.leaf_hash().unwrap().as_bytes(), expected.as_slice());
+
+        // dynamic fields go to the tail behind their offset
+        let row = TupleLeaf::abi().u64(1).string("hi");
+        assert_eq!(row.encode().unwrap(), [
+            &[0; 31][..], &[1], &[0; 31], &[0x40], &[0; 31], &[2], b"hi", &[0; 30]
+        ].concat());
+
+        let tree = MerkleTree::from_tuples(&claims(TupleLeaf::abi)).unwrap();
+        assert_eq!(tree.hash_function(), HashFunction::Keccak256);
+        assert!(tree.proof_for_tuple(&claims(TupleLeaf::abi)[1]).is_some());
+        assert!(matches!(MerkleTree::from_tuples(&[TupleLeaf::abi(), TupleLeaf::new()]), Err(MerkleTreeError::Incompatible(_))));
+        assert!(TupleLeaf::abi().fixed_bytes(&[0; 33]).encode().is_err());
+    }
+}
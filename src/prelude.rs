@@ -0,0 +1,42 @@
+//! The types most code needs, for a single glob import.
+//!
+//! ```
+//! use merkle_tree::prelude::*;
+//!
+//! let tree = MerkleTree::new(&["a".to_string(), "b".to_string(), "c".to_string()]);
+//! let leaf = LeafHash::try_from(tree.leaves()[1].as_slice())?;
+//! assert!(verify_proof(&tree.root(), &leaf, 1, tree.len(), &tree.proof(1)));
+//! let InclusionProof { leaf, proof } = tree.inclusion_proof(1)?;
+//! assert!(proof.verify(&tree.root(), &leaf));
+//! # Ok::<(), MerkleTreeError>(())
+//! ```
+//!
+//! `RootSigner` and `RootVerifier` are the extension points, implemented downstream for
+//! whatever signature scheme signs the roots. Hashing is not one: `HashFunction` and
+//! `HashScheme` are closed enums, so there is no hasher trait to seal.
+//!
+//! The JSON writer behind `to_json`, the hex and base64 codecs and the tree arithmetic
+//! are implementation details, not part of the API. `Layout` and `DepthLimit` are
+//! reached through `merkle_tree::merkle_tree`.
+//!
+//! ```compile_fail
+//! use merkle_tree::json::Json;
+//! ```
+//!
+//! ```compile_fail
+//! use merkle_tree::hex;
+//! ```
+//!
+//! ```compile_fail
+//! use merkle_tree::base64;
+//! ```
+//!
+//! ```compile_fail
+//! use merkle_tree::tree_math::Layout;
+//! ```
+pub use crate::error::{MerkleTreeError, VerifyError};
+pub use crate::hash::{LeafHash, Root};
+pub use crate::merkle_tree::{compute_root_from_proof, verify_proof, MerkleTree, MerkleTreeBuilder};
+pub use crate::proof::{InclusionProof, MerkleProof};
+pub use crate::scheme::{HashFunction, HashScheme, TreeConfig};
+pub use crate::signed_root::{RootSigner, RootVerifier};
@@ -0,0 +1,565 @@
+use crate::base64;
+use crate::codec::{self, Reader};
+use crate::error::{MerkleTreeError, VerifyError};
+use crate::hash::{LeafHash, Root};
+use crate::hex;
+use crate::json::Json;
+use crate::merkle_tree::{compute_root, DEFAULT_ARITY};
+use crate::scheme::{ByteOrder, HashFunction, HashScheme, Padding, PairOrder, TreeConfig, TreeHasher};
+use crate::tree_math::{group_bounds, proof_len};
+use std::hash::{Hash, Hasher};
+
+mod cache;
+mod compact;
+mod explain;
+mod legacy;
+mod limits;
+mod multi;
+mod solidity;
+mod update;
+mod verifier;
+
+pub use cache::VerifierCache;
+pub use compact::{CompactInclusionProof, CompactProof, MAX_COMPACT_SIBLINGS};
+pub use limits::ProofLimits;
+pub use multi::MultiProof;
+pub use update::UpdateProof;
+pub use verifier::ProofVerifier;
+
+// Siblings authenticating the leaf at `index` of a tree with `leaf_count` leaves, along
+// with the shape and hashing conventions of that tree.
+//
+// Siblings are in the one order verification consumes them: from the level of the leaf
+// up, within a level left to right skipping the proven node. Every way the crate makes
+// proofs produces that order. The arity of a tree of at most one leaf changes nothing, such
+// proofs compare, hash and serialize as binary ones
+#[derive(Debug, Clone)]
+pub struct MerkleProof {
+  pub index: usize,
+  pub leaf_count: usize,
+  pub siblings: Vec<Vec<u8>>,
+  pub arity: usize,
+  pub scheme: HashScheme,
+  pub function: HashFunction,
+  // Of the integers of `to_bytes`, nothing a proof hashes depends on it
+  pub byte_order: ByteOrder,
+  pub pair_order: PairOrder
+}
+
+impl MerkleProof {
+  pub fn compute_root(&self, leaf: &LeafHash) -> Result<Root, MerkleTreeError> {
+    compute_root(leaf.as_bytes(), self.index, self.leaf_count, self.arity, self.hasher(), &self.siblings)
+  }
+
+  pub(crate) fn hasher(&self) -> TreeHasher {
+    TreeHasher::new(self.scheme, self.function).with_byte_order(self.byte_order).with_pair_order(self.pair_order)
+  }
+
+  #[must_use]
+  pub fn config(&self) -> TreeConfig {
+    TreeConfig { arity: self.canonical_arity(), scheme: self.scheme, function: self.function, byte_order: self.byte_order, pair_order: self.pair_order, padding: Padding::PromoteOdd }
+  }
+
+  // Whether the proof has the canonical form, as the proofs the crate makes do. Siblings carry
+  // no level, so an out of order proof can only be told by failing the shape checks
+  #[must_use]
+  pub fn is_canonical(&self) -> bool {
+    self.arity == self.canonical_arity()
+      && self.arity >= 2
+      && self.index < self.leaf_count
+      && self.siblings.len() == proof_len(self.index, self.leaf_count, self.arity)
+      && self.siblings.iter().all(|s| s.len() == self.function.output_len())
+  }
+
+  // Drops the arity of trees where it makes no difference. The siblings are left as they
+  // are, without the tree there is no telling where a misplaced one belongs
+  pub fn canonicalize(&mut self) {
+    self.arity = self.canonical_arity();
+  }
+
+  fn canonical_arity(&self) -> usize {
+    if self.leaf_count <= 1 { DEFAULT_ARITY } else { self.arity }
+  }
+
+  #[must_use]
+  pub fn verify(&self, root: &Root, leaf: &LeafHash) -> bool {
+    self.verify_detailed(root, leaf).is_ok()
+  }
+
+  // `verify` for a verifier expecting trees of `config`, a proof of another kind of tree
+  // is an error rather than a failed verification
+  pub fn verify_as(&self, config: &TreeConfig, root: &Root, leaf: &LeafHash) -> Result<bool, MerkleTreeError> {
+    if self.config() != *config {
+      return Err(MerkleTreeError::ConfigMismatch { expected: *config, got: self.config() });
+    }
+    Ok(self.verify(root, leaf))
+  }
+
+  // `verify` naming the first thing wrong with the proof. Two roots make the error larger
+  // than clippy likes, it is only built when verification already failed
+  #[allow(clippy::result_large_err)]
+  pub fn verify_detailed(&self, root: &Root, leaf: &LeafHash) -> Result<(), VerifyError> {
+    let (index, leaf_count) = (self.index, self.leaf_count);
+    if self.arity < 2 {
+      return Err(VerifyError::BadArity { arity: self.arity });
+    }
+    if index >= leaf_count {
+      return Err(VerifyError::IndexOutOfRange { index, leaf_count });
+    }
+    let expected = proof_len(index, leaf_count, self.arity);
+    if self.siblings.len() != expected {
+      return Err(VerifyError::WrongProofLength { expected, got: self.siblings.len() });
+    }
+    let expected = self.function.output_len();
+    if leaf.len() != expected {
+      return Err(VerifyError::BadLeafLength { expected, got: leaf.len() });
+    }
+    if let Some((level, sibling)) = self.sibling_levels().into_iter().zip(&self.siblings).find(|(_, s)| s.len() != expected) {
+      return Err(VerifyError::BadSiblingLength { level, expected, got: sibling.len() });
+    }
+    let computed = self.compute_root(leaf).expect("the shape of the proof was checked");
+    if computed != *root {
+      return Err(VerifyError::RootMismatch { computed, expected: *root });
+    }
+    Ok(())
+  }
+
+  // Level of each sibling, k-ary proofs have several per level and promoted nodes none
+  fn sibling_levels(&self) -> Vec<usize> {
+    let (mut index, mut size, mut level) = (self.index, self.leaf_count, 0);
+    let mut levels = vec![];
+    while size > 1 {
+      let (start, end) = group_bounds(index, size, self.arity);
+      levels.extend(std::iter::repeat_n(level, end - start - 1));
+      index /= self.arity;
+      size = size.div_ceil(self.arity);
+      level += 1;
+    }
+    levels
+  }
+
+  // `{"index":…,"leaf_count":…,"siblings":["0x…",…]}`, followed by "arity", "scheme"
+  // and "hash" only for trees that are not binary SHA3-256 with the legacy scheme
+  #[must_use]
+  pub fn to_json(&self) -> String {
+    Json::object(self.json_fields()).to_string()
+  }
+
+  // Hashes may be hex strings or arrays of bytes
+  pub fn from_json(text: &str) -> Result<Self, MerkleTreeError> {
+    Self::from_json_with_limits(text, &ProofLimits::default())
+  }
+
+  // `from_json` of text from anyone. Text longer than the limits allow is not parsed,
+  // and nothing is hashed while decoding
+  pub fn from_json_with_limits(text: &str, limits: &ProofLimits) -> Result<Self, MerkleTreeError> {
+    limits.check_input(text.len())?;
+    let proof = Self::from_json_value(&Json::parse(text).map_err(codec::invalid)?)?;
+    limits.check_siblings(&proof.siblings)?;
+    limits.check_depth(proof.siblings.len(), proof.arity)?;
+    Ok(proof)
+  }
+
+  // index, leaf_count and arity as u64, the scheme, byte order and hash function id, then
+  // the siblings as their hash length, their count and their raw bytes. Integers are in
+  // `byte_order`
+  pub fn to_bytes(&self) -> Result<Vec<u8>, MerkleTreeError> {
+    let mut out = Vec::with_capacity(self.serialized_size());
+    self.write_bytes(&mut out)?;
+    Ok(out)
+  }
+
+  // Length of `to_bytes`, for proofs that serialize at all
+  #[must_use]
+  pub fn serialized_size(&self) -> usize {
+    Self::serialized_size_of(self.siblings.iter().map(Vec::len).sum())
+  }
+
+  // The fixed fields and the sibling length and count, then the siblings themselves
+  pub(crate) fn serialized_size_of(sibling_bytes: usize) -> usize {
+    3 * 8 + 1 + 1 + 8 + sibling_bytes
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleTreeError> {
+    Self::from_bytes_with_limits(bytes, &ProofLimits::default())
+  }
+
+  // `from_bytes` of input from anyone, the sibling count and length are checked before
+  // any sibling is read
+  pub fn from_bytes_with_limits(bytes: &[u8], limits: &ProofLimits) -> Result<Self, MerkleTreeError> {
+    limits.check_input(bytes.len())?;
+    let mut reader = Reader::new(bytes);
+    let proof = Self::read_bytes(&mut reader, limits)?;
+    reader.finish()?;
+    Ok(proof)
+  }
+
+  // `to_bytes` in URL safe base64 without padding, for URLs and tokens
+  pub fn to_base64(&self) -> Result<String, MerkleTreeError> {
+    Ok(base64::encode_url(&self.to_bytes()?))
+  }
+
+  // Reads `to_base64`, padded or not
+  pub fn from_base64(text: &str) -> Result<Self, MerkleTreeError> {
+    Self::from_base64_with_limits(text, &ProofLimits::default())
+  }
+
+  pub fn from_base64_with_limits(text: &str, limits: &ProofLimits) -> Result<Self, MerkleTreeError> {
+    limits.check_input(limits::base64_len_bound(text))?;
+    Self::from_bytes_with_limits(&base64::decode_url(text).map_err(codec::invalid)?, limits)
+  }
+
+  fn json_fields(&self) -> Vec<(&'static str, Json)> {
+    let mut fields = vec![
+      ("index", self.index.into()),
+      ("leaf_count", self.leaf_count.into()),
+      ("siblings", Json::Array(self.siblings.iter().map(|s| codec::bytes_to_json(s)).collect())),
+    ];
+    if self.canonical_arity() != DEFAULT_ARITY {
+      fields.push(("arity", self.arity.into()));
+    }
+    if self.scheme != HashScheme::Legacy {
+      fields.push(("scheme", self.scheme.name().into()));
+    }
+    if self.function != HashFunction::default() {
+      fields.push(("hash", self.function.name().into()));
+    }
+    if self.byte_order != ByteOrder::default() {
+      fields.push(("byte_order", self.byte_order.name().into()));
+    }
+    if self.pair_order != PairOrder::default() {
+      fields.push(("pair_order", self.pair_order.name().into()));
+    }
+    fields
+  }
+
+  fn from_json_value(json: &Json) -> Result<Self, MerkleTreeError> {
+    let arity = match json.get("arity") {
+      Some(arity) => codec::usize_from_json("arity", Some(arity))?,
+      None => DEFAULT_ARITY
+    };
+    let scheme = match json.get("scheme") {
+      None => HashScheme::Legacy,
+      Some(name) => name.as_str().and_then(HashScheme::from_name)
+        .ok_or_else(|| codec::invalid("scheme must be \"legacy\" or \"v1\""))?
+    };
+    let function = match json.get("hash") {
+      None => HashFunction::default(),
+      Some(name) => name.as_str().and_then(HashFunction::from_name)
+        .ok_or_else(|| codec::invalid("unknown hash function"))?
+    };
+    codec::check_padding(json.get("padding"))?;
+    Ok(Self {
+      index: codec::usize_from_json("index", json.get("index"))?,
+      leaf_count: codec::usize_from_json("leaf_count", json.get("leaf_count"))?,
+      siblings: codec::list_from_json("siblings", json.get("siblings"))?,
+      arity,
+      scheme,
+      function,
+      byte_order: codec::byte_order_from_json(json.get("byte_order"))?,
+      pair_order: codec::pair_order_from_json(json.get("pair_order"))?
+    })
+  }
+
+  fn write_bytes(&self, out: &mut Vec<u8>) -> Result<(), MerkleTreeError> {
+    let order = self.byte_order;
+    codec::put_u64(out, self.index, order);
+    codec::put_u64(out, self.leaf_count, order);
+    codec::put_u64(out, self.canonical_arity(), order);
+    out.push(self.hasher().id());
+    codec::put_hashes(out, &self.siblings, order)
+  }
+
+  // The id giving the byte order follows the three integers written in it
+  fn read_bytes(reader: &mut Reader, limits: &ProofLimits) -> Result<Self, MerkleTreeError> {
+    let hasher = Self::peek_hasher(reader, 0)?;
+    reader.order = hasher.byte_order;
+    let (index, leaf_count, arity) = (reader.usize()?, reader.usize()?, reader.usize()?);
+    reader.u8()?;
+    let siblings = reader.hashes_checked(|len, count| limits.check_frame(len, count).and_then(|()| limits.check_depth(count, arity)))?;
+    Ok(Self { index, leaf_count, siblings, arity, scheme: hasher.scheme, function: hasher.function, byte_order: hasher.byte_order, pair_order: hasher.pair_order })
+  }
+
+  // Hasher of the proof starting `offset` bytes ahead of `reader`
+  fn peek_hasher(reader: &Reader, offset: usize) -> Result<TreeHasher, MerkleTreeError> {
+    TreeHasher::from_id(reader.peek(offset + 3 * 8)?).ok_or_else(|| codec::invalid("unknown scheme id"))
+  }
+}
+
+// Equality of the canonical forms, see `MerkleProof`
+impl PartialEq for MerkleProof {
+  fn eq(&self, other: &Self) -> bool {
+    self.canonical_key() == other.canonical_key()
+  }
+}
+
+impl Eq for MerkleProof {}
+
+impl Hash for MerkleProof {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.canonical_key().hash(state);
+  }
+}
+
+impl MerkleProof {
+  fn canonical_key(&self) -> (usize, usize, &[Vec<u8>], usize, TreeHasher) {
+    (self.index, self.leaf_count, &self.siblings, self.canonical_arity(), self.hasher())
+  }
+}
+
+// A proof together with the leaf hash it proves, what gets handed to verifiers
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InclusionProof {
+  pub leaf: LeafHash,
+  pub proof: MerkleProof
+}
+
+impl InclusionProof {
+  #[must_use]
+  pub fn verify(&self, root: &Root) -> bool {
+    self.proof.verify(root, &self.leaf)
+  }
+
+  pub fn verify_as(&self, config: &TreeConfig, root: &Root) -> Result<bool, MerkleTreeError> {
+    self.proof.verify_as(config, root, &self.leaf)
+  }
+
+  // `{"leaf":"0x…",…}` followed by the fields of `MerkleProof::to_json`
+  #[must_use]
+  pub fn to_json(&self) -> String {
+    let mut fields = vec![("leaf", codec::bytes_to_json(self.leaf.as_bytes()))];
+    fields.extend(self.proof.json_fields());
+    Json::object(fields).to_string()
+  }
+
+  pub fn from_json(text: &str) -> Result<Self, MerkleTreeError> {
+    Self::from_json_with_limits(text, &ProofLimits::default())
+  }
+
+  // See `MerkleProof::from_json_with_limits`
+  pub fn from_json_with_limits(text: &str, limits: &ProofLimits) -> Result<Self, MerkleTreeError> {
+    limits.check_input(text.len())?;
+    let json = Json::parse(text).map_err(codec::invalid)?;
+    let proof = Self {
+      leaf: LeafHash::try_from(codec::bytes_from_json("leaf", json.get("leaf"))?.as_slice())?,
+      proof: MerkleProof::from_json_value(&json)?
+    };
+    limits.check_siblings(&proof.proof.siblings)?;
+    limits.check_depth(proof.proof.siblings.len(), proof.proof.arity)?;
+    Ok(proof)
+  }
+
+  // The leaf as its length and raw bytes, followed by `MerkleProof::to_bytes`. The leaf
+  // frame is in the byte order of the proof
+  pub fn to_bytes(&self) -> Result<Vec<u8>, MerkleTreeError> {
+    let mut out = Vec::with_capacity(self.serialized_size());
+    codec::put_hashes(&mut out, &[self.leaf.to_vec()], self.proof.byte_order)?;
+    self.proof.write_bytes(&mut out)?;
+    Ok(out)
+  }
+
+  // Length of `to_bytes`, the leaf is framed like a list of one hash
+  #[must_use]
+  pub fn serialized_size(&self) -> usize {
+    1 + 8 + self.leaf.len() + self.proof.serialized_size()
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleTreeError> {
+    Self::from_bytes_with_limits(bytes, &ProofLimits::default())
+  }
+
+  // See `MerkleProof::from_bytes_with_limits`, the leaf is held to the sibling length limit
+  pub fn from_bytes_with_limits(bytes: &[u8], limits: &ProofLimits) -> Result<Self, MerkleTreeError> {
+    limits.check_input(bytes.len())?;
+    let mut reader = Reader::new(bytes);
+    // the proof starts after the hash length, the count of one and the leaf
+    reader.order = MerkleProof::peek_hasher(&reader, 1 + 8 + usize::from(reader.peek(0)?))?.byte_order;
+    let leaf = reader.hashes_checked(|len, count| limits.check_frame(len, count))?;
+    let [leaf] = <[Vec<u8>; 1]>::try_from(leaf).map_err(|_| codec::invalid("expected a single leaf"))?;
+    let leaf = LeafHash::try_from(leaf.as_slice())?;
+    let proof = MerkleProof::read_bytes(&mut reader, limits)?;
+    reader.finish()?;
+    Ok(Self { leaf, proof })
+  }
+
+  // `MerkleProof::to_base64` of `to_bytes`
+  pub fn to_base64(&self) -> Result<String, MerkleTreeError> {
+    Ok(base64::encode_url(&self.to_bytes()?))
+  }
+
+  pub fn from_base64(text: &str) -> Result<Self, MerkleTreeError> {
+    Self::from_base64_with_limits(text, &ProofLimits::default())
+  }
+
+  pub fn from_base64_with_limits(text: &str, limits: &ProofLimits) -> Result<Self, MerkleTreeError> {
+    limits.check_input(limits::base64_len_bound(text))?;
+    Self::from_bytes_with_limits(&base64::decode_url(text).map_err(codec::invalid)?, limits)
+  }
+
+  // `to_bytes` as 0x prefixed hex
+  pub fn to_hex(&self) -> Result<String, MerkleTreeError> {
+    Ok(hex::encode(&self.to_bytes()?))
+  }
+
+  // Reads `to_hex`, with or without the prefix
+  pub fn from_hex(text: &str) -> Result<Self, MerkleTreeError> {
+    let limits = ProofLimits::default();
+    limits.check_input(text.len() / 2)?;
+    Self::from_bytes_with_limits(&hex::decode(text).map_err(codec::invalid)?, &limits)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{MerkleTreeError, VerifyError};
+    use crate::hash::LeafHash;
+    use crate::merkle_tree::MerkleTree;
+    use crate::proof::InclusionProof;
+    use crate::scheme::{HashFunction, HashScheme, Padding, PairOrder, TreeConfig};
+
+    fn tree() -> MerkleTree {
+        MerkleTree::new(&(0..6).map(|i| i.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn proofs_of_other_kinds_of_trees_are_config_mismatches() {
+        let elements: Vec<String> = (0..5).map(|i| i.to_string()).collect();
+        let keccak = MerkleTree::from_leaves_with(&MerkleTree::new_sorted(&elements).leaves().iter()
+            .map(|l| LeafHash::try_from(HashFunction::Keccak256.digest(&[l]).as_slice()).unwrap()).collect::<Vec<_>>(), HashFunction::Keccak256).unwrap();
+        let proof = keccak.inclusion_proof(1).unwrap();
+        assert_eq!(proof.proof.config(), keccak.config());
+        assert_eq!(proof.verify_as(&keccak.config(), &keccak.root()), Ok(true));
+
+        let sha3 = TreeConfig::default();
+        assert_eq!(tree().config(), sha3);
+        assert_eq!(proof.verify_as(&sha3, &keccak.root()), Err(MerkleTreeError::ConfigMismatch { expected: sha3, got: keccak.config() }));
+        assert_eq!(MerkleTreeError::ConfigMismatch { expected: sha3, got: keccak.config() }.to_string(),
+            "expected a proof of a 2-ary sha3-256 tree with the legacy scheme, got one of a 2-ary keccak256 tree with the legacy scheme");
+
+        // the pair order alone makes another kind of tree
+        let sorted = MerkleTree::from_sorted_pair_leaves(&keccak.leaves().iter().map(|l| LeafHash::try_from(l.as_slice()).unwrap()).collect::<Vec<_>>()).unwrap();
+        let proof = sorted.inclusion_proof(1).unwrap();
+        assert_eq!(proof.proof.config(), TreeConfig { function: HashFunction::Keccak256, pair_order: PairOrder::Sorted, padding: Padding::PromoteOdd, ..sha3 });
+        assert_eq!(proof.verify_as(&sorted.config(), &sorted.root()), Ok(true));
+        assert_eq!(proof.verify_as(&sha3, &sorted.root()), Err(MerkleTreeError::ConfigMismatch { expected: sha3, got: sorted.config() }));
+        assert_eq!(proof.verify_as(&keccak.config(), &sorted.root()), Err(MerkleTreeError::ConfigMismatch { expected: keccak.config(), got: sorted.config() }));
+        assert!(proof.proof.verify_as(&keccak.config(), &sorted.root(), &proof.leaf).unwrap_err().to_string()
+            .ends_with("got one of a 2-ary keccak256 tree with the legacy scheme, sorted pairs"));
+        assert!(InclusionProof::from_json(&proof.to_json().replace(r#""pair_order""#, r#""padding":"zeros","pair_order""#)).is_err());
+        let wide = TreeConfig { arity: 4, ..sha3 };
+        assert!(tree().inclusion_proof(0).unwrap().verify_as(&wide, &tree().root()).is_err());
+        assert_eq!(tree().inclusion_proof(0).unwrap().verify_as(&sha3, &keccak.root()), Ok(false));
+    }
+
+    #[test]
+    fn every_way_of_making_a_proof_gives_the_same_bytes() {
+        let tree = tree();
+        let all = tree.proofs_for_all();
+        for (index, proof) in all.iter().enumerate() {
+            let leaf = tree.leaf_hash(index).unwrap();
+            let from_index = tree.inclusion_proof(index).unwrap().proof;
+            let from_hash = tree.proofs_by_leaf_hash(&leaf).remove(0).1;
+            let filtered = tree.proofs_where(|i, _| i == index).remove(0).1;
+            assert!(from_index.is_canonical());
+            assert_eq!(from_index.siblings, tree.proof(index));
+            for proof in [proof, &from_hash, &filtered] {
+                assert_eq!(proof.to_bytes(), from_index.to_bytes());
+                assert_eq!(proof.to_json(), from_index.to_json());
+            }
+        }
+    }
+
+    #[test]
+    fn proofs_compare_by_their_canonical_form() {
+        use std::collections::HashSet;
+        let single = ["only".to_string()];
+        let binary = MerkleTree::new(&single).inclusion_proof(0).unwrap();
+        let mut wide = MerkleTree::with_arity(&single, 4).unwrap().inclusion_proof(0).unwrap();
+        assert_eq!(wide, binary);
+        assert_eq!(wide.proof.to_bytes(), binary.proof.to_bytes());
+        assert_eq!(HashSet::from([wide.clone(), binary.clone()]).len(), 1);
+        assert!(!wide.proof.is_canonical());
+        wide.proof.canonicalize();
+        assert!(wide.proof.is_canonical() && wide.proof.arity == 2);
+
+        let mut swapped = tree().inclusion_proof(0).unwrap();
+        swapped.proof.siblings.swap(0, 1);
+        assert_ne!(swapped, tree().inclusion_proof(0).unwrap());
+        let mut short = swapped.proof.clone();
+        short.siblings.pop();
+        assert!(!short.is_canonical());
+    }
+
+    #[test]
+    fn detailed_verification_names_the_failure() {
+        let tree = tree();
+        let InclusionProof { leaf, proof } = tree.inclusion_proof(4).unwrap();
+        assert_eq!(proof.verify_detailed(&tree.root(), &leaf), Ok(()));
+
+        let other = MerkleTree::new(&["x".to_string()]).root();
+        assert_eq!(proof.verify_detailed(&other, &leaf), Err(VerifyError::RootMismatch { computed: tree.root(), expected: other }));
+        let mut moved = proof.clone();
+        moved.index = 6;
+        assert_eq!(moved.verify_detailed(&tree.root(), &leaf), Err(VerifyError::IndexOutOfRange { index: 6, leaf_count: 6 }));
+        let mut short = proof.clone();
+        short.siblings.pop();
+        assert_eq!(short.verify_detailed(&tree.root(), &leaf), Err(VerifyError::WrongProofLength { expected: 2, got: 1 }));
+        // the leaf at 4 is promoted over level 1, its second sibling is on level 2
+        let mut truncated = proof.clone();
+        truncated.siblings[1].pop();
+        assert_eq!(truncated.verify_detailed(&tree.root(), &leaf), Err(VerifyError::BadSiblingLength { level: 2, expected: 32, got: 31 }));
+        let wide = LeafHash::try_from(&[0; 64][..]).unwrap();
+        assert_eq!(proof.verify_detailed(&tree.root(), &wide), Err(VerifyError::BadLeafLength { expected: 32, got: 64 }));
+        assert!(!truncated.verify(&tree.root(), &leaf));
+    }
+
+    #[test]
+    fn inclusion_proofs_verify_and_round_trip_through_json() {
+        let tree = tree();
+        for index in 0..6 {
+            let proof = tree.inclusion_proof(index).unwrap();
+            assert!(proof.verify(&tree.root()));
+            assert_eq!(InclusionProof::from_json(&proof.to_json()).unwrap(), proof);
+        }
+        assert_eq!(tree.inclusion_proof(6).err(), Some(MerkleTreeError::IndexOutOfBounds { index: 6, len: 6 }));
+    }
+
+    #[test]
+    fn counts_past_two_to_the_53_round_trip_exactly() {
+        let mut proof = tree().inclusion_proof(5).unwrap();
+        for leaf_count in [(1 << 53) + 1, usize::MAX] {
+            (proof.proof.index, proof.proof.leaf_count) = (leaf_count - 1, leaf_count);
+            let json = proof.to_json();
+            assert!(json.contains(&format!(r#""index":{},"leaf_count":{leaf_count}"#, leaf_count - 1)));
+            assert_eq!(InclusionProof::from_json(&json).unwrap(), proof);
+        }
+    }
+
+    #[test]
+    fn json_uses_hex_strings() {
+        let json = tree().inclusion_proof(5).unwrap().to_json();
+        assert!(json.starts_with(r#"{"leaf":"0x"#));
+        assert!(json.contains(r#""index":5,"leaf_count":6,"siblings":["0x"#));
+    }
+
+    #[test]
+    fn malformed_json_names_the_problem() {
+        let json = tree().inclusion_proof(1).unwrap().to_json();
+        let bad_hex = json.replacen(r#""siblings":["0x"#, r#""siblings":["0xzz"#, 1);
+        assert!(matches!(InclusionProof::from_json(&bad_hex), Err(MerkleTreeError::InvalidEncoding(e)) if e.starts_with("bad hex in siblings[0]")));
+        let missing = json.replacen(r#""index""#, r#""position""#, 1);
+        assert!(matches!(InclusionProof::from_json(&missing), Err(MerkleTreeError::InvalidEncoding(e)) if e.starts_with("index")));
+    }
+
+    #[test]
+    fn proofs_carry_the_shape_of_non_default_trees() {
+        let elements: Vec<String> = (0..6).map(|i| i.to_string()).collect();
+        for tree in [MerkleTree::with_arity(&elements, 4).unwrap(), MerkleTree::with_scheme(&elements, HashScheme::V1)] {
+            let proof = tree.inclusion_proof(5).unwrap();
+            assert!(proof.verify(&tree.root()));
+            let json = proof.to_json();
+            assert!(json.contains(r#""arity":4"#) || json.contains(r#""scheme":"v1""#));
+            assert_eq!(InclusionProof::from_json(&json).unwrap(), proof);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::hash::{LeafHash, Root};
+use crate::merkle_tree::verify_proof;
+use crate::proof::InclusionProof;
+use crate::scheme::{TreeConfig, TreeHasher};
+use sha3::{Digest, Sha3_256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// Digest of everything a verification result depends on
+type Key = [u8; 32];
+
+// Results of verifications already done, for services checking the same proofs over and
+// over. Entries are keyed by a digest of the root, the leaf, the proof and the conventions
+// of its tree, so a proof made for one kind of tree never answers for another. The least
+// recently used entry goes once `capacity` are held.
+//
+// Verification runs outside the lock and only its own result is stored, threads racing on
+// the same proof store the same answer
+#[derive(Debug)]
+pub struct VerifierCache {
+  capacity: usize,
+  entries: Mutex<Entries>,
+  hits: AtomicU64,
+  misses: AtomicU64
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+  results: HashMap<Key, (bool, u64)>,
+  // keys by the tick they were last used at, the oldest first
+  recency: BTreeMap<u64, Key>,
+  tick: u64
+}
+
+impl VerifierCache {
+  // A cache of capacity 0 stores nothing and verifies every time
+  #[must_use]
+  pub fn new(capacity: usize) -> Self {
+    Self { capacity, entries: Mutex::default(), hits: AtomicU64::new(0), misses: AtomicU64::new(0) }
+  }
+
+  // `InclusionProof::verify` under the tree conventions the proof carries
+  #[must_use]
+  pub fn verify(&self, proof: &InclusionProof, root: &Root) -> bool {
+    let inner = &proof.proof;
+    let key = key(inner.config(), root, proof.leaf.as_bytes(), inner.index, inner.leaf_count, &inner.siblings);
+    self.cached(key, || proof.verify(root))
+  }
+
+  // `merkle_tree::verify_proof`, the binary SHA3-256 trees of `MerkleTree::new`
+  #[must_use]
+  pub fn verify_proof(&self, root: &Root, leaf_hash: &LeafHash, index: usize, leaf_count: usize, proof: &[Vec<u8>]) -> bool {
+    let key = key(TreeConfig::default(), root, leaf_hash.as_bytes(), index, leaf_count, proof);
+    self.cached(key, || verify_proof(root, leaf_hash, index, leaf_count, proof))
+  }
+
+  #[must_use]
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  #[must_use]
+  pub fn len(&self) -> usize {
+    self.lock().results.len()
+  }
+
+  #[must_use]
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  #[must_use]
+  pub fn hits(&self) -> u64 {
+    self.hits.load(Ordering::Relaxed)
+  }
+
+  #[must_use]
+  pub fn misses(&self) -> u64 {
+    self.misses.load(Ordering::Relaxed)
+  }
+
+  pub fn clear(&self) {
+    *self.lock() = Entries::default();
+  }
+
+  fn cached(&self, key: Key, verify: impl FnOnce() -> bool) -> bool {
+    if let Some(valid) = self.lock().get(&key) {
+      self.hits.fetch_add(1, Ordering::Relaxed);
+      return valid;
+    }
+    self.misses.fetch_add(1, Ordering::Relaxed);
+    let valid = verify();
+    if self.capacity > 0 {
+      self.lock().insert(key, valid, self.capacity);
+    }
+    valid
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+    self.entries.lock().expect("a thread panicked while holding the cache")
+  }
+}
+
+impl Entries {
+  fn get(&mut self, key: &Key) -> Option<bool> {
+    let tick = self.next_tick();
+    let (valid, used) = self.results.get_mut(key)?;
+    self.recency.remove(used);
+    self.recency.insert(tick, *key);
+    *used = tick;
+    Some(*valid)
+  }
+
+  fn insert(&mut self, key: Key, valid: bool, capacity: usize) {
+    let tick = self.next_tick();
+    // another thread may have stored the same result since the miss
+    if let Some((_, used)) = self.results.insert(key, (valid, tick)) {
+      self.recency.remove(&used);
+    }
+    self.recency.insert(tick, key);
+    while self.results.len() > capacity {
+      let (_, oldest) = self.recency.pop_first().expect("every entry has a tick");
+      self.results.remove(&oldest);
+    }
+  }
+
+  fn next_tick(&mut self) -> u64 {
+    self.tick += 1;
+    self.tick
+  }
+}
+
+// Every field goes in with its length or at a fixed size, so no two different inputs
+// feed the digest the same bytes
+fn key(config: TreeConfig, root: &Root, leaf: &[u8], index: usize, leaf_count: usize, siblings: &[Vec<u8>]) -> Key {
+  let mut hasher = Sha3_256::new();
+  for n in [config.arity, index, leaf_count, siblings.len()] {
+    hasher.update((n as u64).to_le_bytes());
+  }
+  hasher.update([TreeHasher::of(config).id()]);
+  for hash in [root.as_bytes(), leaf].into_iter().chain(siblings.iter().map(Vec::as_slice)) {
+    hasher.update((hash.len() as u64).to_le_bytes());
+    hasher.update(hash);
+  }
+  hasher.finalize().into()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::MerkleTree;
+    use crate::proof::VerifierCache;
+    use crate::scheme::HashScheme;
+    use std::thread;
+
+    fn tree(count: usize) -> MerkleTree {
+        MerkleTree::new(&(0..count).map(|i| format!("leaf {i}")).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn repeated_verifications_hit_the_cache() {
+        let tree = tree(7);
+        let cache = VerifierCache::new(16);
+        let proof = tree.inclusion_proof(3).unwrap();
+        assert!(cache.verify(&proof, &tree.root()));
+        assert!(cache.verify(&proof, &tree.root()));
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 1, 1));
+
+        // the same verification through the free function shares the entry
+        let leaf = tree.leaf_hash(3).unwrap();
+        assert!(cache.verify_proof(&tree.root(), &leaf, 3, 7, &tree.proof(3)));
+        assert_eq!((cache.hits(), cache.misses()), (2, 1));
+
+        // failures are cached as failures
+        let mut tampered = proof.clone();
+        tampered.proof.siblings[0][0] ^= 1;
+        assert!(!cache.verify(&tampered, &tree.root()));
+        assert!(!cache.verify(&tampered, &tree.root()));
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (3, 2, 2));
+
+        // the same siblings under other conventions are another verification
+        let mut other = proof.clone();
+        other.proof.scheme = HashScheme::V1;
+        assert!(!cache.verify(&other, &tree.root()));
+        assert!(!cache.verify_proof(&tree.root(), &leaf, 3, 4, &tree.proof(3)));
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (3, 4, 4));
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert!(cache.verify(&proof, &tree.root()));
+        assert_eq!(cache.misses(), 5);
+    }
+
+    #[test]
+    fn the_least_recently_used_entry_is_evicted() {
+        let tree = tree(8);
+        let cache = VerifierCache::new(2);
+        let proofs: Vec<_> = (0..3).map(|i| tree.inclusion_proof(i).unwrap()).collect();
+        assert!(cache.verify(&proofs[0], &tree.root()));
+        assert!(cache.verify(&proofs[1], &tree.root()));
+        // using the first again makes the second the oldest
+        assert!(cache.verify(&proofs[0], &tree.root()));
+        assert!(cache.verify(&proofs[2], &tree.root()));
+        assert_eq!((cache.len(), cache.capacity()), (2, 2));
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+
+        assert!(cache.verify(&proofs[0], &tree.root()));
+        assert!(cache.verify(&proofs[2], &tree.root()));
+        assert_eq!(cache.hits(), 3);
+        assert!(cache.verify(&proofs[1], &tree.root()));
+        assert_eq!((cache.misses(), cache.len()), (4, 2));
+
+        let uncached = VerifierCache::new(0);
+        assert!(uncached.verify(&proofs[0], &tree.root()) && uncached.verify(&proofs[0], &tree.root()));
+        assert_eq!((uncached.hits(), uncached.misses(), uncached.len()), (0, 2, 0));
+    }
+
+    #[test]
+    fn racing_verifications_store_only_their_own_results() {
+        let tree = tree(16);
+        let root = tree.root();
+        let cache = VerifierCache::new(8);
+        let proofs: Vec<_> = (0..16).map(|i| {
+            let valid = tree.inclusion_proof(i).unwrap();
+            let mut tampered = valid.clone();
+            tampered.leaf = tree.leaf_hash((i + 1) % 16).unwrap();
+            (valid, tampered)
+        }).collect();
+        thread::scope(|scope| {
+            for worker in 0..8 {
+                let (cache, proofs, root) = (&cache, &proofs, &root);
+                scope.spawn(move || {
+                    for round in 0..200 {
+                        let (valid, tampered) = &proofs[(worker * 7 + round) % 16];
+                        assert!(cache.verify(valid, root));
+                        assert!(!cache.verify(tampered, root));
+                    }
+                });
+            }
+        });
+        assert_eq!(cache.hits() + cache.misses(), 8 * 200 * 2);
+        assert!(cache.len() <= 8);
+        for (valid, tampered) in &proofs {
+            assert!(cache.verify(valid, &root));
+            assert!(!cache.verify(tampered, &root));
+        }
+    }
+}
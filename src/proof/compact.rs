@@ -0,0 +1,280 @@
+use crate::error::MerkleTreeError;
+use crate::hash::{LeafHash, Root};
+use crate::merkle_tree::{MerkleTree, DEFAULT_ARITY, HASH_LEN};
+use crate::proof::{InclusionProof, MerkleProof};
+use crate::scheme::{ByteOrder, Digester, HashFunction, HashScheme, PairOrder, TreeHasher};
+use crate::tree_math::{group_bounds, proof_len};
+
+// A binary tree over any number of leaves has at most one sibling per bit of the count
+pub const MAX_COMPACT_SIBLINGS: usize = usize::BITS as usize;
+
+// `MerkleProof` of a binary tree with 32 byte hashes, the siblings held inline so making
+// and verifying one does not allocate per sibling. Serializes exactly as `MerkleProof`,
+// which keeps its `Vec` siblings for the k-ary and 64 byte proofs no fixed slots hold
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactProof {
+  index: usize,
+  leaf_count: usize,
+  // the first `len` are the siblings from the leaf up, the rest stay zero
+  siblings: [[u8; HASH_LEN]; MAX_COMPACT_SIBLINGS],
+  len: u8,
+  scheme: HashScheme,
+  function: HashFunction,
+  byte_order: ByteOrder,
+  pair_order: PairOrder
+}
+
+impl CompactProof {
+  #[must_use]
+  pub fn index(&self) -> usize {
+    self.index
+  }
+
+  #[must_use]
+  pub fn leaf_count(&self) -> usize {
+    self.leaf_count
+  }
+
+  #[must_use]
+  pub fn siblings(&self) -> &[[u8; HASH_LEN]] {
+    &self.siblings[..usize::from(self.len)]
+  }
+
+  #[must_use]
+  pub fn scheme(&self) -> HashScheme {
+    self.scheme
+  }
+
+  #[must_use]
+  pub fn hash_function(&self) -> HashFunction {
+    self.function
+  }
+
+  #[must_use]
+  pub fn byte_order(&self) -> ByteOrder {
+    self.byte_order
+  }
+
+  #[must_use]
+  pub fn pair_order(&self) -> PairOrder {
+    self.pair_order
+  }
+
+  // Same checks and root as `MerkleProof::compute_root`
+  pub fn compute_root(&self, leaf: &LeafHash) -> Result<Root, MerkleTreeError> {
+    if self.index >= self.leaf_count {
+      return Err(MerkleTreeError::IndexOutOfBounds { index: self.index, len: self.leaf_count });
+    }
+    let expected = proof_len(self.index, self.leaf_count, DEFAULT_ARITY);
+    if self.siblings().len() != expected {
+      return Err(MerkleTreeError::InvalidProofLength { expected, got: self.siblings().len() });
+    }
+    if leaf.len() != HASH_LEN {
+      return Err(MerkleTreeError::InvalidHashLength { expected: HASH_LEN, got: leaf.len() });
+    }
+    let hasher = TreeHasher::new(self.scheme, self.function).with_pair_order(self.pair_order);
+    let mut digester = Digester::new(self.function);
+    let (mut hash, mut parent) = (leaf.to_vec(), Vec::with_capacity(HASH_LEN));
+    let (mut index, mut size) = (self.index, self.leaf_count);
+    let mut siblings = self.siblings().iter();
+    while size > 1 {
+      let (start, end) = group_bounds(index, size, DEFAULT_ARITY);
+      if end - start == 2 {
+        let sibling = siblings.next().expect("the length was checked");
+        let children = if index == start { [&hash[..], sibling] } else { [sibling, &hash[..]] };
+        hasher.node_into(&mut digester, children, &mut parent);
+        std::mem::swap(&mut hash, &mut parent);
+      }
+      index /= DEFAULT_ARITY;
+      size = size.div_ceil(DEFAULT_ARITY);
+    }
+    Root::try_from(hash.as_slice())
+  }
+
+  #[must_use]
+  pub fn verify(&self, root: &Root, leaf: &LeafHash) -> bool {
+    self.compute_root(leaf).is_ok_and(|computed| computed == *root)
+  }
+
+  pub fn to_bytes(&self) -> Result<Vec<u8>, MerkleTreeError> {
+    MerkleProof::from(self).to_bytes()
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleTreeError> {
+    Self::try_from(&MerkleProof::from_bytes(bytes)?)
+  }
+
+  #[must_use]
+  pub fn to_json(&self) -> String {
+    MerkleProof::from(self).to_json()
+  }
+
+  pub fn from_json(text: &str) -> Result<Self, MerkleTreeError> {
+    Self::try_from(&MerkleProof::from_json(text)?)
+  }
+}
+
+// `InclusionProof` holding a `CompactProof`, what `MerkleTree::compact_inclusion_proof`
+// makes for hot paths proving binary 32 byte trees
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactInclusionProof {
+  pub leaf: LeafHash,
+  pub proof: CompactProof
+}
+
+impl CompactInclusionProof {
+  #[must_use]
+  pub fn verify(&self, root: &Root) -> bool {
+    self.proof.verify(root, &self.leaf)
+  }
+}
+
+impl From<&CompactInclusionProof> for InclusionProof {
+  fn from(proof: &CompactInclusionProof) -> Self {
+    Self { leaf: proof.leaf, proof: MerkleProof::from(&proof.proof) }
+  }
+}
+
+impl TryFrom<&InclusionProof> for CompactInclusionProof {
+  type Error = MerkleTreeError;
+
+  fn try_from(proof: &InclusionProof) -> Result<Self, MerkleTreeError> {
+    Ok(Self { leaf: proof.leaf, proof: CompactProof::try_from(&proof.proof)? })
+  }
+}
+
+impl From<&CompactProof> for MerkleProof {
+  fn from(proof: &CompactProof) -> Self {
+    Self {
+      index: proof.index,
+      leaf_count: proof.leaf_count,
+      siblings: proof.siblings().iter().map(|s| s.to_vec()).collect(),
+      arity: DEFAULT_ARITY,
+      scheme: proof.scheme,
+      function: proof.function,
+      byte_order: proof.byte_order,
+      pair_order: proof.pair_order
+    }
+  }
+}
+
+// Proofs of trees that are not binary or hash to another length have no compact form
+impl TryFrom<&MerkleProof> for CompactProof {
+  type Error = MerkleTreeError;
+
+  fn try_from(proof: &MerkleProof) -> Result<Self, MerkleTreeError> {
+    check_shape(proof.config().arity, proof.function)?;
+    if proof.siblings.len() > MAX_COMPACT_SIBLINGS {
+      return Err(MerkleTreeError::InvalidProofLength { expected: MAX_COMPACT_SIBLINGS, got: proof.siblings.len() });
+    }
+    let mut compact = Self::empty(proof.index, proof.leaf_count, proof.hasher());
+    for sibling in &proof.siblings {
+      compact.push(sibling)?;
+    }
+    Ok(compact)
+  }
+}
+
+impl CompactProof {
+  fn empty(index: usize, leaf_count: usize, hasher: TreeHasher) -> Self {
+    let TreeHasher { scheme, function, byte_order, pair_order } = hasher;
+    Self { index, leaf_count, siblings: [[0; HASH_LEN]; MAX_COMPACT_SIBLINGS], len: 0, scheme, function, byte_order, pair_order }
+  }
+
+  fn push(&mut self, sibling: &[u8]) -> Result<(), MerkleTreeError> {
+    self.siblings[usize::from(self.len)] = sibling.try_into()
+      .map_err(|_| MerkleTreeError::InvalidHashLength { expected: HASH_LEN, got: sibling.len() })?;
+    self.len += 1;
+    Ok(())
+  }
+}
+
+fn check_shape(arity: usize, function: HashFunction) -> Result<(), MerkleTreeError> {
+  if arity != DEFAULT_ARITY {
+    return Err(MerkleTreeError::Incompatible(format!("compact proofs are binary, the tree has arity {arity}")));
+  }
+  if function.output_len() != HASH_LEN {
+    return Err(MerkleTreeError::Incompatible(format!("compact proofs hold {HASH_LEN} byte hashes, {} makes {}", function.name(), function.output_len())));
+  }
+  Ok(())
+}
+
+impl MerkleTree {
+  // `inclusion_proof` without the leaf, copied straight into a `CompactProof`
+  pub fn compact_proof(&self, index: usize) -> Result<CompactProof, MerkleTreeError> {
+    check_shape(self.arity(), self.hash_function())?;
+    if index >= self.len() {
+      return Err(MerkleTreeError::IndexOutOfBounds { index, len: self.len() });
+    }
+    let layout = self.layout();
+    let mut proof = CompactProof::empty(index, self.len(), self.hasher());
+    let mut position = index;
+    for level in 0..layout.height() {
+      let size = layout.level_size(level).expect("levels of the tree");
+      let (start, end) = group_bounds(position, size, DEFAULT_ARITY);
+      if end - start == 2 {
+        let sibling = layout.node_index(level, start + end - 1 - position).and_then(|node| self.node(node));
+        proof.push(sibling.expect("siblings are nodes of the tree"))?;
+      }
+      position /= DEFAULT_ARITY;
+    }
+    Ok(proof)
+  }
+
+  // `inclusion_proof` of a binary 32 byte tree as a `CompactInclusionProof`, the siblings
+  // copied straight into it
+  pub fn compact_inclusion_proof(&self, index: usize) -> Result<CompactInclusionProof, MerkleTreeError> {
+    let proof = self.compact_proof(index)?;
+    let leaf = self.leaf_hash(index).expect("the index was checked");
+    Ok(CompactInclusionProof { leaf, proof })
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::merkle_tree::MerkleTree;
+    use crate::proof::{CompactInclusionProof, CompactProof, InclusionProof, MerkleProof};
+    use crate::scheme::{HashFunction, HashScheme};
+
+    fn elements(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("entry {i}")).collect()
+    }
+
+    #[test]
+    fn compact_proofs_are_the_inclusion_proofs() {
+        for count in [1, 2, 5, 8, 13] {
+            for tree in [MerkleTree::new(&elements(count)), MerkleTree::with_scheme(&elements(count), HashScheme::V1)] {
+                for index in 0..count {
+                    let (compact, proof) = (tree.compact_proof(index).unwrap(), tree.inclusion_proof(index).unwrap());
+                    assert_eq!(MerkleProof::from(&compact), proof.proof);
+                    assert_eq!(CompactProof::try_from(&proof.proof).unwrap(), compact);
+                    let inclusion = tree.compact_inclusion_proof(index).unwrap();
+                    assert_eq!(InclusionProof::from(&inclusion), proof);
+                    assert_eq!(CompactInclusionProof::try_from(&proof).unwrap(), inclusion);
+                    assert!(inclusion.verify(&tree.root()));
+                    assert!(compact.verify(&tree.root(), &proof.leaf));
+                    assert!(!compact.verify(&tree.root(), &tree.leaf_hash((index + 1) % count).unwrap()) || count == 1);
+                }
+                assert_eq!(tree.compact_proof(count).err(), Some(MerkleTreeError::IndexOutOfBounds { index: count, len: count }));
+                assert_eq!(tree.compact_inclusion_proof(count).err(), Some(MerkleTreeError::IndexOutOfBounds { index: count, len: count }));
+            }
+        }
+    }
+
+    #[test]
+    fn only_binary_32_byte_trees_have_compact_proofs() {
+        let wide = MerkleTree::with_arity(&elements(5), 3).unwrap();
+        let long = MerkleTree::with_hash_function(&elements(5), HashFunction::Sha3_512);
+        for tree in [&wide, &long] {
+            assert!(matches!(tree.compact_proof(0), Err(MerkleTreeError::Incompatible(_))));
+            assert!(CompactProof::try_from(&tree.inclusion_proof(0).unwrap().proof).is_err());
+        }
+        let mut proof = MerkleTree::new(&elements(5)).inclusion_proof(0).unwrap().proof;
+        proof.siblings = vec![vec![0; 32]; 65];
+        assert!(CompactProof::try_from(&proof).is_err());
+        proof.siblings = vec![vec![0; 31]];
+        assert!(CompactProof::try_from(&proof).is_err());
+    }
+}
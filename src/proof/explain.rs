@@ -0,0 +1,142 @@
+use super::MerkleProof;
+use crate::hash::{LeafHash, Root};
+use crate::hex;
+use crate::merkle_tree::MerkleTree;
+use crate::tree_math::group_bounds;
+use std::fmt::Write;
+
+// One level of the walk from the leaf to the root
+struct Step {
+  position: usize,
+  width: usize,
+  siblings: Vec<Vec<u8>>,
+  parent: Vec<u8>
+}
+
+impl MerkleProof {
+  // The proof as a path from the leaf to the root: on every level the side our running
+  // hash takes, the siblings and the parent they hash to. Ends with the computed and the
+  // expected roots. Only the root can be checked without the tree, see
+  // `MerkleTree::explain_proof` to locate the first wrong level
+  #[must_use]
+  pub fn explain(&self, leaf: &LeafHash, expected_root: &Root) -> String {
+    self.render(leaf, expected_root, None)
+  }
+
+  fn steps(&self, leaf: &LeafHash) -> Vec<Step> {
+    let (mut index, mut size) = (self.index, self.leaf_count);
+    let mut hash = leaf.to_vec();
+    let mut siblings = self.siblings.iter();
+    let mut steps = vec![];
+    while size > 1 {
+      let (start, end) = group_bounds(index, size, self.arity);
+      let level_siblings: Vec<Vec<u8>> = siblings.by_ref().take(end - start - 1).cloned().collect();
+      if !level_siblings.is_empty() {
+        let mut children: Vec<&[u8]> = level_siblings.iter().map(Vec::as_slice).collect();
+        children.insert(index - start, &hash);
+        hash = self.hasher().node(&children);
+      }
+      steps.push(Step { position: index - start, width: end - start, siblings: level_siblings, parent: hash.clone() });
+      index /= self.arity;
+      size = size.div_ceil(self.arity);
+    }
+    steps
+  }
+
+  // `expected_nodes` holds the true node above every level, when known
+  fn render(&self, leaf: &LeafHash, expected_root: &Root, expected_nodes: Option<&[Vec<u8>]>) -> String {
+    let mut out = format!("leaf {leaf} at index {} of {}\n", self.index, self.leaf_count);
+    let computed = match self.compute_root(leaf) {
+      Ok(root) => root,
+      Err(e) => {
+        let _ = writeln!(out, "the proof cannot be evaluated: {e}");
+        return out;
+      }
+    };
+    let mut first_mismatch = None;
+    for (level, step) in self.steps(leaf).iter().enumerate() {
+      let side = match (step.width, step.position) {
+        (1, _) => "promoted".to_string(),
+        (2, 0) => "left child".to_string(),
+        (2, _) => "right child".to_string(),
+        (width, position) => format!("child {} of {width}", position + 1),
+      };
+      let _ = write!(out, "level {level}: {side}");
+      if !step.siblings.is_empty() {
+        let siblings: Vec<String> = step.siblings.iter().map(|s| hex::encode(s)).collect();
+        let _ = write!(out, ", sibling{} {}", if siblings.len() > 1 { "s" } else { "" }, siblings.join(", "));
+      }
+      let _ = write!(out, " -> {}", hex::encode(&step.parent));
+      match expected_nodes.and_then(|nodes| nodes.get(level)) {
+        Some(expected) if first_mismatch.is_none() && *expected != step.parent => {
+          first_mismatch = Some(level);
+          let _ = write!(out, "  <-- first mismatch, the tree has {}", hex::encode(expected));
+        },
+        _ => {}
+      }
+      out.push('\n');
+    }
+    let _ = writeln!(out, "computed root {computed}");
+    let _ = writeln!(out, "expected root {expected_root}");
+    match (computed == *expected_root, first_mismatch) {
+      (true, _) => out.push_str("verified\n"),
+      (false, Some(level)) => { let _ = writeln!(out, "MISMATCH from level {level} on"); },
+      (false, None) => out.push_str("MISMATCH at the root\n"),
+    }
+    out
+  }
+}
+
+impl MerkleTree {
+  // Like `MerkleProof::explain` against the root of this tree, also marking the first
+  // level whose computed parent differs from the node the tree holds there
+  #[must_use]
+  pub fn explain_proof(&self, proof: &MerkleProof, leaf: &LeafHash) -> String {
+    let nodes = (proof.index < self.len()).then(|| self.path_nodes(proof.index));
+    proof.render(leaf, &self.root(), nodes.as_deref())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::MerkleTree;
+
+    fn tree() -> MerkleTree {
+        MerkleTree::new(&["a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()])
+    }
+
+    #[test]
+    fn valid_proofs_explain_every_level() {
+        let tree = tree();
+        let proof = tree.inclusion_proof(2).unwrap();
+        assert_eq!(proof.proof.explain(&proof.leaf, &tree.root()), "\
+leaf 0x263ab762270d3b73d3e2cddf9acc893bb6bd41110347e5d5e4bd1d3c128ea90a at index 2 of 4
+level 0: left child, sibling 0x4ce8765e720c576f6f5a34ca380b3de5f0912e6e3cc5355542c363891e54594b -> 0x19a84217e939015aaa26d5da6b9ca673eae0df32877593df597cd3e5157982b1
+level 1: right child, sibling 0x29df505440ebe180c00857e92b0694c56a33762b08944472492b0cbf6ec607e3 -> 0x5267fec4a5327f9d287233f95213afa39d3aad2fee1fa1384b032b79fb3441e8
+computed root 0x5267fec4a5327f9d287233f95213afa39d3aad2fee1fa1384b032b79fb3441e8
+expected root 0x5267fec4a5327f9d287233f95213afa39d3aad2fee1fa1384b032b79fb3441e8
+verified
+");
+    }
+
+    #[test]
+    fn a_corrupted_sibling_is_located_against_the_tree() {
+        let tree = tree();
+        let mut proof = tree.inclusion_proof(2).unwrap();
+        proof.proof.siblings[1][0] ^= 0xff;
+        assert_eq!(tree.explain_proof(&proof.proof, &proof.leaf), "\
+leaf 0x263ab762270d3b73d3e2cddf9acc893bb6bd41110347e5d5e4bd1d3c128ea90a at index 2 of 4
+level 0: left child, sibling 0x4ce8765e720c576f6f5a34ca380b3de5f0912e6e3cc5355542c363891e54594b -> 0x19a84217e939015aaa26d5da6b9ca673eae0df32877593df597cd3e5157982b1
+level 1: right child, sibling 0xd6df505440ebe180c00857e92b0694c56a33762b08944472492b0cbf6ec607e3 -> 0xe0e0e263f9652b62d482dfcd08dda3b5c7be55499de86903d01ac730022e2c38  <-- first mismatch, the tree has 0x5267fec4a5327f9d287233f95213afa39d3aad2fee1fa1384b032b79fb3441e8
+computed root 0xe0e0e263f9652b62d482dfcd08dda3b5c7be55499de86903d01ac730022e2c38
+expected root 0x5267fec4a5327f9d287233f95213afa39d3aad2fee1fa1384b032b79fb3441e8
+MISMATCH from level 1 on
+");
+        let alone = proof.proof.explain(&proof.leaf, &tree.root());
+        assert!(alone.ends_with("MISMATCH at the root\n") && !alone.contains("<--"));
+
+        proof.proof.siblings.pop();
+        assert!(tree.explain_proof(&proof.proof, &proof.leaf).ends_with("the proof cannot be evaluated: expected a proof of 2 siblings, got 1\n"));
+    }
+}
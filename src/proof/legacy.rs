@@ -0,0 +1,143 @@
+use crate::codec;
+use crate::error::MerkleTreeError;
+use crate::merkle_tree::{DEFAULT_ARITY, HASH_LEN};
+use crate::proof::MerkleProof;
+use crate::scheme::{ByteOrder, HashFunction, HashScheme, PairOrder, TreeConfig};
+use crate::tree_math::{proof_len, DepthLimit};
+
+// Proofs as `MerkleTree::proof` returns them, the siblings alone with the index kept apart.
+// Those are binary SHA3-256 proofs with the legacy scheme and no leaf count
+impl MerkleProof {
+  // Typed proof of a stored legacy one. The leaf count is not stored, it is taken as the
+  // smallest that gives the leaf at `index` as many siblings. Binary proofs of one index
+  // fold the same way for every leaf count giving them that many siblings, so the proof
+  // verifies against the root it was made for. `expected_depth` checks the sibling count
+  pub fn from_legacy(siblings: Vec<Vec<u8>>, index: usize, expected_depth: Option<usize>) -> Result<Self, MerkleTreeError> {
+    DepthLimit::default().check(expected_depth.unwrap_or(0).max(siblings.len()))?;
+    if let Some(sibling) = siblings.iter().find(|s| s.len() != HASH_LEN) {
+      return Err(MerkleTreeError::InvalidHashLength { expected: HASH_LEN, got: sibling.len() });
+    }
+    if let Some(depth) = expected_depth.filter(|&depth| depth != siblings.len()) {
+      return Err(MerkleTreeError::InvalidProofLength { expected: depth, got: siblings.len() });
+    }
+    let leaf_count = leaf_count_for(index, siblings.len())
+      .ok_or_else(|| codec::invalid(format!("no binary tree gives leaf {index} a proof of {} siblings", siblings.len())))?;
+    Ok(Self { index, leaf_count, siblings, arity: DEFAULT_ARITY, scheme: HashScheme::Legacy, function: HashFunction::default(), byte_order: ByteOrder::default(), pair_order: PairOrder::InOrder })
+  }
+
+  // The siblings and the index for code still on the untyped API. Only proofs the legacy
+  // verification reads the same way convert
+  pub fn into_legacy(self) -> Result<(Vec<Vec<u8>>, usize), MerkleTreeError> {
+    let legacy = TreeConfig { byte_order: self.byte_order, ..TreeConfig::default() };
+    if self.config() != legacy {
+      return Err(MerkleTreeError::Incompatible(format!("legacy proofs are of {legacy}, this one is of {}", self.config())));
+    }
+    Ok((self.siblings, self.index))
+  }
+}
+
+// Smallest leaf count whose binary proofs of `index` have `len` siblings. A leaf more adds
+// at most one sibling, so the first count reaching `len` has exactly that many
+fn leaf_count_for(index: usize, len: usize) -> Option<usize> {
+  let siblings = |leaf_count| proof_len(index, leaf_count, DEFAULT_ARITY);
+  let mut low = index.checked_add(1)?;
+  if siblings(low) > len {
+    return None;
+  }
+  let mut high = low;
+  while siblings(high) < len {
+    high = high.checked_mul(2)?;
+  }
+  while low < high {
+    let mid = low + (high - low) / 2;
+    if siblings(mid) < len {
+      low = mid + 1;
+    } else {
+      high = mid;
+    }
+  }
+  Some(low)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::hash::{LeafHash, Root};
+    use crate::hex;
+    use crate::merkle_tree::{hash, verify_proof, MerkleTree};
+    use crate::proof::MerkleProof;
+    use crate::scheme::HashScheme;
+
+    // Stored by the 0.1 `proof()` over "record {i}" elements: leaf count, index, root and siblings
+    const FIXTURES: [(usize, usize, &str, &[&str]); 5] = [
+        (1, 0, "b7d2aadb7dd601008d83249c3c53e0de990e90c420d39df88f53e62b4055e3fc", &[]),
+        (5, 4, "578725c58565e7bcf988c373868ae3eeec39482d35ddcc71eb228d4b7ae65e0c", &["60c64127e2a8e4d24bf31c23760b80c1739a91e951a3597e8f5d6e27ebb9bb89"]),
+        (7, 2, "ab5fcaaafe371c94b5a6e355a51774419a38e18c84c962c17ae7a6e2ca31c8c2", &[
+            "4869b03189ee1d88a5de02b6f88bebd25f59a3b18bd3c93b4ab864b423616f86",
+            "a9e5b49b4bffc899d7363477f47566ecb09897e94c76440f153ac3c5f747ef17",
+            "157528bdb8a690c30436379240787c2354b1c5a9313dfa1705a96b4c4046751a",
+        ]),
+        (8, 5, "e69f8e6c5257a131a9c907b25dbb824f693a23bb1ff2e82abeae9923618292d0", &[
+            "287ff16bb86fd3920351b66e2a20792957446e707055dd1433b45865da2bf000",
+            "711327a1ac746f1db9b64158f71d44dd469d04f68275b3c9c4dc117a388b7520",
+            "60c64127e2a8e4d24bf31c23760b80c1739a91e951a3597e8f5d6e27ebb9bb89",
+        ]),
+        (13, 12, "05fa2637a0eabebbc30115bed6b25b854c5cc95463b15b083ce852747a6e11fc", &[
+            "fe693765bf47732aa89ad49fa1ebd21b8ea38974603280fada411c08398ff0ad",
+            "e69f8e6c5257a131a9c907b25dbb824f693a23bb1ff2e82abeae9923618292d0",
+        ]),
+    ];
+
+    fn fixture(siblings: &[&str]) -> Vec<Vec<u8>> {
+        siblings.iter().map(|s| hex::decode(s).unwrap()).collect()
+    }
+
+    fn leaf(index: usize) -> LeafHash {
+        LeafHash::try_from(hash(format!("record {index}")).as_slice()).unwrap()
+    }
+
+    #[test]
+    fn stored_legacy_proofs_verify_after_conversion() {
+        for (leaf_count, index, root, siblings) in FIXTURES {
+            let root = Root::try_from(hex::decode(root).unwrap().as_slice()).unwrap();
+            let proof = MerkleProof::from_legacy(fixture(siblings), index, Some(siblings.len())).unwrap();
+            assert!(proof.verify(&root, &leaf(index)), "leaf {index} of {leaf_count}");
+            assert!(!proof.verify(&root, &leaf(index + 1)));
+            assert!(verify_proof(&root, &leaf(index), index, leaf_count, &fixture(siblings)));
+            assert_eq!(proof.into_legacy().unwrap(), (fixture(siblings), index));
+
+            // the fixtures are what the tree still proves
+            let elements: Vec<String> = (0..leaf_count).map(|i| format!("record {i}")).collect();
+            assert_eq!(MerkleTree::new(&elements).proof(index), fixture(siblings));
+        }
+    }
+
+    #[test]
+    fn every_legacy_proof_converts() {
+        for leaf_count in 1..40 {
+            let tree = MerkleTree::new(&(0..leaf_count).map(|i| format!("record {i}")).collect::<Vec<_>>());
+            for index in 0..leaf_count {
+                let proof = MerkleProof::from_legacy(tree.proof(index), index, None).unwrap();
+                assert!(proof.verify(&tree.root(), &leaf(index)), "leaf {index} of {leaf_count}");
+                assert!(proof.leaf_count <= leaf_count);
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_legacy_proofs_are_rejected() {
+        let siblings = fixture(FIXTURES[3].3);
+        assert_eq!(MerkleProof::from_legacy(siblings.clone(), 5, Some(4)).unwrap_err(), MerkleTreeError::InvalidProofLength { expected: 4, got: 3 });
+        let mut short = siblings.clone();
+        short[1].pop();
+        assert_eq!(MerkleProof::from_legacy(short, 5, None).unwrap_err(), MerkleTreeError::InvalidHashLength { expected: 32, got: 31 });
+        // leaf 5 has a sibling in every tree holding it
+        assert!(MerkleProof::from_legacy(vec![], 5, None).is_err());
+        assert!(MerkleProof::from_legacy(vec![siblings[0].clone(); 65], 5, None).is_err());
+        assert!(MerkleProof::from_legacy(siblings.clone(), usize::MAX, None).is_err());
+
+        let v1 = MerkleTree::with_scheme(&["a".to_string(), "b".to_string()], HashScheme::V1);
+        assert!(matches!(v1.inclusion_proof(0).unwrap().proof.into_legacy(), Err(MerkleTreeError::Incompatible(_))));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::error::MerkleTreeError;
+use crate::hash::MAX_HASH_LEN;
+use crate::tree_math::DepthLimit;
+
+// Bounds on proofs read from untrusted input, checked before a proof is decoded, let
+// alone hashed. The defaults fit every proof of a tree the crate can build with room to
+// spare, endpoints expecting one kind of tree can lower them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofLimits {
+  pub max_siblings: usize,
+  // Bytes of one sibling, the leaf of an inclusion proof is held to it too
+  pub max_sibling_len: usize,
+  // Bytes of the whole input, binary or text
+  pub max_total_bytes: usize,
+  // Of the tree the siblings imply, each level gives at most `arity - 1` of them
+  pub max_depth: DepthLimit
+}
+
+impl Default for ProofLimits {
+  fn default() -> Self {
+    Self { max_siblings: 1024, max_sibling_len: MAX_HASH_LEN, max_total_bytes: 1 << 20, max_depth: DepthLimit::default() }
+  }
+}
+
+impl ProofLimits {
+  pub(super) fn check_input(&self, len: usize) -> Result<(), MerkleTreeError> {
+    exceeds("max_total_bytes", self.max_total_bytes, len)
+  }
+
+  // The length and count framing binary hashes, read ahead of the hashes
+  pub(super) fn check_frame(&self, len: usize, count: usize) -> Result<(), MerkleTreeError> {
+    exceeds("max_siblings", self.max_siblings, count)?;
+    exceeds("max_sibling_len", self.max_sibling_len, len)
+  }
+
+  pub(super) fn check_siblings(&self, siblings: &[Vec<u8>]) -> Result<(), MerkleTreeError> {
+    exceeds("max_siblings", self.max_siblings, siblings.len())?;
+    siblings.iter().try_for_each(|sibling| exceeds("max_sibling_len", self.max_sibling_len, sibling.len()))
+  }
+
+  // The fewest levels `count` siblings of a proof of that arity fill. Arities too small
+  // for any tree fail verification instead
+  pub(super) fn check_depth(&self, count: usize, arity: usize) -> Result<(), MerkleTreeError> {
+    match arity.checked_sub(1) {
+      Some(per_level) if per_level > 0 => self.max_depth.check(count.div_ceil(per_level)),
+      _ => Ok(()),
+    }
+  }
+}
+
+fn exceeds(limit: &'static str, max: usize, got: usize) -> Result<(), MerkleTreeError> {
+  if got > max {
+    return Err(MerkleTreeError::LimitExceeded { limit, max, got });
+  }
+  Ok(())
+}
+
+// Most bytes base64 text of that length decodes to
+pub(super) fn base64_len_bound(text: &str) -> usize {
+  text.len().saturating_mul(3) / 4
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::base64;
+    use crate::codec::put_u64;
+    use crate::error::MerkleTreeError;
+    use crate::merkle_tree::{compute_root_from_proof, verify_proof, verify_proof_within, MerkleTree};
+    use crate::proof::{InclusionProof, MerkleProof, ProofLimits};
+    use crate::scheme::{digest_count, ByteOrder};
+    use crate::tree_math::{DepthLimit, MAX_SUPPORTED_DEPTH};
+
+    fn proof() -> InclusionProof {
+        MerkleTree::new(&(0..8).map(|i| format!("entry {i}")).collect::<Vec<_>>()).inclusion_proof(5).unwrap()
+    }
+
+    fn exceeded(limit: &'static str, max: usize, got: usize) -> Option<MerkleTreeError> {
+        Some(MerkleTreeError::LimitExceeded { limit, max, got })
+    }
+
+    // The fixed fields of `proof` followed by a sibling frame of `count` siblings of `len` bytes
+    fn claiming(proof: &MerkleProof, len: u8, count: usize, data: &[u8]) -> Vec<u8> {
+        let mut bytes = proof.to_bytes().unwrap()[..3 * 8 + 1].to_vec();
+        bytes.push(len);
+        put_u64(&mut bytes, count, ByteOrder::LittleEndian);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn binary_input_is_rejected_at_each_limit_without_hashing() {
+        let inclusion = self::proof();
+        let proof = inclusion.proof.clone();
+        let bytes = proof.to_bytes().unwrap();
+        let exact = ProofLimits { max_siblings: 3, max_sibling_len: 32, max_total_bytes: bytes.len(), ..ProofLimits::default() };
+        let before = digest_count();
+        assert_eq!(MerkleProof::from_bytes_with_limits(&bytes, &exact).unwrap(), proof);
+        assert_eq!(MerkleProof::from_bytes_with_limits(&bytes, &ProofLimits { max_siblings: 2, ..exact }).err(), exceeded("max_siblings", 2, 3));
+        assert_eq!(MerkleProof::from_bytes_with_limits(&bytes, &ProofLimits { max_sibling_len: 31, ..exact }).err(), exceeded("max_sibling_len", 31, 32));
+        assert_eq!(MerkleProof::from_bytes_with_limits(&bytes, &ProofLimits { max_total_bytes: bytes.len() - 1, ..exact }).err(), exceeded("max_total_bytes", 129, 130));
+
+        // frames claiming far more than they hold are refused on the claim
+        assert_eq!(MerkleProof::from_bytes(&claiming(&proof, 255, 1, &[7; 255])).err(), exceeded("max_sibling_len", 64, 255));
+        assert_eq!(MerkleProof::from_bytes(&claiming(&proof, 32, 40_000, &[7; 64])).err(), exceeded("max_siblings", 1024, 40_000));
+        assert_eq!(MerkleProof::from_bytes(&claiming(&proof, 64, usize::MAX, &[])).err(), exceeded("max_siblings", 1024, usize::MAX));
+        assert_eq!(MerkleProof::from_bytes(&vec![0; (1 << 20) + 1]).err(), exceeded("max_total_bytes", 1 << 20, (1 << 20) + 1));
+
+        let bytes = inclusion.to_bytes().unwrap();
+        let exact = ProofLimits { max_total_bytes: bytes.len(), ..exact };
+        assert_eq!(InclusionProof::from_bytes_with_limits(&bytes, &exact).unwrap(), inclusion);
+        assert_eq!(InclusionProof::from_bytes_with_limits(&bytes, &ProofLimits { max_sibling_len: 31, ..exact }).err(), exceeded("max_sibling_len", 31, 32));
+        assert!(InclusionProof::from_bytes_with_limits(&bytes, &ProofLimits { max_total_bytes: bytes.len() - 1, ..exact }).is_err());
+
+        let text = inclusion.to_base64().unwrap();
+        assert_eq!(InclusionProof::from_base64_with_limits(&text, &exact).unwrap(), inclusion);
+        assert!(InclusionProof::from_base64_with_limits(&text, &ProofLimits { max_total_bytes: bytes.len() - 1, ..exact }).is_err());
+        assert!(MerkleProof::from_base64(&base64::encode_url(&claiming(&proof, 32, 2000, &[]))).is_err());
+        assert_eq!(digest_count(), before);
+    }
+
+    #[test]
+    fn text_input_is_rejected_at_each_limit_without_hashing() {
+        let inclusion = proof();
+        let text = inclusion.to_json();
+        let exact = ProofLimits { max_siblings: 3, max_sibling_len: 32, max_total_bytes: text.len(), ..ProofLimits::default() };
+        let before = digest_count();
+        assert_eq!(InclusionProof::from_json_with_limits(&text, &exact).unwrap(), inclusion);
+        assert_eq!(InclusionProof::from_json_with_limits(&text, &ProofLimits { max_siblings: 2, ..exact }).err(), exceeded("max_siblings", 2, 3));
+        assert_eq!(InclusionProof::from_json_with_limits(&text, &ProofLimits { max_sibling_len: 31, ..exact }).err(), exceeded("max_sibling_len", 31, 32));
+        assert_eq!(InclusionProof::from_json_with_limits(&text, &ProofLimits { max_total_bytes: text.len() - 1, ..exact }).err(), exceeded("max_total_bytes", text.len() - 1, text.len()));
+
+        let blob = format!("\"0x{}\"", "ab".repeat(4096));
+        let wide = format!(r#"{{"index":0,"leaf_count":2,"siblings":[{blob}]}}"#);
+        assert_eq!(MerkleProof::from_json(&wide).err(), exceeded("max_sibling_len", 64, 4096));
+        let many = format!(r#"{{"index":0,"leaf_count":2,"siblings":[{}]}}"#, vec!["[1]"; 2000].join(","));
+        assert_eq!(MerkleProof::from_json(&many).err(), exceeded("max_siblings", 1024, 2000));
+        let huge = format!(r#"{{"index":0,"leaf_count":2,"siblings":["0x{}"]}}"#, "ab".repeat(1 << 20));
+        assert!(matches!(MerkleProof::from_json(&huge), Err(MerkleTreeError::LimitExceeded { limit: "max_total_bytes", .. })));
+        assert_eq!(digest_count(), before);
+    }
+
+    #[test]
+    fn proofs_deeper_than_the_depth_limit_are_refused() {
+        let tree = MerkleTree::new(&(0..8).map(|i| format!("entry {i}")).collect::<Vec<_>>());
+        let proof = tree.inclusion_proof(5).unwrap();
+        let before = digest_count();
+        let mut deep = proof.proof.clone();
+        deep.siblings = vec![vec![0; 32]; 65];
+        deep.leaf_count = usize::MAX;
+        let too_deep = |requested| Some(MerkleTreeError::DepthTooLarge { requested, max: MAX_SUPPORTED_DEPTH });
+        assert_eq!(MerkleProof::from_bytes(&deep.to_bytes().unwrap()).err(), too_deep(65));
+        assert_eq!(MerkleProof::from_json(&deep.to_json()).err(), too_deep(65));
+        let text = InclusionProof { leaf: proof.leaf, proof: deep.clone() }.to_base64().unwrap();
+        assert_eq!(InclusionProof::from_base64(&text).err(), too_deep(65));
+        assert_eq!(compute_root_from_proof(&proof.leaf, 5, usize::MAX, &deep.siblings).err(), too_deep(65));
+        assert!(!verify_proof(&tree.root(), &proof.leaf, 5, usize::MAX, &deep.siblings));
+        assert_eq!(MerkleProof::from_legacy(deep.siblings.clone(), 5, None).err(), too_deep(65));
+        assert_eq!(MerkleProof::from_legacy(vec![], 0, Some(10_000)).err(), too_deep(10_000));
+        assert_eq!(digest_count(), before);
+
+        // a level of a 4-ary tree holds up to three siblings
+        deep.arity = 4;
+        assert!(MerkleProof::from_bytes(&deep.to_bytes().unwrap()).is_ok());
+        deep.arity = 2;
+        let deeper = ProofLimits { max_depth: DepthLimit::new(128).unwrap(), ..ProofLimits::default() };
+        assert_eq!(MerkleProof::from_bytes_with_limits(&deep.to_bytes().unwrap(), &deeper).unwrap(), deep);
+        deep.siblings.extend(vec![vec![0; 32]; 64]);
+        assert_eq!(MerkleProof::from_json_with_limits(&deep.to_json(), &deeper).err(), Some(MerkleTreeError::DepthTooLarge { requested: 129, max: 128 }));
+    }
+
+    #[test]
+    fn proofs_deeper_than_the_largest_tree_are_refused_before_hashing() {
+        let tree = MerkleTree::new(&(0..8).map(|i| format!("entry {i}")).collect::<Vec<_>>());
+        let leaf = tree.leaf_hash(5).unwrap();
+        assert!(verify_proof_within(&tree.root(), &leaf, 5, 8, &tree.proof(5), 8));
+        assert!(verify_proof_within(&tree.root(), &leaf, 5, 8, &tree.proof(5), 1000));
+
+        let before = digest_count();
+        assert!(!verify_proof_within(&tree.root(), &leaf, 5, 8, &tree.proof(5), 7));
+        let mut deep = tree.proof(5);
+        deep.extend(vec![vec![0; 32]; 40]);
+        assert!(!verify_proof_within(&tree.root(), &leaf, 5, 1 << 40, &deep, 1 << 20));
+        assert!(!verify_proof_within(&tree.root(), &leaf, 5, usize::MAX, &vec![vec![0; 1 << 16]; 64], 8));
+        assert_eq!(digest_count(), before);
+    }
+}
@@ -0,0 +1,228 @@
+use super::solidity::{abi_uint, WORD};
+use crate::error::MerkleTreeError;
+use crate::hash::{LeafHash, Root};
+use crate::scheme::HashFunction;
+use std::collections::VecDeque;
+
+// Several leaves proven together in the format of OpenZeppelin's `multiProofVerify`, for
+// trees that hash each pair sorted with Keccak-256 as `StandardMerkleTree` does. Only trees
+// of a power of two leaves lay out as `MerkleTree::from_sorted_pair_leaves` does, for other
+// counts the roots differ.
+//
+// Verification, which on-chain verifiers have to follow exactly:
+// - `leaves.len() + proof.len()` must be `proof_flags.len() + 1`
+// - for each flag in order, take `a` as the next unused leaf, or the oldest unused hash
+//   made by an earlier step once the leaves are used up. Take `b` the same way when the flag
+//   is set and as the next proof node otherwise, then make the hash of `a` and `b`,
+//   smallest first
+// - when there were flags every proof node has to be used, and the root is the last hash
+//   made. Without flags it is the only leaf or else the only proof node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiProof {
+  pub leaves: Vec<[u8; WORD]>,
+  pub proof: Vec<[u8; WORD]>,
+  pub proof_flags: Vec<bool>
+}
+
+impl MultiProof {
+  // Proof of the leaves at `indices` of the tree over `leaves`, in the order the tree holds
+  // them, as `getMultiProof` makes it. The tree is laid out as `StandardMerkleTree` lays it
+  // out: nodes in an array with the root first, node `i` the parent of `2i + 1` and `2i + 2`,
+  // the leaves last in reverse
+  pub fn new(leaves: &[LeafHash], indices: &[usize]) -> Result<Self, MerkleTreeError> {
+    if leaves.is_empty() {
+      return Err(MerkleTreeError::Empty);
+    }
+    let leaves = leaves.iter()
+      .map(|l| <[u8; WORD]>::try_from(l.as_bytes()).map_err(|_| MerkleTreeError::InvalidHashLength { expected: WORD, got: l.len() }))
+      .collect::<Result<Vec<_>, _>>()?;
+    let mut nodes = vec![[0; WORD]; 2 * leaves.len() - 1];
+    let last = nodes.len() - 1;
+    for (i, leaf) in leaves.iter().enumerate() {
+      nodes[last - i] = *leaf;
+    }
+    for i in (0..nodes.len() - leaves.len()).rev() {
+      nodes[i] = hash_sorted_pair(&nodes[2 * i + 1], &nodes[2 * i + 2]);
+    }
+
+    let mut positions = Vec::with_capacity(indices.len());
+    for &index in indices {
+      if index >= leaves.len() {
+        return Err(MerkleTreeError::IndexOutOfBounds { index, len: leaves.len() });
+      }
+      positions.push(last - index);
+    }
+    positions.sort_unstable_by(|a, b| b.cmp(a));
+    if let Some(pair) = positions.windows(2).find(|pair| pair[0] == pair[1]) {
+      return Err(MerkleTreeError::DuplicateIndex(last - pair[0]));
+    }
+
+    let (mut proof, mut proof_flags) = (vec![], vec![]);
+    let mut queue: VecDeque<usize> = positions.iter().copied().collect();
+    while let Some(node) = queue.pop_front().filter(|&node| node > 0) {
+      // node 0 is the root, the others have a sibling on the other side of 0 mod 2
+      let sibling = if node % 2 == 1 { node + 1 } else { node - 1 };
+      if queue.front() == Some(&sibling) {
+        queue.pop_front();
+        proof_flags.push(true);
+      } else {
+        proof.push(nodes[sibling]);
+        proof_flags.push(false);
+      }
+      queue.push_back((node - 1) / 2);
+    }
+    if indices.is_empty() {
+      proof.push(nodes[0]);
+    }
+    Ok(Self { leaves: positions.iter().map(|&p| nodes[p]).collect(), proof, proof_flags })
+  }
+
+  // The root the proof leads to, worked out as the type describes
+  pub fn process(&self) -> Result<Root, MerkleTreeError> {
+    let steps = self.proof_flags.len();
+    if self.leaves.len() + self.proof.len() != steps + 1 {
+      let expected = (steps + 1).saturating_sub(self.leaves.len());
+      return Err(MerkleTreeError::InvalidProofLength { expected, got: self.proof.len() });
+    }
+    let mut hashes: Vec<[u8; WORD]> = Vec::with_capacity(steps);
+    let (mut leaf_pos, mut hash_pos, mut proof_pos) = (0, 0, 0);
+    for &flag in &self.proof_flags {
+      let mut next_hash = |hashes: &[[u8; WORD]]| {
+        let next = match self.leaves.get(leaf_pos) {
+          Some(leaf) => {
+            leaf_pos += 1;
+            Some(*leaf)
+          },
+          None => hashes.get(hash_pos).inspect(|_| hash_pos += 1).copied(),
+        };
+        next.ok_or_else(|| MerkleTreeError::Incompatible("the flags use hashes before they are made".to_string()))
+      };
+      let a = next_hash(&hashes)?;
+      let b = if flag {
+        next_hash(&hashes)?
+      } else {
+        let node = self.proof.get(proof_pos).ok_or_else(|| MerkleTreeError::Incompatible("the flags use more proof nodes than there are".to_string()))?;
+        proof_pos += 1;
+        *node
+      };
+      hashes.push(hash_sorted_pair(&a, &b));
+    }
+    if steps > 0 && proof_pos != self.proof.len() {
+      return Err(MerkleTreeError::Incompatible("the flags leave proof nodes unused".to_string()));
+    }
+    let root = hashes.last().or(self.leaves.first()).or(self.proof.first()).expect("the lengths were checked");
+    Ok(Root::new(*root))
+  }
+
+  #[must_use]
+  pub fn verify(&self, root: &Root) -> bool {
+    self.process().is_ok_and(|computed| computed == *root)
+  }
+
+  // ABI encoding of the `(bytes32[] proof, bool[] proofFlags)` arguments: the offsets of
+  // the two arrays, then each array as its length and one word per item
+  #[must_use]
+  pub fn to_oz_calldata(&self) -> Vec<u8> {
+    let mut out = Vec::with_capacity((4 + self.proof.len() + self.proof_flags.len()) * WORD);
+    out.extend_from_slice(&abi_uint(2 * WORD));
+    out.extend_from_slice(&abi_uint((3 + self.proof.len()) * WORD));
+    out.extend_from_slice(&abi_uint(self.proof.len()));
+    for node in &self.proof {
+      out.extend_from_slice(node);
+    }
+    out.extend_from_slice(&abi_uint(self.proof_flags.len()));
+    for &flag in &self.proof_flags {
+      out.extend_from_slice(&abi_uint(usize::from(flag)));
+    }
+    out
+  }
+}
+
+fn hash_sorted_pair(a: &[u8; WORD], b: &[u8; WORD]) -> [u8; WORD] {
+  let (first, second) = if a <= b { (a, b) } else { (b, a) };
+  HashFunction::Keccak256.digest(&[first, second]).try_into().expect("Keccak-256 digests are 32 bytes")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::hash::{LeafHash, Root};
+    use crate::merkle_tree::TupleLeaf;
+    use crate::proof::MultiProof;
+    use hex_literal::hex;
+
+    // The rows (address 0x1010…, 1000) to (0x1414…, 5000) as `StandardMerkleTree.of(rows,
+    // ["address", "uint256"])` hashes and orders them
+    fn leaves() -> Vec<LeafHash> {
+        let mut leaves: Vec<LeafHash> = (0..5u8).map(|i| TupleLeaf::abi().address([0x10 + i; 20]).u64(1000 * (u64::from(i) + 1)).leaf_hash().unwrap()).collect();
+        leaves.sort();
+        leaves
+    }
+
+    // Root and `getMultiProof` outputs of the OpenZeppelin algorithm for the rows above
+    const ROOT: Root = Root::new(hex!("2efece28f3b0ed3f01558478c13947e2137bd465476d857279accdecd193adfe"));
+    const SORTED_LEAVES: [[u8; 32]; 5] = [
+        hex!("3ae1509fcaaf0e4e6d79b5fa40c6a1e2a9ef7d18504a3e7fe4ce5daada6bfdcd"),
+        hex!("9aaf044b92b5fff96e566f3b60cbb689080b6f27192c97175ba457dd20fb7d96"),
+        hex!("a36e2d7479a29030778e19c03d4e14b00b949f10303fad9ca3dc62f8ad31a8a2"),
+        hex!("b9e3287d205846dec88f4c64a89dc7c334dac377676d3891c38730677437b16c"),
+        hex!("f74a934432fbdf07bc1f68eb2611160dc00ec0076a6e7b80d5645167396d3902"),
+    ];
+
+    #[test]
+    fn multiproofs_match_the_openzeppelin_fixture() {
+        let leaves = leaves();
+        assert_eq!(leaves.iter().map(|l| <[u8; 32]>::try_from(l.as_bytes()).unwrap()).collect::<Vec<_>>(), SORTED_LEAVES);
+        let proof = MultiProof::new(&leaves, &[3, 1]).unwrap();
+        let [l0, l1, l2, l3, l4] = SORTED_LEAVES;
+        assert_eq!(proof, MultiProof { leaves: vec![l1, l3], proof: vec![l0, l2, l4], proof_flags: vec![false, false, false, true] });
+        assert!(proof.verify(&ROOT));
+        let proof = MultiProof::new(&leaves, &[0, 2, 4]).unwrap();
+        assert_eq!(proof, MultiProof { leaves: vec![l0, l2, l4], proof: vec![l1, l3], proof_flags: vec![false, false, true, true] });
+        assert!(proof.verify(&ROOT));
+
+        for indices in [&[][..], &[0], &[4], &[0, 1, 2, 3, 4], &[2, 3]] {
+            assert!(MultiProof::new(&leaves, indices).unwrap().verify(&ROOT), "{indices:?}");
+        }
+        assert!(MultiProof::new(&leaves[..1], &[0]).unwrap().verify(&Root::new(SORTED_LEAVES[0])));
+    }
+
+    #[test]
+    fn shuffled_flags_and_bad_inputs_fail() {
+        let leaves = leaves();
+        let proof = MultiProof::new(&leaves, &[3, 1]).unwrap();
+        for flags in [[true, false, false, false], [false, true, false, false], [false, false, true, false]] {
+            let shuffled = MultiProof { proof_flags: flags.to_vec(), ..proof.clone() };
+            assert!(!shuffled.verify(&ROOT), "{flags:?}");
+        }
+        let mut swapped = proof.clone();
+        swapped.proof.swap(0, 2);
+        assert!(!swapped.verify(&ROOT));
+        let short = MultiProof { proof_flags: vec![false, false, true], ..proof.clone() };
+        assert_eq!(short.process(), Err(MerkleTreeError::InvalidProofLength { expected: 2, got: 3 }));
+        let early = MultiProof { leaves: vec![], proof: vec![[0; 32]; 2], proof_flags: vec![true] };
+        assert!(matches!(early.process(), Err(MerkleTreeError::Incompatible(_))));
+
+        assert_eq!(MultiProof::new(&leaves, &[1, 1]), Err(MerkleTreeError::DuplicateIndex(1)));
+        assert_eq!(MultiProof::new(&leaves, &[5]), Err(MerkleTreeError::IndexOutOfBounds { index: 5, len: 5 }));
+        assert_eq!(MultiProof::new(&[], &[]), Err(MerkleTreeError::Empty));
+    }
+
+    #[test]
+    fn calldata_is_the_abi_encoding_of_proof_and_flags() {
+        let proof = MultiProof { leaves: vec![[0xaa; 32]], proof: vec![[0x11; 32], [0x22; 32]], proof_flags: vec![false, true] };
+        // what `AbiCoder.encode(["bytes32[]", "bool[]"], [proof, proofFlags])` gives
+        let expected = hex!(
+            "0000000000000000000000000000000000000000000000000000000000000040"
+            "00000000000000000000000000000000000000000000000000000000000000a0"
+            "0000000000000000000000000000000000000000000000000000000000000002"
+            "1111111111111111111111111111111111111111111111111111111111111111"
+            "2222222222222222222222222222222222222222222222222222222222222222"
+            "0000000000000000000000000000000000000000000000000000000000000002"
+            "0000000000000000000000000000000000000000000000000000000000000000"
+            "0000000000000000000000000000000000000000000000000000000000000001"
+        );
+        assert_eq!(proof.to_oz_calldata(), expected);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::error::MerkleTreeError;
+use crate::merkle_tree::DEFAULT_ARITY;
+use crate::proof::MerkleProof;
+use crate::scheme::{HashFunction, HashScheme, PairOrder};
+
+// Words of the Solidity ABI
+pub(super) const WORD: usize = 32;
+
+// Siblings as the `bytes32[] proof` argument of on-chain verifiers such as
+// OpenZeppelin's `MerkleProof.verify`, which folds them over the leaf hashing each pair
+// sorted with Keccak-256. Only proofs of binary legacy trees hashing that way verify there,
+// as the trees of `MerkleTree::from_sorted_pair_leaves`, the other proofs are refused
+impl MerkleProof {
+  // The siblings as fixed arrays, what ethers-rs takes for `bytes32[]`
+  pub fn to_bytes32_array(&self) -> Result<Vec<[u8; WORD]>, MerkleTreeError> {
+    let words = self.siblings.iter()
+      .map(|s| <[u8; WORD]>::try_from(s.as_slice()).map_err(|_| MerkleTreeError::InvalidHashLength { expected: WORD, got: s.len() }))
+      .collect::<Result<Vec<_>, _>>()?;
+    let config = self.config();
+    if config.arity != DEFAULT_ARITY || self.scheme != HashScheme::Legacy || self.function != HashFunction::Keccak256 || self.pair_order != PairOrder::Sorted {
+      return Err(MerkleTreeError::Incompatible(format!("on-chain verifiers take binary legacy keccak256 trees with sorted pairs, the proof is of a {config}")));
+    }
+    Ok(words)
+  }
+
+  // ABI encoding of the siblings as a lone `bytes32[]`: the offset of the array, its
+  // length and then one word per sibling
+  pub fn to_solidity_calldata(&self) -> Result<Vec<u8>, MerkleTreeError> {
+    let words = self.to_bytes32_array()?;
+    let mut out = Vec::with_capacity((2 + words.len()) * WORD);
+    out.extend_from_slice(&abi_uint(WORD));
+    out.extend_from_slice(&abi_uint(words.len()));
+    for word in &words {
+      out.extend_from_slice(word);
+    }
+    Ok(out)
+  }
+}
+
+// A `uint256` word, big endian
+pub(super) fn abi_uint(value: usize) -> [u8; WORD] {
+  let mut word = [0; WORD];
+  word[WORD - 8..].copy_from_slice(&(value as u64).to_be_bytes());
+  word
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::hash::LeafHash;
+    use crate::merkle_tree::{MerkleTree, MerkleTreeBuilder, TupleLeaf};
+    use crate::proof::{MerkleProof, MultiProof};
+    use crate::scheme::{ByteOrder, HashFunction, HashScheme, PairOrder};
+    use hex_literal::hex;
+    use sha3::{Digest, Keccak256};
+
+    fn proof(siblings: Vec<Vec<u8>>) -> MerkleProof {
+        MerkleProof { index: 0, leaf_count: 8, siblings, arity: 2, scheme: HashScheme::Legacy, function: HashFunction::Keccak256, byte_order: ByteOrder::LittleEndian, pair_order: PairOrder::Sorted }
+    }
+
+    // `MerkleProof.processProof` of OpenZeppelin: the leaf hashed with each sibling in turn,
+    // the smaller of the two first
+    fn process_proof(proof: &[[u8; 32]], leaf: [u8; 32]) -> [u8; 32] {
+        proof.iter().fold(leaf, |hash, sibling| {
+            let (a, b) = if hash < *sibling { (hash, *sibling) } else { (*sibling, hash) };
+            Keccak256::new().chain_update(a).chain_update(b).finalize().into()
+        })
+    }
+
+    #[test]
+    fn calldata_is_the_abi_encoding_of_bytes32_array() {
+        let siblings = vec![vec![0x11; 32], vec![0x22; 32], vec![0x33; 32]];
+        // what `AbiCoder.encode(["bytes32[]"], [siblings])` gives
+        let expected = hex!(
+            "0000000000000000000000000000000000000000000000000000000000000020"
+            "0000000000000000000000000000000000000000000000000000000000000003"
+            "1111111111111111111111111111111111111111111111111111111111111111"
+            "2222222222222222222222222222222222222222222222222222222222222222"
+            "3333333333333333333333333333333333333333333333333333333333333333"
+        );
+        let proof = proof(siblings);
+        assert_eq!(proof.to_solidity_calldata().unwrap(), expected);
+        assert_eq!(proof.to_bytes32_array().unwrap(), vec![[0x11; 32], [0x22; 32], [0x33; 32]]);
+        assert_eq!(self::proof(vec![]).to_solidity_calldata().unwrap(), [&[0; 31][..], &[0x20], &[0; 32]].concat());
+    }
+
+    #[test]
+    fn sorted_pair_proofs_verify_as_openzeppelin_does() {
+        // rows (0x2020…, 100) to (0x2727…, 800) as `(address, uint256)`, the root, leaf and
+        // calldata of the proof of the sixth row worked out the OpenZeppelin way
+        let rows: Vec<TupleLeaf> = (0..8u8).map(|i| TupleLeaf::abi().address([0x20 + i; 20]).u64(100 * (u64::from(i) + 1))).collect();
+        let root = hex!("3814d1153dd8a590518bde758335d7478d7ab085aafb5531985fab64a4b453d0");
+        let leaf = hex!("c62c9127212ed13ad159c7d205a19ffb7dbafa438a8940d410451cc9010ad1f4");
+        let calldata = hex!(
+            "0000000000000000000000000000000000000000000000000000000000000020"
+            "0000000000000000000000000000000000000000000000000000000000000003"
+            "489d255c866e4dc781dd4023f11f5ade5193619e0b3fb8372c0cd9229178c0f8"
+            "ca1dbc9f1da572fddca3e10b4fab4a9d7fec04584e6e735ad752438cc99a151b"
+            "d5bbfef88995f7f9e394c5537396a180d275368d3c701a2e34cab1f4087c0d29"
+        );
+
+        let tree = MerkleTree::from_tuples(&rows).unwrap();
+        assert_eq!((tree.hash_function(), tree.pair_order()), (HashFunction::Keccak256, PairOrder::Sorted));
+        assert_eq!(tree.root().as_bytes(), root);
+        let inclusion = tree.proof_for_tuple(&rows[5]).unwrap();
+        assert_eq!(inclusion.index, 5);
+        assert_eq!(rows[5].leaf_hash().unwrap().as_bytes(), leaf);
+        assert_eq!(inclusion.to_solidity_calldata().unwrap(), calldata);
+        assert_eq!(process_proof(&inclusion.to_bytes32_array().unwrap(), leaf), root);
+
+        // a power of two leaves lays out the same as `StandardMerkleTree`, the proof is its
+        // multiproof of the one leaf
+        let leaves: Vec<LeafHash> = rows.iter().map(|r| r.leaf_hash().unwrap()).collect();
+        assert_eq!(MultiProof::new(&leaves, &[5]).unwrap().proof, inclusion.to_bytes32_array().unwrap());
+        assert_eq!(MerkleTree::from_sorted_pair_leaves(&leaves).unwrap().root(), tree.root());
+        let mut builder = MerkleTreeBuilder::new().with_hash_function(HashFunction::Keccak256).with_pair_order(PairOrder::Sorted);
+        for element in ["a", "b", "c", "d", "e"] {
+            builder.push(element);
+        }
+        let tree = builder.build();
+        for index in 0..5 {
+            let proof = tree.inclusion_proof(index).unwrap();
+            let leaf = <[u8; 32]>::try_from(proof.leaf.as_bytes()).unwrap();
+            assert_eq!(process_proof(&proof.proof.to_bytes32_array().unwrap(), leaf), tree.root().as_bytes());
+            assert!(proof.verify(&tree.root()));
+        }
+
+        // the order is part of every encoding
+        let proof = tree.inclusion_proof(2).unwrap().proof;
+        assert!(proof.to_json().contains(r#""pair_order":"sorted""#));
+        assert_eq!(MerkleProof::from_json(&proof.to_json()).unwrap().pair_order, PairOrder::Sorted);
+        assert_eq!(MerkleProof::from_bytes(&proof.to_bytes().unwrap()).unwrap(), proof);
+        assert_ne!(MerkleProof { pair_order: PairOrder::InOrder, ..proof.clone() }, proof);
+        for decoded in [MerkleTree::from_json(&tree.to_json()).unwrap(), MerkleTree::from_bytes(&tree.to_bytes()).unwrap(), MerkleTree::from_json_tree(&tree.to_json_tree().unwrap()).unwrap()] {
+            assert_eq!((decoded.pair_order(), decoded.root()), (PairOrder::Sorted, tree.root()));
+        }
+    }
+
+    #[test]
+    fn proofs_on_chain_verifiers_would_reject_are_refused() {
+        let elements: Vec<String> = (0..8).map(|i| i.to_string()).collect();
+        let in_order = MerkleTree::with_hash_function(&elements, HashFunction::Keccak256).inclusion_proof(5).unwrap().proof;
+        assert!(matches!(in_order.to_solidity_calldata(), Err(MerkleTreeError::Incompatible(_))));
+        assert!(matches!(in_order.to_bytes32_array(), Err(MerkleTreeError::Incompatible(_))));
+        let sha3 = MerkleProof { function: HashFunction::Sha3_256, ..proof(vec![vec![0; 32]]) };
+        assert!(matches!(sha3.to_solidity_calldata(), Err(MerkleTreeError::Incompatible(_))));
+        let v1 = MerkleProof { scheme: HashScheme::V1, ..proof(vec![vec![0; 32]]) };
+        assert!(matches!(v1.to_solidity_calldata(), Err(MerkleTreeError::Incompatible(_))));
+        let wide = MerkleProof { arity: 4, ..proof(vec![vec![0; 32]]) };
+        assert!(matches!(wide.to_solidity_calldata(), Err(MerkleTreeError::Incompatible(_))));
+
+        let long = MerkleTree::with_hash_function(&elements, HashFunction::Sha3_512).inclusion_proof(5).unwrap().proof;
+        assert_eq!(long.to_solidity_calldata(), Err(MerkleTreeError::InvalidHashLength { expected: 32, got: 64 }));
+    }
+}
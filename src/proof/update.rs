@@ -0,0 +1,65 @@
+use crate::hash::{LeafHash, Root};
+use crate::proof::MerkleProof;
+
+// The leaf at `proof.index` going from `old_leaf` to `new_leaf` and nothing else changing.
+// Changing one leaf leaves its siblings as they were, so the same proof leads from the
+// old leaf to the old root and from the new leaf to the new root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateProof {
+  pub old_leaf: LeafHash,
+  pub new_leaf: LeafHash,
+  pub proof: MerkleProof
+}
+
+impl UpdateProof {
+  #[must_use]
+  pub fn index(&self) -> usize {
+    self.proof.index
+  }
+
+  // Whether the tree of `old_root` became the tree of `new_root` by this one update
+  #[must_use]
+  pub fn verify(&self, old_root: &Root, new_root: &Root) -> bool {
+    self.proof.verify(old_root, &self.old_leaf) && self.proof.verify(new_root, &self.new_leaf)
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::MerkleTree;
+
+    fn elements(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("account {i}")).collect()
+    }
+
+    #[test]
+    fn update_proofs_link_the_old_and_new_roots() {
+        for count in [1, 2, 7, 8] {
+            for index in 0..count {
+                let mut tree = MerkleTree::new(&elements(count));
+                let old_root = tree.root();
+                let proof = tree.update_with_proof(index, "changed").unwrap();
+                assert_eq!(proof.index(), index);
+                assert!(proof.verify(&old_root, &tree.root()));
+                assert!(!proof.verify(&tree.root(), &old_root));
+            }
+        }
+
+        let mut tree = MerkleTree::new(&elements(6));
+        let old_root = tree.root();
+        let old_leaf = tree.leaf_hash(4).unwrap();
+        tree.update(4, "changed").unwrap();
+        let proof = tree.prove_update(4, &old_leaf).unwrap();
+        assert!(proof.verify(&old_root, &tree.root()));
+
+        // another leaf changing as well is not the transition the proof describes
+        tree.update(1, "unrelated").unwrap();
+        assert!(!proof.verify(&old_root, &tree.root()));
+        let mut swapped = proof.clone();
+        (swapped.old_leaf, swapped.new_leaf) = (proof.new_leaf, proof.old_leaf);
+        assert!(!swapped.verify(&old_root, &proof.proof.compute_root(&proof.new_leaf).unwrap()));
+        assert!(tree.prove_update(6, &old_leaf).is_err());
+        assert!(MerkleTree::new_sorted(&elements(3)).update_with_proof(0, "moved").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::error::MerkleTreeError;
+use crate::hash::{LeafHash, Root};
+use crate::merkle_tree::{DEFAULT_ARITY, HASH_LEN};
+use crate::tree_math::{group_bounds, proof_len};
+use crate::scheme::HashScheme;
+
+// `verify_proof` for proofs that arrive one sibling at a time, from the leaf up. Only
+// the running hash and the position on the current level are kept
+#[derive(Debug, Clone)]
+pub struct ProofVerifier {
+  hash: LeafHash,
+  index: usize,
+  // nodes on the current level
+  size: usize,
+  fed: usize,
+  expected: usize
+}
+
+impl ProofVerifier {
+  pub fn new(leaf: &LeafHash, index: usize, leaf_count: usize) -> Result<Self, MerkleTreeError> {
+    if index >= leaf_count {
+      return Err(MerkleTreeError::IndexOutOfBounds { index, len: leaf_count });
+    }
+    if leaf.len() != HASH_LEN {
+      return Err(MerkleTreeError::InvalidHashLength { expected: HASH_LEN, got: leaf.len() });
+    }
+    Ok(Self { hash: *leaf, index, size: leaf_count, fed: 0, expected: proof_len(index, leaf_count, DEFAULT_ARITY) })
+  }
+
+  // Hashes the next sibling into the running hash, an error once the proof should be over
+  pub fn feed_sibling(&mut self, sibling: &[u8]) -> Result<(), MerkleTreeError> {
+    if self.fed == self.expected {
+      return Err(MerkleTreeError::InvalidProofLength { expected: self.expected, got: self.fed + 1 });
+    }
+    if sibling.len() != HASH_LEN {
+      return Err(MerkleTreeError::InvalidHashLength { expected: HASH_LEN, got: sibling.len() });
+    }
+    // promoted nodes go up without a sibling
+    while self.promoted() {
+      self.climb();
+    }
+    let hash = self.hash;
+    let children: [&[u8]; 2] = if self.index.is_multiple_of(DEFAULT_ARITY) { [hash.as_bytes(), sibling] } else { [sibling, hash.as_bytes()] };
+    self.hash = LeafHash::try_from(HashScheme::Legacy.hash_node(&children).as_slice())?;
+    self.climb();
+    self.fed += 1;
+    Ok(())
+  }
+
+  // Whether the fed siblings lead to `expected_root`, an error if some are still missing
+  pub fn finish(self, expected_root: &Root) -> Result<bool, MerkleTreeError> {
+    if self.fed != self.expected {
+      return Err(MerkleTreeError::InvalidProofLength { expected: self.expected, got: self.fed });
+    }
+    Ok(self.hash.as_bytes() == expected_root.as_bytes())
+  }
+
+  fn promoted(&self) -> bool {
+    let (start, end) = group_bounds(self.index, self.size, DEFAULT_ARITY);
+    end - start == 1
+  }
+
+  fn climb(&mut self) {
+    self.index /= DEFAULT_ARITY;
+    self.size = self.size.div_ceil(DEFAULT_ARITY);
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::merkle_tree::{verify_proof, MerkleTree};
+    use crate::proof::ProofVerifier;
+
+    fn numbered(size: usize) -> MerkleTree {
+        MerkleTree::new(&(0..size).map(|i| i.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn streaming_matches_the_batch_verifier() {
+        for size in 1..=13 {
+            let tree = numbered(size);
+            for index in 0..size {
+                let (leaf, proof) = (tree.leaf_hash(index).unwrap(), tree.proof(index));
+                let mut verifier = ProofVerifier::new(&leaf, index, size).unwrap();
+                for sibling in &proof {
+                    verifier.feed_sibling(sibling).unwrap();
+                }
+                assert!(verify_proof(&tree.root(), &leaf, index, size, &proof));
+                assert_eq!(verifier.clone().finish(&tree.root()), Ok(true));
+                assert_eq!(verifier.finish(&numbered(size + 1).root()), Ok(false));
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_short_and_long_proofs_are_detected() {
+        let tree = numbered(6);
+        let (leaf, mut proof) = (tree.leaf_hash(4).unwrap(), tree.proof(4));
+        let mut verifier = ProofVerifier::new(&leaf, 4, 6).unwrap();
+        verifier.feed_sibling(&proof[0]).unwrap();
+        assert_eq!(verifier.clone().finish(&tree.root()), Err(MerkleTreeError::InvalidProofLength { expected: 2, got: 1 }));
+        verifier.feed_sibling(&proof[1]).unwrap();
+        assert_eq!(verifier.feed_sibling(&proof[1]), Err(MerkleTreeError::InvalidProofLength { expected: 2, got: 3 }));
+        assert_eq!(verifier.finish(&tree.root()), Ok(true));
+
+        proof[1][0] ^= 1;
+        let mut verifier = ProofVerifier::new(&leaf, 4, 6).unwrap();
+        for sibling in &proof {
+            verifier.feed_sibling(sibling).unwrap();
+        }
+        assert!(!verify_proof(&tree.root(), &leaf, 4, 6, &proof));
+        assert_eq!(verifier.finish(&tree.root()), Ok(false));
+
+        let mut verifier = ProofVerifier::new(&leaf, 4, 6).unwrap();
+        assert_eq!(verifier.feed_sibling(&proof[0][..31]), Err(MerkleTreeError::InvalidHashLength { expected: 32, got: 31 }));
+        assert_eq!(ProofVerifier::new(&leaf, 6, 6).err(), Some(MerkleTreeError::IndexOutOfBounds { index: 6, len: 6 }));
+    }
+}
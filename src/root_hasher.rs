@@ -0,0 +1,89 @@
+use crate::hash::Root;
+use crate::merkle_tree::{hash_node, EMPTY_ROOT};
+use sha3::{Digest, Sha3_256};
+
+// Computes the root of the binary tree over a stream of elements without storing it.
+// Only the roots of the complete subtrees seen so far are kept, O(log n) memory.
+#[derive(Debug, Clone, Default)]
+pub struct MerkleRootHasher {
+  // (height, root) of complete subtrees, strictly decreasing heights
+  frontier: Vec<(u32, [u8; 32])>
+}
+
+impl MerkleRootHasher {
+  #[must_use]
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn update(&mut self, element: impl AsRef<[u8]>) {
+    let mut node = (0, Sha3_256::digest(element.as_ref()).into());
+    // two subtrees of the same height become their parent
+    while let Some(&(height, left)) = self.frontier.last() {
+      if height != node.0 {
+        break;
+      }
+      self.frontier.pop();
+      node = (height + 1, parent(&left, &node.1));
+    }
+    self.frontier.push(node);
+  }
+
+  // Root of the elements given so far. It consumes the hasher, like `Digest::finalize`,
+  // use `finalize_reset` to keep it around for a new stream
+  #[must_use]
+  pub fn finalize(self) -> Root {
+    // the smaller subtrees on the right were promoted until they met a bigger one
+    self.frontier.iter().rev()
+      .map(|&(_, hash)| hash)
+      .reduce(|right, left| parent(&left, &right))
+      .map_or(EMPTY_ROOT, Root::new)
+  }
+
+  // Returns the root and starts over as a new hasher
+  pub fn finalize_reset(&mut self) -> Root {
+    std::mem::take(self).finalize()
+  }
+}
+
+fn parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+  let mut hash = [0; 32];
+  hash.copy_from_slice(&hash_node(&[left, right]));
+  hash
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::{MerkleTree, EMPTY_ROOT};
+    use crate::root_hasher::MerkleRootHasher;
+
+    #[test]
+    fn streamed_root_matches_the_tree_root() {
+        for size in 1..=20 {
+            let elements: Vec<String> = (0..size).map(|i| format!("element {i}")).collect();
+            let mut hasher = MerkleRootHasher::new();
+            for element in &elements {
+                hasher.update(element);
+            }
+            assert_eq!(hasher.finalize(), MerkleTree::new(&elements).root());
+        }
+    }
+
+    #[test]
+    fn no_updates_give_the_empty_root() {
+        assert_eq!(MerkleRootHasher::new().finalize(), EMPTY_ROOT);
+        assert_eq!(MerkleTree::new(&[]).root(), EMPTY_ROOT);
+    }
+
+    #[test]
+    fn finalize_reset_starts_a_new_stream() {
+        let mut hasher = MerkleRootHasher::new();
+        hasher.update("hola");
+        hasher.update("moikka");
+        let first = hasher.finalize_reset();
+        hasher.update("hola");
+        hasher.update("moikka");
+        assert_eq!(hasher.finalize(), first);
+    }
+}
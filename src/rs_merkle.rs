@@ -0,0 +1,151 @@
+// Conversions to and from the byte layout of `rs_merkle::MerkleProof`.
+//
+// rs-merkle builds the same shape as this crate: pairs hash as H(left || right) and
+// the odd node at the end of a level moves up unchanged. Its proofs list the siblings
+// from the leaf up and its `to_bytes` concatenates them, which is also the order of
+// `MerkleProof::siblings`. Roots only match when both sides hash with the same function,
+// rs-merkle's own `Sha256` being `HashFunction::Sha256` over the legacy leaf hashes. Its
+// hashes are 32 bytes, and it has no notion of arity or of leaf/node domain separation,
+// so such proofs are rejected instead of converted
+use crate::error::MerkleTreeError;
+use crate::merkle_tree::DEFAULT_ARITY;
+use crate::tree_math::proof_len;
+use crate::proof::MerkleProof;
+use crate::scheme::{ByteOrder, HashFunction, HashScheme, PairOrder};
+
+impl MerkleProof {
+  pub fn to_rs_merkle_bytes(&self) -> Result<Vec<u8>, MerkleTreeError> {
+    if self.arity != DEFAULT_ARITY {
+      return Err(MerkleTreeError::Incompatible(format!("rs-merkle trees are binary, this proof has arity {}", self.arity)));
+    }
+    if self.scheme != HashScheme::Legacy {
+      return Err(MerkleTreeError::Incompatible("rs-merkle cannot domain separate leaves and nodes".to_string()));
+    }
+    check_hash_len(self.function)?;
+    let expected = self.function.output_len();
+    if let Some(s) = self.siblings.iter().find(|s| s.len() != expected) {
+      return Err(MerkleTreeError::InvalidHashLength { expected, got: s.len() });
+    }
+    Ok(self.siblings.concat())
+  }
+
+  // `leaf_indices` and `total_leaves` are what `rs_merkle::MerkleProof::verify` takes
+  // alongside the proof, `function` the one its `Hasher` implements. Only single leaf
+  // proofs are supported, rs-merkle orders the siblings of multi leaf proofs in a way
+  // `MerkleProof` cannot express
+  pub fn from_rs_merkle_bytes(leaf_indices: &[usize], total_leaves: usize, bytes: &[u8], function: HashFunction) -> Result<Self, MerkleTreeError> {
+    check_hash_len(function)?;
+    let &[index] = leaf_indices else {
+      return Err(MerkleTreeError::Incompatible(format!("expected a single leaf index, got {}", leaf_indices.len())));
+    };
+    if index >= total_leaves {
+      return Err(MerkleTreeError::IndexOutOfBounds { index, len: total_leaves });
+    }
+    if !bytes.len().is_multiple_of(RS_MERKLE_HASH_LEN) {
+      return Err(MerkleTreeError::InvalidEncoding(format!("{} bytes is not a whole number of hashes", bytes.len())));
+    }
+    let siblings: Vec<Vec<u8>> = bytes.chunks(RS_MERKLE_HASH_LEN).map(<[u8]>::to_vec).collect();
+    let expected = proof_len(index, total_leaves, DEFAULT_ARITY);
+    if siblings.len() != expected {
+      return Err(MerkleTreeError::InvalidProofLength { expected, got: siblings.len() });
+    }
+    Ok(Self { index, leaf_count: total_leaves, siblings, arity: DEFAULT_ARITY, scheme: HashScheme::Legacy, function, byte_order: ByteOrder::LittleEndian, pair_order: PairOrder::InOrder })
+  }
+}
+
+// rs-merkle hashes are `[u8; 32]`
+const RS_MERKLE_HASH_LEN: usize = 32;
+
+fn check_hash_len(function: HashFunction) -> Result<(), MerkleTreeError> {
+  if function.output_len() != RS_MERKLE_HASH_LEN {
+    return Err(MerkleTreeError::Incompatible(format!("rs-merkle hashes are {RS_MERKLE_HASH_LEN} bytes, {} gives {}", function.name(), function.output_len())));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use crate::error::MerkleTreeError;
+    use crate::merkle_tree::MerkleTree;
+    use crate::proof::MerkleProof;
+    use crate::scheme::{HashFunction, HashScheme};
+
+    // The root rs-merkle's `verify` computes for a single leaf: the index parity picks
+    // the side of each sibling, levels where the node has no sibling are skipped
+    fn rs_merkle_root(leaf: &[u8], mut index: usize, total: usize, bytes: &[u8]) -> Vec<u8> {
+        let mut siblings = bytes.chunks(32);
+        let (mut hash, mut size) = (leaf.to_vec(), total);
+        while size > 1 {
+            if index % 2 == 1 {
+                hash = HashFunction::Sha256.digest(&[siblings.next().unwrap(), &hash]);
+            } else if index + 1 < size {
+                hash = HashFunction::Sha256.digest(&[&hash, siblings.next().unwrap()]);
+            }
+            index /= 2;
+            size = size.div_ceil(2);
+        }
+        hash
+    }
+
+    #[test]
+    fn proofs_convert_both_ways_for_eight_leaves() {
+        let elements: Vec<String> = (0..8).map(|i| i.to_string()).collect();
+        let tree = MerkleTree::with_hash_function(&elements, HashFunction::Sha256);
+        for index in 0..8 {
+            let proof = tree.inclusion_proof(index).unwrap();
+            let bytes = proof.proof.to_rs_merkle_bytes().unwrap();
+            assert_eq!(bytes.len(), 3 * 32);
+            assert_eq!(rs_merkle_root(proof.leaf.as_bytes(), index, 8, &bytes), tree.root().as_bytes());
+            let back = MerkleProof::from_rs_merkle_bytes(&[index], 8, &bytes, HashFunction::Sha256).unwrap();
+            assert_eq!(back, proof.proof);
+            assert!(back.verify(&tree.root(), &proof.leaf));
+        }
+    }
+
+    #[test]
+    fn odd_sized_trees_skip_missing_siblings_like_rs_merkle() {
+        let elements: Vec<String> = (0..5).map(|i| i.to_string()).collect();
+        let tree = MerkleTree::with_hash_function(&elements, HashFunction::Sha256);
+        let proof = tree.inclusion_proof(4).unwrap();
+        let bytes = proof.proof.to_rs_merkle_bytes().unwrap();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(rs_merkle_root(proof.leaf.as_bytes(), 4, 5, &bytes), tree.root().as_bytes());
+    }
+
+    // The tree of rs-merkle's documentation, `Sha256::hash` of "a" to "f" as the leaves
+    #[test]
+    fn proofs_match_a_fixed_rs_merkle_sha256_vector() {
+        let elements: Vec<String> = ["a", "b", "c", "d", "e", "f"].map(String::from).to_vec();
+        let rs_merkle_proof = hex!(
+            "2e7d2c03a9507ae265ecf5b5356885a53393a2029d241394997265a1a25aefc6"
+            "e5a01fee14e0ed5c48714f22180f25ad8365b53f9779f79dc4a3d7e93963f94a"
+            "04fa33f8b4bd3db545fa04cdd51b462509f611797c7bfe5c944ee2bb3b2ed908");
+        let tree = MerkleTree::with_hash_function(&elements, HashFunction::Sha256);
+        assert_eq!(tree.root().as_bytes(), hex!("1f7379539707bcaea00564168d1d4d626b09b73f8a2a365234c62d763f854da2"));
+        let proof = tree.inclusion_proof(3).unwrap();
+        assert_eq!(proof.leaf.as_bytes(), hex!("18ac3e7343f016890c510e93f935261169d9e3f565436429830faf0934f4f8e4"));
+        assert_eq!(proof.proof.to_rs_merkle_bytes().unwrap(), rs_merkle_proof);
+        let imported = MerkleProof::from_rs_merkle_bytes(&[3], 6, &rs_merkle_proof, HashFunction::Sha256).unwrap();
+        assert!(imported.verify(&tree.root(), &proof.leaf));
+        // read as SHA3-256 the same bytes prove nothing about the tree
+        let sha3 = MerkleProof::from_rs_merkle_bytes(&[3], 6, &rs_merkle_proof, HashFunction::Sha3_256).unwrap();
+        assert!(!sha3.verify(&tree.root(), &proof.leaf));
+    }
+
+    #[test]
+    fn incompatible_proofs_are_rejected() {
+        let elements: Vec<String> = (0..8).map(|i| i.to_string()).collect();
+        let v1 = MerkleTree::with_scheme(&elements, HashScheme::V1).inclusion_proof(0).unwrap();
+        assert!(matches!(v1.proof.to_rs_merkle_bytes(), Err(MerkleTreeError::Incompatible(_))));
+        let quad = MerkleTree::with_arity(&elements, 4).unwrap().inclusion_proof(0).unwrap();
+        assert!(matches!(quad.proof.to_rs_merkle_bytes(), Err(MerkleTreeError::Incompatible(_))));
+        let wide = MerkleTree::with_hash_function(&elements, HashFunction::Sha3_512).inclusion_proof(0).unwrap();
+        assert!(matches!(wide.proof.to_rs_merkle_bytes(), Err(MerkleTreeError::Incompatible(_))));
+        assert!(matches!(MerkleProof::from_rs_merkle_bytes(&[0], 8, &[0; 192], HashFunction::Sha3_512), Err(MerkleTreeError::Incompatible(_))));
+        let sha256 = HashFunction::Sha256;
+        assert!(matches!(MerkleProof::from_rs_merkle_bytes(&[0, 1], 8, &[], sha256), Err(MerkleTreeError::Incompatible(_))));
+        assert_eq!(MerkleProof::from_rs_merkle_bytes(&[0], 8, &[0; 64], sha256).err(), Some(MerkleTreeError::InvalidProofLength { expected: 3, got: 2 }));
+        assert!(matches!(MerkleProof::from_rs_merkle_bytes(&[0], 8, &[0; 33], sha256), Err(MerkleTreeError::InvalidEncoding(_))));
+    }
+}
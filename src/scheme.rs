@@ -0,0 +1,679 @@
+use sha3::digest::{FixedOutputReset, Output};
+use sha3::{Digest, Keccak256, Sha3_256, Sha3_512};
+use crate::merkle_tree::DEFAULT_ARITY;
+use std::fmt;
+pub use crate::codec::ByteOrder;
+use std::io::{self, Read};
+#[cfg(feature = "simd")]
+use crate::sha256::sha256_many;
+
+// Versioned hashing conventions of a tree. `Legacy` is the default and keeps the
+// original roots, newer versions are opt in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum HashScheme {
+  // leaf = H(data), node = H(children). The leaf of empty data is H(""), which is also
+  // the root of the empty tree
+  #[default]
+  Legacy,
+  // Domain separated and framed:
+  // leaf = H(0x00 || len(data) as u64 LE || data), node = H(0x01 || children).
+  // Empty data still gets the prefix, no leaf is the hash of nothing
+  V1,
+}
+
+// Digest behind the scheme, SHA3-256 unless chosen otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum HashFunction {
+  #[default]
+  Sha3_256,
+  Sha3_512,
+  // Implemented by the crate, with a batched leaf path behind the `simd` feature
+  Sha256,
+  // The original Keccak padding, as Ethereum hashes
+  Keccak256,
+}
+
+// Order the children of a node are hashed in. `Sorted` hashes them smallest first, as
+// OpenZeppelin's `MerkleProof` does, so proofs need no position to verify. Such a proof
+// still says where its leaf is, only the root no longer commits to the order of the leaves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PairOrder {
+  #[default]
+  InOrder,
+  Sorted,
+}
+
+impl PairOrder {
+  // Name used by the text formats
+  #[must_use]
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::InOrder => "in-order",
+      Self::Sorted => "sorted",
+    }
+  }
+
+  #[must_use]
+  pub fn from_name(name: &str) -> Option<Self> {
+    [Self::InOrder, Self::Sorted].into_iter().find(|o| o.name() == name)
+  }
+}
+
+pub const LEAF_PREFIX: u8 = 0x00;
+pub const NODE_PREFIX: u8 = 0x01;
+
+impl HashScheme {
+  // Name used by the text formats
+  #[must_use]
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Legacy => "legacy",
+      Self::V1 => "v1",
+    }
+  }
+
+  #[must_use]
+  pub fn from_name(name: &str) -> Option<Self> {
+    [Self::Legacy, Self::V1].into_iter().find(|s| s.name() == name)
+  }
+
+  // Identifier used by the binary formats
+  #[must_use]
+  pub fn id(self) -> u8 {
+    match self {
+      Self::Legacy => 0,
+      Self::V1 => 1,
+    }
+  }
+
+  #[must_use]
+  pub fn from_id(id: u8) -> Option<Self> {
+    [Self::Legacy, Self::V1].into_iter().find(|s| s.id() == id)
+  }
+
+  // Leaf hash with SHA3-256, see `TreeHasher` for the other functions
+  #[must_use]
+  pub fn hash_leaf(self, data: &[u8]) -> Vec<u8> {
+    TreeHasher::new(self, HashFunction::Sha3_256).leaf(data)
+  }
+
+  #[must_use]
+  pub fn hash_node(self, children: &[&[u8]]) -> Vec<u8> {
+    TreeHasher::new(self, HashFunction::Sha3_256).node(children)
+  }
+}
+
+impl HashFunction {
+  #[must_use]
+  pub fn output_len(self) -> usize {
+    match self {
+      Self::Sha3_256 | Self::Sha256 | Self::Keccak256 => 32,
+      Self::Sha3_512 => 64,
+    }
+  }
+
+  #[must_use]
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Sha3_256 => "sha3-256",
+      Self::Sha3_512 => "sha3-512",
+      Self::Sha256 => "sha256",
+      Self::Keccak256 => "keccak256",
+    }
+  }
+
+  #[must_use]
+  pub fn from_name(name: &str) -> Option<Self> {
+    Self::ALL.into_iter().find(|f| f.name() == name)
+  }
+
+  #[must_use]
+  pub fn id(self) -> u8 {
+    match self {
+      Self::Sha3_256 => 0,
+      Self::Sha3_512 => 1,
+      Self::Sha256 => 2,
+      Self::Keccak256 => 3,
+    }
+  }
+
+  #[must_use]
+  pub fn from_id(id: u8) -> Option<Self> {
+    Self::ALL.into_iter().find(|f| f.id() == id)
+  }
+
+  pub(crate) const ALL: [Self; 4] = [Self::Sha3_256, Self::Sha3_512, Self::Sha256, Self::Keccak256];
+
+  #[must_use]
+  pub fn digest(self, pieces: &[&[u8]]) -> Vec<u8> {
+    #[cfg(test)]
+    count_digest();
+    fn digest<D: Digest>(pieces: &[&[u8]]) -> Vec<u8> {
+      let mut hasher = D::new();
+      for piece in pieces {
+        hasher.update(piece);
+      }
+      hasher.finalize().to_vec()
+    }
+    match self {
+      Self::Sha3_256 => digest::<Sha3_256>(pieces),
+      Self::Sha3_512 => digest::<Sha3_512>(pieces),
+      Self::Sha256 => crate::sha256::sha256(pieces).to_vec(),
+      Self::Keccak256 => digest::<Keccak256>(pieces),
+    }
+  }
+
+  // `digest` of `pieces` followed by everything `reader` holds, read a buffer at a time.
+  // Also returns how many bytes were read
+  pub(crate) fn digest_reader(self, pieces: &[&[u8]], reader: impl Read) -> io::Result<(Vec<u8>, u64)> {
+    #[cfg(test)]
+    count_digest();
+    fn digest<D: Digest>(pieces: &[&[u8]], reader: impl Read) -> io::Result<(Vec<u8>, u64)> {
+      let mut hasher = D::new();
+      for piece in pieces {
+        hasher.update(piece);
+      }
+      let read = feed(reader, |data| hasher.update(data))?;
+      Ok((hasher.finalize().to_vec(), read))
+    }
+    match self {
+      Self::Sha3_256 => digest::<Sha3_256>(pieces, reader),
+      Self::Sha3_512 => digest::<Sha3_512>(pieces, reader),
+      Self::Sha256 => {
+        let mut hasher = crate::sha256::Sha256::default();
+        for piece in pieces {
+          hasher.update(piece);
+        }
+        let read = feed(reader, |data| hasher.update(data))?;
+        Ok((hasher.finalize().to_vec(), read))
+      },
+      Self::Keccak256 => digest::<Keccak256>(pieces, reader),
+    }
+  }
+}
+
+// Hashing state reused across digests, building a tree sets one up per level rather than
+// one per node and writes each digest over the buffer of its node
+#[derive(Debug, Clone)]
+pub(crate) enum Digester {
+  Sha3_256(Sha3_256),
+  Sha3_512(Sha3_512),
+  Sha256(crate::sha256::Sha256),
+  Keccak256(Keccak256),
+}
+
+impl Digester {
+  pub(crate) fn new(function: HashFunction) -> Self {
+    match function {
+      HashFunction::Sha3_256 => Self::Sha3_256(Sha3_256::new()),
+      HashFunction::Sha3_512 => Self::Sha3_512(Sha3_512::new()),
+      HashFunction::Sha256 => Self::Sha256(crate::sha256::Sha256::default()),
+      HashFunction::Keccak256 => Self::Keccak256(Keccak256::new()),
+    }
+  }
+
+  pub(crate) fn update(&mut self, data: &[u8]) {
+    match self {
+      Self::Sha3_256(hasher) => Digest::update(hasher, data),
+      Self::Sha3_512(hasher) => Digest::update(hasher, data),
+      Self::Sha256(hasher) => hasher.update(data),
+      Self::Keccak256(hasher) => Digest::update(hasher, data),
+    }
+  }
+
+  // Replaces `out` with the digest of everything fed since the last one and starts over,
+  // `out` is only allocated when it is smaller than a digest
+  pub(crate) fn finalize_into(&mut self, out: &mut Vec<u8>) {
+    #[cfg(test)]
+    count_digest();
+    fn finalize_into<D: Digest + FixedOutputReset>(hasher: &mut D, out: &mut Vec<u8>) {
+      out.resize(<D as Digest>::output_size(), 0);
+      Digest::finalize_into_reset(hasher, Output::<D>::from_mut_slice(out));
+    }
+    match self {
+      Self::Sha3_256(hasher) => finalize_into(hasher, out),
+      Self::Sha3_512(hasher) => finalize_into(hasher, out),
+      Self::Sha256(hasher) => {
+        out.clear();
+        out.extend_from_slice(&std::mem::take(hasher).finalize());
+      },
+      Self::Keccak256(hasher) => finalize_into(hasher, out),
+    }
+  }
+}
+
+// Digests taken on this thread, so tests can tell that rejected input was never hashed.
+// The batched SHA-256 path of the `simd` feature is not counted
+#[cfg(test)]
+thread_local! {
+  static DIGESTS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+fn count_digest() {
+  DIGESTS.with(|count| count.set(count.get() + 1));
+}
+
+#[cfg(test)]
+pub(crate) fn digest_count() -> usize {
+  DIGESTS.with(std::cell::Cell::get)
+}
+
+const READ_BUFFER: usize = 64 * 1024;
+
+fn feed(mut reader: impl Read, mut update: impl FnMut(&[u8])) -> io::Result<u64> {
+  let mut buffer = vec![0; READ_BUFFER];
+  let mut total = 0;
+  loop {
+    match reader.read(&mut buffer) {
+      Ok(0) => return Ok(total),
+      Ok(n) => {
+        update(&buffer[..n]);
+        total += n as u64;
+      },
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+      Err(e) => return Err(e),
+    }
+  }
+}
+
+// How a level with a node short of a full group is completed. Trees and their proofs only
+// promote, the zero padded commitments of `MerkleTree::padded_root` have their own
+// `verify_padded_proof`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Padding {
+  // the last group hashes the children it has, a lone child moves up unchanged
+  #[default]
+  PromoteOdd,
+}
+
+impl Padding {
+  // Name used by the text formats
+  #[must_use]
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::PromoteOdd => "promote-odd",
+    }
+  }
+
+  #[must_use]
+  pub fn from_name(name: &str) -> Option<Self> {
+    [Self::PromoteOdd].into_iter().find(|p| p.name() == name)
+  }
+}
+
+// Every convention a proof has to be verified with, a proof checked under another one would
+// only fail without saying why
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeConfig {
+  pub arity: usize,
+  pub scheme: HashScheme,
+  pub function: HashFunction,
+  pub byte_order: ByteOrder,
+  pub pair_order: PairOrder,
+  pub padding: Padding
+}
+
+impl Default for TreeConfig {
+  fn default() -> Self {
+    Self {
+      arity: DEFAULT_ARITY,
+      scheme: HashScheme::Legacy,
+      function: HashFunction::default(),
+      byte_order: ByteOrder::default(),
+      pair_order: PairOrder::default(),
+      padding: Padding::default()
+    }
+  }
+}
+
+impl fmt::Display for TreeConfig {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}-ary {} tree with the {} scheme", self.arity, self.function.name(), self.scheme.name())?;
+    if self.pair_order != PairOrder::default() {
+      write!(f, ", {} pairs", self.pair_order.name())?;
+    }
+    if self.byte_order != ByteOrder::default() {
+      write!(f, " and {} integers", self.byte_order.name())?;
+    }
+    Ok(())
+  }
+}
+
+// A scheme applied with a hash function, what trees and proofs hash their nodes with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub(crate) struct TreeHasher {
+  pub(crate) scheme: HashScheme,
+  pub(crate) function: HashFunction,
+  // of the lengths framing V1 leaves
+  pub(crate) byte_order: ByteOrder,
+  pub(crate) pair_order: PairOrder
+}
+
+impl TreeHasher {
+  pub(crate) fn new(scheme: HashScheme, function: HashFunction) -> Self {
+    Self { scheme, function, byte_order: ByteOrder::default(), pair_order: PairOrder::default() }
+  }
+
+  pub(crate) fn with_byte_order(self, byte_order: ByteOrder) -> Self {
+    Self { byte_order, ..self }
+  }
+
+  pub(crate) fn with_pair_order(self, pair_order: PairOrder) -> Self {
+    Self { pair_order, ..self }
+  }
+
+  pub(crate) fn of(config: TreeConfig) -> Self {
+    Self::new(config.scheme, config.function).with_byte_order(config.byte_order).with_pair_order(config.pair_order)
+  }
+
+  pub(crate) fn output_len(self) -> usize {
+    self.function.output_len()
+  }
+
+  pub(crate) fn leaf(self, data: &[u8]) -> Vec<u8> {
+    match self.scheme {
+      HashScheme::Legacy => self.function.digest(&[data]),
+      HashScheme::V1 => self.function.digest(&[&[LEAF_PREFIX], &self.length_prefix(data), data]),
+    }
+  }
+
+  // `leaf` of the contents of `reader` without holding them in memory. V1 frames the
+  // leaf with its length so it needs `len` up front, when given it is checked either way
+  pub(crate) fn leaf_from_reader(self, reader: impl Read, len: Option<u64>) -> io::Result<Vec<u8>> {
+    let (leaf, read) = match (self.scheme, len) {
+      (HashScheme::Legacy, _) => self.function.digest_reader(&[], reader)?,
+      (HashScheme::V1, Some(len)) => self.function.digest_reader(&[&[LEAF_PREFIX], &self.byte_order.u64_bytes(len)], reader)?,
+      (HashScheme::V1, None) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "v1 leaves need their length before the data")),
+    };
+    match len {
+      Some(len) if len != read => Err(io::Error::new(io::ErrorKind::InvalidData, format!("expected {len} bytes but read {read}"))),
+      _ => Ok(leaf),
+    }
+  }
+
+  // Same hashes as `leaf` on each, SHA-256 batches go through the multi-lane path
+  // when the `simd` feature is on
+  pub(crate) fn leaves(self, data: &[&[u8]]) -> Vec<Vec<u8>> {
+    #[cfg(feature = "simd")]
+    if self.function == HashFunction::Sha256 {
+      let digests = match self.scheme {
+        HashScheme::Legacy => sha256_many(data),
+        HashScheme::V1 => {
+          let framed: Vec<Vec<u8>> = data.iter().map(|d| [&[LEAF_PREFIX][..], &self.length_prefix(d), d].concat()).collect();
+          sha256_many(&framed.iter().map(Vec::as_slice).collect::<Vec<_>>())
+        }
+      };
+      return digests.iter().map(|d| d.to_vec()).collect();
+    }
+    let mut digester = Digester::new(self.function);
+    data.iter().map(|d| {
+      let mut leaf = vec![];
+      self.leaf_into(&mut digester, d, &mut leaf);
+      leaf
+    }).collect()
+  }
+
+  pub(crate) fn leaf_into(self, digester: &mut Digester, data: &[u8], out: &mut Vec<u8>) {
+    if self.scheme == HashScheme::V1 {
+      digester.update(&[LEAF_PREFIX]);
+      digester.update(&self.length_prefix(data));
+    }
+    digester.update(data);
+    digester.finalize_into(out);
+  }
+
+  pub(crate) fn node(self, children: &[&[u8]]) -> Vec<u8> {
+    let mut node = vec![];
+    self.node_into(&mut Digester::new(self.function), children.iter().copied(), &mut node);
+    node
+  }
+
+  pub(crate) fn node_into<'a>(self, digester: &mut Digester, children: impl IntoIterator<Item = &'a [u8]>, out: &mut Vec<u8>) {
+    if self.scheme == HashScheme::V1 {
+      digester.update(&[NODE_PREFIX]);
+    }
+    match self.pair_order {
+      PairOrder::InOrder => children.into_iter().for_each(|child| digester.update(child)),
+      PairOrder::Sorted => sorted(children).into_iter().for_each(|child| digester.update(child)),
+    }
+    digester.finalize_into(out);
+  }
+
+  // Parents of independent groups of children written over `parents`, one per group.
+  // Batched like `leaves`, otherwise one hasher goes through all the groups
+  pub(crate) fn nodes_into(self, groups: &[&[Vec<u8>]], parents: &mut [Vec<u8>]) {
+    #[cfg(feature = "simd")]
+    if self.function == HashFunction::Sha256 {
+      let prefix: &[u8] = match self.scheme {
+        HashScheme::Legacy => &[],
+        HashScheme::V1 => &[NODE_PREFIX],
+      };
+      let messages: Vec<Vec<u8>> = groups.iter().map(|g| {
+        let mut children: Vec<&[u8]> = g.iter().map(Vec::as_slice).collect();
+        if self.pair_order == PairOrder::Sorted {
+          children.sort_unstable();
+        }
+        [prefix].into_iter().chain(children).collect::<Vec<_>>().concat()
+      }).collect();
+      for (parent, digest) in parents.iter_mut().zip(sha256_many(&messages.iter().map(Vec::as_slice).collect::<Vec<_>>())) {
+        parent.clear();
+        parent.extend_from_slice(&digest);
+      }
+      return;
+    }
+    let mut digester = Digester::new(self.function);
+    for (parent, group) in parents.iter_mut().zip(groups) {
+      self.node_into(&mut digester, group.iter().map(Vec::as_slice), parent);
+    }
+  }
+
+  fn length_prefix(self, data: &[u8]) -> [u8; 8] {
+    self.byte_order.u64_bytes(data.len() as u64)
+  }
+
+  // One byte for the binary formats, the scheme in the low two bits, a sorted pairs flag
+  // and a big endian flag above them and the function in the high nibble. Ids written
+  // before pairs could be sorted have the flag clear
+  pub(crate) fn id(self) -> u8 {
+    self.scheme.id()
+      | u8::from(self.pair_order == PairOrder::Sorted) << 2
+      | u8::from(self.byte_order == ByteOrder::BigEndian) << 3
+      | self.function.id() << 4
+  }
+
+  pub(crate) fn from_id(id: u8) -> Option<Self> {
+    let byte_order = if id & 0x08 == 0 { ByteOrder::LittleEndian } else { ByteOrder::BigEndian };
+    let pair_order = if id & 0x04 == 0 { PairOrder::InOrder } else { PairOrder::Sorted };
+    Some(Self::new(HashScheme::from_id(id & 0x03)?, HashFunction::from_id(id >> 4)?).with_byte_order(byte_order).with_pair_order(pair_order))
+  }
+}
+
+fn sorted<'a>(children: impl IntoIterator<Item = &'a [u8]>) -> Vec<&'a [u8]> {
+  let mut children: Vec<&[u8]> = children.into_iter().collect();
+  children.sort_unstable();
+  children
+}
+
+// Of the field lengths of `encode_fields`, fixed since the first version of the encoding
+const FIELDS_ORDER: ByteOrder = ByteOrder::LittleEndian;
+
+// Canonical encoding of a multi-field leaf, each field framed by its u64 length, so
+// ("ab", "c") and ("a", "bc") never encode to the same bytes. The lengths are little endian
+// whatever the byte order of the tree, the encoding is the data of the leaf and the same
+// row has to give the same leaf in every tree
+#[must_use]
+pub fn encode_fields(fields: &[&[u8]]) -> Vec<u8> {
+  let mut encoded = Vec::with_capacity(fields.iter().map(|f| f.len() + 8).sum());
+  for field in fields {
+    encoded.extend_from_slice(&FIELDS_ORDER.u64_bytes(field.len() as u64));
+    encoded.extend_from_slice(field);
+  }
+  encoded
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::{hash, hash_pair, MerkleTree, MerkleTreeBuilder};
+    use crate::scheme::{encode_fields, ByteOrder, Digester, HashFunction, HashScheme, PairOrder, TreeHasher, LEAF_PREFIX, NODE_PREFIX};
+    use hex_literal::hex;
+
+    fn elements() -> Vec<String> {
+        ["hola", "moikka", "heippa", "ahoj", "privet"].iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn legacy_scheme_keeps_the_original_hashes() {
+        assert_eq!(HashScheme::default(), HashScheme::Legacy);
+        assert_eq!(HashScheme::Legacy.hash_leaf(b"hola"), hash("hola".to_string()));
+        let (hola, moikka) = (hash("hola".to_string()), hash("moikka".to_string()));
+        assert_eq!(HashScheme::Legacy.hash_node(&[&hola, &moikka]), hash_pair(hola, moikka));
+    }
+
+    #[test]
+    fn v1_leaf_and_root_vectors() {
+        assert_eq!(HashScheme::V1.hash_leaf(b"hola"), hex!("3e2e3fceb6c8c591e2b62d32c2fe5a5f2d67b6ebebd32f8b9af229913782aae6"));
+        let tree = MerkleTree::with_scheme(&elements(), HashScheme::V1);
+        assert_eq!(tree.root().as_bytes(), hex!("c81538fd0f7e197017138660d1ff1a75dc88698544e14e6bd67b462e230e4881"));
+        for index in 0..5 {
+            assert!(tree.verify(&tree.proof(index), index));
+        }
+        assert_ne!(tree.root(), MerkleTree::new(&elements()).root());
+    }
+
+    #[test]
+    fn byte_order_decides_the_v1_length_prefix() {
+        let v1 = |order| MerkleTreeBuilder::new().with_scheme(HashScheme::V1).with_byte_order(order);
+        let sha3 = |pieces: &[&[u8]]| HashFunction::Sha3_256.digest(pieces);
+        let (mut little, mut big) = (v1(ByteOrder::LittleEndian), v1(ByteOrder::BigEndian));
+        for element in elements() {
+            little.push(&element);
+            big.push(&element);
+        }
+        let (little, big) = (little.build(), big.build());
+        assert_eq!(little.root(), MerkleTree::with_scheme(&elements(), HashScheme::V1).root());
+        assert_eq!(little[0], sha3(&[&[LEAF_PREFIX], &hex!("0400000000000000"), b"hola"]));
+        assert_eq!(big[0], sha3(&[&[LEAF_PREFIX], &hex!("0000000000000004"), b"hola"]));
+        assert_eq!(big.root().as_bytes(), hex!("b2ef72b5bdad470da3fca02ba6a8514899f6aa49c21866d41a0724a95d8a59aa"));
+        assert_eq!(big.byte_order(), ByteOrder::BigEndian);
+        assert_ne!(big.root(), little.root());
+        for index in 0..5 {
+            assert!(big.verify(&big.proof(index), index));
+        }
+        // streamed leaves are framed the same way
+        let mut streamed = v1(ByteOrder::BigEndian);
+        streamed.push_sized_leaf_from_reader(&b"hola"[..], 4).unwrap();
+        assert_eq!(streamed.build()[0], big[0]);
+
+        // legacy leaves frame nothing, the order changes no hash
+        let mut legacy = MerkleTreeBuilder::new().with_byte_order(ByteOrder::BigEndian);
+        elements().iter().for_each(|e| legacy.push(e));
+        assert_eq!(legacy.build().root(), MerkleTree::new(&elements()).root());
+        assert_eq!(ByteOrder::from_name(ByteOrder::BigEndian.name()), Some(ByteOrder::BigEndian));
+        assert_eq!(ByteOrder::BigEndian.u64_from_bytes(ByteOrder::BigEndian.u64_bytes(258)), 258);
+    }
+
+    #[test]
+    fn leaves_and_nodes_are_domain_separated() {
+        let (left, right) = (HashScheme::V1.hash_leaf(b"a"), HashScheme::V1.hash_leaf(b"b"));
+        let node = HashScheme::V1.hash_node(&[&left, &right]);
+        // a leaf whose data is two concatenated hashes no longer collides with their parent
+        assert_ne!(HashScheme::V1.hash_leaf(&[left.clone(), right.clone()].concat()), node);
+        assert_eq!(HashScheme::Legacy.hash_leaf(&[left.clone(), right.clone()].concat()), HashScheme::Legacy.hash_node(&[&left, &right]));
+    }
+
+    #[test]
+    fn framing_removes_the_concatenation_ambiguity() {
+        // unframed, ("ab", "c") and ("a", "bc") are the same bytes
+        assert_eq!([&b"ab"[..], b"c"].concat(), [&b"a"[..], b"bc"].concat());
+        let left = encode_fields(&[b"ab", b"c"]);
+        let right = encode_fields(&[b"a", b"bc"]);
+        assert_ne!(left, right);
+        assert_ne!(HashScheme::V1.hash_leaf(&left), HashScheme::V1.hash_leaf(&right));
+        assert_eq!(encode_fields(&[b"ab"]), [&2u64.to_le_bytes()[..], b"ab"].concat());
+    }
+
+    #[test]
+    fn batched_leaves_match_one_by_one() {
+        let data: Vec<Vec<u8>> = (0..50u8).map(|i| vec![i; usize::from(i) * 3]).collect();
+        let data: Vec<&[u8]> = data.iter().map(Vec::as_slice).collect();
+        for function in [HashFunction::Sha256, HashFunction::Sha3_256] {
+            for (scheme, pair_order) in [(HashScheme::Legacy, PairOrder::InOrder), (HashScheme::V1, PairOrder::InOrder), (HashScheme::Legacy, PairOrder::Sorted)] {
+                let hasher = TreeHasher::new(scheme, function).with_pair_order(pair_order);
+                let leaves = hasher.leaves(&data);
+                assert_eq!(leaves, data.iter().map(|d| hasher.leaf(d)).collect::<Vec<_>>());
+                let groups: Vec<&[Vec<u8>]> = leaves.chunks(3).collect();
+                let one_by_one: Vec<Vec<u8>> = groups.iter().map(|g| hasher.node(&g.iter().map(Vec::as_slice).collect::<Vec<_>>())).collect();
+                let mut parents = vec![vec![]; groups.len()];
+                hasher.nodes_into(&groups, &mut parents);
+                assert_eq!(parents, one_by_one);
+            }
+        }
+        assert_eq!(HashFunction::Sha256.digest(&[b"abc"]), hex!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"));
+        let tree = MerkleTree::with_hash_function(&elements(), HashFunction::Sha256);
+        for index in 0..5 {
+            assert!(tree.verify(&tree.proof(index), index));
+        }
+    }
+
+    #[test]
+    fn reused_hasher_state_gives_the_one_shot_digests() {
+        // xorshift, the inputs only have to be arbitrary and the same on every run
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let data: Vec<Vec<u8>> = (0..40).map(|_| (0..next() % 300).map(|_| next() as u8).collect()).collect();
+        for function in HashFunction::ALL {
+            let mut digester = Digester::new(function);
+            let mut out = vec![0xff; 100];
+            for message in &data {
+                digester.update(&message[..message.len() / 2]);
+                digester.update(&message[message.len() / 2..]);
+                digester.finalize_into(&mut out);
+                assert_eq!(out, function.digest(&[message]));
+            }
+            for scheme in [HashScheme::Legacy, HashScheme::V1] {
+                let hasher = TreeHasher::new(scheme, function);
+                let prefix: &[u8] = if scheme == HashScheme::V1 { &[NODE_PREFIX] } else { &[] };
+                for group in data.chunks(3) {
+                    let children: Vec<&[u8]> = group.iter().map(Vec::as_slice).collect();
+                    let one_shot = function.digest(&[&[prefix], children.as_slice()].concat());
+                    assert_eq!(hasher.node(&children), one_shot);
+                    let mut parent = vec![vec![0; 3]];
+                    hasher.nodes_into(&[group], &mut parent);
+                    assert_eq!(parent, vec![one_shot]);
+                }
+                let framed = |d: &Vec<u8>| function.digest(&[&[LEAF_PREFIX][..], &(d.len() as u64).to_le_bytes(), d]);
+                let leaves: Vec<Vec<u8>> = data.iter().map(|d| if scheme == HashScheme::V1 { framed(d) } else { function.digest(&[d]) }).collect();
+                assert_eq!(hasher.leaves(&data.iter().map(Vec::as_slice).collect::<Vec<_>>()), leaves);
+            }
+        }
+    }
+
+    #[test]
+    fn sorted_pairs_hash_the_smaller_child_first() {
+        let (a, b) = (HashFunction::Keccak256.digest(&[b"a"]), HashFunction::Keccak256.digest(&[b"b"]));
+        let (small, large) = if a < b { (&a, &b) } else { (&b, &a) };
+        let sorted = TreeHasher::new(HashScheme::Legacy, HashFunction::Keccak256).with_pair_order(PairOrder::Sorted);
+        let in_order = TreeHasher::new(HashScheme::Legacy, HashFunction::Keccak256);
+        assert_eq!(sorted.node(&[large, small]), in_order.node(&[small, large]));
+        assert_eq!(sorted.node(&[small, large]), in_order.node(&[small, large]));
+        assert_ne!(in_order.node(&[large, small]), in_order.node(&[small, large]));
+
+        // the flag takes a bit the scheme never used, every earlier id reads as before
+        assert_eq!(in_order.id(), 0x30);
+        assert_eq!(sorted.id(), 0x34);
+        for id in 0..=u8::MAX {
+            if let Some(hasher) = TreeHasher::from_id(id) {
+                assert_eq!(hasher.id(), id);
+                assert_eq!(hasher.pair_order == PairOrder::Sorted, id & 0x04 != 0);
+            }
+        }
+        assert_eq!(PairOrder::from_name(PairOrder::Sorted.name()), Some(PairOrder::Sorted));
+    }
+}
@@ -0,0 +1,298 @@
+// SHA-256 (FIPS 180-4), needed by the formats other systems define over it such as
+// Certificate Transparency. The crate only depends on sha3, so it is implemented here
+const K: [u32; 64] = [
+  0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+  0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+  0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+  0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+  0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+  0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+  0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+  0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const H0: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+// Incremental hasher, `update` may be called with pieces of any size
+#[derive(Debug, Clone)]
+pub(crate) struct Sha256 {
+  state: [u32; 8],
+  buffer: [u8; 64],
+  buffered: usize,
+  len: u64
+}
+
+impl Default for Sha256 {
+  fn default() -> Self {
+    Self { state: H0, buffer: [0; 64], buffered: 0, len: 0 }
+  }
+}
+
+impl Sha256 {
+  pub(crate) fn update(&mut self, mut data: &[u8]) {
+    self.len = self.len.wrapping_add(data.len() as u64);
+    if self.buffered > 0 {
+      let take = data.len().min(64 - self.buffered);
+      self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+      self.buffered += take;
+      data = &data[take..];
+      if self.buffered < 64 {
+        return;
+      }
+      let block = self.buffer;
+      self.compress(&block);
+      self.buffered = 0;
+    }
+    let mut blocks = data.chunks_exact(64);
+    for block in &mut blocks {
+      self.compress(block.try_into().expect("64 byte chunk"));
+    }
+    let rest = blocks.remainder();
+    self.buffer[..rest.len()].copy_from_slice(rest);
+    self.buffered = rest.len();
+  }
+
+  pub(crate) fn finalize(mut self) -> [u8; 32] {
+    let bits = self.len.wrapping_mul(8);
+    let padding = if self.buffered < 56 { 56 - self.buffered } else { 120 - self.buffered };
+    let mut tail = [0u8; 72];
+    tail[0] = 0x80;
+    self.update(&tail[..padding]);
+    self.update(&bits.to_be_bytes());
+    let mut out = [0u8; 32];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(self.state) {
+      chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+  }
+
+  fn compress(&mut self, block: &[u8; 64]) {
+    let mut w = [0u32; 64];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+      w[i] = u32::from_be_bytes(word.try_into().expect("4 byte chunk"));
+    }
+    for i in 16..64 {
+      let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+      let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+      w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+    for i in 0..64 {
+      let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+      let ch = (e & f) ^ (!e & g);
+      let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+      let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+      let maj = (a & b) ^ (a & c) ^ (b & c);
+      let t2 = s0.wrapping_add(maj);
+      h = g;
+      g = f;
+      f = e;
+      e = d.wrapping_add(t1);
+      d = c;
+      c = b;
+      b = a;
+      a = t1.wrapping_add(t2);
+    }
+    for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+      *state = state.wrapping_add(value);
+    }
+  }
+}
+
+pub(crate) fn sha256(pieces: &[&[u8]]) -> [u8; 32] {
+  let mut hasher = Sha256::default();
+  for piece in pieces {
+    hasher.update(piece);
+  }
+  hasher.finalize()
+}
+
+// Messages hashed side by side, one per lane of a `[u32; LANES]` word so the rounds
+// compile to vector instructions
+#[cfg(feature = "simd")]
+const LANES: usize = 8;
+
+#[cfg(feature = "simd")]
+type Lanes = [u32; LANES];
+
+// Digests of every message, same as calling `sha256` on each. Messages spanning the same
+// number of blocks are compressed `LANES` at a time, the rest one by one
+#[cfg(feature = "simd")]
+pub(crate) fn sha256_many(messages: &[&[u8]]) -> Vec<[u8; 32]> {
+  let mut digests = vec![[0; 32]; messages.len()];
+  let mut order: Vec<usize> = (0..messages.len()).collect();
+  order.sort_by_key(|&i| block_count(messages[i].len()));
+  for group in order.chunk_by(|&a, &b| block_count(messages[a].len()) == block_count(messages[b].len())) {
+    let mut lanes = group.chunks_exact(LANES);
+    for batch in &mut lanes {
+      let padded: Vec<Vec<u8>> = batch.iter().map(|&i| pad(messages[i])).collect();
+      for (&i, digest) in batch.iter().zip(digest_lanes(&padded)) {
+        digests[i] = digest;
+      }
+    }
+    for &i in lanes.remainder() {
+      digests[i] = sha256(&[messages[i]]);
+    }
+  }
+  digests
+}
+
+// Blocks of a message once padded with 0x80, zeros and its bit length
+#[cfg(feature = "simd")]
+fn block_count(len: usize) -> usize {
+  (len + 9).div_ceil(64)
+}
+
+#[cfg(feature = "simd")]
+fn pad(message: &[u8]) -> Vec<u8> {
+  let mut padded = Vec::with_capacity(block_count(message.len()) * 64);
+  padded.extend_from_slice(message);
+  padded.push(0x80);
+  padded.resize(block_count(message.len()) * 64 - 8, 0);
+  padded.extend_from_slice(&(message.len() as u64).wrapping_mul(8).to_be_bytes());
+  padded
+}
+
+// `padded` holds `LANES` padded messages of the same length. The lanes only fill whole
+// vector registers with AVX2, so it is picked at runtime when the CPU has it
+#[cfg(feature = "simd")]
+fn digest_lanes(padded: &[Vec<u8>]) -> Vec<[u8; 32]> {
+  #[cfg(target_arch = "x86_64")]
+  if is_x86_feature_detected!("avx2") {
+    // SAFETY: the CPU was just checked to support AVX2
+    return unsafe { digest_lanes_avx2(padded) };
+  }
+  digest_lanes_with(padded)
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+fn digest_lanes_avx2(padded: &[Vec<u8>]) -> Vec<[u8; 32]> {
+  digest_lanes_with(padded)
+}
+
+// Inlined into each caller so the rounds are compiled for its target features
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn digest_lanes_with(padded: &[Vec<u8>]) -> Vec<[u8; 32]> {
+  let mut state: [Lanes; 8] = H0.map(|h| [h; LANES]);
+  for block in 0..padded[0].len() / 64 {
+    let mut w = [[0u32; LANES]; 64];
+    for (lane, message) in padded.iter().enumerate() {
+      for (i, word) in message[block * 64..(block + 1) * 64].chunks_exact(4).enumerate() {
+        w[i][lane] = u32::from_be_bytes(word.try_into().expect("4 byte chunk"));
+      }
+    }
+    compress_lanes(&mut state, &mut w);
+  }
+  (0..LANES).map(|lane| {
+    let mut out = [0u8; 32];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(&state) {
+      chunk.copy_from_slice(&word[lane].to_be_bytes());
+    }
+    out
+  }).collect()
+}
+
+// `Sha256::compress` with every operation applied lane by lane
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn compress_lanes(state: &mut [Lanes; 8], w: &mut [Lanes; 64]) {
+  #[inline(always)]
+  fn map(f: impl Fn(usize) -> u32) -> Lanes {
+    let mut lanes = [0; LANES];
+    for (lane, value) in lanes.iter_mut().enumerate() {
+      *value = f(lane);
+    }
+    lanes
+  }
+  for i in 16..64 {
+    w[i] = map(|l| {
+      let s0 = w[i - 15][l].rotate_right(7) ^ w[i - 15][l].rotate_right(18) ^ (w[i - 15][l] >> 3);
+      let s1 = w[i - 2][l].rotate_right(17) ^ w[i - 2][l].rotate_right(19) ^ (w[i - 2][l] >> 10);
+      w[i - 16][l].wrapping_add(s0).wrapping_add(w[i - 7][l]).wrapping_add(s1)
+    });
+  }
+  let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+  for i in 0..64 {
+    let t1 = map(|l| {
+      let s1 = e[l].rotate_right(6) ^ e[l].rotate_right(11) ^ e[l].rotate_right(25);
+      let ch = (e[l] & f[l]) ^ (!e[l] & g[l]);
+      h[l].wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i][l])
+    });
+    let t2 = map(|l| {
+      let s0 = a[l].rotate_right(2) ^ a[l].rotate_right(13) ^ a[l].rotate_right(22);
+      s0.wrapping_add((a[l] & b[l]) ^ (a[l] & c[l]) ^ (b[l] & c[l]))
+    });
+    h = g;
+    g = f;
+    f = e;
+    e = map(|l| d[l].wrapping_add(t1[l]));
+    d = c;
+    c = b;
+    b = a;
+    a = map(|l| t1[l].wrapping_add(t2[l]));
+  }
+  for (state, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+    *state = map(|l| state[l].wrapping_add(value[l]));
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::sha256::{sha256, Sha256};
+    use hex_literal::hex;
+
+    #[test]
+    fn matches_the_fips_test_vectors() {
+        assert_eq!(sha256(&[]), hex!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"));
+        assert_eq!(sha256(&[b"abc"]), hex!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"));
+        assert_eq!(
+            sha256(&[b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"]),
+            hex!("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1")
+        );
+    }
+
+    #[test]
+    fn split_updates_give_the_same_digest() {
+        let data: Vec<u8> = (0..300u32).map(|i| (i * 7) as u8).collect();
+        let whole = sha256(&[&data]);
+        for split in [1, 55, 56, 63, 64, 65, 128, 299] {
+            let mut hasher = Sha256::default();
+            for piece in data.chunks(split) {
+                hasher.update(piece);
+            }
+            assert_eq!(hasher.finalize(), whole);
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn batches_match_the_scalar_digests() {
+        use crate::sha256::sha256_many;
+        // xorshift, so the lengths and bytes are random but the test is reproducible
+        let mut state = 0x2545f4914f6cdd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for round in 0..20 {
+            // uniform lengths fill whole batches, random ones mostly fall back to the scalar path
+            let messages: Vec<Vec<u8>> = (0..next() % 70)
+                .map(|_| {
+                    let len = if round % 2 == 0 { 32 } else { (next() % 300) as usize };
+                    (0..len).map(|_| next() as u8).collect()
+                })
+                .collect();
+            let refs: Vec<&[u8]> = messages.iter().map(Vec::as_slice).collect();
+            let expected: Vec<[u8; 32]> = refs.iter().map(|m| sha256(&[m])).collect();
+            assert_eq!(sha256_many(&refs), expected);
+        }
+        let edges: Vec<Vec<u8>> = [0, 55, 56, 63, 64, 119, 120].iter().flat_map(|&len| vec![vec![7u8; len]; 8]).collect();
+        let refs: Vec<&[u8]> = edges.iter().map(Vec::as_slice).collect();
+        assert_eq!(sha256_many(&refs), refs.iter().map(|m| sha256(&[m])).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::codec::{self, ByteOrder};
+use crate::error::MerkleTreeError;
+use crate::hash::Root;
+use crate::json::Json;
+use crate::merkle_tree::MerkleTree;
+use crate::proof::InclusionProof;
+
+// Signed messages start with this so a signature over a root can never be taken for
+// a signature over anything else made with the same key
+const DOMAIN: &[u8] = b"merkle-tree signed root v1\0";
+
+// Of the integers of signed messages, fixed since the first version of the format
+const SIGNED_ORDER: ByteOrder = ByteOrder::LittleEndian;
+
+// The signature scheme behind signed roots. The crate does not implement one, these are
+// implemented over the caller's library. With ed25519-dalek, `sign` is
+// `SigningKey::sign(message).to_bytes()` and `verify` is `VerifyingKey::verify_strict`
+// on the signature parsed from the 64 bytes, false when they do not parse. There is no
+// `ed25519` feature doing that yet, the crate builds without that dependency
+pub trait RootSigner {
+  fn sign(&self, message: &[u8]) -> Vec<u8>;
+}
+
+pub trait RootVerifier {
+  fn verify(&self, message: &[u8], signature: &[u8]) -> bool;
+}
+
+// A root and leaf count vouched for by whoever holds the signing key. The nonce comes from
+// the caller, a timestamp or a sequence number, so an old signed root can be told apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedRoot {
+  pub root: Root,
+  pub leaf_count: usize,
+  pub nonce: u64,
+  pub signature: Vec<u8>
+}
+
+impl SignedRoot {
+  // The domain, the root as its length and bytes, then the leaf count and the nonce as u64.
+  // The integers are little endian whatever the byte order of the tree, a signature has to
+  // check out for every verifier of the root and the tree config is not part of it
+  #[must_use]
+  pub fn signing_message(root: &Root, leaf_count: usize, nonce: u64) -> Vec<u8> {
+    let mut message = DOMAIN.to_vec();
+    message.push(root.len() as u8);
+    message.extend_from_slice(root.as_bytes());
+    message.extend_from_slice(&SIGNED_ORDER.u64_bytes(leaf_count as u64));
+    message.extend_from_slice(&SIGNED_ORDER.u64_bytes(nonce));
+    message
+  }
+
+  #[must_use]
+  pub fn verify(&self, key: &impl RootVerifier) -> bool {
+    key.verify(&Self::signing_message(&self.root, self.leaf_count, self.nonce), &self.signature)
+  }
+
+  // `{"root":"0x…","leaf_count":…,"nonce":"0x…","signature":"0x…"}`, the nonce as 8 bytes
+  // big endian
+  #[must_use]
+  pub fn to_json(&self) -> String {
+    Json::object(vec![
+      ("root", codec::bytes_to_json(self.root.as_bytes())),
+      ("leaf_count", self.leaf_count.into()),
+      ("nonce", codec::bytes_to_json(&ByteOrder::BigEndian.u64_bytes(self.nonce))),
+      ("signature", codec::bytes_to_json(&self.signature)),
+    ]).to_string()
+  }
+
+  pub fn from_json(text: &str) -> Result<Self, MerkleTreeError> {
+    let json = Json::parse(text).map_err(codec::invalid)?;
+    let nonce = codec::bytes_from_json("nonce", json.get("nonce"))?;
+    Ok(Self {
+      root: Root::try_from(codec::bytes_from_json("root", json.get("root"))?.as_slice())?,
+      leaf_count: codec::usize_from_json("leaf_count", json.get("leaf_count"))?,
+      nonce: ByteOrder::BigEndian.u64_from_bytes(nonce.try_into().map_err(|_| codec::invalid("nonce must be 8 bytes"))?),
+      signature: codec::bytes_from_json("signature", json.get("signature"))?
+    })
+  }
+}
+
+impl MerkleTree {
+  #[must_use]
+  pub fn sign_root(&self, nonce: u64, key: &impl RootSigner) -> SignedRoot {
+    let (root, leaf_count) = (self.root(), self.len());
+    let signature = key.sign(&SignedRoot::signing_message(&root, leaf_count, nonce));
+    SignedRoot { root, leaf_count, nonce, signature }
+  }
+}
+
+// Whether `proof` is an inclusion proof under a root `key` signed, the proof has to be
+// for a tree of the signed leaf count
+#[must_use]
+pub fn verify_signed(proof: &InclusionProof, signed: &SignedRoot, key: &impl RootVerifier) -> bool {
+  signed.verify(key) && proof.proof.leaf_count == signed.leaf_count && proof.verify(&signed.root)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::{MerkleTree, MerkleTreeBuilder};
+    use crate::scheme::{ByteOrder, HashFunction};
+    use crate::signed_root::*;
+
+    // Keyed hash standing in for a real signature scheme, the key is its own verifier
+    struct TestKey(u8);
+
+    impl RootSigner for TestKey {
+        fn sign(&self, message: &[u8]) -> Vec<u8> {
+            HashFunction::Sha3_256.digest(&[&[self.0], message])
+        }
+    }
+
+    impl RootVerifier for TestKey {
+        fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+            self.sign(message) == signature
+        }
+    }
+
+    fn tree() -> MerkleTree {
+        MerkleTree::new(&(0..5).map(|i| format!("claim {i}")).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn signed_roots_round_trip_and_vouch_for_proofs() {
+        let tree = tree();
+        let signed = tree.sign_root(u64::MAX - 1, &TestKey(7));
+        assert!(signed.verify(&TestKey(7)));
+        let decoded = SignedRoot::from_json(&signed.to_json()).unwrap();
+        assert_eq!(decoded, signed);
+
+        let proof = tree.inclusion_proof(3).unwrap();
+        assert!(verify_signed(&proof, &decoded, &TestKey(7)));
+        assert!(!verify_signed(&proof, &decoded, &TestKey(8)));
+        assert!(!verify_signed(&MerkleTree::new(&["claim 3".to_string()]).inclusion_proof(0).unwrap(), &decoded, &TestKey(7)));
+
+        for leaf_count in [(1 << 53) + 1, usize::MAX] {
+            let signature = TestKey(7).sign(&SignedRoot::signing_message(&signed.root, leaf_count, 3));
+            let large = SignedRoot { leaf_count, nonce: 3, signature, ..signed.clone() };
+            let decoded = SignedRoot::from_json(&large.to_json()).unwrap();
+            assert_eq!(decoded, large);
+            assert!(decoded.verify(&TestKey(7)));
+        }
+    }
+
+    #[test]
+    fn signed_messages_keep_little_endian_integers_for_every_tree() {
+        let big_endian = MerkleTreeBuilder::new().with_byte_order(ByteOrder::BigEndian).build();
+        let root = big_endian.root();
+        let expected = [&b"merkle-tree signed root v1\0"[..], &[32], root.as_bytes(), &[1, 0, 0, 0, 0, 0, 0, 0], &[2, 0, 0, 0, 0, 0, 0, 0]].concat();
+        assert_eq!(SignedRoot::signing_message(&root, 1, 2), expected);
+        let signed = big_endian.sign_root(2, &TestKey(7));
+        assert_eq!(signed.signature, TestKey(7).sign(&SignedRoot::signing_message(&root, 0, 2)));
+    }
+
+    #[test]
+    fn tampered_signed_roots_are_rejected() {
+        let signed = tree().sign_root(1, &TestKey(7));
+        assert!(!signed.verify(&TestKey(8)));
+        for tampered in [
+            SignedRoot { root: MerkleTree::new(&[]).root(), ..signed.clone() },
+            SignedRoot { leaf_count: 6, ..signed.clone() },
+            SignedRoot { nonce: 2, ..signed.clone() },
+        ] {
+            assert!(!tampered.verify(&TestKey(7)));
+        }
+        assert!(SignedRoot::from_json(&signed.to_json().replace("\"nonce\":\"0x", "\"nonce\":\"0x00")).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use crate::error::MerkleTreeError;
+use crate::hash::Root;
+use crate::merkle_tree::MerkleTree;
+use crate::proof::InclusionProof;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+// `MerkleTree` shared between threads. Roots, proofs and verification only take the read
+// lock so readers only wait for writers and not for each other. Everything returned is
+// owned, nothing borrowed from the tree outlives the lock
+pub struct SyncMerkleTree {
+  tree: RwLock<MerkleTree>
+}
+
+impl From<MerkleTree> for SyncMerkleTree {
+  fn from(tree: MerkleTree) -> Self {
+    Self::new(tree)
+  }
+}
+
+impl SyncMerkleTree {
+  #[must_use]
+  pub fn new(tree: MerkleTree) -> Self {
+    Self { tree: RwLock::new(tree) }
+  }
+
+  #[must_use]
+  pub fn into_inner(self) -> MerkleTree {
+    self.tree.into_inner().expect("a writer panicked while holding the tree")
+  }
+
+  #[must_use]
+  pub fn root(&self) -> Root {
+    self.read().root()
+  }
+
+  #[must_use]
+  pub fn len(&self) -> usize {
+    self.read().len()
+  }
+
+  #[must_use]
+  pub fn is_empty(&self) -> bool {
+    self.read().is_empty()
+  }
+
+  // Siblings of the leaf at `index`, empty when it is out of range
+  #[must_use]
+  pub fn proof(&self, index: usize) -> Vec<Vec<u8>> {
+    self.read().proof(index)
+  }
+
+  pub fn inclusion_proof(&self, index: usize) -> Result<InclusionProof, MerkleTreeError> {
+    self.read().inclusion_proof(index)
+  }
+
+  #[must_use]
+  pub fn verify(&self, proof: &[Vec<u8>], index: usize) -> bool {
+    self.read().verify(proof, index)
+  }
+
+  pub fn push(&self, element: &str) {
+    self.write().push(element);
+  }
+
+  pub fn add(&self, elements: &[String]) {
+    self.write().add(elements);
+  }
+
+  // `add` returning the root right after the batch, before any other writer gets in
+  pub fn append_batch(&self, elements: &[String]) -> Root {
+    let mut tree = self.write();
+    tree.add(elements);
+    tree.root()
+  }
+
+  pub fn update(&self, index: usize, element: &str) -> Result<(), MerkleTreeError> {
+    self.write().update(index, element)
+  }
+
+  // Runs `f` under the read lock, for reads that need several values of the same state
+  pub fn with_read<R>(&self, f: impl FnOnce(&MerkleTree) -> R) -> R {
+    f(&self.read())
+  }
+
+  // Runs `f` under the write lock, for the mutations without a method here
+  pub fn with_write<R>(&self, f: impl FnOnce(&mut MerkleTree) -> R) -> R {
+    f(&mut self.write())
+  }
+
+  // Copy of the tree as it is now
+  #[must_use]
+  pub fn snapshot(&self) -> MerkleTree {
+    self.read().clone()
+  }
+
+  fn read(&self) -> RwLockReadGuard<'_, MerkleTree> {
+    self.tree.read().expect("a writer panicked while holding the tree")
+  }
+
+  fn write(&self) -> RwLockWriteGuard<'_, MerkleTree> {
+    self.tree.write().expect("a writer panicked while holding the tree")
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::MerkleTree;
+    use crate::sync::SyncMerkleTree;
+    use std::thread;
+
+    #[test]
+    fn readers_only_see_roots_of_whole_prefixes() {
+        let tree = SyncMerkleTree::new(MerkleTree::new_retaining(&[]));
+        let observed = thread::scope(|scope| {
+            for writer in 0..4 {
+                let tree = &tree;
+                scope.spawn(move || {
+                    for i in 0..50 {
+                        if i % 5 == 0 {
+                            tree.append_batch(&(0..3).map(|j| format!("writer {writer} batch {i} {j}")).collect::<Vec<_>>());
+                        } else {
+                            tree.push(&format!("writer {writer} element {i}"));
+                        }
+                    }
+                });
+            }
+            let readers: Vec<_> = (0..4).map(|_| scope.spawn(|| {
+                (0..200).map(|_| tree.with_read(|t| (t.len(), t.root()))).collect::<Vec<_>>()
+            })).collect();
+            readers.into_iter().flat_map(|reader| reader.join().unwrap()).collect::<Vec<_>>()
+        });
+
+        let tree = tree.into_inner();
+        assert_eq!(tree.len(), 4 * (40 + 10 * 3));
+        let elements: Vec<String> = tree.elements().unwrap().iter().map(|e| String::from_utf8(e.clone()).unwrap()).collect();
+        for (len, root) in observed {
+            assert_eq!(root, MerkleTree::new(&elements[..len]).root());
+        }
+    }
+
+    #[test]
+    fn reads_return_owned_results() {
+        let tree = SyncMerkleTree::from(MerkleTree::new(&["a".to_string(), "b".to_string(), "c".to_string()]));
+        let proof = tree.proof(2);
+        assert!(tree.verify(&proof, 2));
+        tree.update(0, "z").unwrap();
+        assert!(tree.verify(&tree.proof(2), 2));
+        assert!(!tree.verify(&proof, 2));
+        assert!(tree.proof(3).is_empty() && !tree.verify(&proof, 3));
+        assert!(tree.inclusion_proof(3).is_err());
+        assert_eq!(tree.snapshot().root(), tree.root());
+    }
+}
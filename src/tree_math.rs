@@ -0,0 +1,253 @@
+use crate::error::MerkleTreeError;
+use crate::merkle_tree::DEFAULT_ARITY;
+use std::ops::Range;
+
+// Where the nodes of a tree sit in its flat node storage. Levels are numbered from the
+// leaves (0) up to the root and stored the other way round, the root first and the
+// leaves last, each level in order. A level of `size` nodes has `size.div_ceil(arity)`
+// parents, a last group holding a single node promotes it to the level above unchanged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+  arity: usize,
+  // nodes on each level, from the leaves up
+  sizes: Vec<usize>,
+  // position of the first node of each level
+  offsets: Vec<usize>
+}
+
+impl Layout {
+  pub fn new(leaf_count: usize, arity: usize) -> Result<Self, MerkleTreeError> {
+    if arity < 2 {
+      return Err(MerkleTreeError::InvalidArity(arity));
+    }
+    Ok(Self::of(leaf_count, arity))
+  }
+
+  #[must_use]
+  pub fn binary(leaf_count: usize) -> Self {
+    Self::of(leaf_count, DEFAULT_ARITY)
+  }
+
+  pub(crate) fn of(leaf_count: usize, arity: usize) -> Self {
+    let sizes = level_sizes(leaf_count, arity);
+    let mut offsets = vec![0; sizes.len()];
+    for level in (0..sizes.len() - 1).rev() {
+      offsets[level] = offsets[level + 1] + sizes[level + 1];
+    }
+    Self { arity, sizes, offsets }
+  }
+
+  #[must_use]
+  pub fn leaf_count(&self) -> usize {
+    self.sizes[0]
+  }
+
+  #[must_use]
+  pub fn arity(&self) -> usize {
+    self.arity
+  }
+
+  // Levels above the leaves, 0 for trees of at most one leaf
+  #[must_use]
+  pub fn height(&self) -> usize {
+    self.sizes.len() - 1
+  }
+
+  #[must_use]
+  pub fn node_count(&self) -> usize {
+    self.sizes.iter().sum()
+  }
+
+  #[must_use]
+  pub fn level_size(&self, level: usize) -> Option<usize> {
+    self.sizes.get(level).copied()
+  }
+
+  #[must_use]
+  pub fn level_offset(&self, level: usize) -> Option<usize> {
+    self.offsets.get(level).copied()
+  }
+
+  // Storage index of the node at `index` on `level`
+  #[must_use]
+  pub fn node_index(&self, level: usize, index: usize) -> Option<usize> {
+    (index < self.level_size(level)?).then(|| self.offsets[level] + index)
+  }
+
+  // Level of `node` and its index on that level
+  #[must_use]
+  pub fn position(&self, node: usize) -> Option<(usize, usize)> {
+    let level = (0..self.sizes.len()).find(|&level| (self.offsets[level]..self.offsets[level] + self.sizes[level]).contains(&node))?;
+    Some((level, node - self.offsets[level]))
+  }
+
+  #[must_use]
+  pub fn parent(&self, node: usize) -> Option<usize> {
+    let (level, index) = self.position(node)?;
+    self.node_index(level + 1, index / self.arity)
+  }
+
+  // Storage range of the children of `node`, a single child when it was promoted. None for leaves
+  #[must_use]
+  pub fn children(&self, node: usize) -> Option<Range<usize>> {
+    let (level, index) = self.position(node)?;
+    let below = level.checked_sub(1)?;
+    let (start, end) = group_bounds(index * self.arity, self.sizes[below], self.arity);
+    Some(self.offsets[below] + start..self.offsets[below] + end)
+  }
+
+  // Storage range of the group hashed with `node` into its parent, `node` included. None
+  // for the root
+  #[must_use]
+  pub fn group(&self, node: usize) -> Option<Range<usize>> {
+    let (level, index) = self.position(node)?;
+    if level == self.height() {
+      return None;
+    }
+    let (start, end) = group_bounds(index, self.sizes[level], self.arity);
+    Some(self.offsets[level] + start..self.offsets[level] + end)
+  }
+}
+
+// Deepest tree the crate pads, proves or verifies unless told otherwise. A depth past it
+// is a mistake or an attack, not a tree anyone can fill
+pub const MAX_SUPPORTED_DEPTH: usize = 64;
+
+// Most `DepthLimit::new` accepts, enough for sparse trees over 256 bit keys
+pub const HARD_MAX_DEPTH: usize = 256;
+
+// The depth entry points taking a depth check it against, `MAX_SUPPORTED_DEPTH` unless
+// raised on purpose
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DepthLimit(usize);
+
+impl DepthLimit {
+  pub fn new(max: usize) -> Result<Self, MerkleTreeError> {
+    if max > HARD_MAX_DEPTH {
+      return Err(MerkleTreeError::DepthTooLarge { requested: max, max: HARD_MAX_DEPTH });
+    }
+    Ok(Self(max))
+  }
+
+  #[must_use]
+  pub fn max(self) -> usize {
+    self.0
+  }
+
+  pub fn check(self, depth: usize) -> Result<(), MerkleTreeError> {
+    if depth > self.0 {
+      return Err(MerkleTreeError::DepthTooLarge { requested: depth, max: self.0 });
+    }
+    Ok(())
+  }
+}
+
+impl Default for DepthLimit {
+  fn default() -> Self {
+    Self(MAX_SUPPORTED_DEPTH)
+  }
+}
+
+// Nodes of a binary tree of `leaf_count` leaves, the layout of `MerkleTree::new`
+pub(crate) fn node_count(leaf_count: usize) -> usize {
+  Layout::binary(leaf_count).node_count()
+}
+
+// Number of nodes on each level, from the leaves up to the root
+pub(crate) fn level_sizes(count: usize, arity: usize) -> Vec<usize> {
+  // a binary tree has the most levels, one per bit of the count and the root
+  let mut sizes = Vec::with_capacity((usize::BITS - count.leading_zeros()) as usize + 1);
+  sizes.push(count);
+  let mut size = count;
+  while size > 1 {
+    size = size.div_ceil(arity);
+    sizes.push(size);
+  }
+  sizes
+}
+
+// Range of node indices sharing a parent with `index` on a level of `size` nodes. Indices
+// come from proofs anyone can make, the end saturates rather than wrapping around near
+// `usize::MAX`, where it would be past `size` anyway
+pub(crate) fn group_bounds(index: usize, size: usize, arity: usize) -> (usize, usize) {
+  let start = index - index % arity;
+  (start, start.saturating_add(arity).min(size))
+}
+
+// Number of siblings in the proof of the leaf at `index`
+pub(crate) fn proof_len(mut index: usize, leaf_count: usize, arity: usize) -> usize {
+  let mut len = 0;
+  let mut size = leaf_count;
+  while size > 1 {
+    let (start, end) = group_bounds(index, size, arity);
+    len += end - start - 1;
+    index /= arity;
+    size = size.div_ceil(arity);
+  }
+  len
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::MerkleTree;
+    use crate::tree_math::*;
+
+    #[test]
+    fn navigation_round_trips_over_many_sizes() {
+        for leaf_count in 1..=70 {
+            let layout = Layout::binary(leaf_count);
+            let level_of = |node| layout.position(node).map(|(level, _)| level);
+            let sibling = |node| layout.group(node).and_then(|mut group| group.find(|&n| n != node));
+            let count = node_count(leaf_count);
+            for node in 0..count {
+                if let Some(range) = layout.children(node) {
+                    assert!(range.len() == 1 || range.len() == 2);
+                    for child in range {
+                        assert_eq!(layout.parent(child), Some(node));
+                        assert_eq!(level_of(child).unwrap() + 1, level_of(node).unwrap());
+                    }
+                }
+                match sibling(node) {
+                    Some(other) => {
+                        assert_eq!(sibling(other), Some(node));
+                        assert_eq!(layout.parent(other), layout.parent(node));
+                        // siblings are the two halves of a pair on their level
+                        let offset = layout.level_offset(level_of(node).unwrap()).unwrap();
+                        assert_eq!((node - offset) ^ 1, other - offset);
+                    },
+                    None => assert!(layout.parent(node).is_none_or(|p| layout.children(p).unwrap().len() == 1)),
+                }
+            }
+            assert_eq!(layout.parent(0), None);
+            assert_eq!(level_of(count), None);
+            assert_eq!(layout.node_index(0, leaf_count), None);
+        }
+        assert_eq!(node_count(0), 0);
+        assert_eq!(Layout::binary(0).node_index(0, 0), None);
+        assert_eq!(Layout::binary(1).children(0), None);
+    }
+
+    #[test]
+    fn indices_address_the_tree_nodes() {
+        for arity in [2, 3, 4] {
+            for leaf_count in [1, 2, 5, 9, 16, 17] {
+                let elements: Vec<String> = (0..leaf_count).map(|i| i.to_string()).collect();
+                let tree = MerkleTree::with_arity(&elements, arity).unwrap();
+                let layout = Layout::new(leaf_count, arity).unwrap();
+                assert_eq!(tree.layout(), layout);
+                assert_eq!(tree.node(0), Some(tree.root().as_bytes()));
+                assert_eq!(tree.node(layout.node_count()), None);
+                for index in 0..leaf_count {
+                    let node = layout.node_index(0, index).unwrap();
+                    assert_eq!(tree.node(node), Some(&tree[index]));
+                    // the path from the leaf ends at the root after `height` steps
+                    let path: Vec<usize> = std::iter::successors(Some(node), |&n| layout.parent(n)).collect();
+                    assert_eq!(path.len(), layout.height() + 1);
+                    assert_eq!(path.last(), Some(&0));
+                }
+            }
+        }
+        assert!(Layout::new(4, 1).is_err());
+    }
+}